@@ -0,0 +1,173 @@
+// ? INFO
+// Adaptive sampling spends samples where the image is still noisy. Every pixel keeps a
+// running mean and variance (Welford's algorithm) and stops once the standard error of
+// its mean drops below the threshold, flat areas stop right after the first batch.
+
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::sampling::Sampler;
+use crate::tuples::Float;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct PixelStatistics {
+    pub count: usize,
+    pub mean: Color,
+    m2: Color
+}
+
+impl PixelStatistics {
+    pub fn new() -> Self {
+        PixelStatistics {
+            count: 0,
+            mean: Color::black(),
+            m2: Color::black()
+        }
+    }
+
+    pub fn add(&mut self, color: Color) {
+        self.count += 1;
+        let delta = color - self.mean;
+        self.mean = self.mean + delta / self.count as f32;
+        self.m2 = self.m2 + delta * (color - self.mean);
+    }
+
+    // Unbiased sample variance per channel
+    pub fn get_variance(&self) -> Color {
+        if self.count < 2 {
+            return Color::black()
+        }
+
+        self.m2 / (self.count - 1) as f32
+    }
+
+    // Standard error of the mean of the noisiest channel
+    pub fn get_error(&self) -> Float {
+        if self.count < 2 {
+            return Float::INFINITY
+        }
+
+        let variance = self.get_variance();
+        let worst = variance.red.max(variance.green).max(variance.blue) as Float;
+        (worst / self.count as Float).sqrt()
+    }
+}
+
+impl Default for PixelStatistics {
+    fn default() -> Self {
+        PixelStatistics::new()
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct AdaptiveSampler {
+    // Pattern, filter and seed of every batch, samples_per_pixel is the batch size
+    pub sampler: Sampler,
+    pub min_samples: usize,
+    pub max_samples: usize,
+    // Standard error, in color units, below which a pixel counts as converged
+    pub threshold: Float,
+    pub heatmap: bool
+}
+
+impl AdaptiveSampler {
+    pub fn new(min_samples: usize, max_samples: usize, threshold: Float, seed: u64) -> Self {
+        AdaptiveSampler {
+            sampler: Sampler::new(4, seed),
+            min_samples,
+            max_samples,
+            threshold,
+            heatmap: false
+        }
+    }
+
+    pub fn is_converged(&self, statistics: &PixelStatistics) -> bool {
+        statistics.count >= self.max_samples ||
+        (statistics.count >= self.min_samples && statistics.get_error() <= self.threshold)
+    }
+}
+
+pub struct AdaptiveRender {
+    pub image: Canvas,
+    // Samples taken per pixel, row by row
+    pub sample_counts: Vec<usize>,
+    pub heatmap: Option<Canvas>
+}
+
+impl AdaptiveRender {
+    pub fn get_total_samples(&self) -> usize {
+        self.sample_counts.iter().sum()
+    }
+}
+
+// Black for no samples through red and yellow to white for the most sampled pixel
+pub fn create_heatmap(width: usize, height: usize, sample_counts: &[usize]) -> Canvas {
+    let mut heatmap = Canvas::new(width, height);
+    let most = sample_counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+
+    for y in 0..height {
+        for x in 0..width {
+            let heat = sample_counts[y * width + x] as f32 / most * 3.;
+            let color = Color::new(heat.min(1.), (heat - 1.).clamp(0., 1.), (heat - 2.).clamp(0., 1.));
+            heatmap.write_pixel_at(x, y, color);
+        }
+    }
+
+    heatmap
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_running_mean_and_variance() {
+        let mut statistics = PixelStatistics::new();
+        for value in [2., 4., 4., 4., 5., 5., 7., 9.] {
+            statistics.add(Color::new(value, 0., 1.));
+        }
+
+        assert_eq!(statistics.count, 8);
+        assert_eq!(statistics.mean.is_equal_to(Color::new(5., 0., 1.)), true);
+        assert_eq!(f32::abs(statistics.get_variance().red - 32. / 7.) < 0.0001, true);
+        assert_eq!(statistics.get_variance().blue, 0.);
+        assert_eq!(f64::abs(statistics.get_error() - (32. / 7. / 8_f64).sqrt()) < 0.0001, true);
+    }
+
+    #[test]
+    fn test_error_unknown_with_one_sample() {
+        let mut statistics = PixelStatistics::new();
+        statistics.add(Color::white());
+
+        assert_eq!(statistics.get_error(), Float::INFINITY);
+    }
+
+    #[test]
+    fn test_convergence() {
+        let adaptive = AdaptiveSampler::new(4, 16, 0.01, 0);
+        let mut flat = PixelStatistics::new();
+        let mut noisy = PixelStatistics::new();
+
+        for index in 0..4 {
+            flat.add(Color::white());
+            noisy.add(if index % 2 == 0 { Color::white() } else { Color::black() });
+        }
+
+        assert_eq!(adaptive.is_converged(&flat), true);
+        assert_eq!(adaptive.is_converged(&noisy), false);
+
+        for _ in 0..12 {
+            noisy.add(Color::black());
+        }
+        assert_eq!(adaptive.is_converged(&noisy), true);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let heatmap = create_heatmap(3, 1, &[0, 8, 16]);
+
+        assert_eq!(heatmap.get_pixel_at(0, 0), Color::black());
+        assert_eq!(heatmap.get_pixel_at(1, 0).is_equal_to(Color::new(1., 0.5, 0.)), true);
+        assert_eq!(heatmap.get_pixel_at(2, 0), Color::white());
+    }
+}
@@ -0,0 +1,179 @@
+// ? INFO
+// Keyframed transforms for motion blur. Matrices cannot be interpolated directly without
+// shearing and shrinking in between, so every keyframe is split into translation, rotation
+// and scale which are interpolated separately (the rotation with quaternion slerp).
+
+use crate::matrix::{Matrix, MatrixType};
+use crate::quaternion::Quaternion;
+use crate::tuples::{Tuple, Float};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Keyframe {
+    pub time: Float,
+    pub translation: Tuple,
+    pub rotation: Quaternion,
+    pub scale: Tuple
+}
+
+impl Keyframe {
+    pub fn new(time: Float, translation: Tuple, rotation: Quaternion, scale: Tuple) -> Self {
+        Keyframe {
+            time,
+            translation,
+            rotation,
+            scale
+        }
+    }
+
+    // Decomposes a translation * rotation * scaling matrix, shearing is lost
+    pub fn from_matrix(time: Float, m: &MatrixType) -> Self {
+        let column = |j: usize| Tuple::new_vector(m[0][j], m[1][j], m[2][j]);
+        let mut scale = Tuple::new_vector(column(0).get_magnitude(), column(1).get_magnitude(), column(2).get_magnitude());
+
+        // a mirrored matrix keeps a proper rotation by flipping one axis of the scale
+        if Matrix::determinant(&Matrix::submatrix(m, 3, 3)) < 0. {
+            scale.x = -scale.x;
+        }
+
+        let mut rotation = Matrix::identity_4x4();
+        for (j, factor) in [scale.x, scale.y, scale.z].iter().enumerate() {
+            for (i, row) in rotation.iter_mut().take(3).enumerate() {
+                row[j] = m[i][j] / factor;
+            }
+        }
+
+        Keyframe {
+            time,
+            translation: Tuple::new_vector(m[0][3], m[1][3], m[2][3]),
+            rotation: Quaternion::from_rotation_matrix(&rotation),
+            scale
+        }
+    }
+
+    pub fn to_matrix(&self) -> MatrixType {
+        let translation = Matrix::translation(self.translation.x, self.translation.y, self.translation.z);
+        let scaling = Matrix::scaling(self.scale.x, self.scale.y, self.scale.z);
+        Matrix::multiply(&translation, &Matrix::multiply(&self.rotation.to_rotation_matrix(), &scaling))
+    }
+
+    pub fn interpolate(&self, other: &Keyframe, t: Float) -> Keyframe {
+        let lerp = |a: &Tuple, b: &Tuple| *a + (*b - *a) * t;
+
+        Keyframe {
+            time: self.time + (other.time - self.time) * t,
+            translation: lerp(&self.translation, &other.translation),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: lerp(&self.scale, &other.scale)
+        }
+    }
+}
+
+// Transform that changes over time, held constant before the first and after the last keyframe
+#[derive(PartialEq, Debug, Clone)]
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>
+}
+
+impl AnimatedTransform {
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "an animated transform needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        AnimatedTransform {
+            keyframes
+        }
+    }
+
+    // Keyframes given as (time, matrix) pairs
+    pub fn from_matrices(keyframes: &[(Float, MatrixType)]) -> Self {
+        AnimatedTransform::new(keyframes.iter().map(|(time, m)| Keyframe::from_matrix(*time, m)).collect())
+    }
+
+    pub fn get_keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn keyframe_at(&self, time: Float) -> Keyframe {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+
+        if time <= first.time {
+            return first.clone()
+        }
+
+        if time >= last.time {
+            return last.clone()
+        }
+
+        let next = self.keyframes.iter().position(|keyframe| keyframe.time > time).unwrap_or(self.keyframes.len() - 1);
+        let (start, end) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        start.interpolate(end, (time - start.time) / (end.time - start.time))
+    }
+
+    pub fn matrix_at(&self, time: Float) -> MatrixType {
+        self.keyframe_at(time).to_matrix()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_decompose_matrix() {
+        let m = Matrix::multiply(
+            &Matrix::translation(1., 2., 3.),
+            &Matrix::multiply(&Matrix::rotation_y(PI / 3.), &Matrix::scaling(2., 3., 4.))
+        );
+        let keyframe = Keyframe::from_matrix(0., &m);
+
+        assert_eq!(keyframe.translation.is_equal_to(&Tuple::new_vector(1., 2., 3.)), true);
+        assert_eq!(keyframe.scale.is_equal_to(&Tuple::new_vector(2., 3., 4.)), true);
+        assert_eq!(Matrix::are_equal(&keyframe.to_matrix(), &m), true);
+    }
+
+    #[test]
+    fn test_decompose_mirrored_matrix() {
+        let m = Matrix::multiply(&Matrix::rotation_z(0.4), &Matrix::scaling(-1., 2., 1.));
+        let keyframe = Keyframe::from_matrix(0., &m);
+
+        assert_eq!(Matrix::are_equal(&keyframe.to_matrix(), &m), true);
+    }
+
+    #[test]
+    fn test_interpolate_translation_and_scale() {
+        let animation = AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(-2., 0., 0.)),
+            (1., Matrix::multiply(&Matrix::translation(2., 0., 0.), &Matrix::scaling(3., 3., 3.))),
+        ]);
+        let halfway = animation.keyframe_at(0.5);
+
+        assert_eq!(halfway.translation.is_equal_to(&Tuple::new_vector(0., 0., 0.)), true);
+        assert_eq!(halfway.scale.is_equal_to(&Tuple::new_vector(2., 2., 2.)), true);
+    }
+
+    #[test]
+    fn test_interpolate_rotation_does_not_shrink() {
+        let animation = AnimatedTransform::from_matrices(&[
+            (0., Matrix::identity_4x4()),
+            (1., Matrix::rotation_z(PI / 2.)),
+        ]);
+
+        assert_eq!(Matrix::are_equal(&animation.matrix_at(0.5), &Matrix::rotation_z(PI / 4.)), true);
+    }
+
+    #[test]
+    fn test_clamps_outside_keyframes_and_sorts() {
+        let animation = AnimatedTransform::from_matrices(&[
+            (2., Matrix::translation(0., 4., 0.)),
+            (1., Matrix::translation(0., 2., 0.)),
+            (0., Matrix::translation(0., 0., 0.)),
+        ]);
+
+        assert_eq!(Matrix::are_equal(&animation.matrix_at(-1.), &Matrix::identity_4x4()), true);
+        assert_eq!(Matrix::are_equal(&animation.matrix_at(1.5), &Matrix::translation(0., 3., 0.)), true);
+        assert_eq!(Matrix::are_equal(&animation.matrix_at(5.), &Matrix::translation(0., 4., 0.)), true);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::matrix::{Matrix, MatrixType};
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+// Axis aligned bounding box, used to skip groups of shapes a ray cannot hit.
+// A new box is empty, with min above max, and grows as points are added
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct BoundingBox {
+    pub min: Tuple,
+    pub max: Tuple
+}
+
+impl BoundingBox {
+    pub fn new() -> Self {
+        BoundingBox {
+            min: Tuple::new_point(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+            max: Tuple::new_point(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY)
+        }
+    }
+
+    pub fn new_with_bounds(min: Tuple, max: Tuple) -> Self {
+        BoundingBox {
+            min,
+            max
+        }
+    }
+
+    pub fn infinite() -> Self {
+        BoundingBox {
+            min: Tuple::new_point(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY),
+            max: Tuple::new_point(Float::INFINITY, Float::INFINITY, Float::INFINITY)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn add_point(&mut self, point: &Tuple) {
+        self.min = Tuple::new_point(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z));
+        self.max = Tuple::new_point(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z));
+    }
+
+    pub fn merge(&mut self, other: &BoundingBox) {
+        if !other.is_empty() {
+            self.add_point(&other.min);
+            self.add_point(&other.max);
+        }
+    }
+
+    pub fn contains_point(&self, point: &Tuple) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+
+    pub fn contains_box(&self, other: &BoundingBox) -> bool {
+        self.contains_point(&other.min) && self.contains_point(&other.max)
+    }
+
+    pub fn get_center(&self) -> Tuple {
+        Tuple::new_point(
+            (self.min.x + self.max.x) / 2.,
+            (self.min.y + self.max.y) / 2.,
+            (self.min.z + self.max.z) / 2.
+        )
+    }
+
+    // Box around all eight transformed corners, infinite boxes stay infinite
+    pub fn transform(&self, m: &MatrixType) -> BoundingBox {
+        if self.is_empty() {
+            return *self
+        }
+
+        if [self.min.x, self.min.y, self.min.z, self.max.x, self.max.y, self.max.z].iter().any(|v| v.is_infinite()) {
+            return BoundingBox::infinite()
+        }
+
+        let mut bounds = BoundingBox::new();
+
+        for x in [self.min.x, self.max.x] {
+            for y in [self.min.y, self.max.y] {
+                for z in [self.min.z, self.max.z] {
+                    bounds.add_point(&Matrix::multiply_tuple(m, &Tuple::new_point(x, y, z)));
+                }
+            }
+        }
+
+        bounds
+    }
+
+    // Slab test, also true when the ray starts inside the box
+    pub fn intersects(&self, ray: &Ray) -> bool {
+        matches!(self.intersection_range(ray), Some((_, t_max)) if t_max >= 0.)
+    }
+
+    // Where the ray's line enters and leaves the box, either may be behind the origin
+    pub fn intersection_range(&self, ray: &Ray) -> Option<(Float, Float)> {
+        if self.is_empty() {
+            return None
+        }
+
+        let (x_min, x_max) = BoundingBox::check_axis(ray.origin.x, ray.direction.x, self.min.x, self.max.x);
+        let (y_min, y_max) = BoundingBox::check_axis(ray.origin.y, ray.direction.y, self.min.y, self.max.y);
+        let (z_min, z_max) = BoundingBox::check_axis(ray.origin.z, ray.direction.z, self.min.z, self.max.z);
+
+        let t_min = x_min.max(y_min).max(z_min);
+        let t_max = x_max.min(y_max).min(z_max);
+
+        if t_min <= t_max { Some((t_min, t_max)) } else { None }
+    }
+
+    fn check_axis(origin: Float, direction: Float, min: Float, max: Float) -> (Float, Float) {
+        if direction == 0. {
+            return if (min..=max).contains(&origin) {
+                (Float::NEG_INFINITY, Float::INFINITY)
+            } else {
+                (Float::INFINITY, Float::NEG_INFINITY)
+            }
+        }
+
+        let t1 = (min - origin) / direction;
+        let t2 = (max - origin) / direction;
+
+        (t1.min(t2), t1.max(t2))
+    }
+}
+
+impl Default for BoundingBox {
+    fn default() -> Self {
+        BoundingBox::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn unit_box() -> BoundingBox {
+        BoundingBox::new_with_bounds(Tuple::new_point(-1., -1., -1.), Tuple::new_point(1., 1., 1.))
+    }
+
+    #[test]
+    fn test_box_grows_with_points() {
+        let mut bounds = BoundingBox::new();
+        assert_eq!(bounds.is_empty(), true);
+
+        bounds.add_point(&Tuple::new_point(-5., 2., 0.));
+        bounds.add_point(&Tuple::new_point(7., 0., -3.));
+
+        assert_eq!(bounds.min, Tuple::new_point(-5., 0., -3.));
+        assert_eq!(bounds.max, Tuple::new_point(7., 2., 0.));
+        assert_eq!(bounds.get_center(), Tuple::new_point(1., 1., -1.5));
+    }
+
+    #[test]
+    fn test_merge_and_contain() {
+        let mut bounds = unit_box();
+        bounds.merge(&BoundingBox::new_with_bounds(Tuple::new_point(0., 0., 0.), Tuple::new_point(3., 1., 1.)));
+        bounds.merge(&BoundingBox::new());
+
+        assert_eq!(bounds.max, Tuple::new_point(3., 1., 1.));
+        assert_eq!(bounds.contains_point(&Tuple::new_point(2., 0., 0.)), true);
+        assert_eq!(bounds.contains_box(&unit_box()), true);
+        assert_eq!(unit_box().contains_box(&bounds), false);
+    }
+
+    #[test]
+    fn test_transformed_box() {
+        let bounds = unit_box().transform(&Matrix::multiply(&Matrix::rotation_x(PI / 4.), &Matrix::rotation_y(PI / 4.)));
+
+        assert_eq!(bounds.min.is_equal_to(&Tuple::new_point(-2_f64.sqrt(), -1.70710, -1.70710)), true);
+        assert_eq!(bounds.max.is_equal_to(&Tuple::new_point(2_f64.sqrt(), 1.70710, 1.70710)), true);
+        assert_eq!(BoundingBox::infinite().transform(&Matrix::translation(1., 0., 0.)), BoundingBox::infinite());
+    }
+
+    #[test]
+    fn test_ray_box_intersection() {
+        let bounds = unit_box();
+
+        assert_eq!(bounds.intersects(&Ray::new(Tuple::new_point(5., 0.5, 0.), Tuple::new_vector(-1., 0., 0.))), true);
+        assert_eq!(bounds.intersects(&Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.))), true);
+        assert_eq!(bounds.intersects(&Ray::new(Tuple::new_point(2., 0., 2.), Tuple::new_vector(0., 0., -1.))), false);
+        assert_eq!(bounds.intersects(&Ray::new(Tuple::new_point(0., 0., 5.), Tuple::new_vector(0., 0., 1.))), false);
+        assert_eq!(BoundingBox::infinite().intersects(&Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(1., 0., 0.))), true);
+
+        assert_eq!(bounds.intersection_range(&Ray::new(Tuple::new_point(5., 0.5, 0.), Tuple::new_vector(-1., 0., 0.))), Some((4., 6.)));
+        assert_eq!(bounds.intersection_range(&Ray::new(Tuple::new_point(0., 0., 5.), Tuple::new_vector(0., 0., 1.))), Some((-6., -4.)));
+    }
+}
@@ -0,0 +1,152 @@
+use std::f64::consts::PI;
+
+use crate::canvas::Canvas;
+use crate::random::Random;
+use crate::tuples::Float;
+
+// How many times a masked aperture retries before it falls back to the lens center
+const MASK_ATTEMPTS: usize = 64;
+
+// Shape of the lens opening, it is also the shape out of focus highlights (bokeh) take
+#[derive(PartialEq, Debug, Clone)]
+pub enum Aperture {
+    Disk,
+    // Regular polygon inscribed in the unit circle, like the blades of a real diaphragm
+    Polygon { blades: usize, rotation: Float },
+    // Grayscale image stretched over the lens, brightness is the chance a point lets light through
+    Mask(Canvas)
+}
+
+impl Aperture {
+    // Point on the lens in [-1, 1] x [-1, 1], scaled by the aperture radius in the camera
+    pub fn sample(&self, random: &mut Random) -> (Float, Float) {
+        match self {
+            Aperture::Disk => sample_concentric_disk(random.next_float(), random.next_float()),
+            Aperture::Polygon { blades, rotation } => {
+                sample_polygon(*blades, *rotation, random.next_float(), random.next_float())
+            },
+            Aperture::Mask(mask) => sample_mask(mask, random)
+        }
+    }
+}
+
+// Shirley-Chiu mapping, keeps stratified inputs well spread over the disk
+pub fn sample_concentric_disk(u1: Float, u2: Float) -> (Float, Float) {
+    let x = 2. * u1 - 1.;
+    let y = 2. * u2 - 1.;
+
+    if x == 0. && y == 0. {
+        return (0., 0.)
+    }
+
+    let (radius, theta) = if x.abs() > y.abs() {
+        (x, PI / 4. * (y / x))
+    } else {
+        (y, PI / 2. - PI / 4. * (x / y))
+    };
+
+    (radius * theta.cos(), radius * theta.sin())
+}
+
+// Picks one of the equal triangles fanning out from the center, then a point inside it
+pub fn sample_polygon(blades: usize, rotation: Float, u1: Float, u2: Float) -> (Float, Float) {
+    let blades = blades.max(3);
+    let scaled = u1 * blades as Float;
+    let triangle = (scaled.floor() as usize).min(blades - 1);
+    let u1 = scaled - triangle as Float;
+
+    let angle = |index: usize| rotation + 2. * PI * index as Float / blades as Float;
+    let (start, end) = (angle(triangle), angle(triangle + 1));
+
+    // uniform point in the triangle (center, start, end)
+    let root = u1.sqrt();
+    let a = root * (1. - u2);
+    let b = root * u2;
+
+    (a * start.cos() + b * end.cos(), a * start.sin() + b * end.sin())
+}
+
+fn sample_mask(mask: &Canvas, random: &mut Random) -> (Float, Float) {
+    let (width, height) = (mask.get_width(), mask.get_height());
+
+    for _ in 0..MASK_ATTEMPTS {
+        let x = 2. * random.next_float() - 1.;
+        let y = 2. * random.next_float() - 1.;
+
+        let px = (((x + 1.) / 2. * width as Float) as usize).min(width - 1);
+        let py = (((1. - y) / 2. * height as Float) as usize).min(height - 1);
+        let openness = mask.get_pixel_at(px, py).get_luminance() as Float;
+
+        if random.next_float() < openness {
+            return (x, y)
+        }
+    }
+
+    (0., 0.)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn test_disk_samples_stay_in_unit_disk() {
+        let mut random = Random::new(1);
+
+        for _ in 0..500 {
+            let (x, y) = Aperture::Disk.sample(&mut random);
+            assert_eq!(x * x + y * y <= 1. + 1e-9, true);
+        }
+
+        assert_eq!(sample_concentric_disk(0.5, 0.5), (0., 0.));
+        let (x, y) = sample_concentric_disk(1., 0.5);
+        assert_eq!(f64::abs(x - 1.) < 1e-9 && f64::abs(y) < 1e-9, true);
+    }
+
+    #[test]
+    fn test_polygon_samples_stay_inside_polygon() {
+        let mut random = Random::new(2);
+        let aperture = Aperture::Polygon { blades: 6, rotation: 0. };
+        // the inscribed circle of a hexagon in the unit circle has radius cos(30 degrees)
+        let apothem = (PI / 6.).cos();
+
+        for _ in 0..500 {
+            let (x, y) = aperture.sample(&mut random);
+            let angle = y.atan2(x).rem_euclid(PI / 3.) - PI / 6.;
+            let limit = apothem / angle.cos();
+            assert_eq!((x * x + y * y).sqrt() <= limit + 1e-9, true);
+        }
+    }
+
+    #[test]
+    fn test_polygon_corners() {
+        let (x, y) = sample_polygon(4, 0., 0., 0.);
+        assert_eq!((x, y), (0., 0.));
+
+        // u1 close to one ends at the outer edge, u2 = 0 is the starting corner
+        let (x, y) = sample_polygon(4, 0., 0.2499999999, 0.);
+        assert_eq!(f64::abs(x - 1.) < 1e-4 && f64::abs(y) < 1e-4, true);
+    }
+
+    #[test]
+    fn test_mask_only_lets_light_through_open_parts() {
+        let mut mask = Canvas::new(2, 1);
+        mask.write_pixel_at(1, 0, Color::white());
+        let aperture = Aperture::Mask(mask);
+        let mut random = Random::new(3);
+
+        for _ in 0..200 {
+            let (x, _) = aperture.sample(&mut random);
+            assert_eq!(x >= 0., true);
+        }
+    }
+
+    #[test]
+    fn test_closed_mask_falls_back_to_center() {
+        let aperture = Aperture::Mask(Canvas::new(4, 4));
+
+        assert_eq!(aperture.sample(&mut Random::new(0)), (0., 0.));
+    }
+}
@@ -0,0 +1,609 @@
+pub mod aperture;
+pub mod projection;
+
+pub use aperture::Aperture;
+pub use projection::Projection;
+
+use crate::adaptive::{AdaptiveSampler, AdaptiveRender, PixelStatistics, create_heatmap};
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::film::Film;
+use crate::random::Random;
+use crate::sampling::Sampler;
+use crate::matrix::{Matrix, MatrixType};
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+use crate::world::{World, MAX_RECURSION_DEPTH};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Camera {
+    pub hsize: usize,
+    pub vsize: usize,
+    pub field_of_view: Float,
+    pub projection: Projection,
+    // Sideways shift of the eye, positive to the left, see stereo_pair
+    pub eye_offset: Float,
+    // Rays are spread evenly over the time the shutter is open, equal times disable motion blur
+    pub shutter_open: Float,
+    pub shutter_close: Float,
+    // Thin lens, only used by the perspective projection. A radius of zero keeps the camera a pinhole with everything in focus
+    pub aperture: Aperture,
+    pub aperture_radius: Float,
+    // Distance along the view direction of the plane that is in perfect focus
+    pub focal_distance: Float,
+    transform: MatrixType,
+    inverse: MatrixType,
+    half_width: Float,
+    half_height: Float,
+    pixel_size: Float
+}
+
+impl Camera {
+    pub fn new(hsize: usize, vsize: usize, field_of_view: Float) -> Self {
+        let half_view = (field_of_view / 2.).tan();
+        let aspect = hsize as Float / vsize as Float;
+
+        let (half_width, half_height) = if aspect >= 1. {
+            (half_view, half_view / aspect)
+        } else {
+            (half_view * aspect, half_view)
+        };
+
+        Camera {
+            hsize,
+            vsize,
+            field_of_view,
+            projection: Projection::Perspective,
+            eye_offset: 0.,
+            shutter_open: 0.,
+            shutter_close: 0.,
+            aperture: Aperture::Disk,
+            aperture_radius: 0.,
+            focal_distance: 1.,
+            transform: Matrix::identity_4x4(),
+            inverse: Matrix::identity_4x4(),
+            half_width,
+            half_height,
+            pixel_size: (half_width * 2.) / hsize as Float
+        }
+    }
+
+    pub fn get_transform(&self) -> &MatrixType {
+        &self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: MatrixType) {
+        self.inverse = Matrix::inverse(&transform);
+        self.transform = transform;
+    }
+
+    pub fn get_pixel_size(&self) -> Float {
+        self.pixel_size
+    }
+
+    // World space size one pixel covers at the point, used to tessellate finer close up
+    pub fn get_pixel_footprint(&self, point: &Tuple) -> Float {
+        let eye = Matrix::multiply_tuple(&self.inverse, &Tuple::new_point(self.eye_offset, 0., 0.));
+        let distance = (*point - eye).get_magnitude();
+
+        match self.projection {
+            Projection::Perspective => distance * self.pixel_size,
+            Projection::Orthographic { view_width } => view_width / self.hsize as Float,
+            Projection::Fisheye { field_of_view } => distance * field_of_view / self.hsize.min(self.vsize) as Float,
+            Projection::Equirectangular => distance * 2. * std::f64::consts::PI / self.hsize as Float
+        }
+    }
+
+    // Ray through the center of the pixel
+    pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
+        self.ray_for_pixel_offset(px, py, 0.5, 0.5)
+    }
+
+    // Ray through an arbitrary spot of the pixel, offsets go from 0 to 1
+    pub fn ray_for_pixel_offset(&self, px: usize, py: usize, x_offset: Float, y_offset: Float) -> Ray {
+        let (origin, direction) = self.camera_space_ray(px as Float + x_offset, py as Float + y_offset);
+        self.to_world(&origin, &direction)
+    }
+
+    // Origin and direction before the camera transform, x and y are in pixels from the top left
+    fn camera_space_ray(&self, x: Float, y: Float) -> (Tuple, Tuple) {
+        let eye = Tuple::new_point(self.eye_offset, 0., 0.);
+
+        match self.projection {
+            Projection::Perspective => {
+                let world_x = self.half_width - x * self.pixel_size;
+                let world_y = self.half_height - y * self.pixel_size;
+                (eye, Tuple::new_vector(world_x, world_y, -1.))
+            },
+            Projection::Orthographic { view_width } => {
+                let pixel_size = view_width / self.hsize as Float;
+                let world_x = view_width / 2. - x * pixel_size;
+                let world_y = pixel_size * self.vsize as Float / 2. - y * pixel_size;
+                (Tuple::new_point(world_x + self.eye_offset, world_y, 0.), Tuple::new_vector(0., 0., -1.))
+            },
+            Projection::Fisheye { field_of_view } => {
+                let half_size = self.hsize.min(self.vsize) as Float / 2.;
+                let circle_x = (self.hsize as Float / 2. - x) / half_size;
+                let circle_y = (self.vsize as Float / 2. - y) / half_size;
+                (eye, projection::fisheye_direction(circle_x, circle_y, field_of_view))
+            },
+            Projection::Equirectangular => {
+                let u = x / self.hsize as Float;
+                let v = y / self.vsize as Float;
+                // each column gets its own eye position on a circle, omni-directional stereo
+                let origin = Tuple::new_point(0., 0., 0.) + projection::equirectangular_left(u) * self.eye_offset;
+                (origin, projection::equirectangular_direction(u, v))
+            }
+        }
+    }
+
+    fn to_world(&self, origin: &Tuple, direction: &Tuple) -> Ray {
+        let origin = Matrix::multiply_tuple(&self.inverse, origin);
+        let direction = Matrix::multiply_tuple(&self.inverse, direction).normalize();
+        Ray::new_at_time(origin, direction, self.shutter_open)
+    }
+
+    // Left and right eye cameras, interocular_distance apart and looking the same way
+    pub fn stereo_pair(&self, interocular_distance: Float) -> (Camera, Camera) {
+        let mut left = self.clone();
+        left.eye_offset = self.eye_offset + interocular_distance / 2.;
+        let mut right = self.clone();
+        right.eye_offset = self.eye_offset - interocular_distance / 2.;
+        (left, right)
+    }
+
+    // Ray from a point on the lens, lens_x and lens_y go from -1 to 1 and are scaled by
+    // the aperture radius. Every ray through the same pixel spot meets on the focal plane
+    pub fn ray_through_lens(&self, px: usize, py: usize, x_offset: Float, y_offset: Float, lens_x: Float, lens_y: Float) -> Ray {
+        let world_x = self.half_width - (px as Float + x_offset) * self.pixel_size;
+        let world_y = self.half_height - (py as Float + y_offset) * self.pixel_size;
+
+        let focus = Tuple::new_point(world_x * self.focal_distance + self.eye_offset, world_y * self.focal_distance, -self.focal_distance);
+        let lens = Tuple::new_point(lens_x * self.aperture_radius + self.eye_offset, lens_y * self.aperture_radius, 0.);
+
+        self.to_world(&lens, &(focus - lens))
+    }
+
+    // Pinhole ray when there is no aperture or the projection is not a perspective one,
+    // otherwise a ray from a sampled lens position. The time is picked inside the shutter interval
+    pub fn sample_ray(&self, px: usize, py: usize, x_offset: Float, y_offset: Float, random: &mut Random) -> Ray {
+        let mut ray = if self.aperture_radius <= 0. || self.projection != Projection::Perspective {
+            self.ray_for_pixel_offset(px, py, x_offset, y_offset)
+        } else {
+            let (lens_x, lens_y) = self.aperture.sample(random);
+            self.ray_through_lens(px, py, x_offset, y_offset, lens_x, lens_y)
+        };
+
+        if self.shutter_close > self.shutter_open {
+            ray.time = self.shutter_open + random.next_float() * (self.shutter_close - self.shutter_open);
+        }
+
+        ray
+    }
+
+    pub fn render(&self, world: &World) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let ray = self.ray_for_pixel(x, y);
+                image.write_pixel_at(x, y, world.color_at(&ray, MAX_RECURSION_DEPTH));
+            }
+        }
+
+        image
+    }
+
+    // Whitted-style render with several samples per pixel
+    pub fn render_supersampled(&self, world: &World, sampler: &Sampler) -> Canvas {
+        self.render_sampled(sampler, |ray, _| world.color_at(ray, MAX_RECURSION_DEPTH))
+    }
+
+    // Shoots the sampler's pattern through every pixel and reconstructs the image with
+    // its filter. Every pixel has its own generator so the result does not depend on
+    // the order pixels are rendered in
+    pub fn render_sampled<F>(&self, sampler: &Sampler, mut radiance: F) -> Canvas
+    where F: FnMut(&Ray, &mut Random) -> Color {
+        let mut film = Film::new(self.hsize, self.vsize, sampler.filter);
+
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let mut random = Random::for_pixel(sampler.seed, x, y);
+
+                for (x_offset, y_offset) in sampler.generate_offsets(&mut random) {
+                    let ray = self.sample_ray(x, y, x_offset, y_offset, &mut random);
+                    let color = radiance(&ray, &mut random);
+                    film.add_sample(x as Float + x_offset, y as Float + y_offset, color);
+                }
+            }
+        }
+
+        film.to_canvas()
+    }
+
+    // Like render_sampled, but every pixel takes batches of samples until its estimated
+    // error is below the threshold or it reaches the maximum sample count
+    pub fn render_adaptive<F>(&self, adaptive: &AdaptiveSampler, mut radiance: F) -> AdaptiveRender
+    where F: FnMut(&Ray, &mut Random) -> Color {
+        let sampler = &adaptive.sampler;
+        let mut film = Film::new(self.hsize, self.vsize, sampler.filter);
+        let mut sample_counts = vec![0; self.hsize * self.vsize];
+
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let mut random = Random::for_pixel(sampler.seed, x, y);
+                let mut statistics = PixelStatistics::new();
+
+                while !adaptive.is_converged(&statistics) {
+                    let offsets = sampler.generate_offsets(&mut random);
+                    if offsets.is_empty() {
+                        break;
+                    }
+
+                    for (x_offset, y_offset) in offsets {
+                        if statistics.count >= adaptive.max_samples {
+                            break;
+                        }
+
+                        let ray = self.sample_ray(x, y, x_offset, y_offset, &mut random);
+                        let color = radiance(&ray, &mut random);
+                        statistics.add(color);
+                        film.add_sample(x as Float + x_offset, y as Float + y_offset, color);
+                    }
+                }
+
+                sample_counts[y * self.hsize + x] = statistics.count;
+            }
+        }
+
+        let heatmap = if adaptive.heatmap {
+            Some(create_heatmap(self.hsize, self.vsize, &sample_counts))
+        } else {
+            None
+        };
+
+        AdaptiveRender {
+            image: film.to_canvas(),
+            sample_counts,
+            heatmap
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::PI;
+    use crate::adaptive::AdaptiveSampler;
+    use crate::animation::AnimatedTransform;
+    use crate::film::Filter;
+    use crate::material::Material;
+    use crate::sampling::SamplePattern;
+    use crate::shapes::{Shape, Sphere};
+    use crate::utils::NumberUtils;
+    use crate::world::tests::default_world;
+
+    #[test]
+    fn test_pixel_size() {
+        let horizontal = Camera::new(200, 125, PI / 2.);
+        assert_eq!(NumberUtils::compare_floats(horizontal.get_pixel_size(), 0.01), true);
+
+        let vertical = Camera::new(125, 200, PI / 2.);
+        assert_eq!(NumberUtils::compare_floats(vertical.get_pixel_size(), 0.01), true);
+    }
+
+    #[test]
+    fn test_pixel_footprint_grows_with_distance() {
+        let mut camera = Camera::new(200, 125, PI / 2.);
+        let from = Tuple::new_point(0., 0., -5.);
+        camera.set_transform(Matrix::view_transform(&from, &Tuple::new_point(0., 0., 0.), &Tuple::new_vector(0., 1., 0.)));
+
+        assert_eq!(NumberUtils::compare_floats(camera.get_pixel_footprint(&Tuple::new_point(0., 0., 0.)), 0.05), true);
+        assert_eq!(NumberUtils::compare_floats(camera.get_pixel_footprint(&Tuple::new_point(0., 0., 5.)), 0.1), true);
+
+        camera.projection = Projection::Orthographic { view_width: 4. };
+        assert_eq!(NumberUtils::compare_floats(camera.get_pixel_footprint(&Tuple::new_point(0., 0., 5.)), 0.02), true);
+    }
+
+    #[test]
+    fn test_ray_through_center_and_corner() {
+        let camera = Camera::new(201, 101, PI / 2.);
+
+        let ray = camera.ray_for_pixel(100, 50);
+        assert_eq!(ray.origin, Tuple::new_point(0., 0., 0.));
+        assert_eq!(ray.direction.is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+
+        let ray = camera.ray_for_pixel(0, 0);
+        assert_eq!(ray.direction.is_equal_to(&Tuple::new_vector(0.66519, 0.33259, -0.66851)), true);
+    }
+
+    #[test]
+    fn test_ray_with_transformed_camera() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.set_transform(Matrix::multiply(&Matrix::rotation_y(PI / 4.), &Matrix::translation(0., -2., 5.)));
+
+        let ray = camera.ray_for_pixel(100, 50);
+        let value = 2_f64.sqrt() / 2.;
+        assert_eq!(ray.origin.is_equal_to(&Tuple::new_point(0., 2., -5.)), true);
+        assert_eq!(ray.direction.is_equal_to(&Tuple::new_vector(value, 0., -value)), true);
+    }
+
+    #[test]
+    fn test_render_world() {
+        let world = default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        let from = Tuple::new_point(0., 0., -5.);
+        let to = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+
+        let image = camera.render(&world);
+        assert_eq!(image.get_pixel_at(5, 5).is_equal_to(Color::new(0.38066, 0.47583, 0.2855)), true);
+    }
+
+    #[test]
+    fn test_single_centered_sample_matches_render() {
+        let world = default_world();
+        let mut camera = Camera::new(5, 5, PI / 2.);
+        let from = Tuple::new_point(0., 0., -5.);
+        let to = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+
+        let mut sampler = Sampler::new(1, 0);
+        sampler.pattern = SamplePattern::Regular;
+
+        let image = camera.render(&world);
+        let supersampled = camera.render_supersampled(&world, &sampler);
+        assert_eq!(image.format_ppm_data(), supersampled.format_ppm_data());
+    }
+
+    #[test]
+    fn test_supersampling_smooths_edges() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.set_material(Material::new_emissive(Color::white()));
+        world.add_object(Box::new(sphere));
+
+        let mut camera = Camera::new(9, 9, PI / 3.);
+        let from = Tuple::new_point(0., 0., -4.);
+        let to = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+
+        let patterns = [SamplePattern::Stratified, SamplePattern::Halton, SamplePattern::Sobol];
+        for pattern in patterns.iter() {
+            let mut sampler = Sampler::new(16, 3);
+            sampler.pattern = *pattern;
+            sampler.filter = Filter::gaussian();
+
+            let image = camera.render_supersampled(&world, &sampler);
+            let partially_covered = (0..9).flat_map(|y| (0..9).map(move |x| (x, y)))
+                .map(|(x, y)| image.get_pixel_at(x, y).red)
+                .filter(|value| *value > 0.05 && *value < 0.95)
+                .count();
+
+            assert_eq!(image.get_pixel_at(4, 4).is_equal_to(Color::white()), true);
+            assert_eq!(partially_covered > 0, true);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_render_spends_samples_on_edges() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.set_material(Material::new_emissive(Color::white()));
+        world.add_object(Box::new(sphere));
+
+        let mut camera = Camera::new(9, 9, PI / 3.);
+        let from = Tuple::new_point(0., 0., -4.);
+        let to = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+
+        let mut adaptive = AdaptiveSampler::new(4, 32, 0.01, 1);
+        adaptive.heatmap = true;
+        let render = camera.render_adaptive(&adaptive, |ray, _| world.color_at(ray, MAX_RECURSION_DEPTH));
+
+        let background = render.sample_counts[0];
+        let center = render.sample_counts[4 * 9 + 4];
+        let most = render.sample_counts.iter().copied().max().unwrap();
+
+        assert_eq!(background, 4);
+        assert_eq!(center, 4);
+        assert_eq!(most, 32);
+        assert_eq!(render.get_total_samples() < 9 * 9 * 32, true);
+        assert_eq!(render.image.get_pixel_at(4, 4).is_equal_to(Color::white()), true);
+        assert_eq!(render.heatmap.unwrap().get_pixel_at(0, 0).red < 1., true);
+    }
+
+    #[test]
+    fn test_pinhole_when_aperture_is_closed() {
+        let camera = Camera::new(11, 11, PI / 2.);
+        let ray = camera.sample_ray(3, 4, 0.5, 0.5, &mut Random::new(0));
+
+        assert_eq!(ray, camera.ray_for_pixel(3, 4));
+    }
+
+    #[test]
+    fn test_lens_rays_meet_on_focal_plane() {
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.set_transform(Matrix::translation(0., 0., -3.));
+        camera.aperture_radius = 0.5;
+        camera.focal_distance = 4.;
+        camera.aperture = Aperture::Polygon { blades: 5, rotation: 0.3 };
+
+        let pinhole = camera.ray_for_pixel_offset(2, 7, 0.25, 0.75);
+        let mut random = Random::new(6);
+        let mut origins_differ = false;
+
+        for _ in 0..10 {
+            let ray = camera.sample_ray(2, 7, 0.25, 0.75, &mut random);
+            // the focal plane sits 4 units in front of the lens along -z of the camera
+            let t = (ray.origin.z + 4. - 3.) / -ray.direction.z;
+            let pinhole_t = (pinhole.origin.z + 4. - 3.) / -pinhole.direction.z;
+
+            assert_eq!(ray.position(t).is_equal_to(&pinhole.position(pinhole_t)), true);
+            origins_differ = origins_differ || !ray.origin.is_equal_to(&pinhole.origin);
+        }
+
+        assert_eq!(origins_differ, true);
+    }
+
+    #[test]
+    fn test_depth_of_field_blurs_out_of_focus_objects() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.set_transform(Matrix::scaling(0.5, 0.5, 0.5));
+        sphere.set_material(Material::new_emissive(Color::white()));
+        world.add_object(Box::new(sphere));
+
+        let mut camera = Camera::new(9, 9, PI / 3.);
+        let from = Tuple::new_point(0., 0., -4.);
+        let to = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+
+        let sampler = Sampler::new(16, 5);
+        let soft_pixels = |camera: &Camera| {
+            let image = camera.render_supersampled(&world, &sampler);
+            (0..81).map(|index| image.get_pixel_at(index % 9, index / 9).red)
+                .filter(|value| *value > 0.05 && *value < 0.95)
+                .count()
+        };
+
+        camera.aperture_radius = 0.3;
+        camera.focal_distance = 3.5;
+        let in_focus = soft_pixels(&camera);
+
+        camera.focal_distance = 1.;
+        let out_of_focus = soft_pixels(&camera);
+
+        assert_eq!(out_of_focus > in_focus, true);
+    }
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let mut camera = Camera::new(10, 5, PI / 2.);
+        camera.projection = Projection::Orthographic { view_width: 4. };
+
+        let center = camera.ray_for_pixel_offset(5, 2, 0., 0.5);
+        assert_eq!(center.origin.is_equal_to(&Tuple::new_point(0., 0., 0.)), true);
+        assert_eq!(center.direction, Tuple::new_vector(0., 0., -1.));
+
+        let corner = camera.ray_for_pixel_offset(0, 0, 0., 0.);
+        assert_eq!(corner.origin.is_equal_to(&Tuple::new_point(2., 1., 0.)), true);
+        assert_eq!(corner.direction, Tuple::new_vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn test_orthographic_follows_view_transform() {
+        let mut camera = Camera::new(10, 10, PI / 2.);
+        camera.projection = Projection::Orthographic { view_width: 2. };
+        let from = Tuple::new_point(0., 10., 0.);
+        let to = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 0., 1.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+
+        let ray = camera.ray_for_pixel_offset(5, 5, 0., 0.);
+        assert_eq!(ray.origin.is_equal_to(&Tuple::new_point(0., 10., 0.)), true);
+        assert_eq!(ray.direction.is_equal_to(&Tuple::new_vector(0., -1., 0.)), true);
+    }
+
+    #[test]
+    fn test_fisheye_rays() {
+        let mut camera = Camera::new(20, 10, PI / 2.);
+        camera.projection = Projection::Fisheye { field_of_view: PI };
+
+        let center = camera.ray_for_pixel_offset(10, 5, 0., 0.);
+        assert_eq!(center.direction.is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+
+        // the top edge of the image circle looks straight up
+        let top = camera.ray_for_pixel_offset(10, 0, 0., 0.);
+        assert_eq!(top.direction.is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+    }
+
+    #[test]
+    fn test_equirectangular_rays_cover_the_sphere() {
+        let mut camera = Camera::new(8, 4, PI / 2.);
+        camera.projection = Projection::Equirectangular;
+
+        let forward = camera.ray_for_pixel_offset(4, 2, 0., 0.);
+        assert_eq!(forward.direction.is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+
+        let behind = camera.ray_for_pixel_offset(0, 2, 0., 0.);
+        assert_eq!(behind.direction.is_equal_to(&Tuple::new_vector(0., 0., 1.)), true);
+
+        let up = camera.ray_for_pixel_offset(2, 0, 0., 0.);
+        assert_eq!(up.direction.is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+    }
+
+    #[test]
+    fn test_stereo_pair() {
+        let camera = Camera::new(11, 11, PI / 2.);
+        let (left, right) = camera.stereo_pair(0.064);
+
+        let left_ray = left.ray_for_pixel(5, 5);
+        let right_ray = right.ray_for_pixel(5, 5);
+        assert_eq!(left_ray.origin.is_equal_to(&Tuple::new_point(0.032, 0., 0.)), true);
+        assert_eq!(right_ray.origin.is_equal_to(&Tuple::new_point(-0.032, 0., 0.)), true);
+        assert_eq!(left_ray.direction, right_ray.direction);
+    }
+
+    #[test]
+    fn test_omni_directional_stereo_panorama() {
+        let mut camera = Camera::new(8, 4, PI / 2.);
+        camera.projection = Projection::Equirectangular;
+        let (left, right) = camera.stereo_pair(0.064);
+
+        // looking backwards the eyes swap sides compared to looking forwards
+        let forward = left.ray_for_pixel_offset(4, 2, 0., 0.);
+        let backward = left.ray_for_pixel_offset(0, 2, 0., 0.);
+        assert_eq!(forward.origin.is_equal_to(&Tuple::new_point(0.032, 0., 0.)), true);
+        assert_eq!(backward.origin.is_equal_to(&Tuple::new_point(-0.032, 0., 0.)), true);
+        assert_eq!(right.ray_for_pixel_offset(4, 2, 0., 0.).origin.is_equal_to(&Tuple::new_point(-0.032, 0., 0.)), true);
+    }
+
+    #[test]
+    fn test_rays_spread_over_shutter_interval() {
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.shutter_open = 0.25;
+        camera.shutter_close = 0.75;
+        let mut random = Random::new(4);
+
+        let times: Vec<Float> = (0..20).map(|_| camera.sample_ray(5, 5, 0.5, 0.5, &mut random).time).collect();
+        assert_eq!(times.iter().all(|time| (0.25..0.75).contains(time)), true);
+        assert_eq!(times[0] != times[1], true);
+        assert_eq!(camera.ray_for_pixel(5, 5).time, 0.25);
+    }
+
+    #[test]
+    fn test_motion_blur_smears_moving_object() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.set_material(Material::new_emissive(Color::white()));
+        sphere.set_motion(AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(-1., 0., 0.)),
+            (1., Matrix::translation(1., 0., 0.)),
+        ]));
+        world.add_object(Box::new(sphere));
+
+        let mut camera = Camera::new(9, 9, PI / 2.);
+        let from = Tuple::new_point(0., 0., -5.);
+        let to = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+
+        let sampler = Sampler::new(16, 2);
+        let still = camera.render_supersampled(&world, &sampler);
+        camera.shutter_close = 1.;
+        let blurred = camera.render_supersampled(&world, &sampler);
+
+        // at time 0 the sphere is off to the left, with the shutter open it streaks across
+        let right_edge = 5;
+        assert_eq!(still.get_pixel_at(right_edge, 4), Color::black());
+        assert_eq!(blurred.get_pixel_at(right_edge, 4).red > 0.1, true);
+        assert_eq!(blurred.get_pixel_at(right_edge, 4).red < 0.9, true);
+    }
+}
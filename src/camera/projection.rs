@@ -0,0 +1,103 @@
+use std::f64::consts::PI;
+
+use crate::tuples::{Tuple, Float};
+
+// How the camera spreads its rays over the image. Camera space looks down -z with +y up
+// and +x to the left of the image, the same frame Matrix::view_transform builds
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Projection {
+    // Pinhole or thin lens, uses the field of view of the camera
+    Perspective,
+    // Parallel rays, view_width is how much of the scene fits across the image
+    Orthographic { view_width: Float },
+    // Equidistant fisheye, the angle from the view axis grows linearly with the distance from
+    // the image center. field_of_view spans the circle touching the shorter image side
+    Fisheye { field_of_view: Float },
+    // Full 360 x 180 degree panorama, longitude across and latitude down the image
+    Equirectangular
+}
+
+// Direction for a fisheye, x and y go from -1 to 1 across the image circle
+pub fn fisheye_direction(x: Float, y: Float, field_of_view: Float) -> Tuple {
+    let radius = (x * x + y * y).sqrt();
+    let theta = (radius * field_of_view / 2.).min(PI);
+    let phi = y.atan2(x);
+
+    Tuple::new_vector(theta.sin() * phi.cos(), theta.sin() * phi.sin(), -theta.cos())
+}
+
+// Longitude of an equirectangular image column, u goes from 0 to 1 left to right
+pub fn equirectangular_longitude(u: Float) -> Float {
+    (u - 0.5) * 2. * PI
+}
+
+// Direction for a panorama, u and v go from 0 to 1 across and down the image
+pub fn equirectangular_direction(u: Float, v: Float) -> Tuple {
+    let longitude = equirectangular_longitude(u);
+    let latitude = (0.5 - v) * PI;
+
+    Tuple::new_vector(
+        -longitude.sin() * latitude.cos(),
+        latitude.sin(),
+        -longitude.cos() * latitude.cos()
+    )
+}
+
+// Where a direction lands in a panorama, the inverse of equirectangular_direction
+pub fn equirectangular_coordinates(direction: &Tuple) -> (Float, Float) {
+    let direction = direction.normalize();
+    let longitude = (-direction.x).atan2(-direction.z);
+    let latitude = direction.y.clamp(-1., 1.).asin();
+
+    (longitude / (2. * PI) + 0.5, 0.5 - latitude / PI)
+}
+
+// Unit vector to the viewer's left when facing the given longitude, used to place
+// the eyes of an omni-directional stereo panorama
+pub fn equirectangular_left(u: Float) -> Tuple {
+    let longitude = equirectangular_longitude(u);
+    Tuple::new_vector(longitude.cos(), 0., -longitude.sin())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_fisheye_directions() {
+        assert_eq!(fisheye_direction(0., 0., PI).is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+        // the edge of a 180 degree fisheye looks sideways
+        assert_eq!(fisheye_direction(1., 0., PI).is_equal_to(&Tuple::new_vector(1., 0., 0.)), true);
+        assert_eq!(fisheye_direction(0., -1., PI).is_equal_to(&Tuple::new_vector(0., -1., 0.)), true);
+        // full frame corners keep going but never past straight backwards
+        assert_eq!(fisheye_direction(3., 0., PI).is_equal_to(&Tuple::new_vector(0., 0., 1.)), true);
+    }
+
+    #[test]
+    fn test_equirectangular_directions() {
+        assert_eq!(equirectangular_direction(0.5, 0.5).is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+        assert_eq!(equirectangular_direction(0.75, 0.5).is_equal_to(&Tuple::new_vector(-1., 0., 0.)), true);
+        assert_eq!(equirectangular_direction(0., 0.5).is_equal_to(&Tuple::new_vector(0., 0., 1.)), true);
+        assert_eq!(equirectangular_direction(0.3, 0.).is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+    }
+
+    #[test]
+    fn test_equirectangular_coordinates_invert_directions() {
+        for (u, v) in [(0.5, 0.5), (0.75, 0.5), (0.1, 0.3), (0.9, 0.8)] {
+            let (back_u, back_v) = equirectangular_coordinates(&equirectangular_direction(u, v));
+            assert_eq!((back_u - u).abs() < 1e-9 && (back_v - v).abs() < 1e-9, true);
+        }
+    }
+
+    #[test]
+    fn test_equirectangular_left_is_perpendicular() {
+        for u in [0., 0.2, 0.5, 0.9] {
+            let left = equirectangular_left(u);
+            let forward = equirectangular_direction(u, 0.5);
+
+            assert_eq!(f64::abs(left.calculate_dot_product(&forward)) < 1e-9, true);
+            assert_eq!(left.get_vector_cross_product(&forward).y > 0., true);
+        }
+    }
+}
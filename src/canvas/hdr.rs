@@ -0,0 +1,216 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::canvas::Canvas;
+use crate::color::Color;
+
+// Radiance RGBE (.hdr) images, a shared exponent byte after the three mantissas keeps
+// colors above 1, which is what environment maps need
+impl Canvas {
+    pub fn load_hdr(path: &str) -> Result<Canvas> {
+        Canvas::from_hdr(&fs::read(path)?)
+    }
+
+    pub fn from_hdr(bytes: &[u8]) -> Result<Canvas> {
+        let mut position = 0;
+        let mut next_line = || -> Result<String> {
+            let start = position;
+            let end = bytes[start..].iter().position(|&byte| byte == b'\n')
+                .ok_or_else(|| invalid("unexpected end of header"))?;
+            position = start + end + 1;
+            Ok(String::from_utf8_lossy(&bytes[start..start + end]).trim().to_string())
+        };
+
+        let magic = next_line()?;
+        if magic != "#?RADIANCE" && magic != "#?RGBE" {
+            return Err(invalid("not a Radiance HDR file"))
+        }
+
+        loop {
+            let line = next_line()?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid(&format!("unsupported format {}", format)))
+                }
+            }
+        }
+
+        // Only the standard orientation, rows top to bottom and columns left to right
+        let resolution = next_line()?;
+        let parts: Vec<&str> = resolution.split_whitespace().collect();
+        let (height, width) = match parts.as_slice() {
+            ["-Y", height, "+X", width] => (parse_size(height)?, parse_size(width)?),
+            _ => return Err(invalid(&format!("unsupported resolution line {}", resolution)))
+        };
+
+        let mut canvas = Canvas::new(width, height);
+        let mut data = &bytes[position..];
+
+        for y in 0..height {
+            let (scanline, rest) = read_scanline(data, width)?;
+            data = rest;
+
+            for (x, rgbe) in scanline.iter().enumerate() {
+                canvas.write_pixel_at(x, y, rgbe_to_color(*rgbe));
+            }
+        }
+
+        Ok(canvas)
+    }
+
+    // Flat scanlines without run length encoding, any reader understands them
+    pub fn to_hdr(&self) -> Vec<u8> {
+        let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.get_height(), self.get_width());
+        let mut bytes = header.into_bytes();
+
+        for y in 0..self.get_height() {
+            for x in 0..self.get_width() {
+                bytes.extend_from_slice(&color_to_rgbe(self.get_pixel_at(x, y)));
+            }
+        }
+
+        bytes
+    }
+
+    pub fn save_hdr(&self, path: &str) -> Result<()> {
+        fs::write(path, self.to_hdr())
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn parse_size(value: &str) -> Result<usize> {
+    value.parse().map_err(|_| invalid(&format!("invalid image size {}", value)))
+}
+
+// Either a flat scanline or the newer format that run length encodes every channel apart
+fn read_scanline(data: &[u8], width: usize) -> Result<(Vec<[u8; 4]>, &[u8])> {
+    let is_rle = (8..32768).contains(&width) && data.len() >= 4 && data[0] == 2 && data[1] == 2 && data[2] & 0x80 == 0;
+
+    if !is_rle {
+        let size = width * 4;
+        if data.len() < size {
+            return Err(invalid("unexpected end of pixel data"))
+        }
+
+        let scanline = data[..size].chunks(4).map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]]).collect();
+        return Ok((scanline, &data[size..]))
+    }
+
+    if ((data[2] as usize) << 8 | data[3] as usize) != width {
+        return Err(invalid("scanline width does not match the image"))
+    }
+
+    let mut scanline = vec![[0; 4]; width];
+    let mut position = 4;
+
+    for channel in 0..4 {
+        let mut x = 0;
+
+        while x < width {
+            let count = *data.get(position).ok_or_else(|| invalid("unexpected end of pixel data"))? as usize;
+            position += 1;
+
+            if count > 128 {
+                let run = count - 128;
+                let value = *data.get(position).ok_or_else(|| invalid("unexpected end of pixel data"))?;
+                position += 1;
+
+                if x + run > width {
+                    return Err(invalid("run goes past the end of the scanline"))
+                }
+
+                for pixel in &mut scanline[x..x + run] {
+                    pixel[channel] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || x + count > width || position + count > data.len() {
+                    return Err(invalid("bad literal run in scanline"))
+                }
+
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(&data[position..position + count]) {
+                    pixel[channel] = *value;
+                }
+                x += count;
+                position += count;
+            }
+        }
+    }
+
+    Ok((scanline, &data[position..]))
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::black()
+    }
+
+    let scale = 2_f32.powi(rgbe[3] as i32 - 136);
+    Color::new(rgbe[0] as f32 * scale, rgbe[1] as f32 * scale, rgbe[2] as f32 * scale)
+}
+
+fn color_to_rgbe(color: Color) -> [u8; 4] {
+    let max = color.red.max(color.green).max(color.blue);
+
+    if max < 1e-32 {
+        return [0; 4]
+    }
+
+    // max = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256. / 2_f32.powi(exponent);
+    let channel = |value: f32| (value.max(0.) * scale).min(255.) as u8;
+
+    [channel(color.red), channel(color.green), channel(color.blue), (exponent + 128) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_hdr_round_trip() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel_at(0, 0, Color::new(1., 0.5, 0.25));
+        canvas.write_pixel_at(2, 1, Color::new(40., 3., 0.));
+
+        let loaded = Canvas::from_hdr(&canvas.to_hdr()).unwrap();
+
+        assert_eq!(loaded.get_width(), 3);
+        assert_eq!(loaded.get_pixel_at(0, 0), Color::new(1., 0.5, 0.25));
+        assert_eq!((loaded.get_pixel_at(2, 1).red - 40.).abs() < 0.2, true);
+        assert_eq!(loaded.get_pixel_at(1, 1), Color::black());
+    }
+
+    #[test]
+    fn test_run_length_encoded_scanline() {
+        let mut bytes = b"#?RADIANCE\n# made by hand\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        bytes.extend_from_slice(&[2, 2, 0, 8]);
+        // red: a run of 8, green: 8 literals, blue: two runs of 4, exponent: a run of 8
+        bytes.extend_from_slice(&[136, 128]);
+        bytes.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        bytes.extend_from_slice(&[132, 0, 132, 255]);
+        bytes.extend_from_slice(&[136, 129]);
+
+        let canvas = Canvas::from_hdr(&bytes).unwrap();
+
+        assert_eq!(canvas.get_pixel_at(0, 0), Color::new(1., 0., 0.));
+        assert_eq!(canvas.get_pixel_at(7, 0), Color::new(1., 0.875, 1.9921875));
+    }
+
+    #[test]
+    fn test_invalid_hdr_files() {
+        assert_eq!(Canvas::from_hdr(b"P3\n1 1\n255\n").is_err(), true);
+        assert_eq!(Canvas::from_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n").is_err(), true);
+        assert_eq!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 2 +X 2\n\x80\x80").is_err(), true);
+    }
+}
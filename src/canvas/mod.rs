@@ -1,3 +1,5 @@
+pub mod hdr;
+
 use std::fs::File;
 use std::io::prelude::*;
 
@@ -5,6 +7,7 @@ use crate::color::Color;
 
 type Size = usize;
 
+#[derive(PartialEq, Debug, Clone)]
 pub struct Canvas {
     width: Size,
     height: Size,
@@ -14,20 +17,28 @@ pub struct Canvas {
 impl Canvas {
     pub fn new(width: Size, height: Size) -> Self {
         Canvas {
-            width: width,
-            height: height,
+            width,
+            height,
             pixels: vec![vec![Color::black(); width]; height]
         }
     }
 
     pub fn new_with_color(width: Size, height: Size, color: Color) -> Self {
         Canvas {
-            width: width,
-            height: height,
+            width,
+            height,
             pixels: vec![vec![color; width]; height]
         }
     }
 
+    pub fn get_width(&self) -> Size {
+        self.width
+    }
+
+    pub fn get_height(&self) -> Size {
+        self.height
+    }
+
     pub fn get_pixel_at(&self, x: Size, y: Size) -> Color {
         self.pixels[y][x]
     }
@@ -65,7 +76,7 @@ impl Canvas {
 
                     if temp.len() > 70 {
                         formated_row += &format!("{}\n", prev.trim());
-                        prev = format!("{}", v);
+                        prev = v.to_string();
                     } else {
                         prev = temp;
                     }
@@ -83,13 +94,14 @@ impl Canvas {
 
         let mut file = File::create("image.ppm")?;        
 
-        file.write(header.as_bytes());
-        file.write(data.as_bytes());
+        file.write_all(header.as_bytes())?;
+        file.write_all(data.as_bytes())?;
 
         Ok(())
     }
 }
 
+#[cfg(test)]
 mod tests {
 
     use super::*;
@@ -0,0 +1,210 @@
+// ? INFO
+// Section views cut shapes open to show what is inside. Intersections outside the kept region
+// are thrown away, which leaves a hollow shell. To make cut solids look filled, the ray is
+// checked for being inside a solid where it crosses into or out of the kept region: if the
+// next surface along the ray is one it leaves through, the cut face is hit right there
+
+use crate::bounds::BoundingBox;
+use crate::color::Color;
+use crate::intersection::{Intersection, sort_intersections};
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum ClipRegion {
+    // Keeps the side the normal points away from
+    Plane { point: Tuple, normal: Tuple },
+    // Keeps what is inside the box
+    Box(BoundingBox)
+}
+
+// Region of world space that shapes are cut down to. With a cap color the faces of the cut are
+// filled in that color, which needs closed shapes that do not overlap
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Clip {
+    pub region: ClipRegion,
+    pub cap: Option<Color>
+}
+
+// Filled in face of a cut, the normal is in world space and points out of the cut solid
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Cap {
+    pub normal: Tuple,
+    pub color: Color
+}
+
+impl Clip {
+    pub fn new_plane(point: Tuple, normal: Tuple) -> Self {
+        Clip {
+            region: ClipRegion::Plane { point, normal: normal.normalize() },
+            cap: None
+        }
+    }
+
+    pub fn new_box(bounds: BoundingBox) -> Self {
+        Clip {
+            region: ClipRegion::Box(bounds),
+            cap: None
+        }
+    }
+
+    pub fn is_kept(&self, point: &Tuple) -> bool {
+        match self.region {
+            ClipRegion::Plane { point: origin, normal } => (*point - origin).calculate_dot_product(&normal) <= 0.,
+            ClipRegion::Box(bounds) => bounds.contains_point(point)
+        }
+    }
+
+    // Stretch of the ray inside the kept region, both regions are convex so there is one at most
+    pub fn kept_range(&self, ray: &Ray) -> Option<(Float, Float)> {
+        match self.region {
+            ClipRegion::Plane { point, normal } => {
+                let speed = ray.direction.calculate_dot_product(&normal);
+                let height = (ray.origin - point).calculate_dot_product(&normal);
+
+                if speed == 0. {
+                    return if height <= 0. { Some((Float::NEG_INFINITY, Float::INFINITY)) } else { None }
+                }
+
+                let crossing = -height / speed;
+                if speed > 0. { Some((Float::NEG_INFINITY, crossing)) } else { Some((crossing, Float::INFINITY)) }
+            },
+            ClipRegion::Box(bounds) => bounds.intersection_range(ray)
+        }
+    }
+
+    // Drops the intersections outside the kept region and adds the caps
+    pub fn clip<'a>(&self, ray: &Ray, xs: Vec<Intersection<'a>>) -> Vec<Intersection<'a>> {
+        let (start, end) = match self.kept_range(ray) {
+            Some(range) => range,
+            None => return vec![]
+        };
+
+        let mut kept: Vec<Intersection> = xs.iter().filter(|x| x.t >= start && x.t <= end).copied().collect();
+        let color = match self.cap {
+            Some(color) => color,
+            None => return kept
+        };
+
+        let mut capped = false;
+        for boundary in [start, end].iter().copied().filter(|t| t.is_finite()) {
+            let next = xs.iter().filter(|x| x.t > boundary).min_by(|a, b| a.t.total_cmp(&b.t));
+
+            if let Some(next) = next.filter(|next| leaves_solid(next, ray)) {
+                let normal = self.face_normal(&ray.position(boundary));
+                kept.push(Intersection::new_cap(boundary, next.object, Cap { normal, color }));
+                capped = true;
+            }
+        }
+
+        if capped {
+            sort_intersections(&mut kept);
+        }
+        kept
+    }
+
+    // Outward normal of the region's surface at a point on it
+    fn face_normal(&self, point: &Tuple) -> Tuple {
+        match self.region {
+            ClipRegion::Plane { normal, .. } => normal,
+            ClipRegion::Box(bounds) => {
+                let faces = [
+                    ((point.x - bounds.min.x).abs(), Tuple::new_vector(-1., 0., 0.)),
+                    ((point.x - bounds.max.x).abs(), Tuple::new_vector(1., 0., 0.)),
+                    ((point.y - bounds.min.y).abs(), Tuple::new_vector(0., -1., 0.)),
+                    ((point.y - bounds.max.y).abs(), Tuple::new_vector(0., 1., 0.)),
+                    ((point.z - bounds.min.z).abs(), Tuple::new_vector(0., 0., -1.)),
+                    ((point.z - bounds.max.z).abs(), Tuple::new_vector(0., 0., 1.))
+                ];
+                faces.iter().min_by(|a, b| a.0.total_cmp(&b.0)).unwrap().1
+            }
+        }
+    }
+}
+
+// Applies every clip in turn, so caps of one clip are cut by the others
+pub fn clip_intersections<'a>(clips: &[Clip], ray: &Ray, xs: Vec<Intersection<'a>>) -> Vec<Intersection<'a>> {
+    clips.iter().fold(xs, |xs, clip| clip.clip(ray, xs))
+}
+
+// Whether the ray goes out of the solid through this hit
+fn leaves_solid(hit: &Intersection, ray: &Ray) -> bool {
+    let normal = match hit.cap {
+        Some(cap) => cap.normal,
+        None => hit.object.normal_at_hit(&ray.position(hit.t), hit, ray.time)
+    };
+    normal.calculate_dot_product(&ray.direction) > 0.
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::shapes::{Shape, Sphere};
+
+    fn along_z(z: Float, direction: Float) -> Ray {
+        Ray::new(Tuple::new_point(0., 0., z), Tuple::new_vector(0., 0., direction))
+    }
+
+    #[test]
+    fn test_clipping_plane() {
+        let mut sphere = Sphere::new();
+        sphere.add_clip(Clip::new_plane(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 2.)));
+
+        let xs = sphere.intersect(&along_z(-5., 1.));
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 4.);
+
+        // seen from the removed side the sphere is an open bowl
+        let xs = sphere.intersect(&along_z(5., -1.));
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 6.);
+
+        // parallel rays are kept or lost as a whole
+        let clip = Clip::new_plane(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(clip.kept_range(&Ray::new(Tuple::new_point(0., 0., -1.), Tuple::new_vector(1., 0., 0.))).is_some(), true);
+        assert_eq!(clip.kept_range(&Ray::new(Tuple::new_point(0., 0., 1.), Tuple::new_vector(1., 0., 0.))).is_none(), true);
+    }
+
+    #[test]
+    fn test_capped_plane() {
+        let mut sphere = Sphere::new();
+        let mut clip = Clip::new_plane(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+        clip.cap = Some(Color::red());
+        sphere.add_clip(clip);
+
+        let ray = along_z(5., -1.);
+        let xs = sphere.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 5.);
+        assert_eq!(xs[0].cap, Some(Cap { normal: Tuple::new_vector(0., 0., 1.), color: Color::red() }));
+
+        // the cap is shaded in its own color, facing the removed side
+        let comps = xs[0].prepare_computations(&ray, &xs);
+        assert_eq!(comps.color, Color::red());
+        assert_eq!(comps.normalv, Tuple::new_vector(0., 0., 1.));
+        assert_eq!(comps.inside, false);
+
+        // rays that miss the cut face get no cap
+        let beside = Ray::new(Tuple::new_point(0.9, 0.9, 5.), Tuple::new_vector(0., 0., -1.));
+        assert_eq!(sphere.intersect(&beside).iter().any(|x| x.cap.is_some()), false);
+    }
+
+    #[test]
+    fn test_capped_box() {
+        let mut sphere = Sphere::new();
+        sphere.set_transform(crate::matrix::Matrix::scaling(2., 2., 2.));
+        let mut clip = Clip::new_box(BoundingBox::new_with_bounds(Tuple::new_point(-1., -1., -1.), Tuple::new_point(1., 1., 1.)));
+        clip.cap = Some(Color::blue());
+        sphere.add_clip(clip);
+
+        // inside the sphere the whole way through the box, so both faces are capped
+        let xs = sphere.intersect(&along_z(-5., 1.));
+        assert_eq!(xs.iter().map(|x| x.t).collect::<Vec<Float>>(), vec![4., 6.]);
+        assert_eq!(xs[0].cap.unwrap().normal, Tuple::new_vector(0., 0., -1.));
+        assert_eq!(xs[1].cap.unwrap().normal, Tuple::new_vector(0., 0., 1.));
+
+        assert_eq!(clip.is_kept(&Tuple::new_point(0.5, 0., 0.)) && !clip.is_kept(&Tuple::new_point(1.5, 0., 0.)), true);
+        assert_eq!(sphere.intersect(&Ray::new(Tuple::new_point(1.5, 0., -5.), Tuple::new_vector(0., 0., 1.))).len(), 0);
+    }
+}
@@ -2,6 +2,7 @@
 // Each pixel on your computer monitor is a composite of three colors: red, green, and blue. 
 // If you take those three colors and mired them in different quantities, you get just about every other color you can imagine
 
+use std::fmt;
 use std::ops;
 
 use crate::utils::NumberUtils;
@@ -19,19 +20,14 @@ impl Color {
     
     pub fn new(red: ColorType, green: ColorType, blue: ColorType) -> Self {
         Color {
-            red: red,
-            green: green,
-            blue: blue,
+            red,
+            green,
+            blue,
         }
     }
 
     fn to_ppm_value(value: ColorType) -> ColorType {
-        let value = ColorType::round(255. * value);
-        match value {
-            n if n < 0. => 0.,
-            n if n > 255. => 255.,
-            n => n
-        }
+        ColorType::round(255. * value).clamp(0., 255.)
     }    
 
     pub fn is_equal_to(self, other_color: Color) -> bool {
@@ -40,11 +36,9 @@ impl Color {
         NumberUtils::compare_floats_32(self.blue, other_color.blue)
     }
 
-    pub fn to_string(&self) -> String {
-        let red = Color::to_ppm_value(self.red);
-        let green = Color::to_ppm_value(self.green);
-        let blue = Color::to_ppm_value(self.blue);
-        format!("{} {} {}", red, green, blue)
+    // Perceived brightness, Rec. 709 weights
+    pub fn get_luminance(&self) -> ColorType {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
     }
 
     pub fn black() -> Self {
@@ -89,6 +83,15 @@ impl Color {
     
 }
 
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let red = Color::to_ppm_value(self.red);
+        let green = Color::to_ppm_value(self.green);
+        let blue = Color::to_ppm_value(self.blue);
+        write!(f, "{} {} {}", red, green, blue)
+    }
+}
+
 impl ops::Add<Color> for Color {
     type Output = Color;
 
@@ -152,6 +155,7 @@ impl ops::Mul<Color> for Color {
     }
 }
 
+#[cfg(test)]
 mod tests {
 
     use super::*;
@@ -190,6 +194,13 @@ mod tests {
         assert_eq!(new_color.is_equal_to(Color { red: 0.4, green: 0.6, blue: 0.8 }), true);
     }
 
+    #[test]
+    fn test_luminance() {
+        assert_eq!(Color::white().get_luminance(), 1.);
+        assert_eq!(Color::black().get_luminance(), 0.);
+        assert_eq!(Color::green().get_luminance() > Color::red().get_luminance(), true);
+    }
+
     #[test]
     fn test_hadamard_product() {
         let color_1 = Color::new(1., 0.2, 0.4);
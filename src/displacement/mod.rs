@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::camera::Camera;
+use crate::camera::projection::equirectangular_coordinates;
+use crate::canvas::Canvas;
+use crate::matrix::Matrix;
+use crate::medium::fractal_noise;
+use crate::shapes::{Mesh, NormalWeighting, Shape};
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+// Segments primitives like spheres are cut into before the adaptive refinement
+pub const BASE_SEGMENTS: usize = 16;
+
+// How many times an edge of the base tessellation may be halved
+pub const MAX_EDGE_SPLITS: i32 = 10;
+
+// Scalar field the surface is pushed out by, between 0 and 1
+#[derive(PartialEq, Debug, Clone)]
+pub enum DisplacementMap {
+    // Fractal value noise over the object space point
+    Noise { frequency: Float, octaves: usize, seed: u64 },
+    // Luminance of an image at the texture coordinates, bilinearly filtered and repeating.
+    // Surfaces without texture coordinates use a spherical mapping around the object origin
+    Texture(Canvas)
+}
+
+// Moves the surface along its normal by scale times the map, so unlike shading normal tricks
+// it changes silhouettes and shadows. Shapes are turned into meshes by World::displace
+#[derive(PartialEq, Debug, Clone)]
+pub struct Displacement {
+    pub map: DisplacementMap,
+    pub scale: Float
+}
+
+impl Displacement {
+    pub fn new_noise(frequency: Float, octaves: usize, seed: u64, scale: Float) -> Self {
+        Displacement {
+            map: DisplacementMap::Noise { frequency, octaves, seed },
+            scale
+        }
+    }
+
+    pub fn new_texture(image: Canvas, scale: Float) -> Self {
+        Displacement {
+            map: DisplacementMap::Texture(image),
+            scale
+        }
+    }
+
+    // Offset along the normal at an object space point
+    pub fn height_at(&self, point: &Tuple, texture_coordinates: Option<(Float, Float)>) -> Float {
+        let value = match &self.map {
+            DisplacementMap::Noise { frequency, octaves, seed } => fractal_noise(&(*point * *frequency), *octaves, *seed),
+            DisplacementMap::Texture(image) => {
+                let (u, v) = texture_coordinates.unwrap_or_else(|| {
+                    equirectangular_coordinates(&Tuple::new_vector(point.x, point.y, point.z).normalize())
+                });
+                sample_luminance(image, u, v)
+            }
+        };
+
+        value * self.scale
+    }
+}
+
+// Texture v grows upwards while image rows go down
+fn sample_luminance(image: &Canvas, u: Float, v: Float) -> Float {
+    let (width, height) = (image.get_width(), image.get_height());
+    let x = u.rem_euclid(1.) * width as Float - 0.5;
+    let y = (1. - v).rem_euclid(1.) * height as Float - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |dx: Float, dy: Float| {
+        let column = (x0 + dx).rem_euclid(width as Float) as usize;
+        let row = (y0 + dy).rem_euclid(height as Float) as usize;
+        image.get_pixel_at(column, row).get_luminance() as Float
+    };
+
+    let top = texel(0., 0.) * (1. - fx) + texel(1., 0.) * fx;
+    let bottom = texel(0., 1.) * (1. - fx) + texel(1., 1.) * fx;
+    top * (1. - fy) + bottom * fy
+}
+
+// Splits the triangles of a world space mesh until no edge covers more than edge_length
+// pixels. Whether an edge is split only depends on the edge, so both triangles sharing it
+// agree and displacing the result cannot open cracks
+struct Refinement<'a> {
+    camera: &'a Camera,
+    edge_length: Float,
+    min_length: Float,
+    positions: Vec<Tuple>,
+    normals: Vec<Tuple>,
+    texture_coordinates: Vec<(Float, Float)>,
+    midpoints: HashMap<(usize, usize), usize>,
+    triangles: Vec<[usize; 3]>
+}
+
+impl Refinement<'_> {
+    fn split(&mut self, a: usize, b: usize) -> Option<usize> {
+        let key = (a.min(b), a.max(b));
+        if let Some(&middle) = self.midpoints.get(&key) {
+            return Some(middle)
+        }
+
+        let (pa, pb) = (self.positions[a], self.positions[b]);
+        let length = (pb - pa).get_magnitude();
+        let middle = pa + (pb - pa) * 0.5;
+
+        if length <= self.min_length || length / self.camera.get_pixel_footprint(&middle) <= self.edge_length {
+            return None
+        }
+
+        self.positions.push(middle);
+        self.normals.push((self.normals[a] + self.normals[b]).normalize());
+        if !self.texture_coordinates.is_empty() {
+            let ((u1, v1), (u2, v2)) = (self.texture_coordinates[a], self.texture_coordinates[b]);
+            self.texture_coordinates.push(((u1 + u2) / 2., (v1 + v2) / 2.));
+        }
+
+        self.midpoints.insert(key, self.positions.len() - 1);
+        Some(self.positions.len() - 1)
+    }
+
+    fn refine(&mut self, [a, b, c]: [usize; 3]) {
+        let children = match (self.split(a, b), self.split(b, c), self.split(c, a)) {
+            (None, None, None) => return self.triangles.push([a, b, c]),
+            (Some(ab), None, None) => vec![[a, ab, c], [ab, b, c]],
+            (None, Some(bc), None) => vec![[a, b, bc], [a, bc, c]],
+            (None, None, Some(ca)) => vec![[a, b, ca], [ca, b, c]],
+            (Some(ab), Some(bc), None) => vec![[ab, b, bc], [a, ab, bc], [a, bc, c]],
+            (None, Some(bc), Some(ca)) => vec![[bc, c, ca], [a, b, bc], [a, bc, ca]],
+            (Some(ab), None, Some(ca)) => vec![[a, ab, ca], [ab, b, c], [ab, c, ca]],
+            (Some(ab), Some(bc), Some(ca)) => vec![[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+        };
+
+        for child in children {
+            self.refine(child);
+        }
+    }
+}
+
+// World space mesh of the shape refined to edge_length pixels as seen by the camera and
+// displaced by its material, close ups easily run to many thousands of faces which the
+// mesh finds through its hierarchy. None for shapes without a displacement or that cannot
+// be tessellated. The shape's transform is baked in, motion blur is not kept
+pub fn displace_shape(shape: &dyn Shape, camera: &Camera, edge_length: Float) -> Option<Mesh> {
+    let displacement = shape.get_material().displacement.as_ref()?;
+    let triangles = shape.tessellate(BASE_SEGMENTS);
+    if triangles.is_empty() {
+        return None
+    }
+
+    let mut base = Mesh::from_triangles(&triangles);
+    base.weld(EPSILON);
+    if !base.is_smooth() {
+        base.generate_normals(PI, NormalWeighting::Angle);
+    }
+
+    let longest = base.get_triangles().iter()
+        .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+        .map(|(a, b)| (base.get_positions()[b] - base.get_positions()[a]).get_magnitude())
+        .fold(0., Float::max);
+
+    let mut refinement = Refinement {
+        camera,
+        edge_length,
+        min_length: longest / (2. as Float).powi(MAX_EDGE_SPLITS),
+        positions: base.get_positions().to_vec(),
+        normals: base.get_normals().to_vec(),
+        texture_coordinates: base.get_texture_coordinates().to_vec(),
+        midpoints: HashMap::new(),
+        triangles: vec![]
+    };
+    for &triangle in base.get_triangles() {
+        refinement.refine(triangle);
+    }
+
+    let to_object = shape.get_inverse_transform();
+    let positions = refinement.positions.iter().enumerate().map(|(index, position)| {
+        let texture = refinement.texture_coordinates.get(index).copied();
+        let height = displacement.height_at(&Matrix::multiply_tuple(to_object, position), texture);
+        *position + refinement.normals[index] * height
+    }).collect();
+
+    let mut mesh = Mesh::new(positions, refinement.triangles);
+    if !refinement.texture_coordinates.is_empty() {
+        mesh.set_texture_coordinates(refinement.texture_coordinates);
+    }
+    mesh.generate_normals(PI, NormalWeighting::Angle);
+
+    let mut material = shape.get_material().clone();
+    material.displacement = None;
+    mesh.set_material(material);
+
+    // the mesh stands in for the shape, so it is seen, lit and cut the same way
+    let properties = shape.get_properties();
+    mesh.set_visibility(properties.visibility);
+    mesh.set_light_links(properties.light_links.clone());
+    mesh.get_properties_mut().clips = properties.clips.clone();
+    Some(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::Sphere;
+
+    fn camera(distance: Float) -> Camera {
+        let mut camera = Camera::new(100, 100, PI / 3.);
+        let from = Tuple::new_point(0., 0., -distance);
+        camera.set_transform(Matrix::view_transform(&from, &Tuple::new_point(0., 0., 0.), &Tuple::new_vector(0., 1., 0.)));
+        camera
+    }
+
+    fn displaced_sphere(displacement: Displacement) -> Sphere {
+        let mut sphere = Sphere::new();
+        let mut material = Material::new();
+        material.displacement = Some(displacement);
+        sphere.set_material(material);
+        sphere
+    }
+
+    #[test]
+    fn test_height_from_texture() {
+        let mut image = Canvas::new(2, 1);
+        image.write_pixel_at(1, 0, Color::white());
+        let displacement = Displacement::new_texture(image, 0.5);
+        let point = Tuple::new_point(0., 0., 0.);
+
+        assert_eq!((displacement.height_at(&point, Some((0.75, 0.5))) - 0.5).abs() < 1e-6, true);
+        assert_eq!(displacement.height_at(&point, Some((0.25, 0.5))), 0.);
+        // halfway between the texel centers
+        assert_eq!((displacement.height_at(&point, Some((0.5, 0.5))) - 0.25).abs() < 1e-6, true);
+    }
+
+    #[test]
+    fn test_tessellation_follows_screen_size() {
+        let sphere = displaced_sphere(Displacement::new_noise(1., 1, 7, 0.));
+        let far = displace_shape(&sphere, &camera(50.), 4.).unwrap();
+        let near = displace_shape(&sphere, &camera(5.), 4.).unwrap();
+
+        assert_eq!(far.get_triangle_count() < near.get_triangle_count(), true);
+        assert_eq!(near.get_material().displacement, None);
+
+        // no edge of the close up is longer than the target once projected
+        let camera = camera(5.);
+        for &[a, b, _] in near.get_triangles() {
+            let (pa, pb) = (near.get_positions()[a], near.get_positions()[b]);
+            let middle = pa + (pb - pa) * 0.5;
+            assert_eq!((pb - pa).get_magnitude() / camera.get_pixel_footprint(&middle) <= 4. + 1e-9, true);
+        }
+    }
+
+    #[test]
+    fn test_displacement_changes_the_silhouette() {
+        let mut image = Canvas::new(1, 1);
+        image.write_pixel_at(0, 0, Color::white());
+        let sphere = displaced_sphere(Displacement::new_texture(image, 0.5));
+        let mesh = displace_shape(&sphere, &camera(10.), 8.).unwrap();
+
+        // a constant map grows the sphere evenly and keeps it closed
+        for position in mesh.get_positions() {
+            let radius = (*position - Tuple::new_point(0., 0., 0.)).get_magnitude();
+            assert_eq!(radius > 1.45 && radius < 1.5 + 1e-6, true);
+        }
+
+        let ray = Ray::new(Tuple::new_point(0.1, 0.2, -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = mesh.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t < 3.6, true);
+
+        // grazing the original sphere now hits the displaced one
+        let grazing = Ray::new(Tuple::new_point(1.2, 0.1, -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(sphere.intersect(&grazing).len(), 0);
+        assert_eq!(mesh.intersect(&grazing).len(), 2);
+    }
+
+    #[test]
+    fn test_refined_mesh_hits_match_its_triangles() {
+        let sphere = displaced_sphere(Displacement::new_noise(1., 1, 7, 0.));
+        let mesh = displace_shape(&sphere, &camera(5.), 2.).unwrap();
+        let triangles = mesh.to_triangles();
+        assert_eq!(triangles.len() > 1000, true);
+
+        for (x, y) in [(0., 0.), (0.3, -0.6), (-0.7, 0.2), (0.9, 0.9)] {
+            let ray = Ray::new(Tuple::new_point(x, y, -5.), Tuple::new_vector(0., 0., 1.));
+            let nearest = triangles.iter()
+                .flat_map(|triangle| triangle.intersect(&ray))
+                .map(|hit| hit.t)
+                .fold(Float::INFINITY, Float::min);
+            let xs = mesh.intersect(&ray);
+            assert_eq!(xs.first().map_or(Float::INFINITY, |hit| hit.t), nearest);
+        }
+    }
+
+    #[test]
+    fn test_shapes_without_displacement_are_skipped() {
+        let camera = camera(5.);
+        assert_eq!(displace_shape(&Sphere::new(), &camera, 4.), None);
+
+        let mut plane = crate::shapes::Plane::new();
+        let mut material = Material::new();
+        material.displacement = Some(Displacement::new_noise(1., 1, 1, 1.));
+        plane.set_material(material);
+        assert_eq!(displace_shape(&plane, &camera, 4.), None);
+    }
+}
@@ -0,0 +1,315 @@
+use std::f64::consts::PI;
+
+use crate::camera::projection::{equirectangular_coordinates, equirectangular_direction};
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::sampling::Distribution1D;
+use crate::tuples::{Tuple, Float};
+
+// Light arriving from infinitely far away, seen by rays that miss every object
+#[derive(PartialEq, Debug, Clone)]
+pub enum Environment {
+    Constant(Color),
+    Map(EnvironmentMap),
+    Sky(Sky)
+}
+
+impl Environment {
+    pub fn color_in(&self, direction: &Tuple) -> Color {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Map(map) => map.color_in(direction),
+            Environment::Sky(sky) => sky.color_in(direction)
+        }
+    }
+
+    // Direction towards the environment and its solid angle pdf. Maps are importance
+    // sampled by brightness, the rest uniformly over the sphere
+    pub fn sample(&self, u1: Float, u2: Float) -> (Tuple, Float) {
+        match self {
+            Environment::Map(map) => map.sample(u1, u2),
+            _ => (uniform_sample_sphere(u1, u2), 1. / (4. * PI))
+        }
+    }
+
+    pub fn pdf(&self, direction: &Tuple) -> Float {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            _ => 1. / (4. * PI)
+        }
+    }
+}
+
+pub fn uniform_sample_sphere(u1: Float, u2: Float) -> Tuple {
+    let z = 1. - 2. * u1;
+    let radius = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+
+    Tuple::new_vector(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+// Equirectangular panorama around the scene, laid out like the camera's equirectangular
+// projection. Pixels are looked up without filtering so the sampling pdf matches exactly
+#[derive(PartialEq, Debug, Clone)]
+pub struct EnvironmentMap {
+    image: Canvas,
+    intensity: Float,
+    // One distribution across every row and one over the rows themselves
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Canvas) -> Self {
+        EnvironmentMap::new_with_intensity(image, 1.)
+    }
+
+    pub fn new_with_intensity(image: Canvas, intensity: Float) -> Self {
+        let (width, height) = (image.get_width(), image.get_height());
+        assert!(width > 0 && height > 0, "Environment map needs at least one pixel");
+
+        // Rows near the poles cover less of the sphere
+        let rows: Vec<Distribution1D> = (0..height).map(|y| {
+            let sin_theta = ((y as Float + 0.5) / height as Float * PI).sin();
+            Distribution1D::new((0..width).map(|x| image.get_pixel_at(x, y).get_luminance() as Float * sin_theta).collect())
+        }).collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.get_integral()).collect());
+
+        EnvironmentMap {
+            image,
+            intensity,
+            rows,
+            marginal
+        }
+    }
+
+    pub fn get_image(&self) -> &Canvas {
+        &self.image
+    }
+
+    pub fn color_in(&self, direction: &Tuple) -> Color {
+        let (x, y) = self.pixel_for(direction);
+        self.image.get_pixel_at(x, y) * self.intensity as f32
+    }
+
+    pub fn sample(&self, u1: Float, u2: Float) -> (Tuple, Float) {
+        let (v, row, _) = self.marginal.sample(u1);
+        let (u, _, _) = self.rows[row].sample(u2);
+        let direction = equirectangular_direction(u, v);
+
+        (direction, self.pdf(&direction))
+    }
+
+    pub fn pdf(&self, direction: &Tuple) -> Float {
+        let (x, y) = self.pixel_for(direction);
+        let sin_theta = ((y as Float + 0.5) / self.image.get_height() as Float * PI).sin();
+
+        if sin_theta <= 0. {
+            return 0.
+        }
+
+        // Image space density over the 2 PI by PI solid angle the panorama is wrapped around
+        self.marginal.pdf(y) * self.rows[y].pdf(x) / (2. * PI * PI * sin_theta)
+    }
+
+    fn pixel_for(&self, direction: &Tuple) -> (usize, usize) {
+        let (u, v) = equirectangular_coordinates(direction);
+        let (width, height) = (self.image.get_width(), self.image.get_height());
+
+        (((u * width as Float) as usize).min(width - 1), ((v * height as Float) as usize).min(height - 1))
+    }
+}
+
+// Preetham daylight model, the sky colour depends on the sun position and the haziness
+// of the air. Below the horizon is a flat ground colour
+#[derive(PartialEq, Debug, Clone)]
+pub struct Sky {
+    pub sun_direction: Tuple,
+    // 2 is a very clear sky, 10 a hazy one
+    pub turbidity: Float,
+    // Scale from the model's kilocandela per square metre to scene units
+    pub exposure: Float,
+    // Angular radius of the sun disk in radians, larger than the real sun to keep noise down
+    pub sun_radius: Float,
+    pub sun_color: Color,
+    pub ground: Color
+}
+
+impl Sky {
+    pub fn new(sun_direction: Tuple) -> Self {
+        Sky {
+            sun_direction: sun_direction.normalize(),
+            turbidity: 3.,
+            exposure: 0.05,
+            sun_radius: 0.02,
+            sun_color: Color::new(50., 45., 40.),
+            ground: Color::new(0.1, 0.1, 0.1)
+        }
+    }
+
+    pub fn color_in(&self, direction: &Tuple) -> Color {
+        let direction = direction.normalize();
+
+        if direction.y < 0. {
+            return self.ground
+        }
+
+        let cos_gamma = direction.calculate_dot_product(&self.sun_direction).clamp(-1., 1.);
+        let mut color = self.sky_radiance(&direction, cos_gamma.acos());
+
+        if self.sun_direction.y > 0. && cos_gamma >= self.sun_radius.cos() {
+            color = color + self.sun_color;
+        }
+
+        color
+    }
+
+    // Tabulates the sky into a panorama so the path tracer can importance sample the sun
+    pub fn bake(&self, width: usize, height: usize) -> EnvironmentMap {
+        let mut image = Canvas::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let u = (x as Float + 0.5) / width as Float;
+                let v = (y as Float + 0.5) / height as Float;
+                image.write_pixel_at(x, y, self.color_in(&equirectangular_direction(u, v)));
+            }
+        }
+
+        EnvironmentMap::new(image)
+    }
+
+    fn sky_radiance(&self, direction: &Tuple, gamma: Float) -> Color {
+        let t = self.turbidity;
+        // the sun is treated as resting on the horizon once it sets, the sky fades with exposure
+        let theta_sun = self.sun_direction.y.clamp(0., 1.).acos();
+        let theta = direction.y.clamp(0.001, 1.).acos();
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = zenith_chromaticity(t, theta_sun, [0.00166, -0.00375, 0.00209, 0.], [-0.02903, 0.06377, -0.03202, 0.00394], [0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y = zenith_chromaticity(t, theta_sun, [0.00275, -0.00610, 0.00317, 0.], [-0.04214, 0.08970, -0.04153, 0.00516], [0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let luminance_coefficients = [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+        let x_coefficients = [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+        let y_coefficients = [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+
+        let relative = |coefficients: &[Float; 5]| perez(coefficients, theta, gamma) / perez(coefficients, 0., theta_sun);
+        let luminance = (zenith_luminance * relative(&luminance_coefficients)).max(0.) * self.exposure;
+        let x = zenith_x * relative(&x_coefficients);
+        let y = zenith_y * relative(&y_coefficients);
+
+        xyy_to_rgb(x, y, luminance)
+    }
+}
+
+// Distribution of brightness over the sky relative to the zenith
+fn perez(coefficients: &[Float; 5], theta: Float, gamma: Float) -> Float {
+    let [a, b, c, d, e] = *coefficients;
+    (1. + a * (b / theta.cos().max(0.001)).exp()) * (1. + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+// Cubic in the sun angle for each power of the turbidity
+fn zenith_chromaticity(t: Float, theta_sun: Float, squared: [Float; 4], linear: [Float; 4], constant: [Float; 4]) -> Float {
+    let cubic = |c: [Float; 4]| ((c[0] * theta_sun + c[1]) * theta_sun + c[2]) * theta_sun + c[3];
+    t * t * cubic(squared) + t * cubic(linear) + cubic(constant)
+}
+
+// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: Float, y: Float, luminance: Float) -> Color {
+    if y <= 0. {
+        return Color::black()
+    }
+
+    let big_x = x / y * luminance;
+    let big_z = (1. - x - y) / y * luminance;
+
+    Color::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.) as f32,
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.) as f32,
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.) as f32
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::random::Random;
+
+    fn bright_spot_map() -> EnvironmentMap {
+        let mut image = Canvas::new_with_color(16, 8, Color::new(0.1, 0.1, 0.1));
+        image.write_pixel_at(4, 2, Color::new(100., 100., 100.));
+        EnvironmentMap::new(image)
+    }
+
+    #[test]
+    fn test_constant_environment() {
+        let environment = Environment::Constant(Color::new(0.2, 0.3, 0.4));
+
+        assert_eq!(environment.color_in(&Tuple::new_vector(0., 1., 0.)), Color::new(0.2, 0.3, 0.4));
+        assert_eq!(environment.pdf(&Tuple::new_vector(1., 0., 0.)), 1. / (4. * PI));
+    }
+
+    #[test]
+    fn test_map_lookup_follows_panorama_layout() {
+        let mut image = Canvas::new(4, 2);
+        image.write_pixel_at(2, 0, Color::red());
+        let map = EnvironmentMap::new_with_intensity(image, 2.);
+
+        // column 2 of 4 on the top row is straight ahead and up
+        let direction = equirectangular_direction(0.6, 0.25);
+        assert_eq!(map.color_in(&direction), Color::new(2., 0., 0.));
+        assert_eq!(map.color_in(&Tuple::new_vector(0., -1., 0.)), Color::black());
+    }
+
+    #[test]
+    fn test_map_sampling_prefers_bright_pixels() {
+        let map = bright_spot_map();
+        let mut random = Random::new(3);
+        let spot = (0..1000).filter(|_| {
+            let (direction, _) = map.sample(random.next_float(), random.next_float());
+            map.color_in(&direction).red > 1.
+        }).count();
+
+        assert_eq!(spot > 900, true);
+    }
+
+    #[test]
+    fn test_map_pdf_integrates_to_one() {
+        let map = bright_spot_map();
+        let mut random = Random::new(5);
+        let count = 20000;
+
+        // uniform sphere estimate of the integral of the pdf
+        let sum: Float = (0..count).map(|_| map.pdf(&uniform_sample_sphere(random.next_float(), random.next_float()))).sum();
+        let integral = sum / count as Float * 4. * PI;
+        assert_eq!((integral - 1.).abs() < 0.1, true);
+
+        let (direction, pdf) = map.sample(0.3, 0.6);
+        assert_eq!(pdf, map.pdf(&direction));
+    }
+
+    #[test]
+    fn test_sky_is_blue_and_bright_towards_the_sun() {
+        let sky = Sky::new(Tuple::new_vector(0., 0.5, -1.));
+        let zenith = sky.color_in(&Tuple::new_vector(0., 1., 0.));
+        let near_sun = sky.color_in(&Tuple::new_vector(0., 0.6, -1.));
+        let away = sky.color_in(&Tuple::new_vector(0., 0.6, 1.));
+
+        assert_eq!(zenith.blue > zenith.red, true);
+        assert_eq!(near_sun.get_luminance() > away.get_luminance(), true);
+        assert_eq!(sky.color_in(&sky.sun_direction).red > 50., true);
+        assert_eq!(sky.color_in(&Tuple::new_vector(0., -1., 0.)), sky.ground);
+    }
+
+    #[test]
+    fn test_baked_sky_samples_the_sun() {
+        let mut sky = Sky::new(Tuple::new_vector(1., 1., 0.));
+        sky.sun_radius = 0.1;
+        let map = sky.bake(64, 32);
+
+        let (direction, _) = map.sample(0.5, 0.5);
+        assert_eq!(direction.calculate_dot_product(&sky.sun_direction) > 0.9, true);
+    }
+}
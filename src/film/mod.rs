@@ -0,0 +1,216 @@
+// ? INFO
+// A film collects samples at arbitrary positions on the image plane and reconstructs
+// pixels from them. Each sample is spread over every pixel within the filter radius,
+// weighted by the filter, and every pixel ends up as its weighted average.
+
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::tuples::Float;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Filter {
+    // Every sample only counts for the pixel it falls in
+    Box,
+    Tent { radius: Float },
+    Gaussian { radius: Float, alpha: Float },
+    // Cubic with a slightly negative lobe, sharper than a Gaussian. Radius is 2 pixels
+    MitchellNetravali { b: Float, c: Float }
+}
+
+impl Filter {
+    pub fn tent() -> Self {
+        Filter::Tent { radius: 1. }
+    }
+
+    pub fn gaussian() -> Self {
+        Filter::Gaussian { radius: 1.5, alpha: 2. }
+    }
+
+    pub fn mitchell_netravali() -> Self {
+        Filter::MitchellNetravali { b: 1. / 3., c: 1. / 3. }
+    }
+
+    pub fn get_radius(&self) -> Float {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent { radius } => *radius,
+            Filter::Gaussian { radius, .. } => *radius,
+            Filter::MitchellNetravali { .. } => 2.
+        }
+    }
+
+    // Weight of a sample dx, dy pixels away from the pixel center
+    pub fn evaluate(&self, dx: Float, dy: Float) -> Float {
+        match self {
+            // half open so a sample on a pixel border is not counted twice
+            Filter::Box => {
+                let inside = |d: Float| d > -0.5 && d <= 0.5;
+                if inside(dx) && inside(dy) { 1. } else { 0. }
+            },
+            Filter::Tent { radius } => {
+                (radius - dx.abs()).max(0.) * (radius - dy.abs()).max(0.)
+            },
+            Filter::Gaussian { radius, alpha } => {
+                let gaussian = |d: Float| ((-alpha * d * d).exp() - (-alpha * radius * radius).exp()).max(0.);
+                gaussian(dx) * gaussian(dy)
+            },
+            Filter::MitchellNetravali { b, c } => {
+                Filter::mitchell_1d(dx, *b, *c) * Filter::mitchell_1d(dy, *b, *c)
+            }
+        }
+    }
+
+    fn mitchell_1d(x: Float, b: Float, c: Float) -> Float {
+        let x = x.abs();
+
+        let value = if x < 1. {
+            (12. - 9. * b - 6. * c) * x.powi(3) + (-18. + 12. * b + 6. * c) * x.powi(2) + (6. - 2. * b)
+        } else if x < 2. {
+            (-b - 6. * c) * x.powi(3) + (6. * b + 30. * c) * x.powi(2) + (-12. * b - 48. * c) * x + (8. * b + 24. * c)
+        } else {
+            0.
+        };
+
+        value / 6.
+    }
+}
+
+pub struct Film {
+    width: usize,
+    height: usize,
+    filter: Filter,
+    sums: Vec<Color>,
+    weights: Vec<Float>
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter) -> Self {
+        Film {
+            width,
+            height,
+            filter,
+            sums: vec![Color::black(); width * height],
+            weights: vec![0.; width * height]
+        }
+    }
+
+    // x and y are raster positions, pixel (i, j) covers [i, i + 1) x [j, j + 1)
+    pub fn add_sample(&mut self, x: Float, y: Float, color: Color) {
+        let radius = self.filter.get_radius();
+        let first_x = (x - 0.5 - radius).ceil().max(0.) as usize;
+        let first_y = (y - 0.5 - radius).ceil().max(0.) as usize;
+        let last_x = ((x - 0.5 + radius).floor().max(-1.) + 1.) as usize;
+        let last_y = ((y - 0.5 + radius).floor().max(-1.) + 1.) as usize;
+
+        for py in first_y..last_y.min(self.height) {
+            for px in first_x..last_x.min(self.width) {
+                let weight = self.filter.evaluate(px as Float + 0.5 - x, py as Float + 0.5 - y);
+
+                if weight != 0. {
+                    let index = py * self.width + px;
+                    self.sums[index] = self.sums[index] + color * weight as f32;
+                    self.weights[index] += weight;
+                }
+            }
+        }
+    }
+
+    pub fn get_pixel_at(&self, x: usize, y: usize) -> Color {
+        let index = y * self.width + x;
+
+        // filters with negative lobes can cancel out completely
+        if self.weights[index] <= 0. {
+            return Color::black()
+        }
+
+        self.sums[index] / self.weights[index] as f32
+    }
+
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                canvas.write_pixel_at(x, y, self.get_pixel_at(x, y));
+            }
+        }
+
+        canvas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn all_filters() -> Vec<Filter> {
+        vec![Filter::Box, Filter::tent(), Filter::gaussian(), Filter::mitchell_netravali()]
+    }
+
+    #[test]
+    fn test_filters_peak_at_center_and_vanish_at_radius() {
+        for filter in all_filters() {
+            let radius = filter.get_radius();
+
+            assert_eq!(filter.evaluate(0., 0.) > 0., true);
+            assert_eq!(filter.evaluate(0., 0.) >= filter.evaluate(0.25, 0.25), true);
+            assert_eq!(filter.evaluate(radius + 0.01, 0.), 0.);
+        }
+    }
+
+    #[test]
+    fn test_box_filter_is_half_open() {
+        assert_eq!(Filter::Box.evaluate(0.5, 0.), 1.);
+        assert_eq!(Filter::Box.evaluate(-0.5, 0.), 0.);
+    }
+
+    #[test]
+    fn test_mitchell_netravali_has_negative_lobe() {
+        let filter = Filter::mitchell_netravali();
+
+        assert_eq!(filter.evaluate(1.5, 0.) < 0., true);
+        assert_eq!(f64::abs(filter.evaluate(0., 0.) - (8. / 9.) * (8. / 9.)) < 0.0001, true);
+    }
+
+    #[test]
+    fn test_box_film_averages_samples_in_pixel() {
+        let mut film = Film::new(2, 1, Filter::Box);
+        film.add_sample(0.25, 0.5, Color::new(1., 0., 0.));
+        film.add_sample(0.75, 0.5, Color::new(0., 0., 1.));
+
+        assert_eq!(film.get_pixel_at(0, 0), Color::new(0.5, 0., 0.5));
+        assert_eq!(film.get_pixel_at(1, 0), Color::black());
+    }
+
+    #[test]
+    fn test_constant_image_stays_constant_with_any_filter() {
+        let color = Color::new(0.2, 0.4, 0.6);
+
+        for filter in all_filters() {
+            let mut film = Film::new(3, 3, filter);
+            for y in 0..3 {
+                for x in 0..3 {
+                    film.add_sample(x as Float + 0.3, y as Float + 0.6, color);
+                    film.add_sample(x as Float + 0.8, y as Float + 0.1, color);
+                }
+            }
+
+            let canvas = film.to_canvas();
+            assert_eq!(canvas.get_pixel_at(0, 0).is_equal_to(color), true);
+            assert_eq!(canvas.get_pixel_at(1, 1).is_equal_to(color), true);
+            assert_eq!(canvas.get_pixel_at(2, 1).is_equal_to(color), true);
+        }
+    }
+
+    #[test]
+    fn test_wide_filter_spreads_into_neighbours() {
+        let mut film = Film::new(3, 1, Filter::tent());
+        film.add_sample(1.3, 0.5, Color::white());
+        film.add_sample(0.7, 0.5, Color::black());
+
+        assert_eq!(film.get_pixel_at(1, 0).is_equal_to(Color::new(0.8, 0.8, 0.8)), true);
+        assert_eq!(film.get_pixel_at(0, 0).is_equal_to(Color::new(0.2, 0.2, 0.2)), true);
+        assert_eq!(film.get_pixel_at(2, 0), Color::black());
+    }
+}
@@ -0,0 +1,234 @@
+// ? INFO
+// Fractals are traced through distance estimators: a lower bound on the distance to the set
+// worked out from how fast a point's orbit escapes. Points whose orbit stays bounded for all
+// iterations count as inside, so more iterations give finer detail
+
+use crate::bounds::BoundingBox;
+use crate::color::Color;
+use crate::quaternion::Quaternion;
+use crate::sdf::box_distance;
+use crate::tuples::{Tuple, Float};
+
+// Orbits getting further from the origin than this are taken to escape
+pub const ESCAPE_RADIUS: Float = 4.;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Fractal {
+    // Power 8 gives the classic bulb, powers below 2 are not supported
+    Mandelbulb { power: Float, iterations: usize },
+    // Slice through the 4D Julia set of q² + c where the fourth component is zero
+    Julia { c: Quaternion, iterations: usize },
+    // Cube of half size 1 with the middle of every face punched through, repeated at a third the size
+    Menger { iterations: usize }
+}
+
+impl Fractal {
+    pub fn new_mandelbulb(power: Float, iterations: usize) -> Self {
+        Fractal::Mandelbulb { power, iterations }
+    }
+
+    pub fn new_julia(c: Quaternion, iterations: usize) -> Self {
+        Fractal::Julia { c, iterations }
+    }
+
+    pub fn new_menger(iterations: usize) -> Self {
+        Fractal::Menger { iterations }
+    }
+
+    pub fn distance(&self, point: &Tuple) -> Float {
+        self.estimate(point).0
+    }
+
+    // Iteration count the orbit escaped at, plus the closest it came to the origin, scaled
+    // to 0..1. For the sponge it is the level the surface was carved at
+    pub fn orbit_trap(&self, point: &Tuple) -> Float {
+        self.estimate(point).1
+    }
+
+    // Sphere every orbit starting outside of escapes from
+    pub fn get_bounds(&self) -> BoundingBox {
+        let radius = match self {
+            Fractal::Mandelbulb { .. } => 2.,
+            // |q|² - |c| > |q| past this radius, so the orbit grows without bound
+            Fractal::Julia { c, .. } => (1. + (1. + 4. * c.get_magnitude()).sqrt()) / 2.,
+            Fractal::Menger { .. } => 1.
+        };
+
+        BoundingBox::new_with_bounds(Tuple::new_point(-radius, -radius, -radius), Tuple::new_point(radius, radius, radius))
+    }
+
+    fn estimate(&self, point: &Tuple) -> (Float, Float) {
+        match self {
+            Fractal::Mandelbulb { power, iterations } => mandelbulb(point, *power, *iterations),
+            Fractal::Julia { c, iterations } => julia(point, c, *iterations),
+            Fractal::Menger { iterations } => menger(point, *iterations)
+        }
+    }
+}
+
+// Triplex power in spherical coordinates, the derivative only tracks its length
+fn mandelbulb(point: &Tuple, power: Float, iterations: usize) -> (Float, Float) {
+    let (mut x, mut y, mut z) = (point.x, point.y, point.z);
+    let mut r = (x * x + y * y + z * z).sqrt();
+    let (mut derivative, mut trap, mut count) = (1., r, 0);
+
+    while count < iterations && r <= ESCAPE_RADIUS {
+        let theta = (z / r.max(Float::MIN_POSITIVE)).clamp(-1., 1.).acos() * power;
+        let phi = y.atan2(x) * power;
+        let scaled = r.powf(power);
+
+        derivative = power * r.powf(power - 1.) * derivative + 1.;
+        x = scaled * theta.sin() * phi.cos() + point.x;
+        y = scaled * theta.sin() * phi.sin() + point.y;
+        z = scaled * theta.cos() + point.z;
+        r = (x * x + y * y + z * z).sqrt();
+        trap = trap.min(r);
+        count += 1;
+    }
+
+    (escape_distance(r, derivative), orbit_value(count, trap, iterations))
+}
+
+fn julia(point: &Tuple, c: &Quaternion, iterations: usize) -> (Float, Float) {
+    let mut q = Quaternion::new(point.x, point.y, point.z, 0.);
+    let mut r = q.get_magnitude();
+    let (mut derivative, mut trap, mut count) = (1., r, 0);
+
+    while count < iterations && r <= ESCAPE_RADIUS {
+        derivative *= 2. * r;
+        q = q * q + *c;
+        r = q.get_magnitude();
+        trap = trap.min(r);
+        count += 1;
+    }
+
+    (escape_distance(r, derivative), orbit_value(count, trap, iterations))
+}
+
+// Exact distance, each level cuts three orthogonal square beams out of every remaining cube
+fn menger(point: &Tuple, iterations: usize) -> (Float, Float) {
+    let mut distance = box_distance(point, &Tuple::new_vector(1., 1., 1.));
+    let (mut scale, mut carved_at) = (1., None);
+
+    for level in 0..iterations {
+        let fold = move |value: Float| 1. - 3. * ((value * scale).rem_euclid(2.) - 1.).abs();
+        scale *= 3.;
+        let (x, y, z) = (fold(point.x).abs(), fold(point.y).abs(), fold(point.z).abs());
+        let beams = (x.max(y).min(y.max(z)).min(z.max(x)) - 1.) / scale;
+
+        if beams > distance {
+            distance = beams;
+            carved_at = Some(level);
+        }
+    }
+
+    let trap = carved_at.map_or(0., |level| (level + 1) as Float / iterations as Float);
+    (distance, trap)
+}
+
+// Hubbard-Douady estimate, only meaningful once the orbit escaped. Bounded orbits are
+// inside, they get the same size with the sign flipped
+fn escape_distance(r: Float, derivative: Float) -> Float {
+    let r = r.max(Float::MIN_POSITIVE);
+    let estimate = 0.5 * r.ln() * r / derivative;
+
+    if r > ESCAPE_RADIUS {
+        estimate
+    } else {
+        -estimate.abs()
+    }
+}
+
+fn orbit_value(count: usize, trap: Float, iterations: usize) -> Float {
+    (count as Float + trap.min(1.)) / (iterations as Float + 1.)
+}
+
+// Colors spread evenly over 0..1 and blended in between, used to turn orbit traps into colors
+#[derive(PartialEq, Debug, Clone)]
+pub struct Palette {
+    colors: Vec<Color>
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Color>) -> Self {
+        Palette {
+            colors
+        }
+    }
+
+    pub fn get_colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn color_at(&self, value: Float) -> Color {
+        match self.colors.len() {
+            0 => Color::black(),
+            1 => self.colors[0],
+            count => {
+                let position = value.clamp(0., 1.) * (count - 1) as Float;
+                let index = (position.floor() as usize).min(count - 2);
+                let fraction = (position - index as Float) as f32;
+                self.colors[index] * (1. - fraction) + self.colors[index + 1] * fraction
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_mandelbulb_distance_estimate() {
+        let bulb = Fractal::new_mandelbulb(8., 10);
+
+        assert_eq!(bulb.distance(&Tuple::new_point(0., 0., 0.)) <= 0., true);
+        // a lower bound, the origin is inside and only 3 away
+        let outside = bulb.distance(&Tuple::new_point(3., 0., 0.));
+        assert_eq!(outside > 0. && outside <= 3., true);
+        assert_eq!(bulb.distance(&Tuple::new_point(5., 0., 0.)) > outside, true);
+
+        let trap = bulb.orbit_trap(&Tuple::new_point(0.5, 0.5, 0.5));
+        assert_eq!((0. ..=1.).contains(&trap), true);
+    }
+
+    #[test]
+    fn test_julia_with_zero_constant_is_the_unit_ball() {
+        let julia = Fractal::new_julia(Quaternion::new(0., 0., 0., 0.), 16);
+
+        assert_eq!(julia.distance(&Tuple::new_point(0.5, 0.3, 0.)) < 0., true);
+        // 0.5 r ln r, a lower bound on the true distance of 1
+        let estimate = julia.distance(&Tuple::new_point(0., 2., 0.));
+        assert_eq!((estimate - 2_f64.ln()).abs() < 1e-9, true);
+
+        // orbits inside never escape, so they reach the top of the range
+        assert_eq!(julia.orbit_trap(&Tuple::new_point(0., 0., 0.)) > julia.orbit_trap(&Tuple::new_point(0., 2., 0.)), true);
+        assert_eq!(Fractal::new_julia(Quaternion::new(2., 0., 0., 0.), 8).get_bounds().max.x, 2.);
+    }
+
+    #[test]
+    fn test_menger_sponge() {
+        let sponge = Fractal::new_menger(3);
+
+        // the center of the cube and the middle of each face are carved away
+        assert_eq!(sponge.distance(&Tuple::new_point(0., 0., 0.)) > 0., true);
+        assert_eq!(sponge.distance(&Tuple::new_point(0., 0., 0.9)) > 0., true);
+        assert_eq!(sponge.distance(&Tuple::new_point(0.95, 0.95, 0.95)) < 0., true);
+        assert_eq!(sponge.distance(&Tuple::new_point(3., 0.9, 0.9)), 2.);
+
+        assert_eq!(sponge.orbit_trap(&Tuple::new_point(0., 0., 0.)), 1. / 3.);
+        assert_eq!(sponge.orbit_trap(&Tuple::new_point(3., 0.9, 0.9)), 0.);
+    }
+
+    #[test]
+    fn test_palette() {
+        let palette = Palette::new(vec![Color::black(), Color::red(), Color::white()]);
+
+        assert_eq!(palette.color_at(0.), Color::black());
+        assert_eq!(palette.color_at(0.25), Color::new(0.5, 0., 0.));
+        assert_eq!(palette.color_at(1.), Color::white());
+        assert_eq!(palette.color_at(7.), Color::white());
+        assert_eq!(Palette::new(vec![]).color_at(0.5), Color::black());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::clipping::Cap;
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::shapes::Shape;
+use crate::tuples::{Tuple, Float};
+use crate::light::reflect;
+use crate::utils::EPSILON;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Intersection<'a> {
+    pub t: Float,
+    pub object: &'a dyn Shape,
+    // Where on the surface the hit is, for shapes that interpolate over it like triangles
+    pub u: Float,
+    pub v: Float,
+    // Which triangle of a mesh was hit, zero for every other shape
+    pub face: usize,
+    // Object space normal worked out while intersecting, for surfaces that turn to face
+    // the ray like curve ribbons
+    pub normal: Option<Tuple>,
+    // Set on the filled in face of a clipped solid, which replaces the shape's own surface
+    pub cap: Option<Cap>
+}
+
+impl<'a> Intersection<'a> {
+    pub fn new(t: Float, object: &'a dyn Shape) -> Self {
+        Intersection::new_with_uv(t, object, 0., 0.)
+    }
+
+    pub fn new_with_uv(t: Float, object: &'a dyn Shape, u: Float, v: Float) -> Self {
+        Intersection::new_on_face(t, object, 0, u, v)
+    }
+
+    pub fn new_on_face(t: Float, object: &'a dyn Shape, face: usize, u: Float, v: Float) -> Self {
+        Intersection {
+            t,
+            object,
+            u,
+            v,
+            face,
+            normal: None,
+            cap: None
+        }
+    }
+
+    pub fn new_with_normal(t: Float, object: &'a dyn Shape, u: Float, v: Float, normal: Tuple) -> Self {
+        Intersection {
+            normal: Some(normal),
+            ..Intersection::new_with_uv(t, object, u, v)
+        }
+    }
+
+    pub fn new_cap(t: Float, object: &'a dyn Shape, cap: Cap) -> Self {
+        Intersection {
+            cap: Some(cap),
+            ..Intersection::new(t, object)
+        }
+    }
+
+    pub fn is_same_object(&self, object: &dyn Shape) -> bool {
+        std::ptr::addr_eq(self.object, object)
+    }
+
+    // Everything shading needs to know about a hit, `xs` are all intersections
+    // along the ray sorted by t and are used to find the refractive indices
+    pub fn prepare_computations(&self, ray: &Ray, xs: &[Intersection<'a>]) -> Computations<'a> {
+        let point = ray.position(self.t);
+        let eyev = ray.direction.negate();
+        let (mut normalv, tangent, color) = match self.cap {
+            Some(cap) => (cap.normal, None, cap.color),
+            None => (
+                self.object.normal_at_hit(&point, self, ray.time),
+                self.object.tangent_at_hit(&point, self, ray.time),
+                self.object.color_at_hit(&point, self, ray.time)
+            )
+        };
+        let inside = normalv.calculate_dot_product(&eyev) < 0.;
+
+        if inside {
+            normalv = normalv.negate();
+        }
+
+        let (n1, n2) = self.find_refractive_indices(xs);
+
+        Computations {
+            t: self.t,
+            object: self.object,
+            point,
+            eyev,
+            normalv,
+            color,
+            tangent,
+            inside,
+            over_point: point + normalv * EPSILON,
+            under_point: point - normalv * EPSILON,
+            reflectv: reflect(&ray.direction, &normalv),
+            n1,
+            n2,
+            time: ray.time
+        }
+    }
+
+    fn find_refractive_indices(&self, xs: &[Intersection<'a>]) -> (Float, Float) {
+        let mut containers: Vec<&dyn Shape> = vec![];
+        let mut n1 = 1.;
+        let mut n2 = 1.;
+
+        for intersection in xs {
+            let is_hit = intersection.t == self.t && intersection.is_same_object(self.object);
+
+            if is_hit {
+                n1 = containers.last().map_or(1., |object| object.get_material().refractive_index);
+            }
+
+            match containers.iter().position(|object| intersection.is_same_object(*object)) {
+                Some(index) => { containers.remove(index); },
+                None => containers.push(intersection.object)
+            }
+
+            if is_hit {
+                n2 = containers.last().map_or(1., |object| object.get_material().refractive_index);
+                break;
+            }
+        }
+
+        (n1, n2)
+    }
+}
+
+impl PartialEq for Intersection<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.t == other.t && self.is_same_object(other.object)
+    }
+}
+
+pub fn sort_intersections(xs: &mut [Intersection]) {
+    xs.sort_by(|a, b| a.t.total_cmp(&b.t));
+}
+
+// Lowest non-negative intersection, xs do not need to be sorted
+pub fn hit<'a, 'b>(xs: &'b [Intersection<'a>]) -> Option<&'b Intersection<'a>> {
+    xs.iter()
+        .filter(|intersection| intersection.t >= 0.)
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+#[derive(Debug, Clone)]
+pub struct Computations<'a> {
+    pub t: Float,
+    pub object: &'a dyn Shape,
+    pub point: Tuple,
+    pub eyev: Tuple,
+    pub normalv: Tuple,
+    // Surface color at the point, the material's unless the shape colors itself
+    pub color: Color,
+    // Direction along the surface for fibers like curves, which are shaded by it
+    pub tangent: Option<Tuple>,
+    pub inside: bool,
+    pub over_point: Tuple,
+    pub under_point: Tuple,
+    pub reflectv: Tuple,
+    pub n1: Float,
+    pub n2: Float,
+    // Time of the ray that found the hit, rays continuing from here keep it
+    pub time: Float
+}
+
+impl Computations<'_> {
+    // Schlick approximation of the Fresnel reflectance
+    pub fn schlick(&self) -> Float {
+        let mut cos = self.eyev.calculate_dot_product(&self.normalv);
+
+        if self.n1 > self.n2 {
+            let n = self.n1 / self.n2;
+            let sin2_t = n.powi(2) * (1. - cos.powi(2));
+            if sin2_t > 1. {
+                return 1.
+            }
+            cos = (1. - sin2_t).sqrt();
+        }
+
+        let r0 = ((self.n1 - self.n2) / (self.n1 + self.n2)).powi(2);
+        r0 + (1. - r0) * (1. - cos).powi(5)
+    }
+
+    // Direction of the refracted ray, None on total internal reflection
+    pub fn refract_direction(&self) -> Option<Tuple> {
+        let n_ratio = self.n1 / self.n2;
+        let cos_i = self.eyev.calculate_dot_product(&self.normalv);
+        let sin2_t = n_ratio.powi(2) * (1. - cos_i.powi(2));
+
+        if sin2_t > 1. {
+            return None
+        }
+
+        let cos_t = (1. - sin2_t).sqrt();
+        Some(self.normalv * (n_ratio * cos_i - cos_t) - self.eyev * n_ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::shapes::{Sphere, Plane};
+    use crate::utils::NumberUtils;
+
+    fn glass_sphere() -> Sphere {
+        let mut sphere = Sphere::new();
+        let mut material = sphere.get_material().clone();
+        material.transparency = 1.;
+        material.refractive_index = 1.5;
+        sphere.set_material(material);
+        sphere
+    }
+
+    #[test]
+    fn test_new_intersection() {
+        let sphere = Sphere::new();
+        let intersection = Intersection::new(3.5, &sphere);
+
+        assert_eq!(intersection.t, 3.5);
+        assert_eq!(intersection.is_same_object(&sphere), true);
+    }
+
+    #[test]
+    fn test_hit() {
+        let sphere = Sphere::new();
+
+        let xs = vec![Intersection::new(1., &sphere), Intersection::new(2., &sphere)];
+        assert_eq!(hit(&xs).unwrap().t, 1.);
+
+        let xs = vec![Intersection::new(-1., &sphere), Intersection::new(1., &sphere)];
+        assert_eq!(hit(&xs).unwrap().t, 1.);
+
+        let xs = vec![Intersection::new(-2., &sphere), Intersection::new(-1., &sphere)];
+        assert_eq!(hit(&xs), None);
+
+        let xs = vec![
+            Intersection::new(5., &sphere),
+            Intersection::new(7., &sphere),
+            Intersection::new(-3., &sphere),
+            Intersection::new(2., &sphere),
+        ];
+        assert_eq!(hit(&xs).unwrap().t, 2.);
+    }
+
+    #[test]
+    fn test_prepare_computations_outside_and_inside() {
+        let sphere = Sphere::new();
+
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let intersection = Intersection::new(4., &sphere);
+        let comps = intersection.prepare_computations(&ray, &[intersection]);
+        assert_eq!(comps.point, Tuple::new_point(0., 0., -1.));
+        assert_eq!(comps.eyev, Tuple::new_vector(0., 0., -1.));
+        assert_eq!(comps.normalv, Tuple::new_vector(0., 0., -1.));
+        assert_eq!(comps.inside, false);
+
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+        let intersection = Intersection::new(1., &sphere);
+        let comps = intersection.prepare_computations(&ray, &[intersection]);
+        assert_eq!(comps.point, Tuple::new_point(0., 0., 1.));
+        assert_eq!(comps.normalv, Tuple::new_vector(0., 0., -1.));
+        assert_eq!(comps.inside, true);
+    }
+
+    #[test]
+    fn test_over_and_under_point() {
+        let mut sphere = glass_sphere();
+        sphere.set_transform(Matrix::translation(0., 0., 1.));
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let intersection = Intersection::new(5., &sphere);
+        let comps = intersection.prepare_computations(&ray, &[intersection]);
+
+        assert_eq!(comps.over_point.z < -EPSILON / 2., true);
+        assert_eq!(comps.point.z > comps.over_point.z, true);
+        assert_eq!(comps.under_point.z > EPSILON / 2., true);
+        assert_eq!(comps.point.z < comps.under_point.z, true);
+    }
+
+    #[test]
+    fn test_reflection_vector() {
+        let plane = Plane::new();
+        let value = 2_f64.sqrt() / 2.;
+        let ray = Ray::new(Tuple::new_point(0., 1., -1.), Tuple::new_vector(0., -value, value));
+        let intersection = Intersection::new(2_f64.sqrt(), &plane);
+        let comps = intersection.prepare_computations(&ray, &[intersection]);
+
+        assert_eq!(comps.reflectv.is_equal_to(&Tuple::new_vector(0., value, value)), true);
+    }
+
+    #[test]
+    fn test_refractive_indices() {
+        let mut a = glass_sphere();
+        a.set_transform(Matrix::scaling(2., 2., 2.));
+        let mut b = glass_sphere();
+        b.set_transform(Matrix::translation(0., 0., -0.25));
+        let mut material = b.get_material().clone();
+        material.refractive_index = 2.;
+        b.set_material(material);
+        let mut c = glass_sphere();
+        c.set_transform(Matrix::translation(0., 0., 0.25));
+        let mut material = c.get_material().clone();
+        material.refractive_index = 2.5;
+        c.set_material(material);
+
+        let ray = Ray::new(Tuple::new_point(0., 0., -4.), Tuple::new_vector(0., 0., 1.));
+        let xs = vec![
+            Intersection::new(2., &a),
+            Intersection::new(2.75, &b),
+            Intersection::new(3.25, &c),
+            Intersection::new(4.75, &b),
+            Intersection::new(5.25, &c),
+            Intersection::new(6., &a),
+        ];
+        let expected = [(1., 1.5), (1.5, 2.), (2., 2.5), (2.5, 2.5), (2.5, 1.5), (1.5, 1.)];
+
+        for (index, (n1, n2)) in expected.iter().enumerate() {
+            let comps = xs[index].prepare_computations(&ray, &xs);
+            assert_eq!(comps.n1, *n1);
+            assert_eq!(comps.n2, *n2);
+        }
+    }
+
+    #[test]
+    fn test_schlick() {
+        let sphere = glass_sphere();
+        let value = 2_f64.sqrt() / 2.;
+
+        let ray = Ray::new(Tuple::new_point(0., 0., value), Tuple::new_vector(0., 1., 0.));
+        let xs = vec![Intersection::new(-value, &sphere), Intersection::new(value, &sphere)];
+        assert_eq!(xs[1].prepare_computations(&ray, &xs).schlick(), 1.);
+        assert_eq!(xs[1].prepare_computations(&ray, &xs).refract_direction(), None);
+
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 1., 0.));
+        let xs = vec![Intersection::new(-1., &sphere), Intersection::new(1., &sphere)];
+        let reflectance = xs[1].prepare_computations(&ray, &xs).schlick();
+        assert_eq!(NumberUtils::compare_floats(reflectance, 0.04), true);
+
+        let ray = Ray::new(Tuple::new_point(0., 0.99, -2.), Tuple::new_vector(0., 0., 1.));
+        let xs = vec![Intersection::new(1.8589, &sphere)];
+        let reflectance = xs[0].prepare_computations(&ray, &xs).schlick();
+        assert_eq!(f64::abs(reflectance - 0.48873) < 0.0001, true);
+    }
+}
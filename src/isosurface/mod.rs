@@ -0,0 +1,285 @@
+// ? INFO
+// An isosurface is where a scalar field crosses a level, like the boundary of bone in a CT
+// scan or the zero of an implicit function. Marching cubes walks the grid cube by cube and
+// places triangles where the corners disagree about being inside. Here every cube is cut
+// into six tetrahedra, which needs no case tables, has no ambiguous cases and, as
+// neighbouring cubes cut their shared faces along the same diagonal, leaves no cracks
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::bounds::BoundingBox;
+use crate::shapes::{Mesh, NormalWeighting};
+use crate::tuples::{Tuple, Float};
+
+// Sample indices along x, y and z
+type GridPoint = (usize, usize, usize);
+
+// Tetrahedra around the cube's diagonal from corner 0 to corner 7. Corner bits are x, y, z
+const TETRAHEDRA: [[usize; 4]; 6] = [[0, 1, 3, 7], [0, 3, 2, 7], [0, 2, 6, 7], [0, 6, 4, 7], [0, 4, 5, 7], [0, 5, 1, 7]];
+
+// Samples of a scalar field at evenly spaced points spanning bounds, x fastest then y then z.
+// Values below the iso level are inside, like signed distances. Densities where higher is
+// more solid, as in CT scans, are negated first
+#[derive(PartialEq, Debug, Clone)]
+pub struct VoxelGrid {
+    resolution: (usize, usize, usize),
+    bounds: BoundingBox,
+    values: Vec<Float>
+}
+
+impl VoxelGrid {
+    pub fn new(resolution: (usize, usize, usize), bounds: BoundingBox, values: Vec<Float>) -> Self {
+        let (nx, ny, nz) = resolution;
+        assert!(nx >= 2 && ny >= 2 && nz >= 2, "a voxel grid needs at least 2 samples along each axis");
+        assert_eq!(values.len(), nx * ny * nz, "one value per sample");
+
+        VoxelGrid {
+            resolution,
+            bounds,
+            values
+        }
+    }
+
+    pub fn from_function<F: Fn(&Tuple) -> Float>(resolution: (usize, usize, usize), bounds: BoundingBox, function: F) -> Self {
+        let (nx, ny, nz) = resolution;
+        let mut grid = VoxelGrid::new(resolution, bounds, vec![0.; nx * ny * nz]);
+
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let value = function(&grid.get_position(x, y, z));
+                    grid.values[(z * ny + y) * nx + x] = value;
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn get_resolution(&self) -> (usize, usize, usize) {
+        self.resolution
+    }
+
+    pub fn get_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+
+    pub fn get_value(&self, x: usize, y: usize, z: usize) -> Float {
+        let (nx, ny, _) = self.resolution;
+        self.values[(z * ny + y) * nx + x]
+    }
+
+    pub fn get_position(&self, x: usize, y: usize, z: usize) -> Tuple {
+        let (nx, ny, nz) = self.resolution;
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        let along = |index: usize, count: usize, low: Float, high: Float| low + (high - low) * index as Float / (count - 1) as Float;
+
+        Tuple::new_point(along(x, nx, min.x, max.x), along(y, ny, min.y, max.y), along(z, nz, min.z, max.z))
+    }
+
+    // Central differences, one sided at the edges of the grid
+    pub fn get_gradient(&self, x: usize, y: usize, z: usize) -> Tuple {
+        let (nx, ny, nz) = self.resolution;
+        let size = self.bounds.max - self.bounds.min;
+        let difference = |before: GridPoint, after: GridPoint, steps: usize, length: Float| {
+            let spacing = length / (steps - 1) as Float;
+            let span = (after.0 + after.1 + after.2 - before.0 - before.1 - before.2) as Float * spacing;
+            (self.get_value(after.0, after.1, after.2) - self.get_value(before.0, before.1, before.2)) / span
+        };
+
+        Tuple::new_vector(
+            difference((x.saturating_sub(1), y, z), ((x + 1).min(nx - 1), y, z), nx, size.x),
+            difference((x, y.saturating_sub(1), z), (x, (y + 1).min(ny - 1), z), ny, size.y),
+            difference((x, y, z.saturating_sub(1)), (x, y, (z + 1).min(nz - 1)), nz, size.z)
+        )
+    }
+
+    // Turns densities into values that are below the level inside
+    pub fn negate(&mut self) {
+        for value in &mut self.values {
+            *value = -*value;
+        }
+    }
+
+    // Mesh of where the field crosses iso_level, with normals blended from the grid's gradients
+    pub fn extract(&self, iso_level: Float) -> Mesh {
+        self.march(iso_level, |a, b, t, _| {
+            let (ga, gb) = (self.get_gradient(a.0, a.1, a.2), self.get_gradient(b.0, b.1, b.2));
+            ga * (1. - t) + gb * t
+        })
+    }
+
+    // normal_at gets the two grid points of the edge, how far along it the vertex is and its position
+    fn march<N>(&self, iso_level: Float, normal_at: N) -> Mesh
+    where N: Fn(GridPoint, GridPoint, Float, &Tuple) -> Tuple {
+        let (nx, ny, nz) = self.resolution;
+        let mut positions = vec![];
+        let mut normals = vec![];
+        // vertices shared between tetrahedra by the grid points of their edge
+        let mut vertices: HashMap<(GridPoint, GridPoint), usize> = HashMap::new();
+        let mut triangles = vec![];
+
+        let mut vertex_on = |a: GridPoint, b: GridPoint| -> usize {
+            let (va, vb) = (self.get_value(a.0, a.1, a.2), self.get_value(b.0, b.1, b.2));
+            let t = ((iso_level - va) / (vb - va)).clamp(0., 1.);
+            // crossings right on a grid point are keyed by the point, so they are shared too
+            let key = if t == 0. { (a, a) } else if t == 1. { (b, b) } else { (a.min(b), a.max(b)) };
+
+            *vertices.entry(key).or_insert_with(|| {
+                let (pa, pb) = (self.get_position(a.0, a.1, a.2), self.get_position(b.0, b.1, b.2));
+                let position = pa + (pb - pa) * t;
+                positions.push(position);
+                normals.push(normal_at(a, b, t, &position));
+                positions.len() - 1
+            })
+        };
+
+        for z in 0..nz - 1 {
+            for y in 0..ny - 1 {
+                for x in 0..nx - 1 {
+                    let corner = |index: usize| (x + (index & 1), y + ((index >> 1) & 1), z + ((index >> 2) & 1));
+
+                    for tetrahedron in &TETRAHEDRA {
+                        let corners = tetrahedron.map(corner);
+                        let (inside, outside): (Vec<_>, Vec<_>) = corners.iter().copied()
+                            .partition(|&(cx, cy, cz)| self.get_value(cx, cy, cz) < iso_level);
+
+                        match (inside.as_slice(), outside.as_slice()) {
+                            ([a], [b, c, d]) | ([b, c, d], [a]) => {
+                                triangles.push([vertex_on(*a, *b), vertex_on(*a, *c), vertex_on(*a, *d)]);
+                            },
+                            ([a, b], [c, d]) => {
+                                let (ac, ad, bc, bd) = (vertex_on(*a, *c), vertex_on(*a, *d), vertex_on(*b, *c), vertex_on(*b, *d));
+                                triangles.push([ac, ad, bd]);
+                                triangles.push([ac, bd, bc]);
+                            },
+                            _ => ()
+                        }
+                    }
+                }
+            }
+        }
+
+        // triangles that collapsed onto grid points are dropped, the rest face along the normals
+        let triangles: Vec<[usize; 3]> = triangles.into_iter()
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .map(|[a, b, c]| {
+                let face = (positions[c] - positions[a]).get_vector_cross_product(&(positions[b] - positions[a]));
+                let normal = normals[a] + normals[b] + normals[c];
+                if face.calculate_dot_product(&normal) < 0. { [a, c, b] } else { [a, b, c] }
+            })
+            .collect();
+
+        let smooth = normals.iter().all(|normal| normal.get_magnitude() > 0.);
+        let mut mesh = Mesh::new(positions, triangles);
+        if smooth {
+            mesh.set_normals(normals.iter().map(|normal| normal.normalize()).collect());
+        } else {
+            mesh.generate_normals(PI, NormalWeighting::Angle);
+        }
+        mesh
+    }
+}
+
+// Samples the function on a grid of resolution points across bounds and extracts the surface
+// where it equals iso_level. Normals come from the function's own gradient at each vertex
+pub fn polygonize<F: Fn(&Tuple) -> Float>(function: F, bounds: BoundingBox, resolution: (usize, usize, usize), iso_level: Float) -> Mesh {
+    let grid = VoxelGrid::from_function(resolution, bounds, &function);
+    let size = bounds.max - bounds.min;
+    let h = size.x.min(size.y).min(size.z) / (resolution.0.max(resolution.1).max(resolution.2) * 100) as Float;
+
+    grid.march(iso_level, |_, _, _, position| {
+        let difference = |offset: Tuple| function(&(*position + offset)) - function(&(*position - offset));
+        Tuple::new_vector(
+            difference(Tuple::new_vector(h, 0., 0.)),
+            difference(Tuple::new_vector(0., h, 0.)),
+            difference(Tuple::new_vector(0., 0., h))
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+
+    fn cube(half: Float) -> BoundingBox {
+        BoundingBox::new_with_bounds(Tuple::new_point(-half, -half, -half), Tuple::new_point(half, half, half))
+    }
+
+    // Every edge is shared by exactly two triangles
+    fn is_closed(mesh: &Mesh) -> bool {
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for &[a, b, c] in mesh.get_triangles() {
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((from.min(to), from.max(to))).or_insert(0) += 1;
+            }
+        }
+        edges.values().all(|&count| count == 2)
+    }
+
+    #[test]
+    fn test_grid_sampling() {
+        let grid = VoxelGrid::from_function((3, 5, 2), cube(1.), |point| point.x + 2. * point.y);
+
+        assert_eq!(grid.get_position(2, 0, 1), Tuple::new_point(1., -1., 1.));
+        assert_eq!(grid.get_value(1, 3, 0), 1.);
+        assert_eq!(grid.get_gradient(1, 2, 0), Tuple::new_vector(1., 2., 0.));
+        assert_eq!(grid.get_gradient(0, 4, 1), Tuple::new_vector(1., 2., 0.));
+    }
+
+    #[test]
+    fn test_sphere_from_function() {
+        let mesh = polygonize(|point| (*point - Tuple::new_point(0., 0., 0.)).get_magnitude() - 1., cube(1.5), (16, 16, 16), 0.);
+
+        assert_eq!(mesh.get_triangle_count() > 100, true);
+        assert_eq!(is_closed(&mesh), true);
+        for (position, normal) in mesh.get_positions().iter().zip(mesh.get_normals()) {
+            let radial = *position - Tuple::new_point(0., 0., 0.);
+            assert_eq!((radial.get_magnitude() - 1.).abs() < 0.03, true);
+            assert_eq!(normal.calculate_dot_product(&radial.normalize()) > 0.99, true);
+        }
+
+        let ray = Ray::new(Tuple::new_point(0.1, 0.2, -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = mesh.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!((xs[0].t - 4.025).abs() < 0.03, true);
+    }
+
+    #[test]
+    fn test_density_grid() {
+        // a single dense voxel in the middle of a 3 x 3 x 3 scan
+        let mut values = vec![0.; 27];
+        values[13] = 1.;
+        let mut grid = VoxelGrid::new((3, 3, 3), cube(1.), values);
+        grid.negate();
+        let mesh = grid.extract(-0.5);
+
+        assert_eq!(is_closed(&mesh), true);
+        // every vertex sits halfway from the center to a neighbour
+        for position in mesh.get_positions() {
+            let distance = (*position - Tuple::new_point(0., 0., 0.)).get_magnitude();
+            let halfway = [0.5, 0.5 * 2_f64.sqrt(), 0.5 * 3_f64.sqrt()].iter().any(|expected| (distance - expected).abs() < 1e-9);
+            assert_eq!(halfway, true);
+        }
+
+        // normals point away from the dense center
+        for (position, normal) in mesh.get_positions().iter().zip(mesh.get_normals()) {
+            assert_eq!(normal.calculate_dot_product(&(*position - Tuple::new_point(0., 0., 0.))) > 0., true);
+        }
+    }
+
+    #[test]
+    fn test_degenerate_and_empty_surfaces() {
+        let empty = VoxelGrid::from_function((4, 4, 4), cube(1.), |_| 1.).extract(0.);
+        assert_eq!(empty.get_triangle_count(), 0);
+
+        // a plane through a layer of grid points shares its vertices and collapses nothing
+        let plane = VoxelGrid::from_function((5, 5, 5), cube(1.), |point| point.y).extract(0.);
+        assert_eq!(plane.get_triangles().iter().all(|[a, b, c]| a != b && b != c && c != a), true);
+        assert_eq!(plane.get_vertex_count(), 25);
+        assert_eq!(plane.get_positions().iter().all(|position| position.y == 0.), true);
+    }
+}
@@ -0,0 +1,37 @@
+// Tests compare predicates with assert_eq!(..., true) throughout
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+
+pub mod tuples;
+pub mod utils;
+pub mod color;
+pub mod canvas;
+pub mod matrix;
+pub mod quaternion;
+pub mod animation;
+pub mod film;
+pub mod adaptive;
+pub mod random;
+pub mod sampling;
+pub mod ray;
+pub mod material;
+pub mod microfacet;
+pub mod medium;
+pub mod displacement;
+pub mod environment;
+pub mod light;
+pub mod bounds;
+pub mod clipping;
+pub mod fractal;
+pub mod sdf;
+pub mod shapes;
+pub mod intersection;
+pub mod world;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod xyz;
+pub mod patch;
+pub mod subdivision;
+pub mod isosurface;
+pub mod camera;
+pub mod path_tracer;
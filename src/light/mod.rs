@@ -0,0 +1,157 @@
+use crate::color::Color;
+use crate::material::Material;
+use crate::tuples::Tuple;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct PointLight {
+    pub position: Tuple,
+    pub intensity: Color
+}
+
+impl PointLight {
+    pub fn new(position: Tuple, intensity: Color) -> Self {
+        PointLight {
+            position,
+            intensity
+        }
+    }
+}
+
+// Phong reflection model, used by the Whitted-style shading in World
+pub fn lighting(material: &Material, light: &PointLight, point: &Tuple, eyev: &Tuple, normalv: &Tuple, in_shadow: bool) -> Color {
+    let effective_color = material.color * light.intensity;
+    let ambient = effective_color * material.ambient as f32;
+
+    if in_shadow {
+        return ambient
+    }
+
+    let lightv = (light.position - *point).normalize();
+    let light_dot_normal = lightv.calculate_dot_product(normalv);
+
+    if light_dot_normal < 0. {
+        return ambient
+    }
+
+    let diffuse = effective_color * (material.diffuse * light_dot_normal) as f32;
+    let reflectv = reflect(&lightv.negate(), normalv);
+    let reflect_dot_eye = reflectv.calculate_dot_product(eyev);
+
+    if reflect_dot_eye <= 0. {
+        return ambient + diffuse
+    }
+
+    let factor = reflect_dot_eye.powf(material.shininess);
+    let specular = light.intensity * (material.specular * factor) as f32;
+
+    ambient + diffuse + specular
+}
+
+// Kajiya-Kay model for thin fibers like hair, shaded by their tangent instead of a normal.
+// Light reaching a fiber from any side around it lights it, and the highlight is the cone
+// of directions a mirror cylinder would reflect into
+pub fn hair_lighting(material: &Material, light: &PointLight, point: &Tuple, eyev: &Tuple, tangent: &Tuple, in_shadow: bool) -> Color {
+    let effective_color = material.color * light.intensity;
+    let ambient = effective_color * material.ambient as f32;
+
+    if in_shadow {
+        return ambient
+    }
+
+    let lightv = (light.position - *point).normalize();
+    let (cos_light, cos_eye) = (tangent.calculate_dot_product(&lightv), tangent.calculate_dot_product(eyev));
+    let (sin_light, sin_eye) = ((1. - cos_light * cos_light).max(0.).sqrt(), (1. - cos_eye * cos_eye).max(0.).sqrt());
+
+    let diffuse = effective_color * (material.diffuse * sin_light) as f32;
+    let highlight = (sin_light * sin_eye - cos_light * cos_eye).max(0.);
+    let specular = light.intensity * (material.specular * highlight.powf(material.shininess)) as f32;
+
+    ambient + diffuse + specular
+}
+
+pub fn reflect(vector: &Tuple, normal: &Tuple) -> Tuple {
+    *vector - *normal * (2. * vector.calculate_dot_product(normal))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn setup() -> (Material, Tuple) {
+        (Material::new(), Tuple::new_point(0., 0., 0.))
+    }
+
+    #[test]
+    fn test_reflect() {
+        let vector = Tuple::new_vector(1., -1., 0.);
+        let normal = Tuple::new_vector(0., 1., 0.);
+        assert_eq!(reflect(&vector, &normal), Tuple::new_vector(1., 1., 0.));
+
+        let vector = Tuple::new_vector(0., -1., 0.);
+        let normal = Tuple::new_vector(2_f64.sqrt() / 2., 2_f64.sqrt() / 2., 0.);
+        assert_eq!(reflect(&vector, &normal).is_equal_to(&Tuple::new_vector(1., 0., 0.)), true);
+    }
+
+    #[test]
+    fn test_lighting_eye_between_light_and_surface() {
+        let (material, position) = setup();
+        let eyev = Tuple::new_vector(0., 0., -1.);
+        let normalv = Tuple::new_vector(0., 0., -1.);
+        let light = PointLight::new(Tuple::new_point(0., 0., -10.), Color::white());
+
+        let result = lighting(&material, &light, &position, &eyev, &normalv, false);
+        assert_eq!(result.is_equal_to(Color::new(1.9, 1.9, 1.9)), true);
+    }
+
+    #[test]
+    fn test_lighting_eye_in_path_of_reflection() {
+        let (material, position) = setup();
+        let eyev = Tuple::new_vector(0., -2_f64.sqrt() / 2., -2_f64.sqrt() / 2.);
+        let normalv = Tuple::new_vector(0., 0., -1.);
+        let light = PointLight::new(Tuple::new_point(0., 10., -10.), Color::white());
+
+        let result = lighting(&material, &light, &position, &eyev, &normalv, false);
+        assert_eq!(f32::abs(result.red - 1.6364) < 0.0001, true);
+    }
+
+    #[test]
+    fn test_lighting_light_behind_surface() {
+        let (material, position) = setup();
+        let eyev = Tuple::new_vector(0., 0., -1.);
+        let normalv = Tuple::new_vector(0., 0., -1.);
+        let light = PointLight::new(Tuple::new_point(0., 0., 10.), Color::white());
+
+        let result = lighting(&material, &light, &position, &eyev, &normalv, false);
+        assert_eq!(result.is_equal_to(Color::new(0.1, 0.1, 0.1)), true);
+    }
+
+    #[test]
+    fn test_lighting_in_shadow() {
+        let (material, position) = setup();
+        let eyev = Tuple::new_vector(0., 0., -1.);
+        let normalv = Tuple::new_vector(0., 0., -1.);
+        let light = PointLight::new(Tuple::new_point(0., 0., -10.), Color::white());
+
+        let result = lighting(&material, &light, &position, &eyev, &normalv, true);
+        assert_eq!(result.is_equal_to(Color::new(0.1, 0.1, 0.1)), true);
+    }
+
+    #[test]
+    fn test_hair_lighting() {
+        let (material, position) = setup();
+        let eyev = Tuple::new_vector(0., 0., -1.);
+        let light = PointLight::new(Tuple::new_point(0., 0., -10.), Color::white());
+
+        // a fiber across the view is lit fully, one pointing at the light only by ambient
+        let across = hair_lighting(&material, &light, &position, &eyev, &Tuple::new_vector(1., 0., 0.), false);
+        assert_eq!(across.is_equal_to(Color::new(1.9, 1.9, 1.9)), true);
+        let along = hair_lighting(&material, &light, &position, &eyev, &Tuple::new_vector(0., 0., 1.), false);
+        assert_eq!(along.is_equal_to(Color::new(0.1, 0.1, 0.1)), true);
+
+        // unlike a surface it is lit just as much from behind
+        let behind = PointLight::new(Tuple::new_point(0., 0., 10.), Color::white());
+        let result = hair_lighting(&material, &behind, &position, &eyev, &Tuple::new_vector(1., 0., 0.), false);
+        assert_eq!(result.is_equal_to(Color::new(1.9, 1.9, 1.9)), true);
+    }
+}
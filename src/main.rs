@@ -1,13 +1,7 @@
-mod tuples;
-mod utils;
-mod color;
-mod canvas;
-mod matrix;
+use ray_tracer::tuples::{Tuple};
+use ray_tracer::canvas::Canvas;
+use ray_tracer::color::Color;
 
-use tuples::{Tuple};
-use canvas::Canvas;
-use color::Color;
-use matrix::Matrix;
 fn main() {
     println!("Hello, world!");
 
@@ -33,8 +27,6 @@ fn main() {
     }
 
     //canvas.save_to_disk();
-    
-    let m = Matrix::construct_empty_3x3();
 }
 
 // Putting it Together CH 1
@@ -0,0 +1,122 @@
+use crate::color::Color;
+use crate::displacement::Displacement;
+use crate::medium::Medium;
+use crate::microfacet::Microfacet;
+use crate::tuples::Float;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Material {
+    pub color: Color,
+    pub ambient: Float,
+    pub diffuse: Float,
+    pub specular: Float,
+    pub shininess: Float,
+    pub reflective: Float,
+    pub transparency: Float,
+    pub refractive_index: Float,
+    // Light given off by the surface itself, black for anything that is not a lamp
+    pub emissive: Color,
+    // Physically based surface for the path tracer, replaces the Phong, reflective and
+    // transparency settings there. The Whitted-style shading ignores it
+    pub microfacet: Option<Microfacet>,
+    // Makes the shape an invisible boundary filled with the medium, only used by the path tracer
+    pub medium: Option<Medium>,
+    // Geometry offset along the normal, applied by World::displace before rendering
+    pub displacement: Option<Displacement>
+}
+
+impl Material {
+    pub fn new() -> Self {
+        Material {
+            color: Color::white(),
+            ambient: 0.1,
+            diffuse: 0.9,
+            specular: 0.9,
+            shininess: 200.,
+            reflective: 0.,
+            transparency: 0.,
+            refractive_index: 1.,
+            emissive: Color::black(),
+            microfacet: None,
+            medium: None,
+            displacement: None
+        }
+    }
+
+    pub fn new_emissive(emissive: Color) -> Self {
+        Material {
+            emissive,
+            ..Material::new()
+        }
+    }
+
+    pub fn new_microfacet(microfacet: Microfacet) -> Self {
+        Material {
+            microfacet: Some(microfacet),
+            ..Material::new()
+        }
+    }
+
+    pub fn new_medium(medium: Medium) -> Self {
+        Material {
+            medium: Some(medium),
+            ..Material::new()
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emissive.red > 0. || self.emissive.green > 0. || self.emissive.blue > 0.
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_default_material() {
+        let material = Material::new();
+
+        assert_eq!(material.color, Color::white());
+        assert_eq!(material.ambient, 0.1);
+        assert_eq!(material.diffuse, 0.9);
+        assert_eq!(material.specular, 0.9);
+        assert_eq!(material.shininess, 200.);
+        assert_eq!(material.reflective, 0.);
+        assert_eq!(material.transparency, 0.);
+        assert_eq!(material.refractive_index, 1.);
+        assert_eq!(material.is_emissive(), false);
+        assert_eq!(material.microfacet, None);
+        assert_eq!(material.medium, None);
+    }
+
+    #[test]
+    fn test_emissive_material() {
+        let material = Material::new_emissive(Color::new(4., 4., 4.));
+
+        assert_eq!(material.is_emissive(), true);
+        assert_eq!(material.diffuse, 0.9);
+    }
+
+    #[test]
+    fn test_microfacet_material() {
+        let material = Material::new_microfacet(Microfacet::gold());
+
+        assert_eq!(material.microfacet, Some(Microfacet::gold()));
+        assert_eq!(material.is_emissive(), false);
+    }
+
+    #[test]
+    fn test_medium_material() {
+        let material = Material::new_medium(Medium::new(0.1, 0.4));
+
+        assert_eq!(material.medium, Some(Medium::new(0.1, 0.4)));
+    }
+}
@@ -1,23 +1,32 @@
 use crate::utils::NumberUtils;
+use crate::tuples::{Tuple, Float};
 
-type MatrixType = Vec<Vec<f64>>;
+pub type MatrixType = Vec<Vec<Float>>;
 
 pub struct Matrix;
 
 impl Matrix {
     pub fn construct_empty_4x4() -> MatrixType {
         let inner = vec![0., 0., 0., 0.];
-        vec![inner.clone(), inner.clone(), inner.clone(), inner.clone()]
+        vec![inner.clone(), inner.clone(), inner.clone(), inner]
     }
 
     pub fn construct_empty_3x3() -> MatrixType {
         let inner = vec![0., 0., 0.];
-        vec![inner.clone(), inner.clone(), inner.clone()]
+        vec![inner.clone(), inner.clone(), inner]
     }
 
     pub fn construct_empty_2x2() -> MatrixType {
         let inner = vec![0., 0.];
-        vec![inner.clone(), inner.clone()]
+        vec![inner.clone(), inner]
+    }
+
+    pub fn identity_4x4() -> MatrixType {
+        let mut m = Matrix::construct_empty_4x4();
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.;
+        }
+        m
     }
 
     pub fn are_equal(m1: &MatrixType, m2: &MatrixType) -> bool {
@@ -39,11 +48,168 @@ impl Matrix {
 
         true
     }
+
+    pub fn multiply(m1: &MatrixType, m2: &MatrixType) -> MatrixType {
+        let size = m1.len();
+        let mut m = vec![vec![0.; size]; size];
+
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = (0..size).map(|k| m1[i][k] * m2[k][j]).sum();
+            }
+        }
+
+        m
+    }
+
+    pub fn multiply_tuple(m: &MatrixType, tuple: &Tuple) -> Tuple {
+        let row = |i: usize| m[i][0] * tuple.x + m[i][1] * tuple.y + m[i][2] * tuple.z + m[i][3] * tuple.w;
+        Tuple::new_tuple(row(0), row(1), row(2), row(3))
+    }
+
+    pub fn transpose(m: &MatrixType) -> MatrixType {
+        let size = m.len();
+        let mut transposed = vec![vec![0.; size]; size];
+
+        for (i, row) in m.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                transposed[j][i] = *value;
+            }
+        }
+
+        transposed
+    }
+
+    pub fn determinant(m: &MatrixType) -> Float {
+        if m.len() == 2 {
+            return m[0][0] * m[1][1] - m[0][1] * m[1][0]
+        }
+
+        (0..m.len()).map(|column| m[0][column] * Matrix::cofactor(m, 0, column)).sum()
+    }
+
+    pub fn submatrix(m: &MatrixType, row: usize, column: usize) -> MatrixType {
+        m.iter()
+            .enumerate()
+            .filter(|(i, _)| *i != row)
+            .map(|(_, r)| {
+                r.iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != column)
+                    .map(|(_, value)| *value)
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn minor(m: &MatrixType, row: usize, column: usize) -> Float {
+        Matrix::determinant(&Matrix::submatrix(m, row, column))
+    }
+
+    pub fn cofactor(m: &MatrixType, row: usize, column: usize) -> Float {
+        let minor = Matrix::minor(m, row, column);
+        if (row + column).is_multiple_of(2) { minor } else { -minor }
+    }
+
+    pub fn is_invertible(m: &MatrixType) -> bool {
+        !NumberUtils::compare_floats(Matrix::determinant(m), 0.)
+    }
+
+    // Panics on a singular matrix, check with is_invertible first when unsure
+    pub fn inverse(m: &MatrixType) -> MatrixType {
+        let determinant = Matrix::determinant(m);
+        assert!(determinant != 0., "matrix is not invertible");
+
+        let size = m.len();
+        let mut inverse = vec![vec![0.; size]; size];
+
+        for (column, inverse_row) in inverse.iter_mut().enumerate() {
+            for (row, cell) in inverse_row.iter_mut().enumerate() {
+                // transposed on purpose, row and column swap places
+                *cell = Matrix::cofactor(m, row, column) / determinant;
+            }
+        }
+
+        inverse
+    }
+
+    // Transformations
+
+    pub fn translation(x: Float, y: Float, z: Float) -> MatrixType {
+        let mut m = Matrix::identity_4x4();
+        m[0][3] = x;
+        m[1][3] = y;
+        m[2][3] = z;
+        m
+    }
+
+    pub fn scaling(x: Float, y: Float, z: Float) -> MatrixType {
+        let mut m = Matrix::identity_4x4();
+        m[0][0] = x;
+        m[1][1] = y;
+        m[2][2] = z;
+        m
+    }
+
+    pub fn rotation_x(radians: Float) -> MatrixType {
+        let mut m = Matrix::identity_4x4();
+        m[1][1] = radians.cos();
+        m[1][2] = -radians.sin();
+        m[2][1] = radians.sin();
+        m[2][2] = radians.cos();
+        m
+    }
+
+    pub fn rotation_y(radians: Float) -> MatrixType {
+        let mut m = Matrix::identity_4x4();
+        m[0][0] = radians.cos();
+        m[0][2] = radians.sin();
+        m[2][0] = -radians.sin();
+        m[2][2] = radians.cos();
+        m
+    }
+
+    pub fn rotation_z(radians: Float) -> MatrixType {
+        let mut m = Matrix::identity_4x4();
+        m[0][0] = radians.cos();
+        m[0][1] = -radians.sin();
+        m[1][0] = radians.sin();
+        m[1][1] = radians.cos();
+        m
+    }
+
+    pub fn shearing(xy: Float, xz: Float, yx: Float, yz: Float, zx: Float, zy: Float) -> MatrixType {
+        let mut m = Matrix::identity_4x4();
+        m[0][1] = xy;
+        m[0][2] = xz;
+        m[1][0] = yx;
+        m[1][2] = yz;
+        m[2][0] = zx;
+        m[2][1] = zy;
+        m
+    }
+
+    pub fn view_transform(from: &Tuple, to: &Tuple, up: &Tuple) -> MatrixType {
+        let forward = (*to - *from).normalize();
+        let left = forward.get_vector_cross_product(&up.normalize());
+        let true_up = left.get_vector_cross_product(&forward);
+
+        let orientation = vec![
+            vec![left.x, left.y, left.z, 0.],
+            vec![true_up.x, true_up.y, true_up.z, 0.],
+            vec![-forward.x, -forward.y, -forward.z, 0.],
+            vec![0., 0., 0., 1.],
+        ];
+
+        Matrix::multiply(&orientation, &Matrix::translation(-from.x, -from.y, -from.z))
+    }
 }
 
+#[cfg(test)]
 mod tests {
 
     use super::*;
+    use std::f64::consts::PI;
 
     #[test]
     fn test_matrix_4x4() {
@@ -61,11 +227,191 @@ mod tests {
 
         assert_eq!(Matrix::are_equal(&m1, &m2), true);
         assert_eq!(Matrix::are_equal(&m2, &m3), false);
-        
+
         m1[2][3] = 15.34;
 
         assert_eq!(Matrix::are_equal(&m1, &m2), false);
 
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_multiply_matrices() {
+        let m1 = vec![
+            vec![1., 2., 3., 4.],
+            vec![5., 6., 7., 8.],
+            vec![9., 8., 7., 6.],
+            vec![5., 4., 3., 2.],
+        ];
+        let m2 = vec![
+            vec![-2., 1., 2., 3.],
+            vec![3., 2., 1., -1.],
+            vec![4., 3., 6., 5.],
+            vec![1., 2., 7., 8.],
+        ];
+        let expected = vec![
+            vec![20., 22., 50., 48.],
+            vec![44., 54., 114., 108.],
+            vec![40., 58., 110., 102.],
+            vec![16., 26., 46., 42.],
+        ];
+
+        assert_eq!(Matrix::are_equal(&Matrix::multiply(&m1, &m2), &expected), true);
+    }
+
+    #[test]
+    fn test_multiply_by_tuple() {
+        let m = vec![
+            vec![1., 2., 3., 4.],
+            vec![2., 4., 4., 2.],
+            vec![8., 6., 4., 1.],
+            vec![0., 0., 0., 1.],
+        ];
+        let tuple = Tuple::new_point(1., 2., 3.);
+
+        assert_eq!(Matrix::multiply_tuple(&m, &tuple), Tuple::new_point(18., 24., 33.));
+        assert_eq!(Matrix::multiply_tuple(&Matrix::identity_4x4(), &tuple), tuple);
+    }
+
+    #[test]
+    fn test_transpose() {
+        let m = vec![
+            vec![0., 9., 3., 0.],
+            vec![9., 8., 0., 8.],
+            vec![1., 8., 5., 3.],
+            vec![0., 0., 5., 8.],
+        ];
+        let expected = vec![
+            vec![0., 9., 1., 0.],
+            vec![9., 8., 8., 0.],
+            vec![3., 0., 5., 5.],
+            vec![0., 8., 3., 8.],
+        ];
+
+        assert_eq!(Matrix::are_equal(&Matrix::transpose(&m), &expected), true);
+        assert_eq!(Matrix::are_equal(&Matrix::transpose(&Matrix::identity_4x4()), &Matrix::identity_4x4()), true);
+    }
+
+    #[test]
+    fn test_determinant() {
+        let m = vec![vec![1., 5.], vec![-3., 2.]];
+        assert_eq!(Matrix::determinant(&m), 17.);
+
+        let m = vec![
+            vec![-2., -8., 3., 5.],
+            vec![-3., 1., 7., 3.],
+            vec![1., 2., -9., 6.],
+            vec![-6., 7., 7., -9.],
+        ];
+        assert_eq!(Matrix::cofactor(&m, 0, 0), 690.);
+        assert_eq!(Matrix::cofactor(&m, 0, 3), 51.);
+        assert_eq!(Matrix::determinant(&m), -4071.);
+    }
+
+    #[test]
+    fn test_submatrix() {
+        let m = vec![
+            vec![1., 5., 0.],
+            vec![-3., 2., 7.],
+            vec![0., 6., -3.],
+        ];
+        let expected = vec![vec![-3., 2.], vec![0., 6.]];
+
+        assert_eq!(Matrix::submatrix(&m, 0, 2), expected);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = vec![
+            vec![8., -5., 9., 2.],
+            vec![7., 5., 6., 1.],
+            vec![-6., 0., 9., 6.],
+            vec![-3., 0., -9., -4.],
+        ];
+        let expected = [
+            [-0.15385, -0.15385, -0.28205, -0.53846],
+            [-0.07692, 0.12308, 0.02564, 0.03077],
+            [0.35897, 0.35897, 0.43590, 0.92308],
+            [-0.69231, -0.69231, -0.76923, -1.92308],
+        ];
+        let inverse = Matrix::inverse(&m);
+
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_eq!(f64::abs(inverse[i][j] - expected[i][j]) < 0.0001, true);
+            }
+        }
+
+        assert_eq!(Matrix::are_equal(&Matrix::multiply(&m, &inverse), &Matrix::identity_4x4()), true);
+    }
+
+    #[test]
+    fn test_is_invertible() {
+        let m = vec![
+            vec![-4., 2., -2., -3.],
+            vec![9., 6., 2., 6.],
+            vec![0., -5., 1., -5.],
+            vec![0., 0., 0., 0.],
+        ];
+
+        assert_eq!(Matrix::is_invertible(&m), false);
+        assert_eq!(Matrix::is_invertible(&Matrix::identity_4x4()), true);
+    }
+
+    #[test]
+    fn test_translation() {
+        let transform = Matrix::translation(5., -3., 2.);
+        let point = Tuple::new_point(-3., 4., 5.);
+        let vector = Tuple::new_vector(-3., 4., 5.);
+
+        assert_eq!(Matrix::multiply_tuple(&transform, &point), Tuple::new_point(2., 1., 7.));
+        assert_eq!(Matrix::multiply_tuple(&Matrix::inverse(&transform), &point), Tuple::new_point(-8., 7., 3.));
+        assert_eq!(Matrix::multiply_tuple(&transform, &vector), vector);
+    }
+
+    #[test]
+    fn test_scaling() {
+        let transform = Matrix::scaling(2., 3., 4.);
+
+        assert_eq!(Matrix::multiply_tuple(&transform, &Tuple::new_point(-4., 6., 8.)), Tuple::new_point(-8., 18., 32.));
+        assert_eq!(Matrix::multiply_tuple(&transform, &Tuple::new_vector(-4., 6., 8.)), Tuple::new_vector(-8., 18., 32.));
+    }
+
+    #[test]
+    fn test_rotations() {
+        let point = Tuple::new_point(0., 1., 0.);
+        let rotated = Matrix::multiply_tuple(&Matrix::rotation_x(PI / 2.), &point);
+        assert_eq!(rotated.is_equal_to(&Tuple::new_point(0., 0., 1.)), true);
+
+        let point = Tuple::new_point(0., 0., 1.);
+        let rotated = Matrix::multiply_tuple(&Matrix::rotation_y(PI / 2.), &point);
+        assert_eq!(rotated.is_equal_to(&Tuple::new_point(1., 0., 0.)), true);
+
+        let point = Tuple::new_point(0., 1., 0.);
+        let rotated = Matrix::multiply_tuple(&Matrix::rotation_z(PI / 2.), &point);
+        assert_eq!(rotated.is_equal_to(&Tuple::new_point(-1., 0., 0.)), true);
+    }
+
+    #[test]
+    fn test_shearing() {
+        let transform = Matrix::shearing(1., 0., 0., 0., 0., 0.);
+        let point = Tuple::new_point(2., 3., 4.);
+
+        assert_eq!(Matrix::multiply_tuple(&transform, &point), Tuple::new_point(5., 3., 4.));
+    }
+
+    #[test]
+    fn test_view_transform() {
+        let from = Tuple::new_point(0., 0., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+
+        let default = Matrix::view_transform(&from, &Tuple::new_point(0., 0., -1.), &up);
+        assert_eq!(Matrix::are_equal(&default, &Matrix::identity_4x4()), true);
+
+        let looking_backwards = Matrix::view_transform(&from, &Tuple::new_point(0., 0., 1.), &up);
+        assert_eq!(Matrix::are_equal(&looking_backwards, &Matrix::scaling(-1., 1., -1.)), true);
+
+        let moved = Matrix::view_transform(&Tuple::new_point(0., 0., 8.), &from, &up);
+        assert_eq!(Matrix::are_equal(&moved, &Matrix::translation(0., 0., -8.)), true);
+    }
+
+}
@@ -0,0 +1,425 @@
+use std::f64::consts::PI;
+
+use crate::bounds::BoundingBox;
+use crate::color::Color;
+use crate::matrix::{Matrix, MatrixType};
+use crate::random::Random;
+use crate::ray::Ray;
+use crate::sampling::to_world;
+use crate::tuples::{Tuple, Float};
+
+// How thick the medium is at a point, as a multiplier of its absorption and scattering
+#[derive(PartialEq, Debug, Clone)]
+pub enum Density {
+    // The same everywhere, homogeneous fog
+    Constant,
+    // Values from a 3D grid, see DensityGrid
+    Grid(DensityGrid),
+    // Fractal value noise in [0, 1], for smoke and clouds
+    Noise { frequency: Float, octaves: usize, seed: u64 }
+}
+
+// Volume of density values stretched over the cube from -1 to 1 in the space of the
+// shape holding the medium, trilinearly interpolated and zero outside of it
+#[derive(PartialEq, Debug, Clone)]
+pub struct DensityGrid {
+    width: usize,
+    height: usize,
+    depth: usize,
+    values: Vec<Float>
+}
+
+impl DensityGrid {
+    pub fn new(width: usize, height: usize, depth: usize) -> Self {
+        assert!(width > 0 && height > 0 && depth > 0, "Density grid needs at least one value");
+
+        DensityGrid {
+            width,
+            height,
+            depth,
+            values: vec![0.; width * height * depth]
+        }
+    }
+
+    pub fn get_value(&self, x: usize, y: usize, z: usize) -> Float {
+        self.values[(z * self.height + y) * self.width + x]
+    }
+
+    pub fn set_value(&mut self, x: usize, y: usize, z: usize, value: Float) {
+        self.values[(z * self.height + y) * self.width + x] = value;
+    }
+
+    pub fn get_max(&self) -> Float {
+        self.values.iter().cloned().fold(0., Float::max)
+    }
+
+    pub fn sample(&self, point: &Tuple) -> Float {
+        if [point.x, point.y, point.z].iter().any(|v| !(-1. ..=1.).contains(v)) {
+            return 0.
+        }
+
+        let (x0, x1, fx) = DensityGrid::cell(point.x, self.width);
+        let (y0, y1, fy) = DensityGrid::cell(point.y, self.height);
+        let (z0, z1, fz) = DensityGrid::cell(point.z, self.depth);
+
+        let lerp = |a: Float, b: Float, t: Float| a + (b - a) * t;
+        let row = |y: usize, z: usize| lerp(self.get_value(x0, y, z), self.get_value(x1, y, z), fx);
+        let slice = |z: usize| lerp(row(y0, z), row(y1, z), fy);
+
+        lerp(slice(z0), slice(z1), fz)
+    }
+
+    // Neighbouring grid indices and the fraction between them for a coordinate in [-1, 1]
+    fn cell(coordinate: Float, size: usize) -> (usize, usize, Float) {
+        let position = (coordinate + 1.) / 2. * (size - 1) as Float;
+        let lower = (position.floor() as usize).min(size - 1);
+        let upper = (lower + 1).min(size - 1);
+
+        (lower, upper, position - lower as Float)
+    }
+}
+
+// Participating medium that absorbs and scatters light, either filling the whole world
+// as fog or the inside of a shape. Coefficients are per unit of distance at density 1
+#[derive(PartialEq, Debug, Clone)]
+pub struct Medium {
+    pub absorption: Float,
+    pub scattering: Float,
+    // Tint of the scattered light
+    pub color: Color,
+    // Henyey-Greenstein g, negative scatters back, zero evenly, positive forward
+    pub asymmetry: Float,
+    pub density: Density
+}
+
+impl Medium {
+    pub fn new(absorption: Float, scattering: Float) -> Self {
+        Medium {
+            absorption,
+            scattering,
+            color: Color::white(),
+            asymmetry: 0.,
+            density: Density::Constant
+        }
+    }
+
+    pub fn new_heterogeneous(absorption: Float, scattering: Float, density: Density) -> Self {
+        Medium {
+            density,
+            ..Medium::new(absorption, scattering)
+        }
+    }
+
+    pub fn get_extinction(&self) -> Float {
+        self.absorption + self.scattering
+    }
+
+    // Chance that a collision with the medium scatters the light instead of absorbing it
+    pub fn get_albedo(&self) -> Float {
+        let extinction = self.get_extinction();
+
+        if extinction > 0. {
+            self.scattering / extinction
+        } else {
+            0.
+        }
+    }
+
+    pub fn get_max_density(&self) -> Float {
+        match &self.density {
+            Density::Constant | Density::Noise { .. } => 1.,
+            Density::Grid(grid) => grid.get_max()
+        }
+    }
+
+    // Density at a world point, to_local takes it into the space of the shape holding the medium
+    pub fn density_at(&self, point: &Tuple, to_local: Option<&MatrixType>) -> Float {
+        let local = match to_local {
+            Some(inverse) => Matrix::multiply_tuple(inverse, point),
+            None => *point
+        };
+
+        match &self.density {
+            Density::Constant => 1.,
+            Density::Grid(grid) => grid.sample(&local),
+            Density::Noise { frequency, octaves, seed } => fractal_noise(&(local * *frequency), *octaves, *seed)
+        }
+    }
+
+    // Distance along the ray to the next collision with the medium, None when the ray gets
+    // past t_max. Homogeneous media are sampled exactly, others with delta tracking.
+    // The ray direction is expected to be normalized
+    pub fn sample_distance(&self, ray: &Ray, t_max: Float, to_local: Option<&MatrixType>, random: &mut Random) -> Option<Float> {
+        let majorant = self.get_extinction() * self.get_max_density();
+
+        if majorant <= 0. {
+            return None
+        }
+
+        let (mut t, t_max) = self.density_range(ray, 0., t_max, to_local)?;
+
+        loop {
+            t -= (1. - random.next_float()).ln() / majorant;
+
+            if t >= t_max {
+                return None
+            }
+
+            if self.density == Density::Constant {
+                return Some(t)
+            }
+
+            // Real collision in proportion to the density, otherwise a null collision to step over
+            if random.next_float() * self.get_max_density() < self.density_at(&ray.position(t), to_local) {
+                return Some(t)
+            }
+        }
+    }
+
+    // Fraction of light that makes it through the medium between t_start and t_end along the ray,
+    // exact for homogeneous media and estimated with ratio tracking for others
+    pub fn transmittance(&self, ray: &Ray, t_start: Float, t_end: Float, to_local: Option<&MatrixType>, random: &mut Random) -> Float {
+        let extinction = self.get_extinction();
+        let max_density = self.get_max_density();
+        let majorant = extinction * max_density;
+
+        if majorant <= 0. {
+            return 1.
+        }
+
+        if self.density == Density::Constant {
+            return (-extinction * (t_end - t_start)).exp()
+        }
+
+        let (mut t, t_end) = match self.density_range(ray, t_start, t_end, to_local) {
+            Some(range) => range,
+            None => return 1.
+        };
+        // noise would have to be tracked forever, the path tracer ends fog at the scene's bounds
+        assert!(t_end.is_finite(), "heterogeneous media can only be tracked over a finite distance");
+
+        let mut transmittance = 1.;
+
+        loop {
+            t -= (1. - random.next_float()).ln() / majorant;
+
+            if t >= t_end {
+                return transmittance
+            }
+
+            transmittance *= 1. - self.density_at(&ray.position(t), to_local) / max_density;
+        }
+    }
+
+    // Stretch between t_start and t_end where the density can be above zero. Grids are zero
+    // outside their cube, None when the ray misses it
+    fn density_range(&self, ray: &Ray, t_start: Float, t_end: Float, to_local: Option<&MatrixType>) -> Option<(Float, Float)> {
+        if !matches!(self.density, Density::Grid(_)) {
+            return Some((t_start, t_end))
+        }
+
+        let local_ray = match to_local {
+            Some(inverse) => ray.transform(inverse),
+            None => *ray
+        };
+        let cube = BoundingBox::new_with_bounds(Tuple::new_point(-1., -1., -1.), Tuple::new_point(1., 1., 1.));
+        let (enter, leave) = cube.intersection_range(&local_ray)?;
+        let (start, end) = (t_start.max(enter), t_end.min(leave));
+
+        if start < end { Some((start, end)) } else { None }
+    }
+
+    pub fn phase(&self, direction: &Tuple, scattered: &Tuple) -> Float {
+        henyey_greenstein(direction.calculate_dot_product(scattered), self.asymmetry)
+    }
+
+    // New travel direction after scattering, picked with the phase function itself as the pdf
+    pub fn sample_phase(&self, direction: &Tuple, random: &mut Random) -> Tuple {
+        sample_henyey_greenstein(direction, self.asymmetry, random.next_float(), random.next_float())
+    }
+}
+
+// Phase function for the cosine between the travel direction and the scattered direction
+pub fn henyey_greenstein(cos_theta: Float, g: Float) -> Float {
+    let denominator = 1. + g * g - 2. * g * cos_theta;
+    (1. - g * g) / (4. * PI * denominator * denominator.sqrt())
+}
+
+pub fn sample_henyey_greenstein(direction: &Tuple, g: Float, u1: Float, u2: Float) -> Tuple {
+    let cos_theta = if g.abs() < 0.001 {
+        1. - 2. * u1
+    } else {
+        let square = (1. - g * g) / (1. - g + 2. * g * u1);
+        ((1. + g * g - square * square) / (2. * g)).clamp(-1., 1.)
+    };
+
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+    let local = Tuple::new_vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+
+    to_world(&local, direction).normalize()
+}
+
+// Sum of value noise octaves, each twice the frequency and half the weight of the last
+pub fn fractal_noise(point: &Tuple, octaves: usize, seed: u64) -> Float {
+    let mut sum = 0.;
+    let mut weight = 1.;
+    let mut total = 0.;
+    let mut scale = 1.;
+
+    for octave in 0..octaves.max(1) {
+        sum += weight * value_noise(&(*point * scale), seed.wrapping_add(octave as u64));
+        total += weight;
+        weight /= 2.;
+        scale *= 2.;
+    }
+
+    sum / total
+}
+
+// Random values on the integer lattice, smoothly interpolated in between
+pub fn value_noise(point: &Tuple, seed: u64) -> Float {
+    let (x0, y0, z0) = (point.x.floor(), point.y.floor(), point.z.floor());
+    let smooth = |t: Float| t * t * (3. - 2. * t);
+    let (fx, fy, fz) = (smooth(point.x - x0), smooth(point.y - y0), smooth(point.z - z0));
+
+    let corner = |dx: i64, dy: i64, dz: i64| lattice_value(x0 as i64 + dx, y0 as i64 + dy, z0 as i64 + dz, seed);
+    let lerp = |a: Float, b: Float, t: Float| a + (b - a) * t;
+    let row = |dy: i64, dz: i64| lerp(corner(0, dy, dz), corner(1, dy, dz), fx);
+    let slice = |dz: i64| lerp(row(0, dz), row(1, dz), fy);
+
+    lerp(slice(0), slice(1), fz)
+}
+
+fn lattice_value(x: i64, y: i64, z: i64, seed: u64) -> Float {
+    let mut hash = seed ^ 0x9e37_79b9_7f4a_7c15;
+
+    for value in [x, y, z] {
+        hash ^= value as u64;
+        hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash ^= hash >> 31;
+    }
+
+    (hash >> 11) as Float / (1u64 << 53) as Float
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::utils::NumberUtils;
+
+    fn average<F: FnMut(&mut Random) -> Float>(count: usize, mut f: F) -> Float {
+        let mut random = Random::new(7);
+        (0..count).map(|_| f(&mut random)).sum::<Float>() / count as Float
+    }
+
+    #[test]
+    fn test_isotropic_phase_function() {
+        assert_eq!(NumberUtils::compare_floats(henyey_greenstein(0.3, 0.), 1. / (4. * PI)), true);
+        assert_eq!(henyey_greenstein(1., 0.8) > henyey_greenstein(-1., 0.8), true);
+    }
+
+    #[test]
+    fn test_sampled_directions_follow_asymmetry() {
+        let direction = Tuple::new_vector(0., 0., 1.);
+        let mut forward = Medium::new(0., 1.);
+        forward.asymmetry = 0.8;
+        let mut backward = Medium::new(0., 1.);
+        backward.asymmetry = -0.8;
+
+        // the mean cosine of Henyey-Greenstein is g
+        let forward_cos = average(2000, |random| forward.sample_phase(&direction, random).z);
+        let backward_cos = average(2000, |random| backward.sample_phase(&direction, random).z);
+        assert_eq!((forward_cos - 0.8).abs() < 0.05, true);
+        assert_eq!((backward_cos + 0.8).abs() < 0.05, true);
+    }
+
+    #[test]
+    fn test_homogeneous_transmittance() {
+        let medium = Medium::new(0.5, 0.5);
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(1., 0., 0.));
+
+        let transmittance = medium.transmittance(&ray, 1., 3., None, &mut Random::new(0));
+        assert_eq!(NumberUtils::compare_floats(transmittance, (-2. as Float).exp()), true);
+        assert_eq!(medium.get_albedo(), 0.5);
+    }
+
+    #[test]
+    fn test_mean_free_path() {
+        let medium = Medium::new(0., 2.);
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(1., 0., 0.));
+
+        let mean = average(4000, |random| medium.sample_distance(&ray, Float::INFINITY, None, random).unwrap());
+        assert_eq!((mean - 0.5).abs() < 0.03, true);
+        assert_eq!(medium.sample_distance(&ray, 0., None, &mut Random::new(0)), None);
+    }
+
+    #[test]
+    fn test_density_grid_interpolates() {
+        let mut grid = DensityGrid::new(2, 2, 2);
+        grid.set_value(1, 1, 1, 1.);
+
+        assert_eq!(grid.sample(&Tuple::new_point(1., 1., 1.)), 1.);
+        assert_eq!(grid.sample(&Tuple::new_point(0., 0., 0.)), 0.125);
+        assert_eq!(grid.sample(&Tuple::new_point(1.5, 0., 0.)), 0.);
+        assert_eq!(grid.get_max(), 1.);
+    }
+
+    #[test]
+    fn test_heterogeneous_transmittance_matches_density() {
+        let mut grid = DensityGrid::new(2, 2, 2);
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    grid.set_value(x, y, z, 0.5);
+                }
+            }
+        }
+        let medium = Medium::new_heterogeneous(1., 1., Density::Grid(grid));
+        let ray = Ray::new(Tuple::new_point(-1., 0., 0.), Tuple::new_vector(1., 0., 0.));
+
+        // half density over a distance of 2 with extinction 2
+        let estimate = average(4000, |random| medium.transmittance(&ray, 0., 2., None, random));
+        assert_eq!((estimate - (-2. as Float).exp()).abs() < 0.02, true);
+    }
+
+    #[test]
+    fn test_grid_is_tracked_inside_its_cube_only() {
+        let mut grid = DensityGrid::new(1, 1, 1);
+        grid.set_value(0, 0, 0, 1.);
+        let medium = Medium::new_heterogeneous(1., 0., Density::Grid(grid));
+
+        // reaching to infinity, the light only has the cube to get through
+        let through = Ray::new(Tuple::new_point(-5., 0., 0.), Tuple::new_vector(1., 0., 0.));
+        let estimate = average(4000, |random| medium.transmittance(&through, 0., Float::INFINITY, None, random));
+        assert_eq!((estimate - (-2. as Float).exp()).abs() < 0.02, true);
+
+        let past = Ray::new(Tuple::new_point(-5., 2., 0.), Tuple::new_vector(1., 0., 0.));
+        assert_eq!(medium.transmittance(&past, 0., Float::INFINITY, None, &mut Random::new(0)), 1.);
+        assert_eq!(medium.sample_distance(&past, Float::INFINITY, None, &mut Random::new(0)), None);
+    }
+
+    #[test]
+    fn test_density_in_shape_space() {
+        let mut grid = DensityGrid::new(2, 1, 1);
+        grid.set_value(1, 0, 0, 1.);
+        let medium = Medium::new_heterogeneous(1., 0., Density::Grid(grid));
+        let inverse = Matrix::inverse(&Matrix::scaling(2., 2., 2.));
+
+        assert_eq!(medium.density_at(&Tuple::new_point(2., 0., 0.), Some(&inverse)), 1.);
+        assert_eq!(medium.density_at(&Tuple::new_point(2., 0., 0.), None), 0.);
+    }
+
+    #[test]
+    fn test_noise_is_deterministic_and_bounded() {
+        let point = Tuple::new_point(1.3, -2.7, 0.4);
+
+        assert_eq!(fractal_noise(&point, 4, 1), fractal_noise(&point, 4, 1));
+        assert_eq!(fractal_noise(&point, 4, 1) == fractal_noise(&point, 4, 2), false);
+        for i in 0..50 {
+            let value = fractal_noise(&Tuple::new_point(i as Float * 0.37, i as Float * 0.11, 0.5), 3, 0);
+            assert_eq!((0. ..=1.).contains(&value), true);
+        }
+    }
+}
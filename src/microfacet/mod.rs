@@ -0,0 +1,467 @@
+// ? INFO
+// Microfacet theory models a rough surface as lots of tiny perfect mirrors. GGX describes how
+// those mirrors are oriented, the Smith term how many of them hide each other and Fresnel how
+// much light each one reflects. Used by the path tracer, the Whitted shading keeps using Phong.
+
+use std::f64::consts::PI;
+
+use crate::color::Color;
+use crate::light::reflect;
+use crate::random::Random;
+use crate::sampling::{cosine_sample_hemisphere, to_world};
+use crate::tuples::{Tuple, Float};
+
+// Complex index of refraction, eta + i * k, one value per color channel
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color
+}
+
+impl ComplexIor {
+    pub fn new(eta: Color, k: Color) -> Self {
+        ComplexIor {
+            eta,
+            k
+        }
+    }
+}
+
+// Metal/roughness material, blends a conductor lobe (metallic) with a dielectric one.
+// The dielectric is either a coat over a diffuse base or, with transmission, a rough glass
+#[derive(PartialEq, Debug, Clone)]
+pub struct Microfacet {
+    // Diffuse color of dielectrics, tint of transmitted light and reflectance at normal
+    // incidence of metals without a complex index of refraction
+    pub base_color: Color,
+    pub metallic: Float,
+    // Perceptual roughness, the GGX alpha is its square
+    pub roughness: Float,
+    // Index of refraction of the dielectric part
+    pub ior: Float,
+    pub conductor: Option<ComplexIor>,
+    pub transmission: Float
+}
+
+// Direction picked by Microfacet::sample and the BSDF * cos / pdf weight that goes with it
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct BsdfSample {
+    pub direction: Tuple,
+    pub weight: Color
+}
+
+impl Microfacet {
+    pub fn new(base_color: Color, metallic: Float, roughness: Float) -> Self {
+        Microfacet {
+            base_color,
+            metallic,
+            roughness,
+            ior: 1.5,
+            conductor: None,
+            transmission: 0.
+        }
+    }
+
+    pub fn new_conductor(conductor: ComplexIor, roughness: Float) -> Self {
+        Microfacet {
+            conductor: Some(conductor),
+            ..Microfacet::new(Color::white(), 1., roughness)
+        }
+    }
+
+    pub fn new_transmissive(ior: Float, roughness: Float) -> Self {
+        Microfacet {
+            ior,
+            transmission: 1.,
+            ..Microfacet::new(Color::white(), 0., roughness)
+        }
+    }
+
+    // Presets, complex indices are sampled at roughly 650, 550 and 450 nm
+
+    pub fn gold() -> Self {
+        Microfacet::new_conductor(ComplexIor::new(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603)), 0.2)
+    }
+
+    pub fn copper() -> Self {
+        Microfacet::new_conductor(ComplexIor::new(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142)), 0.2)
+    }
+
+    pub fn aluminium() -> Self {
+        Microfacet::new_conductor(ComplexIor::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837)), 0.2)
+    }
+
+    pub fn glass() -> Self {
+        Microfacet::new_transmissive(1.5, 0.)
+    }
+
+    pub fn water() -> Self {
+        Microfacet::new_transmissive(1.333, 0.)
+    }
+
+    pub fn get_alpha(&self) -> Float {
+        // a perfectly smooth GGX is a delta, keep a tiny lobe to stay finite
+        (self.roughness * self.roughness).max(0.001)
+    }
+
+    // Indices of refraction on the eye side and on the far side of the surface
+    fn get_indices(&self, inside: bool) -> (Float, Float) {
+        if inside { (self.ior, 1.) } else { (1., self.ior) }
+    }
+
+    fn conductor_fresnel(&self, cos: Float) -> Color {
+        match &self.conductor {
+            Some(conductor) => fresnel_conductor(cos, conductor),
+            None => fresnel_schlick(cos, self.base_color)
+        }
+    }
+
+    // BSDF for light arriving along lightv and leaving along eyev, both point away from the
+    // surface and the normal faces the eye. Only reflection is evaluated, light from point
+    // sources reaching through a transmissive surface is not gathered.
+    pub fn evaluate(&self, eyev: &Tuple, lightv: &Tuple, normalv: &Tuple, inside: bool) -> Color {
+        let cos_i = eyev.calculate_dot_product(normalv);
+        let cos_o = lightv.calculate_dot_product(normalv);
+
+        if cos_i <= 0. || cos_o <= 0. {
+            return Color::black()
+        }
+
+        let alpha = self.get_alpha();
+        let half = (*eyev + *lightv).normalize();
+        let cos_h = eyev.calculate_dot_product(&half);
+        let specular = ggx_distribution(half.calculate_dot_product(normalv), alpha)
+            * smith_g1(cos_i, alpha) * smith_g1(cos_o, alpha) / (4. * cos_i * cos_o);
+
+        let (n1, n2) = self.get_indices(inside);
+        let metal = self.conductor_fresnel(cos_h) * specular as f32;
+        let coat = fresnel_dielectric(cos_h, n1, n2) * specular;
+        let diffuse = self.base_color * ((1. - fresnel_dielectric(cos_i, n1, n2)) / PI) as f32;
+
+        let dielectric = Color::new(1., 1., 1.) * coat as f32 + diffuse * (1. - self.transmission) as f32;
+        metal * self.metallic as f32 + dielectric * (1. - self.metallic) as f32
+    }
+
+    // Importance samples one lobe, None when the path should end (direction below the surface)
+    pub fn sample(&self, eyev: &Tuple, normalv: &Tuple, inside: bool, random: &mut Random) -> Option<BsdfSample> {
+        let cos_i = eyev.calculate_dot_product(normalv);
+
+        if cos_i <= 0. {
+            return None
+        }
+
+        let alpha = self.get_alpha();
+        let (n1, n2) = self.get_indices(inside);
+        let lobe = random.next_float();
+        let m = sample_ggx_normal(normalv, alpha, random.next_float(), random.next_float());
+        let cos_m = eyev.calculate_dot_product(&m);
+
+        if cos_m <= 0. {
+            return None
+        }
+
+        // G * (i.m) / ((i.n) (m.n)) is what is left of BSDF * cos / pdf for sampled normals
+        let microfacet_weight = |direction: &Tuple| {
+            let cos_o = direction.calculate_dot_product(normalv).abs();
+            smith_g1(cos_i, alpha) * smith_g1(cos_o, alpha) * cos_m / (cos_i * m.calculate_dot_product(normalv))
+        };
+
+        if lobe < self.metallic {
+            let direction = reflect(&eyev.negate(), &m);
+            if direction.calculate_dot_product(normalv) <= 0. {
+                return None
+            }
+
+            let weight = self.conductor_fresnel(cos_m) * microfacet_weight(&direction) as f32;
+            return Some(BsdfSample { direction, weight })
+        }
+
+        let fresnel = fresnel_dielectric(cos_m, n1, n2);
+        let transmissive = random.next_float() < self.transmission;
+
+        if transmissive {
+            let refracted = refract(eyev, &m, n1 / n2);
+
+            return match refracted {
+                Some(direction) if random.next_float() >= fresnel => {
+                    if direction.calculate_dot_product(normalv) >= 0. {
+                        return None
+                    }
+                    Some(BsdfSample { direction, weight: self.base_color * microfacet_weight(&direction) as f32 })
+                },
+                _ => {
+                    let direction = reflect(&eyev.negate(), &m);
+                    if direction.calculate_dot_product(normalv) <= 0. {
+                        return None
+                    }
+                    Some(BsdfSample { direction, weight: Color::white() * microfacet_weight(&direction) as f32 })
+                }
+            }
+        }
+
+        let coat_fresnel = fresnel_dielectric(cos_i, n1, n2);
+        let coat_probability = coat_fresnel.clamp(0.1, 0.9);
+
+        if random.next_float() < coat_probability {
+            let direction = reflect(&eyev.negate(), &m);
+            if direction.calculate_dot_product(normalv) <= 0. {
+                return None
+            }
+
+            let weight = fresnel * microfacet_weight(&direction) / coat_probability;
+            return Some(BsdfSample { direction, weight: Color::white() * weight as f32 })
+        }
+
+        let direction = cosine_sample_hemisphere(normalv, random.next_float(), random.next_float());
+        let weight = self.base_color * ((1. - coat_fresnel) / (1. - coat_probability)) as f32;
+        Some(BsdfSample { direction, weight })
+    }
+}
+
+// GGX (Trowbridge-Reitz) normal distribution, cos is between the microfacet and the normal
+pub fn ggx_distribution(cos: Float, alpha: Float) -> Float {
+    if cos <= 0. {
+        return 0.
+    }
+
+    let alpha2 = alpha * alpha;
+    let denominator = cos * cos * (alpha2 - 1.) + 1.;
+    alpha2 / (PI * denominator * denominator)
+}
+
+// Smith masking for one direction, cos is between the direction and the normal
+pub fn smith_g1(cos: Float, alpha: Float) -> Float {
+    let cos2 = (cos * cos).max(1e-12);
+    let tan2 = (1. - cos2) / cos2;
+    2. / (1. + (1. + alpha * alpha * tan2).sqrt())
+}
+
+// Microfacet normal distributed as D(m) * (m.n) around the normal
+pub fn sample_ggx_normal(normal: &Tuple, alpha: Float, u1: Float, u2: Float) -> Tuple {
+    let theta = (alpha * (u1 / (1. - u1)).sqrt()).atan();
+    let phi = 2. * PI * u2;
+    let local = Tuple::new_vector(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+
+    to_world(&local, normal).normalize()
+}
+
+// Exact Fresnel reflectance of unpolarized light at a boundary between two dielectrics
+pub fn fresnel_dielectric(cos_i: Float, n1: Float, n2: Float) -> Float {
+    let cos_i = cos_i.clamp(0., 1.);
+    let sin2_t = (n1 / n2).powi(2) * (1. - cos_i * cos_i);
+
+    if sin2_t >= 1. {
+        return 1.
+    }
+
+    let cos_t = (1. - sin2_t).sqrt();
+    let parallel = (n2 * cos_i - n1 * cos_t) / (n2 * cos_i + n1 * cos_t);
+    let perpendicular = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+
+    (parallel * parallel + perpendicular * perpendicular) / 2.
+}
+
+// Fresnel reflectance of a conductor, evaluated per channel from the complex index
+pub fn fresnel_conductor(cos_i: Float, ior: &ComplexIor) -> Color {
+    let channel = |eta: f32, k: f32| -> f32 {
+        let (eta, k) = (eta as Float, k as Float);
+        let cos2 = cos_i.clamp(0., 1.).powi(2);
+        let sin2 = 1. - cos2;
+
+        let t0 = eta * eta - k * k - sin2;
+        let a2b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+        let t1 = a2b2 + cos2;
+        let a = (0.5 * (a2b2 + t0)).max(0.).sqrt();
+        let t2 = 2. * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        ((rs + rp) / 2.) as f32
+    };
+
+    Color::new(
+        channel(ior.eta.red, ior.k.red),
+        channel(ior.eta.green, ior.k.green),
+        channel(ior.eta.blue, ior.k.blue)
+    )
+}
+
+pub fn fresnel_schlick(cos: Float, f0: Color) -> Color {
+    let factor = (1. - cos.clamp(0., 1.)).powi(5) as f32;
+    f0 + (Color::white() - f0) * factor
+}
+
+// Refracts a direction pointing away from the surface (towards the eye) through the normal,
+// n_ratio is n1 / n2. None on total internal reflection
+pub fn refract(eyev: &Tuple, normal: &Tuple, n_ratio: Float) -> Option<Tuple> {
+    let cos_i = eyev.calculate_dot_product(normal);
+    let sin2_t = n_ratio * n_ratio * (1. - cos_i * cos_i);
+
+    if sin2_t > 1. {
+        return None
+    }
+
+    let cos_t = (1. - sin2_t).sqrt();
+    Some((*normal * (n_ratio * cos_i - cos_t) - *eyev * n_ratio).normalize())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::utils::NumberUtils;
+
+    fn up() -> Tuple {
+        Tuple::new_vector(0., 1., 0.)
+    }
+
+    #[test]
+    fn test_ggx_distribution_is_normalized() {
+        // integral of D(m) (m.n) over the hemisphere is 1, sampled uniformly with pdf 1 / (2 PI)
+        let mut random = Random::new(11);
+        let samples = 20000;
+        let mut sum = 0.;
+
+        for _ in 0..samples {
+            let cos = random.next_float();
+            sum += ggx_distribution(cos, 0.5) * cos * 2. * PI;
+        }
+
+        assert_eq!(f64::abs(sum / samples as Float - 1.) < 0.05, true);
+    }
+
+    #[test]
+    fn test_smith_g1() {
+        assert_eq!(NumberUtils::compare_floats(smith_g1(1., 0.5), 1.), true);
+        assert_eq!(smith_g1(0.1, 0.5) < smith_g1(0.5, 0.5), true);
+        assert_eq!(smith_g1(0.5, 0.8) < smith_g1(0.5, 0.2), true);
+    }
+
+    #[test]
+    fn test_fresnel_dielectric() {
+        assert_eq!(NumberUtils::compare_floats(fresnel_dielectric(1., 1., 1.5), 0.04), true);
+        assert_eq!(NumberUtils::compare_floats(fresnel_dielectric(0., 1., 1.5), 1.), true);
+        // total internal reflection leaving glass at a grazing angle
+        assert_eq!(fresnel_dielectric(0.2, 1.5, 1.), 1.);
+    }
+
+    #[test]
+    fn test_conductor_without_absorption_matches_dielectric() {
+        let ior = ComplexIor::new(Color::new(1.5, 1.5, 1.5), Color::black());
+
+        for cos in [1., 0.7, 0.3] {
+            let conductor = fresnel_conductor(cos, &ior);
+            let dielectric = fresnel_dielectric(cos, 1., 1.5) as f32;
+            assert_eq!(f32::abs(conductor.red - dielectric) < 0.0001, true);
+        }
+    }
+
+    #[test]
+    fn test_gold_reflects_more_red_than_blue() {
+        let gold = Microfacet::gold();
+        let fresnel = fresnel_conductor(1., gold.conductor.as_ref().unwrap());
+
+        assert_eq!(fresnel.red > 0.9, true);
+        assert_eq!(fresnel.red > fresnel.blue, true);
+    }
+
+    #[test]
+    fn test_fresnel_schlick() {
+        let f0 = Color::new(0.5, 0.25, 0.);
+
+        assert_eq!(fresnel_schlick(1., f0), f0);
+        assert_eq!(fresnel_schlick(0., f0), Color::white());
+    }
+
+    #[test]
+    fn test_sampled_normals_stay_in_hemisphere() {
+        let mut random = Random::new(1);
+
+        for _ in 0..500 {
+            let m = sample_ggx_normal(&up(), 0.3, random.next_float(), random.next_float());
+            assert_eq!(m.calculate_dot_product(&up()) > 0., true);
+            assert_eq!(NumberUtils::compare_floats(m.get_magnitude(), 1.), true);
+        }
+    }
+
+    #[test]
+    fn test_smooth_metal_samples_mirror_direction() {
+        let metal = Microfacet::new(Color::white(), 1., 0.);
+        let eyev = Tuple::new_vector(1., 1., 0.).normalize();
+        let sample = metal.sample(&eyev, &up(), false, &mut Random::new(4)).unwrap();
+
+        assert_eq!(f64::abs(sample.direction.x + eyev.x) < 0.01, true);
+        assert_eq!(f64::abs(sample.direction.y - eyev.y) < 0.01, true);
+        assert_eq!(f32::abs(sample.weight.red - 1.) < 0.01, true);
+    }
+
+    #[test]
+    fn test_rough_metal_does_not_create_energy() {
+        let metal = Microfacet::new(Color::white(), 1., 0.5);
+        let eyev = Tuple::new_vector(0.3, 1., 0.).normalize();
+        let mut random = Random::new(8);
+        let samples = 5000;
+        let mut sum = 0.;
+
+        for _ in 0..samples {
+            if let Some(sample) = metal.sample(&eyev, &up(), false, &mut random) {
+                sum += sample.weight.red;
+            }
+        }
+
+        let albedo = sum / samples as f32;
+        assert_eq!(albedo <= 1.01 && albedo > 0.8, true);
+    }
+
+    #[test]
+    fn test_glass_transmits_most_light_at_normal_incidence() {
+        let glass = Microfacet::glass();
+        let mut random = Random::new(2);
+        let mut transmitted = 0;
+
+        for _ in 0..1000 {
+            let sample = glass.sample(&up(), &up(), false, &mut random).unwrap();
+            if sample.direction.y < 0. {
+                transmitted += 1;
+            }
+        }
+
+        // 4% reflection at normal incidence
+        assert_eq!(transmitted > 930 && transmitted < 990, true);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let plastic = Microfacet::new(Color::new(1., 0.5, 0.), 0., 0.5);
+        let eyev = up();
+
+        let below = plastic.evaluate(&eyev, &Tuple::new_vector(0., -1., 0.), &up(), false);
+        assert_eq!(below, Color::black());
+
+        // mostly the diffuse base, (1 - 0.04) / PI plus a bit of coat
+        let color = plastic.evaluate(&eyev, &Tuple::new_vector(1., 1., 0.).normalize(), &up(), false);
+        assert_eq!(color.red > 0.3 && color.red < 0.4, true);
+        assert_eq!(color.blue > 0. && color.blue < 0.05, true);
+    }
+
+    #[test]
+    fn test_refract() {
+        let eyev = Tuple::new_vector(1., 1., 0.).normalize();
+
+        let straight = refract(&eyev, &up(), 1.).unwrap();
+        assert_eq!(straight.is_equal_to(&eyev.negate()), true);
+
+        assert_eq!(refract(&Tuple::new_vector(1., 0.1, 0.).normalize(), &up(), 1.5), None);
+    }
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(Microfacet::copper().metallic, 1.);
+        assert_eq!(Microfacet::aluminium().conductor.is_some(), true);
+        assert_eq!(Microfacet::water().ior, 1.333);
+        assert_eq!(Microfacet::glass().transmission, 1.);
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::io::Result;
+
+use crate::shapes::Shape;
+use crate::tuples::{Tuple, Float};
+
+// Writes shapes as OBJ text, tessellated with their transforms baked in and one object per
+// shape. Smooth triangles keep their normals and texture coordinates are written when
+// present. Shapes without a tessellation, like planes, are left out
+pub fn export_obj(shapes: &[Box<dyn Shape>], segments: usize) -> String {
+    let mut obj = String::new();
+    let (mut vertex_count, mut normal_count, mut texture_count) = (0, 0, 0);
+
+    for (index, shape) in shapes.iter().enumerate() {
+        let triangles = shape.tessellate(segments);
+
+        if triangles.is_empty() {
+            continue;
+        }
+
+        obj += &format!("o object_{}\n", index + 1);
+
+        for triangle in &triangles {
+            for point in triangle.get_points() {
+                obj += &format!("v {}\n", format_tuple(point));
+            }
+
+            let normals = triangle.get_vertex_normals().map(|normals| {
+                for normal in normals {
+                    obj += &format!("vn {}\n", format_tuple(normal));
+                }
+                normal_count += 3;
+                normal_count - 2
+            });
+
+            let textures = triangle.get_texture_coordinates().map(|coordinates| {
+                for (u, v) in coordinates {
+                    obj += &format!("vt {} {}\n", u, v);
+                }
+                texture_count += 3;
+                texture_count - 2
+            });
+
+            let corners: Vec<String> = (0..3).map(|i| {
+                let vertex = vertex_count + 1 + i;
+                match (textures, normals) {
+                    (Some(texture), Some(normal)) => format!("{}/{}/{}", vertex, texture + i, normal + i),
+                    (Some(texture), None) => format!("{}/{}", vertex, texture + i),
+                    (None, Some(normal)) => format!("{}//{}", vertex, normal + i),
+                    (None, None) => vertex.to_string()
+                }
+            }).collect();
+
+            obj += &format!("f {}\n", corners.join(" "));
+            vertex_count += 3;
+        }
+    }
+
+    obj
+}
+
+pub fn save_obj(path: &str, shapes: &[Box<dyn Shape>], segments: usize) -> Result<()> {
+    fs::write(path, export_obj(shapes, segments))
+}
+
+fn format_tuple(tuple: &Tuple) -> String {
+    let clean = |value: Float| if value == 0. { 0. } else { value };
+    format!("{} {} {}", clean(tuple.x), clean(tuple.y), clean(tuple.z))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::obj::ObjFile;
+    use crate::ray::Ray;
+    use crate::shapes::{Sphere, Plane, Triangle};
+
+    #[test]
+    fn test_export_bakes_transforms() {
+        let mut triangle = Triangle::new(Tuple::new_point(0., 1., 0.), Tuple::new_point(-1., 0., 0.), Tuple::new_point(1., 0., 0.));
+        triangle.set_transform(Matrix::translation(0., 0., 5.));
+        let shapes: Vec<Box<dyn Shape>> = vec![Box::new(Plane::new()), Box::new(triangle)];
+
+        assert_eq!(export_obj(&shapes, 8), "o object_2\nv 0 1 5\nv -1 0 5\nv 1 0 5\nf 1 2 3\n");
+    }
+
+    #[test]
+    fn test_exported_sphere_loads_back() {
+        let mut sphere = Sphere::new();
+        sphere.set_transform(Matrix::multiply(&Matrix::translation(3., 0., 0.), &Matrix::scaling(2., 2., 2.)));
+        let shapes: Vec<Box<dyn Shape>> = vec![Box::new(sphere)];
+
+        let file = ObjFile::parse(&export_obj(&shapes, 16)).unwrap();
+
+        // 16 slices, 8 rings, the rings at the poles have one triangle per slice
+        assert_eq!(file.get_triangle_count(), 16 * 2 * 7);
+        assert_eq!(file.vertices.iter().all(|v| ((*v - Tuple::new_point(3., 0., 0.)).get_magnitude() - 2.).abs() < 1e-9), true);
+
+        let group = file.to_group();
+        let ray = Ray::new(Tuple::new_point(3.13, 0.1, -10.), Tuple::new_vector(0., 0., 1.));
+        let xs = group.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!((xs[0].t - 8.).abs() < 0.1, true);
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::material::Material;
+use crate::shapes::{Shape, Group, Mesh, Triangle};
+use crate::tuples::{Tuple, Float};
+
+pub mod mtl;
+pub mod export;
+
+pub use mtl::MtlFile;
+pub use export::{export_obj, save_obj};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    // Line numbers start at 1
+    Parse { line: usize, message: String }
+}
+
+impl ObjError {
+    pub fn parse(line: usize, message: &str) -> Self {
+        ObjError::Parse { line, message: message.to_string() }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(error) => write!(f, "{}", error),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<std::io::Error> for ObjError {
+    fn from(error: std::io::Error) -> Self {
+        ObjError::Io(error)
+    }
+}
+
+// Corner of a face, indices are zero based into the file's lists
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct ObjVertex {
+    pub position: usize,
+    pub texture: Option<usize>,
+    pub normal: Option<usize>
+}
+
+// Faces are stored as triangles, polygons are fanned out from their first corner
+#[derive(PartialEq, Debug, Clone)]
+pub struct ObjTriangle {
+    pub vertices: [ObjVertex; 3],
+    pub material: Option<String>
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ObjGroup {
+    pub name: String,
+    pub triangles: Vec<ObjTriangle>
+}
+
+// Wavefront OBJ geometry. Faces before any g or o statement land in the default group,
+// which comes first and has an empty name
+#[derive(PartialEq, Debug, Clone)]
+pub struct ObjFile {
+    pub vertices: Vec<Tuple>,
+    pub normals: Vec<Tuple>,
+    pub texture_coordinates: Vec<(Float, Float)>,
+    pub groups: Vec<ObjGroup>,
+    pub material_libraries: Vec<String>,
+    // Statements the parser does not understand, like smoothing groups or curves
+    pub ignored_lines: usize
+}
+
+impl ObjFile {
+    pub fn load(path: &str) -> Result<ObjFile, ObjError> {
+        ObjFile::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> Result<ObjFile, ObjError> {
+        let mut file = ObjFile {
+            vertices: vec![],
+            normals: vec![],
+            texture_coordinates: vec![],
+            groups: vec![ObjGroup { name: String::new(), triangles: vec![] }],
+            material_libraries: vec![],
+            ignored_lines: 0
+        };
+        let mut group = 0;
+        let mut material: Option<String> = None;
+
+        for (index, line) in content.lines().enumerate() {
+            let number = index + 1;
+            let mut parts = line.split_whitespace();
+
+            let keyword = match parts.next() {
+                Some(keyword) if !keyword.starts_with('#') => keyword,
+                _ => continue
+            };
+            let arguments: Vec<&str> = parts.collect();
+
+            match keyword {
+                "v" => {
+                    let values = parse_floats(&arguments, 3, 4, number)?;
+                    let w = values.get(3).copied().unwrap_or(1.);
+                    if w == 0. {
+                        return Err(ObjError::parse(number, "vertex weight of zero"))
+                    }
+                    file.vertices.push(Tuple::new_point(values[0] / w, values[1] / w, values[2] / w));
+                },
+                "vn" => {
+                    let values = parse_floats(&arguments, 3, 3, number)?;
+                    file.normals.push(Tuple::new_vector(values[0], values[1], values[2]));
+                },
+                "vt" => {
+                    let values = parse_floats(&arguments, 1, 3, number)?;
+                    file.texture_coordinates.push((values[0], values.get(1).copied().unwrap_or(0.)));
+                },
+                "f" => {
+                    if arguments.len() < 3 {
+                        return Err(ObjError::parse(number, "face needs at least three vertices"))
+                    }
+
+                    let corners = arguments.iter()
+                        .map(|corner| file.parse_vertex(corner, number))
+                        .collect::<Result<Vec<ObjVertex>, ObjError>>()?;
+
+                    for i in 1..corners.len() - 1 {
+                        file.groups[group].triangles.push(ObjTriangle {
+                            vertices: [corners[0], corners[i], corners[i + 1]],
+                            material: material.clone()
+                        });
+                    }
+                },
+                "g" | "o" => {
+                    let name = arguments.join(" ");
+                    group = match file.groups.iter().position(|existing| existing.name == name) {
+                        Some(existing) => existing,
+                        None => {
+                            file.groups.push(ObjGroup { name, triangles: vec![] });
+                            file.groups.len() - 1
+                        }
+                    };
+                },
+                "usemtl" => {
+                    if arguments.is_empty() {
+                        return Err(ObjError::parse(number, "usemtl needs a material name"))
+                    }
+                    material = Some(arguments.join(" "));
+                },
+                "mtllib" => file.material_libraries.extend(arguments.iter().map(|name| name.to_string())),
+                _ => file.ignored_lines += 1
+            }
+        }
+
+        Ok(file)
+    }
+
+    // Index forms are v, v/vt, v//vn and v/vt/vn, negative indices count back from the end
+    fn parse_vertex(&self, corner: &str, line: usize) -> Result<ObjVertex, ObjError> {
+        let indices: Vec<&str> = corner.split('/').collect();
+
+        if indices.len() > 3 {
+            return Err(ObjError::parse(line, &format!("invalid face vertex {}", corner)))
+        }
+
+        let position = resolve_index(indices[0], self.vertices.len(), "vertex", line)?;
+        let texture = match indices.get(1) {
+            Some(index) if !index.is_empty() => Some(resolve_index(index, self.texture_coordinates.len(), "texture coordinate", line)?),
+            _ => None
+        };
+        let normal = match indices.get(2) {
+            Some(index) if !index.is_empty() => Some(resolve_index(index, self.normals.len(), "normal", line)?),
+            _ => None
+        };
+
+        Ok(ObjVertex { position, texture, normal })
+    }
+
+    pub fn get_triangle_count(&self) -> usize {
+        self.groups.iter().map(|group| group.triangles.len()).sum()
+    }
+
+    // Materials from every mtllib statement, the library names are relative to directory
+    pub fn load_materials(&self, directory: &Path) -> Result<HashMap<String, Material>, ObjError> {
+        let mut materials = HashMap::new();
+
+        for library in &self.material_libraries {
+            let path = directory.join(library);
+            materials.extend(MtlFile::parse(&fs::read_to_string(path)?)?.materials);
+        }
+
+        Ok(materials)
+    }
+
+    pub fn to_group(&self) -> Group {
+        self.to_group_with_materials(&HashMap::new())
+    }
+
+    // Triangles of the default group go straight into the result, named groups become
+    // child groups. Triangles with normals at every corner are smooth
+    pub fn to_group_with_materials(&self, materials: &HashMap<String, Material>) -> Group {
+        let mut root = Group::new();
+
+        for obj_group in &self.groups {
+            let mut triangles: Vec<Box<dyn Shape>> = obj_group.triangles.iter()
+                .map(|triangle| Box::new(self.build_triangle(triangle, materials)) as Box<dyn Shape>)
+                .collect();
+
+            if obj_group.name.is_empty() {
+                triangles.drain(..).for_each(|triangle| root.add_child(triangle));
+            } else if !triangles.is_empty() {
+                let mut group = Group::new_named(&obj_group.name);
+                triangles.drain(..).for_each(|triangle| group.add_child(triangle));
+                root.add_child(Box::new(group));
+            }
+        }
+
+        root
+    }
+
+    // Every group in one indexed mesh, materials are dropped. Each distinct combination of
+    // position, texture and normal index becomes one mesh vertex, normals and texture
+    // coordinates are kept only when every corner has them
+    pub fn to_mesh(&self) -> Mesh {
+        let corners = || self.groups.iter().flat_map(|group| group.triangles.iter()).flat_map(|triangle| triangle.vertices.iter());
+        let smooth = corners().all(|corner| corner.normal.is_some());
+        let textured = corners().all(|corner| corner.texture.is_some());
+
+        let mut indices: HashMap<(usize, Option<usize>, Option<usize>), usize> = HashMap::new();
+        let (mut positions, mut normals, mut texture_coordinates) = (vec![], vec![], vec![]);
+
+        let triangles = self.groups.iter().flat_map(|group| group.triangles.iter()).map(|triangle| triangle.vertices.map(|corner| {
+            let key = (corner.position, corner.texture.filter(|_| textured), corner.normal.filter(|_| smooth));
+
+            *indices.entry(key).or_insert_with(|| {
+                positions.push(self.vertices[corner.position]);
+                if let Some(normal) = key.2 {
+                    normals.push(self.normals[normal]);
+                }
+                if let Some(texture) = key.1 {
+                    texture_coordinates.push(self.texture_coordinates[texture]);
+                }
+                positions.len() - 1
+            })
+        })).collect();
+
+        let mut mesh = Mesh::new(positions, triangles);
+        if smooth {
+            mesh.set_normals(normals);
+        }
+        if textured {
+            mesh.set_texture_coordinates(texture_coordinates);
+        }
+        mesh
+    }
+
+    fn build_triangle(&self, triangle: &ObjTriangle, materials: &HashMap<String, Material>) -> Triangle {
+        let [a, b, c] = triangle.vertices;
+        let points = (self.vertices[a.position], self.vertices[b.position], self.vertices[c.position]);
+
+        let mut shape = match (a.normal, b.normal, c.normal) {
+            (Some(n1), Some(n2), Some(n3)) => Triangle::new_smooth(points.0, points.1, points.2, self.normals[n1], self.normals[n2], self.normals[n3]),
+            _ => Triangle::new(points.0, points.1, points.2)
+        };
+
+        if let (Some(t1), Some(t2), Some(t3)) = (a.texture, b.texture, c.texture) {
+            shape.set_texture_coordinates([self.texture_coordinates[t1], self.texture_coordinates[t2], self.texture_coordinates[t3]]);
+        }
+
+        if let Some(material) = triangle.material.as_ref().and_then(|name| materials.get(name)) {
+            shape.set_material(material.clone());
+        }
+
+        shape
+    }
+}
+
+pub(crate) fn parse_floats(arguments: &[&str], min: usize, max: usize, line: usize) -> Result<Vec<Float>, ObjError> {
+    if arguments.len() < min || arguments.len() > max {
+        return Err(ObjError::parse(line, &format!("expected {} to {} numbers, found {}", min, max, arguments.len())))
+    }
+
+    arguments.iter()
+        .map(|value| value.parse::<Float>().map_err(|_| ObjError::parse(line, &format!("invalid number {}", value))))
+        .collect()
+}
+
+fn resolve_index(value: &str, count: usize, kind: &str, line: usize) -> Result<usize, ObjError> {
+    let index: i64 = value.parse().map_err(|_| ObjError::parse(line, &format!("invalid {} index {}", kind, value)))?;
+
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjError::parse(line, &format!("{} index {} out of range", kind, value)))
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ray::Ray;
+
+    fn parse_error_line(content: &str) -> usize {
+        match ObjFile::parse(content) {
+            Err(ObjError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_ignores_unrecognized_lines() {
+        let content = "There was a young lady named Bright\nwho traveled much faster than light.\n\n# a comment\ns 1\n";
+        let file = ObjFile::parse(content).unwrap();
+
+        assert_eq!(file.ignored_lines, 3);
+        assert_eq!(file.get_triangle_count(), 0);
+    }
+
+    #[test]
+    fn test_vertex_records() {
+        let file = ObjFile::parse("v -1 1 0\nv -1.0000 0.5000 0.0000\nv 1 0 0\nv 2 4 6 2\n").unwrap();
+
+        assert_eq!(file.vertices[0], Tuple::new_point(-1., 1., 0.));
+        assert_eq!(file.vertices[1], Tuple::new_point(-1., 0.5, 0.));
+        assert_eq!(file.vertices[3], Tuple::new_point(1., 2., 3.));
+    }
+
+    #[test]
+    fn test_faces_are_fanned_into_triangles() {
+        let content = "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\nv 0 2 0\n\nf 1 2 3 4 5\n";
+        let file = ObjFile::parse(content).unwrap();
+        let triangles = &file.groups[0].triangles;
+
+        assert_eq!(triangles.len(), 3);
+        assert_eq!(triangles[0].vertices.map(|v| v.position), [0, 1, 2]);
+        assert_eq!(triangles[1].vertices.map(|v| v.position), [0, 2, 3]);
+        assert_eq!(triangles[2].vertices.map(|v| v.position), [0, 3, 4]);
+    }
+
+    #[test]
+    fn test_named_groups() {
+        let content = "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\ng FirstGroup\nf 1 2 3\ng SecondGroup\nf 1 3 4\ng FirstGroup\nf 1 2 4\n";
+        let file = ObjFile::parse(content).unwrap();
+
+        assert_eq!(file.groups.len(), 3);
+        assert_eq!(file.groups[1].name, "FirstGroup");
+        assert_eq!(file.groups[1].triangles.len(), 2);
+        assert_eq!(file.groups[2].triangles.len(), 1);
+
+        let group = file.to_group();
+        assert_eq!(group.get_children().len(), 2);
+    }
+
+    #[test]
+    fn test_normals_texture_coordinates_and_negative_indices() {
+        let content = "v 0 1 0\nv -1 0 0\nv 1 0 0\nvn -1 0 0\nvn 1 2 3\nvt 0.5 1\nvt 0 0\nf 1//2 2//1 3//2\nf -3/-2/2 -2/-1/1 -1/1/2\n";
+        let file = ObjFile::parse(content).unwrap();
+        let [first, second] = [&file.groups[0].triangles[0], &file.groups[0].triangles[1]];
+
+        assert_eq!(file.normals[1], Tuple::new_vector(1., 2., 3.));
+        assert_eq!(first.vertices[0], ObjVertex { position: 0, texture: None, normal: Some(1) });
+        assert_eq!(second.vertices, [
+            ObjVertex { position: 0, texture: Some(0), normal: Some(1) },
+            ObjVertex { position: 1, texture: Some(1), normal: Some(0) },
+            ObjVertex { position: 2, texture: Some(0), normal: Some(1) },
+        ]);
+
+        let group = file.to_group();
+        let triangle = &group.get_children()[0];
+        let hit = crate::intersection::Intersection::new_with_uv(1., triangle.as_ref(), 1., 0.);
+        assert_eq!(triangle.normal_at_hit(&Tuple::new_point(0., 0., 0.), &hit, 0.), Tuple::new_vector(-1., 0., 0.));
+    }
+
+    #[test]
+    fn test_malformed_lines_report_their_number() {
+        assert_eq!(parse_error_line("v 1 2 3\nv 1 two 3\n"), 2);
+        assert_eq!(parse_error_line("v 1 2 3\n\n\nf 1 2\n"), 4);
+        assert_eq!(parse_error_line("v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1 2 4\n"), 4);
+        assert_eq!(parse_error_line("v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1 2 0\n"), 4);
+        assert_eq!(parse_error_line("vn 1 2\n"), 1);
+        assert_eq!(parse_error_line("v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1/1 2/1 3/1\n"), 4);
+        assert_eq!(format!("{}", ObjError::parse(7, "bad")), "line 7: bad");
+    }
+
+    #[test]
+    fn test_converts_to_shapes() {
+        let content = "v 0 1 0\nv -1 0 0\nv 1 0 0\nvn 0 0 -1\nusemtl red\nf 1//1 2//1 3//1\ng flat\nf 1 2 3\n";
+        let file = ObjFile::parse(content).unwrap();
+        let mut red = Material::new();
+        red.color = crate::color::Color::red();
+        let materials = HashMap::from([("red".to_string(), red.clone())]);
+
+        let group = file.to_group_with_materials(&materials);
+        assert_eq!(group.get_children().len(), 2);
+        assert_eq!(group.get_children()[0].get_material(), &red);
+
+        let ray = Ray::new(Tuple::new_point(0., 0.5, -2.), Tuple::new_vector(0., 0., 1.));
+        let xs = group.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 2.);
+    }
+
+    #[test]
+    fn test_converts_to_mesh() {
+        // the shared corner keeps one vertex, the corner used with two normals gets two
+        let content = "v 0 1 0\nv -1 0 0\nv 1 0 0\nv 0 -1 0\nvn 0 0 -1\nvn 0 1 0\nf 1//1 2//1 3//1\ng lower\nf 2//1 4//1 3//2\n";
+        let mesh = ObjFile::parse(content).unwrap().to_mesh();
+
+        assert_eq!(mesh.get_triangle_count(), 2);
+        assert_eq!(mesh.get_vertex_count(), 5);
+        assert_eq!(mesh.get_triangles()[1][0], 1);
+        assert_eq!(mesh.is_smooth(), true);
+        assert_eq!(mesh.get_texture_coordinates().len(), 0);
+    }
+}
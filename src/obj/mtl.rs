@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::color::Color;
+use crate::material::Material;
+use crate::tuples::Float;
+
+use super::{ObjError, parse_floats};
+
+// Wavefront MTL materials mapped onto the Phong material. Ka and Ks become the ambient and
+// specular strengths, the mean of their channels, d and Tr set the transparency and Ke the
+// emission. Texture maps and illumination models are counted as ignored
+#[derive(PartialEq, Debug, Clone)]
+pub struct MtlFile {
+    pub materials: HashMap<String, Material>,
+    pub ignored_lines: usize
+}
+
+impl MtlFile {
+    pub fn load(path: &str) -> Result<MtlFile, ObjError> {
+        MtlFile::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> Result<MtlFile, ObjError> {
+        let mut file = MtlFile {
+            materials: HashMap::new(),
+            ignored_lines: 0
+        };
+        let mut current: Option<(String, Material)> = None;
+
+        for (index, line) in content.lines().enumerate() {
+            let number = index + 1;
+            let mut parts = line.split_whitespace();
+
+            let keyword = match parts.next() {
+                Some(keyword) if !keyword.starts_with('#') => keyword,
+                _ => continue
+            };
+            let arguments: Vec<&str> = parts.collect();
+
+            if keyword == "newmtl" {
+                if arguments.is_empty() {
+                    return Err(ObjError::parse(number, "newmtl needs a material name"))
+                }
+
+                if let Some((name, material)) = current.take() {
+                    file.materials.insert(name, material);
+                }
+                current = Some((arguments.join(" "), Material::new()));
+                continue;
+            }
+
+            let known = ["Kd", "Ka", "Ks", "Ke", "Ns", "Ni", "d", "Tr"].contains(&keyword);
+            let material = match (&mut current, known) {
+                (_, false) => {
+                    file.ignored_lines += 1;
+                    continue;
+                },
+                (None, true) => return Err(ObjError::parse(number, &format!("{} before any newmtl", keyword))),
+                (Some((_, material)), true) => material
+            };
+
+            match keyword {
+                "Kd" => material.color = parse_color(&arguments, number)?,
+                "Ka" => material.ambient = channel_mean(parse_color(&arguments, number)?),
+                "Ks" => material.specular = channel_mean(parse_color(&arguments, number)?),
+                "Ke" => material.emissive = parse_color(&arguments, number)?,
+                "Ns" => material.shininess = parse_floats(&arguments, 1, 1, number)?[0],
+                "Ni" => material.refractive_index = parse_floats(&arguments, 1, 1, number)?[0],
+                "d" => material.transparency = 1. - parse_floats(&arguments, 1, 1, number)?[0],
+                _ => material.transparency = parse_floats(&arguments, 1, 1, number)?[0]
+            }
+        }
+
+        if let Some((name, material)) = current {
+            file.materials.insert(name, material);
+        }
+
+        Ok(file)
+    }
+}
+
+fn parse_color(arguments: &[&str], line: usize) -> Result<Color, ObjError> {
+    let values = parse_floats(arguments, 3, 3, line)?;
+    Ok(Color::new(values[0] as f32, values[1] as f32, values[2] as f32))
+}
+
+fn channel_mean(color: Color) -> Float {
+    ((color.red + color.green + color.blue) / 3.) as Float
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_materials() {
+        let content = "# exported\nnewmtl glass\nKd 0.1 0.2 0.3\nKs 0.9 0.9 0.9\nNs 300\nNi 1.5\nd 0.25\nillum 7\n\nnewmtl lamp\nKe 4 4 4\nmap_Kd lamp.png\n";
+        let file = MtlFile::parse(content).unwrap();
+        let glass = &file.materials["glass"];
+
+        assert_eq!(file.materials.len(), 2);
+        assert_eq!(glass.color, Color::new(0.1, 0.2, 0.3));
+        assert_eq!((glass.specular - 0.9).abs() < 1e-6, true);
+        assert_eq!(glass.shininess, 300.);
+        assert_eq!(glass.refractive_index, 1.5);
+        assert_eq!(glass.transparency, 0.75);
+        assert_eq!(file.materials["lamp"].is_emissive(), true);
+        assert_eq!(file.ignored_lines, 2);
+    }
+
+    #[test]
+    fn test_malformed_materials_report_their_line() {
+        let line = |content: &str| match MtlFile::parse(content) {
+            Err(ObjError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other)
+        };
+
+        assert_eq!(line("Kd 1 1 1\n"), 1);
+        assert_eq!(line("newmtl a\n\nKd 1 1\n"), 3);
+        assert_eq!(line("newmtl a\nNs shiny\n"), 2);
+        assert_eq!(line("newmtl\n"), 1);
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::shapes::{BezierPatch, Group, Mesh, Shape};
+use crate::tuples::{Tuple, Float};
+
+// Bicubic patches in the text format the Utah teapot is passed around in: the number of
+// patches, one line of 16 one-based vertex indices per patch, the number of vertices and one
+// line of x, y, z per vertex. Values are separated by commas or whitespace
+#[derive(PartialEq, Debug, Clone)]
+pub struct PatchFile {
+    pub vertices: Vec<Tuple>,
+    // Zero-based, rows of four along u
+    pub patches: Vec<[usize; 16]>
+}
+
+impl PatchFile {
+    pub fn load(path: &str) -> Result<PatchFile> {
+        let text = fs::read_to_string(path)?;
+        PatchFile::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<PatchFile> {
+        let mut lines = text.lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let patch_count = read_count(&mut lines, "patches")?;
+        let mut indices = Vec::with_capacity(patch_count);
+        for _ in 0..patch_count {
+            let (number, values) = read_values::<usize>(&mut lines, 16, "patch")?;
+            let mut patch = [0; 16];
+            for (slot, value) in patch.iter_mut().zip(values) {
+                if value == 0 {
+                    return Err(invalid(&format!("line {}: vertex indices start at 1", number)))
+                }
+                *slot = value - 1;
+            }
+            indices.push((number, patch));
+        }
+
+        let vertex_count = read_count(&mut lines, "vertices")?;
+        let mut vertices = Vec::with_capacity(vertex_count);
+        for _ in 0..vertex_count {
+            let (_, values) = read_values::<Float>(&mut lines, 3, "vertex")?;
+            vertices.push(Tuple::new_point(values[0], values[1], values[2]));
+        }
+
+        let mut patches = Vec::with_capacity(patch_count);
+        for (number, patch) in indices {
+            if patch.iter().any(|&index| index >= vertex_count) {
+                return Err(invalid(&format!("line {}: vertex index out of range", number)))
+            }
+            patches.push(patch);
+        }
+
+        Ok(PatchFile { vertices, patches })
+    }
+
+    pub fn to_patches(&self) -> Vec<BezierPatch> {
+        self.patches.iter()
+            .map(|patch| BezierPatch::new(patch.map(|index| self.vertices[index])))
+            .collect()
+    }
+
+    // Every patch as a shape of its own, intersected directly
+    pub fn to_group(&self) -> Group {
+        let mut group = Group::new();
+        for patch in self.to_patches() {
+            group.add_child(Box::new(patch));
+        }
+        group
+    }
+
+    // Each patch tessellated into segments x segments quads, welded where patches meet
+    pub fn to_mesh(&self, segments: usize) -> Mesh {
+        let triangles: Vec<_> = self.to_patches().iter()
+            .flat_map(|patch| patch.local_tessellate(segments))
+            .collect();
+        let mut mesh = Mesh::from_triangles(&triangles);
+        mesh.weld(1e-9);
+        mesh
+    }
+}
+
+fn read_count<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>, what: &str) -> Result<usize> {
+    let (number, line) = lines.next().ok_or_else(|| invalid(&format!("missing number of {}", what)))?;
+    line.trim_end_matches(',').parse::<usize>().map_err(|_| invalid(&format!("line {}: invalid number of {} {}", number, what, line)))
+}
+
+fn read_values<'a, T: std::str::FromStr>(lines: &mut impl Iterator<Item = (usize, &'a str)>, count: usize, what: &str) -> Result<(usize, Vec<T>)> {
+    let (number, line) = lines.next().ok_or_else(|| invalid(&format!("file ends before the last {}", what)))?;
+    let values = line.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse::<T>().map_err(|_| invalid(&format!("line {}: invalid number {}", number, value))))
+        .collect::<Result<Vec<T>>>()?;
+
+    if values.len() != count {
+        return Err(invalid(&format!("line {}: expected {} values for a {}, found {}", number, count, what, values.len())))
+    }
+    Ok((number, values))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ray::Ray;
+
+    // Two patches side by side over a 7 x 4 grid of vertices, sharing the column at x = 3
+    fn two_patches() -> String {
+        let index = |row: usize, column: usize| row * 7 + column + 1;
+        let patch = |first: usize| (0..16).map(|i| index(i / 4, first + i % 4).to_string()).collect::<Vec<String>>().join(", ");
+        let vertices = (0..28).map(|i| format!("{}, {}, 0.0", i % 7, i / 7)).collect::<Vec<String>>().join("\n");
+
+        format!("2\n{}\n{}\n28\n{}\n", patch(0), patch(3), vertices)
+    }
+
+    #[test]
+    fn test_parse_patches() {
+        let file = PatchFile::parse(&two_patches()).unwrap();
+
+        assert_eq!(file.vertices.len(), 28);
+        assert_eq!(file.patches.len(), 2);
+        assert_eq!(file.patches[1][0], 3);
+        assert_eq!(file.vertices[file.patches[1][15]], Tuple::new_point(6., 3., 0.));
+
+        let group = file.to_group();
+        let ray = Ray::new(Tuple::new_point(4.5, 1.5, -2.), Tuple::new_vector(0., 0., 1.));
+        let xs = group.intersect(&ray);
+        assert_eq!(xs.len(), 1);
+        assert_eq!((xs[0].t - 2.).abs() < 1e-9, true);
+    }
+
+    #[test]
+    fn test_patches_to_mesh() {
+        let mesh = PatchFile::parse(&two_patches()).unwrap().to_mesh(2);
+
+        // the shared border is welded, so the 2 x 4 grid of quads has 3 x 5 vertices
+        assert_eq!(mesh.get_triangle_count(), 16);
+        assert_eq!(mesh.get_vertex_count(), 15);
+    }
+
+    #[test]
+    fn test_invalid_patch_files() {
+        assert_eq!(PatchFile::parse("").is_err(), true);
+        assert_eq!(PatchFile::parse("1\n1,2,3\n").is_err(), true);
+        let out_of_range = format!("1\n{}\n1\n0, 0, 0\n", vec!["2"; 16].join(","));
+        assert_eq!(PatchFile::parse(&out_of_range).is_err(), true);
+        let zero = format!("1\n{}\n1\n0, 0, 0\n", vec!["0"; 16].join(","));
+        assert_eq!(PatchFile::parse(&zero).is_err(), true);
+    }
+}
@@ -0,0 +1,676 @@
+use std::borrow::Cow;
+use std::f64::consts::PI;
+
+use crate::adaptive::{AdaptiveSampler, AdaptiveRender};
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::intersection::{Computations, hit};
+use crate::matrix::MatrixType;
+use crate::medium::{Density, Medium};
+use crate::random::Random;
+use crate::ray::{Ray, RayKind};
+use crate::sampling::{Sampler, cosine_sample_hemisphere, power_heuristic};
+use crate::shapes::{LightLinks, Shape};
+use crate::tuples::{Tuple, Float};
+use crate::world::World;
+
+// Medium boundaries a path may pass through, a safeguard against rays caught between
+// boundaries that are too close to get past
+const MAX_CROSSINGS: usize = 256;
+
+// Medium a path is travelling through, with the shape it fills. The shape's light links
+// light the medium and its transform at the ray's time places it. The world's fog has no
+// shape, is looked up in world space and lit by every light
+type Volume<'a> = (&'a Medium, Option<&'a dyn Shape>);
+
+// Monte Carlo path tracer, an alternative to the Whitted-style World::color_at.
+// Diffuse bounces are cosine sampled, point lights are sampled directly at every
+// diffuse hit (next event estimation) and emissive surfaces add their light when
+// a path runs into them. Point lights fall off with the square of the distance.
+// Fog and media inside shapes are sampled with delta tracking, see Medium.
+#[derive(PartialEq, Debug, Clone)]
+pub struct PathTracer {
+    pub sampler: Sampler,
+    pub max_depth: usize,
+    // Bounces that always happen before Russian roulette may end a path
+    pub roulette_depth: usize
+}
+
+impl PathTracer {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        PathTracer {
+            sampler: Sampler::new(samples_per_pixel, seed),
+            max_depth: 16,
+            roulette_depth: 3
+        }
+    }
+
+    pub fn render(&self, camera: &Camera, world: &World) -> Canvas {
+        camera.render_sampled(&self.sampler, |ray, random| self.trace(world, ray, random))
+    }
+
+    // Uses the adaptive sampler instead of the fixed one, max_depth and roulette still apply
+    pub fn render_adaptive(&self, camera: &Camera, world: &World, adaptive: &AdaptiveSampler) -> AdaptiveRender {
+        camera.render_adaptive(adaptive, |ray, random| self.trace(world, ray, random))
+    }
+
+    // Radiance arriving along the ray
+    pub fn trace(&self, world: &World, ray: &Ray, random: &mut Random) -> Color {
+        let mut radiance = Color::black();
+        let mut throughput = Color::white();
+        let mut ray = *ray;
+        let fog = world.fog.as_ref().map(|fog| (fog, None));
+        let mut volume: Option<Volume> = fog;
+        // Pdf of the last diffuse bounce, which also sampled the environment directly.
+        // None after other bounces, their rays see the environment with full weight
+        let mut bsdf_pdf: Option<Float> = None;
+        // Passing through the boundary of a medium is not a bounce, so it has a budget of its own
+        let mut depth = 0;
+        let mut crossings = 0;
+
+        while depth < self.max_depth {
+            let xs = world.intersect(&ray);
+            let closest = hit(&xs);
+
+            if let Some((medium, shape)) = volume {
+                let t_max = fog_reach(world, &ray, medium, shape, closest.map_or(Float::INFINITY, |intersection| intersection.t));
+
+                if let Some(t) = medium.sample_distance(&ray, t_max, to_local(shape, ray.time).as_deref(), random) {
+                    // Collision inside the medium, the light is either absorbed or scattered
+                    if random.next_float() >= medium.get_albedo() {
+                        break;
+                    }
+
+                    let point = ray.position(t);
+                    throughput = throughput * medium.color;
+
+                    let phase = |lightv: &Tuple| Color::white() * medium.phase(&ray.direction, lightv) as f32;
+                    radiance = radiance + throughput * PathTracer::direct_lighting(world, &point, None, light_links(shape), ray.time, volume, fog, random, phase);
+
+                    ray = Ray::new_at_time(point, medium.sample_phase(&ray.direction, random), ray.time).with_kind(RayKind::Reflection);
+                    bsdf_pdf = None;
+
+                    if !self.survives_roulette(depth, &mut throughput, random) {
+                        break;
+                    }
+
+                    depth += 1;
+                    continue;
+                }
+            }
+
+            let intersection = match closest {
+                Some(intersection) => intersection,
+                None => {
+                    if let Some(environment) = &world.environment {
+                        let weight = bsdf_pdf.map_or(1., |pdf| power_heuristic(pdf, environment.pdf(&ray.direction)));
+                        radiance = radiance + throughput * environment.color_in(&ray.direction) * weight as f32;
+                    }
+                    break
+                }
+            };
+
+            let comps = intersection.prepare_computations(&ray, &xs);
+            let material = comps.object.get_material();
+
+            if let Some(medium) = &material.medium {
+                // The surface only bounds the medium, the ray carries on past it
+                volume = if comps.inside {
+                    fog
+                } else {
+                    Some((medium, Some(comps.object)))
+                };
+
+                ray = Ray::new_at_time(comps.under_point, ray.direction, comps.time).with_kind(ray.kind);
+                crossings += 1;
+                if crossings > MAX_CROSSINGS {
+                    break;
+                }
+                continue;
+            }
+
+            radiance = radiance + throughput * material.emissive;
+
+            if let Some(microfacet) = &material.microfacet {
+                let bsdf = |lightv: &Tuple| microfacet.evaluate(&comps.eyev, lightv, &comps.normalv, comps.inside);
+                radiance = radiance + throughput * PathTracer::direct_lighting(world, &comps.over_point, Some(&comps.normalv), &comps.object.get_properties().light_links, comps.time, volume, fog, random, bsdf);
+
+                let sample = match microfacet.sample(&comps.eyev, &comps.normalv, comps.inside, random) {
+                    Some(sample) => sample,
+                    None => break
+                };
+
+                let (origin, kind) = if sample.direction.calculate_dot_product(&comps.normalv) > 0. {
+                    (comps.over_point, RayKind::Reflection)
+                } else {
+                    (comps.under_point, RayKind::Refraction)
+                };
+
+                ray = Ray::new_at_time(origin, sample.direction, comps.time).with_kind(kind);
+                throughput = throughput * sample.weight;
+                bsdf_pdf = None;
+            } else {
+                // One lobe is picked in proportion to its weight, so no extra factor is needed
+                let lobe = random.next_float();
+                bsdf_pdf = None;
+
+                if lobe < material.transparency {
+                    let reflect = match comps.refract_direction() {
+                        Some(_) => random.next_float() < comps.schlick(),
+                        None => true
+                    };
+
+                    ray = match comps.refract_direction() {
+                        Some(direction) if !reflect => Ray::new_at_time(comps.under_point, direction, comps.time).with_kind(RayKind::Refraction),
+                        _ => Ray::new_at_time(comps.over_point, comps.reflectv, comps.time).with_kind(RayKind::Reflection)
+                    };
+                } else if lobe < material.transparency + material.reflective {
+                    ray = Ray::new_at_time(comps.over_point, comps.reflectv, comps.time).with_kind(RayKind::Reflection);
+                } else {
+                    let albedo = comps.color * material.diffuse as f32;
+                    let bsdf = |_: &Tuple| albedo / PI as f32;
+                    radiance = radiance + throughput * PathTracer::direct_lighting(world, &comps.over_point, Some(&comps.normalv), &comps.object.get_properties().light_links, comps.time, volume, fog, random, bsdf);
+                    radiance = radiance + throughput * PathTracer::environment_lighting(world, &comps, volume, fog, random, albedo / PI as f32);
+
+                    let direction = cosine_sample_hemisphere(&comps.normalv, random.next_float(), random.next_float());
+                    ray = Ray::new_at_time(comps.over_point, direction, comps.time).with_kind(RayKind::Reflection);
+                    throughput = throughput * albedo;
+                    bsdf_pdf = Some(direction.calculate_dot_product(&comps.normalv) / PI);
+                }
+            }
+
+            if !self.survives_roulette(depth, &mut throughput, random) {
+                break;
+            }
+            depth += 1;
+        }
+
+        radiance
+    }
+
+    // Russian roulette once past roulette_depth, survivors are weighted up to stay unbiased
+    fn survives_roulette(&self, depth: usize, throughput: &mut Color, random: &mut Random) -> bool {
+        if depth + 1 < self.roulette_depth {
+            return true
+        }
+
+        let survival = PathTracer::max_component(*throughput).min(0.95);
+
+        if survival <= 0. || random.next_float() >= survival {
+            return false
+        }
+
+        *throughput = *throughput / survival as f32;
+        true
+    }
+
+    // Next event estimation, light reaching the point straight from every point light.
+    // Surfaces pass their normal for the cosine term, scattering inside a medium does not
+    #[allow(clippy::too_many_arguments)]
+    fn direct_lighting<F: Fn(&Tuple) -> Color>(world: &World, point: &Tuple, normalv: Option<&Tuple>, light_links: &LightLinks, time: Float, volume: Option<Volume>, fog: Option<Volume>, random: &mut Random, scatter: F) -> Color {
+        let mut color = Color::black();
+
+        let linked = world.lights.iter().enumerate()
+            .filter(|(index, _)| light_links.is_lit_by(*index))
+            .map(|(_, light)| light);
+
+        for light in linked {
+            let to_light = light.position - *point;
+            let distance_squared = to_light.calculate_dot_product(&to_light);
+            let lightv = to_light.normalize();
+            let cos = normalv.map_or(1., |normalv| lightv.calculate_dot_product(normalv));
+
+            if cos <= 0. {
+                continue;
+            }
+
+            let ray = Ray::new_at_time(*point, lightv, time).with_kind(RayKind::Shadow);
+            let transmittance = PathTracer::transmittance(world, &ray, to_light.get_magnitude(), volume, fog, random);
+
+            if transmittance > 0. {
+                color = color + scatter(&lightv) * light.intensity * (cos * transmittance / distance_squared) as f32;
+            }
+        }
+
+        color
+    }
+
+    // Environment light at a diffuse hit, sampled from the environment and weighted
+    // against the cosine sampled bounce that may also find it
+    fn environment_lighting(world: &World, comps: &Computations, volume: Option<Volume>, fog: Option<Volume>, random: &mut Random, bsdf: Color) -> Color {
+        let environment = match &world.environment {
+            Some(environment) => environment,
+            None => return Color::black()
+        };
+
+        let (direction, pdf) = environment.sample(random.next_float(), random.next_float());
+        let cos = direction.calculate_dot_product(&comps.normalv);
+
+        if cos <= 0. || pdf <= 0. {
+            return Color::black()
+        }
+
+        let ray = Ray::new_at_time(comps.over_point, direction, comps.time).with_kind(RayKind::Shadow);
+        let transmittance = PathTracer::transmittance(world, &ray, Float::INFINITY, volume, fog, random);
+        let weight = power_heuristic(pdf, cos / PI);
+
+        environment.color_in(&direction) * bsdf * (transmittance * cos * weight / pdf) as f32
+    }
+
+    // Fraction of light making it distance along the ray, zero when an opaque surface is in
+    // the way. Medium boundaries on the way switch between their medium and the fog
+    fn transmittance<'a>(world: &'a World, ray: &Ray, distance: Float, mut volume: Option<Volume<'a>>, fog: Option<Volume<'a>>, random: &mut Random) -> Float {
+        let time = ray.time;
+        let xs = world.intersect(ray);
+
+        let mut transmittance = 1.;
+        let mut start = 0.;
+
+        for intersection in xs.iter().filter(|intersection| intersection.t > 0. && intersection.t < distance) {
+            let medium = match &intersection.object.get_material().medium {
+                Some(medium) => medium,
+                None => return 0.
+            };
+
+            if let Some((current, shape)) = volume {
+                transmittance *= current.transmittance(ray, start, intersection.t, to_local(shape, time).as_deref(), random);
+            }
+
+            let normalv = intersection.object.normal_at_hit(&ray.position(intersection.t), intersection, time);
+            volume = if normalv.calculate_dot_product(&ray.direction) < 0. {
+                Some((medium, Some(intersection.object)))
+            } else {
+                fog
+            };
+            start = intersection.t;
+        }
+
+        if let Some((current, shape)) = volume {
+            let end = fog_reach(world, ray, current, shape, distance);
+            transmittance *= current.transmittance(ray, start, end, to_local(shape, time).as_deref(), random);
+        }
+
+        transmittance
+    }
+
+    fn max_component(color: Color) -> Float {
+        color.red.max(color.green).max(color.blue) as Float
+    }
+}
+
+// World-to-local matrix of the shape holding a medium, where the shape is at time
+fn to_local(shape: Option<&dyn Shape>, time: Float) -> Option<Cow<'_, MatrixType>> {
+    shape.map(|shape| shape.get_properties().get_inverse_at(time))
+}
+
+// Heterogeneous fog is taken to end where the scene does, so rays leaving the scene are not
+// tracked through it forever and the environment's light still gets in
+fn fog_reach(world: &World, ray: &Ray, medium: &Medium, shape: Option<&dyn Shape>, t_max: Float) -> Float {
+    if shape.is_some() || medium.density == Density::Constant || t_max.is_finite() {
+        return t_max
+    }
+
+    world.get_bounds().intersection_range(ray).map_or(0., |(_, leave)| leave.max(0.))
+}
+
+// Lights reaching into a medium, every light for the fog
+fn light_links(shape: Option<&dyn Shape>) -> &LightLinks {
+    shape.map_or(&LightLinks::All, |shape| &shape.get_properties().light_links)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::animation::AnimatedTransform;
+    use crate::camera::projection::equirectangular_direction;
+    use crate::environment::{Environment, EnvironmentMap};
+    use crate::light::PointLight;
+    use crate::material::Material;
+    use crate::matrix::Matrix;
+    use crate::medium::DensityGrid;
+    use crate::microfacet::Microfacet;
+    use crate::shapes::{Shape, Sphere, Plane};
+
+    fn setup_camera(size: usize) -> Camera {
+        let mut camera = Camera::new(size, size, PI / 3.);
+        let from = Tuple::new_point(0., 1., -5.);
+        let to = Tuple::new_point(0., 1., 0.);
+        let up = Tuple::new_vector(0., 1., 0.);
+        camera.set_transform(Matrix::view_transform(&from, &to, &up));
+        camera
+    }
+
+    // A floor under a glowing ceiling, there is no point light at all
+    fn setup_room() -> World {
+        let mut world = World::new();
+
+        let floor = Plane::new();
+        let mut ceiling = Plane::new();
+        ceiling.set_transform(Matrix::translation(0., 3., 0.));
+        ceiling.set_material(Material::new_emissive(Color::new(1., 1., 1.)));
+
+        world.add_object(Box::new(floor));
+        world.add_object(Box::new(ceiling));
+        world
+    }
+
+    #[test]
+    fn test_miss_is_black() {
+        let world = World::new();
+        let tracer = PathTracer::new(1, 0);
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)), Color::black());
+    }
+
+    #[test]
+    fn test_emissive_surface_seen_directly() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        let mut material = Material::new_emissive(Color::new(2., 1., 0.5));
+        material.diffuse = 0.;
+        sphere.set_material(material);
+        world.add_object(Box::new(sphere));
+
+        let tracer = PathTracer::new(1, 0);
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)), Color::new(2., 1., 0.5));
+    }
+
+    #[test]
+    fn test_next_event_estimation_towards_point_light() {
+        let mut world = World::new();
+        world.add_object(Box::new(Plane::new()));
+        world.add_light(PointLight::new(Tuple::new_point(0., 2., 0.), Color::white()));
+
+        let mut tracer = PathTracer::new(1, 0);
+        tracer.max_depth = 1;
+        let ray = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+
+        // albedo 0.9, cos 1, distance 2: 0.9 / (4 PI)
+        let color = tracer.trace(&world, &ray, &mut Random::new(0));
+        assert_eq!(f32::abs(color.red - (0.9 / (4. * PI)) as f32) < 0.0001, true);
+    }
+
+    #[test]
+    fn test_indirect_light_reaches_floor() {
+        let world = setup_room();
+        let tracer = PathTracer::new(64, 1);
+        let ray = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+
+        let mut random = Random::new(5);
+        let mut sum = 0.;
+        for _ in 0..tracer.sampler.samples_per_pixel {
+            sum += tracer.trace(&world, &ray, &mut random).red;
+        }
+
+        // the floor only sees the ceiling, a diffuse bounce brings roughly albedo * emission
+        let average = sum / tracer.sampler.samples_per_pixel as f32;
+        assert_eq!(average > 0.5, true);
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        let world = setup_room();
+        let camera = setup_camera(4);
+
+        let first = PathTracer::new(2, 9).render(&camera, &world);
+        let second = PathTracer::new(2, 9).render(&camera, &world);
+        let other_seed = PathTracer::new(2, 10).render(&camera, &world);
+
+        assert_eq!(first.format_ppm_data(), second.format_ppm_data());
+        assert_eq!(first.get_pixel_at(0, 3) == other_seed.get_pixel_at(0, 3), false);
+    }
+
+    #[test]
+    fn test_adaptive_render_converges_on_flat_room() {
+        let world = setup_room();
+        let camera = setup_camera(3);
+        let adaptive = AdaptiveSampler::new(4, 8, 0.5, 2);
+
+        let render = PathTracer::new(1, 0).render_adaptive(&camera, &world, &adaptive);
+        assert_eq!(render.sample_counts.iter().all(|count| (4..=8).contains(count)), true);
+        assert_eq!(render.heatmap.is_none(), true);
+    }
+
+    #[test]
+    fn test_microfacet_surface_lit_by_point_light() {
+        let mut world = World::new();
+        let mut floor = Plane::new();
+        floor.set_material(Material::new_microfacet(Microfacet::new(Color::new(1., 0., 0.), 0., 0.5)));
+        world.add_object(Box::new(floor));
+        world.add_light(PointLight::new(Tuple::new_point(1., 2., 0.), Color::white()));
+
+        let mut tracer = PathTracer::new(1, 0);
+        tracer.max_depth = 1;
+        let ray = Ray::new(Tuple::new_point(-1., 2., 0.), Tuple::new_vector(1., -2., 0.).normalize());
+        let color = tracer.trace(&world, &ray, &mut Random::new(0));
+
+        // red diffuse base plus a white coat highlight in the mirror direction
+        assert_eq!(color.red > color.green, true);
+        assert_eq!(color.green > 0., true);
+    }
+
+    #[test]
+    fn test_light_passes_through_microfacet_glass() {
+        let mut world = setup_room();
+        let mut pane = Plane::new();
+        pane.set_transform(Matrix::translation(0., 1.5, 0.));
+        pane.set_material(Material::new_microfacet(Microfacet::glass()));
+        world.add_object(Box::new(pane));
+
+        let tracer = PathTracer::new(32, 0);
+        let ray = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+        let mut random = Random::new(3);
+        let mut sum = 0.;
+        for _ in 0..tracer.sampler.samples_per_pixel {
+            sum += tracer.trace(&world, &ray, &mut random).red;
+        }
+
+        assert_eq!(sum / tracer.sampler.samples_per_pixel as f32 > 0.4, true);
+    }
+
+    #[test]
+    fn test_russian_roulette_ends_paths_between_mirrors() {
+        let mut world = World::new();
+        let mut material = Material::new();
+        material.reflective = 1.;
+
+        let mut lower = Plane::new();
+        lower.set_material(material.clone());
+        let mut upper = Plane::new();
+        upper.set_material(material);
+        upper.set_transform(Matrix::translation(0., 1., 0.));
+        world.add_object(Box::new(lower));
+        world.add_object(Box::new(upper));
+
+        let mut tracer = PathTracer::new(1, 0);
+        tracer.max_depth = usize::MAX;
+        let ray = Ray::new(Tuple::new_point(0., 0.5, 0.), Tuple::new_vector(0., 1., 0.));
+
+        // perfect mirrors keep full throughput, only the 0.95 survival cap ends the path
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)), Color::black());
+    }
+
+    #[test]
+    fn test_fog_scatters_light_into_missing_rays() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Tuple::new_point(0., 0., 0.), Color::white()));
+        let tracer = PathTracer::new(1, 0);
+        let ray = Ray::new(Tuple::new_point(-5., 1., 0.), Tuple::new_vector(1., 0., 0.));
+
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)), Color::black());
+
+        world.fog = Some(Medium::new(0., 0.2));
+        let mut random = Random::new(1);
+        let sum: f32 = (0..64).map(|_| tracer.trace(&world, &ray, &mut random).red).sum();
+        assert_eq!(sum / 64. > 0.005, true);
+    }
+
+    #[test]
+    fn test_medium_scattering_follows_light_links() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Tuple::new_point(0., 0., 0.), Color::white()));
+        let mut cloud = Sphere::new();
+        cloud.set_transform(Matrix::scaling(3., 3., 3.));
+        cloud.set_material(Material::new_medium(Medium::new(0., 0.2)));
+        world.add_object(Box::new(cloud));
+
+        let tracer = PathTracer::new(1, 0);
+        let ray = Ray::new(Tuple::new_point(-5., 1., 0.), Tuple::new_vector(1., 0., 0.));
+        let mut random = Random::new(1);
+        let sum: f32 = (0..64).map(|_| tracer.trace(&world, &ray, &mut random).red).sum();
+        assert_eq!(sum / 64. > 0.005, true);
+
+        world.objects[0].set_light_links(LightLinks::Exclude(vec![0]));
+        let mut random = Random::new(1);
+        assert_eq!((0..64).all(|_| tracer.trace(&world, &ray, &mut random) == Color::black()), true);
+    }
+
+    #[test]
+    fn test_absorbing_fog_dims_lit_floor() {
+        let mut world = World::new();
+        world.add_object(Box::new(Plane::new()));
+        world.add_light(PointLight::new(Tuple::new_point(0., 2., 0.), Color::white()));
+        world.fog = Some(Medium::new(0.5, 0.));
+
+        let mut tracer = PathTracer::new(1, 0);
+        tracer.max_depth = 1;
+        let ray = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+
+        let mut random = Random::new(2);
+        let average = (0..4000).map(|_| tracer.trace(&world, &ray, &mut random).red).sum::<f32>() / 4000.;
+
+        // absorbed over 1 unit on the way down and 2 units towards the light
+        let expected = 0.9 / (4. * PI) * (-1.5 as Float).exp();
+        assert_eq!((average as Float - expected).abs() < 0.002, true);
+    }
+
+    #[test]
+    fn test_medium_inside_shape_casts_shadow() {
+        let mut world = World::new();
+        world.add_object(Box::new(Plane::new()));
+        world.add_light(PointLight::new(Tuple::new_point(0., 3., 0.), Color::white()));
+
+        let mut tracer = PathTracer::new(1, 0);
+        tracer.max_depth = 1;
+        let ray = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+        let lit = tracer.trace(&world, &ray, &mut Random::new(0));
+
+        let mut cloud = Sphere::new();
+        cloud.set_transform(Matrix::multiply(&Matrix::translation(0., 2., 0.), &Matrix::scaling(0.5, 0.5, 0.5)));
+        cloud.set_material(Material::new_medium(Medium::new(0., 0.)));
+        world.add_object(Box::new(cloud));
+
+        // an empty medium leaves the boundary invisible
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)), lit);
+
+        world.objects[1].get_properties_mut().material = Material::new_medium(Medium::new(20., 0.));
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)).red < lit.red * 0.001, true);
+    }
+
+    fn average_red(tracer: &PathTracer, world: &World, ray: &Ray, count: usize) -> f32 {
+        let mut random = Random::new(11);
+        (0..count).map(|_| tracer.trace(world, ray, &mut random).red).sum::<f32>() / count as f32
+    }
+
+    #[test]
+    fn test_moving_medium_is_looked_up_where_it_is() {
+        let mut world = World::new();
+        world.add_object(Box::new(Plane::new()));
+        world.add_light(PointLight::new(Tuple::new_point(5., 3., 0.), Color::white()));
+
+        let mut grid = DensityGrid::new(1, 1, 1);
+        grid.set_value(0, 0, 0, 1.);
+        let mut cloud = Sphere::new();
+        cloud.set_motion(AnimatedTransform::from_matrices(&[
+            (0., Matrix::multiply(&Matrix::translation(0., 2., 0.), &Matrix::scaling(0.5, 0.5, 0.5))),
+            (1., Matrix::multiply(&Matrix::translation(5., 2., 0.), &Matrix::scaling(0.5, 0.5, 0.5))),
+        ]));
+        cloud.set_material(Material::new_medium(Medium::new_heterogeneous(20., 0., Density::Grid(grid))));
+        world.add_object(Box::new(cloud));
+
+        // by time 1 the cloud has moved between the floor and the light
+        let mut tracer = PathTracer::new(1, 0);
+        tracer.max_depth = 1;
+        let ray = Ray::new_at_time(Tuple::new_point(5., 1., 0.), Tuple::new_vector(0., -1., 0.), 1.);
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)).red < 0.001, true);
+
+        let early = Ray::new_at_time(Tuple::new_point(5., 1., 0.), Tuple::new_vector(0., -1., 0.), 0.);
+        assert_eq!(tracer.trace(&world, &early, &mut Random::new(0)).red > 0.01, true);
+    }
+
+    #[test]
+    fn test_passing_through_a_medium_is_not_a_bounce() {
+        let mut world = World::new();
+        world.add_object(Box::new(Plane::new()));
+        world.add_light(PointLight::new(Tuple::new_point(2., 3., 0.), Color::white()));
+
+        let mut tracer = PathTracer::new(1, 0);
+        tracer.max_depth = 2;
+        let ray = Ray::new(Tuple::new_point(0., 5., 0.), Tuple::new_vector(0., -1., 0.));
+        let lit = tracer.trace(&world, &ray, &mut Random::new(0));
+        assert_eq!(lit.red > 0.01, true);
+
+        // the ray goes in and out of the empty medium on its way down and still reaches the floor
+        let mut cloud = Sphere::new();
+        cloud.set_transform(Matrix::translation(0., 3., 0.));
+        cloud.set_material(Material::new_medium(Medium::new(0., 0.)));
+        world.add_object(Box::new(cloud));
+        assert_eq!(tracer.trace(&world, &ray, &mut Random::new(0)), lit);
+    }
+
+    #[test]
+    fn test_sky_shines_through_noise_fog() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.set_transform(Matrix::scaling(3., 3., 3.));
+        sphere.set_material(Material::new_medium(Medium::new(0., 0.)));
+        world.add_object(Box::new(sphere));
+        world.fog = Some(Medium::new_heterogeneous(0.2, 0., Density::Noise { frequency: 1., octaves: 2, seed: 0 }));
+
+        // the fog ends with the scene, so light from the sky is dimmed but not blocked
+        let fog = world.fog.as_ref().map(|fog| (fog, None));
+        let ray = Ray::new(Tuple::new_point(1.5, 2.8, 0.), Tuple::new_vector(1., 0., 0.));
+        let mut random = Random::new(0);
+        let transmittance = (0..200).map(|_| PathTracer::transmittance(&world, &ray, Float::INFINITY, fog, fog, &mut random)).sum::<Float>() / 200.;
+        assert_eq!(transmittance > 0.5 && transmittance < 1., true);
+    }
+
+    #[test]
+    fn test_floor_under_uniform_sky() {
+        let mut world = World::new();
+        world.add_object(Box::new(Plane::new()));
+        world.environment = Some(Environment::Constant(Color::white()));
+
+        let tracer = PathTracer::new(1, 0);
+        let up = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., 1., 0.));
+        let down = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+
+        // the floor only sees the sky, so it reflects its albedo of it
+        assert_eq!(tracer.trace(&world, &up, &mut Random::new(0)), Color::white());
+        assert_eq!((average_red(&tracer, &world, &down, 2000) - 0.9).abs() < 0.03, true);
+    }
+
+    #[test]
+    fn test_importance_sampled_map_matches_constant_sky() {
+        let mut world = World::new();
+        world.add_object(Box::new(Plane::new()));
+        let mut image = Canvas::new_with_color(32, 16, Color::new(0.5, 0.5, 0.5));
+        image.write_pixel_at(10, 3, Color::new(200., 200., 200.));
+        let environment = Environment::Map(EnvironmentMap::new(image));
+
+        // the bright pixel adds its share of the sphere to the irradiance of the floor
+        let pixel_direction = equirectangular_direction(10.5 / 32., 3.5 / 16.);
+        let solid_angle = 2. * PI * PI * (3.5 / 16. * PI).sin() / (32. * 16.) as Float;
+        let expected = 0.9 * (0.5 + 199.5 * solid_angle * pixel_direction.y / PI);
+        world.environment = Some(environment);
+
+        let tracer = PathTracer::new(1, 0);
+        let down = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+        assert_eq!((average_red(&tracer, &world, &down, 4000) as Float - expected).abs() < expected * 0.05, true);
+    }
+}
@@ -0,0 +1,420 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::color::Color;
+use crate::shapes::{Group, Mesh, PointCloud, SplatType, Triangle};
+use crate::subdivision::Cage;
+use crate::tuples::{Tuple, Float};
+
+// Stanford PLY meshes and point clouds in ASCII or binary of either byte order. Vertex
+// positions, optional vertex normals and colors and polygon faces are read, every other
+// element and property is skipped
+#[derive(PartialEq, Debug, Clone)]
+pub struct PlyFile {
+    pub vertices: Vec<Tuple>,
+    // Empty when the file has no nx, ny and nz properties
+    pub normals: Vec<Tuple>,
+    // Empty when the file has no red, green and blue properties
+    pub colors: Vec<Color>,
+    pub faces: Vec<Vec<usize>>
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar> {
+        match name {
+            "char" | "int8" => Ok(Scalar::Int8),
+            "uchar" | "uint8" => Ok(Scalar::UInt8),
+            "short" | "int16" => Ok(Scalar::Int16),
+            "ushort" | "uint16" => Ok(Scalar::UInt16),
+            "int" | "int32" => Ok(Scalar::Int32),
+            "uint" | "uint32" => Ok(Scalar::UInt32),
+            "float" | "float32" => Ok(Scalar::Float32),
+            "double" | "float64" => Ok(Scalar::Float64),
+            _ => Err(invalid(&format!("unknown property type {}", name)))
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::UInt8 => 1,
+            Scalar::Int16 | Scalar::UInt16 => 2,
+            Scalar::Int32 | Scalar::UInt32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8
+        }
+    }
+
+    // Colors stored as integers use the whole range of the type
+    fn color_scale(&self) -> Float {
+        match self {
+            Scalar::Float32 | Scalar::Float64 => 1.,
+            Scalar::Int16 | Scalar::UInt16 => 65535.,
+            _ => 255.
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Property {
+    Scalar { name: String, kind: Scalar },
+    List { name: String, count: Scalar, item: Scalar }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+// Values come either from whitespace separated text or from packed bytes
+enum Source<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], position: usize, little_endian: bool }
+}
+
+impl Source<'_> {
+    fn read(&mut self, kind: Scalar) -> Result<Float> {
+        match self {
+            Source::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(|| invalid("unexpected end of data"))?;
+                token.parse::<Float>().map_err(|_| invalid(&format!("invalid number {}", token)))
+            },
+            Source::Binary { bytes, position, little_endian } => {
+                let size = kind.size();
+                let data = bytes.get(*position..*position + size).ok_or_else(|| invalid("unexpected end of data"))?;
+                *position += size;
+
+                let mut buffer = [0u8; 8];
+                buffer[..size].copy_from_slice(data);
+                if !*little_endian {
+                    buffer[..size].reverse();
+                }
+
+                Ok(match kind {
+                    Scalar::Int8 => buffer[0] as i8 as Float,
+                    Scalar::UInt8 => buffer[0] as Float,
+                    Scalar::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as Float,
+                    Scalar::UInt16 => u16::from_le_bytes([buffer[0], buffer[1]]) as Float,
+                    Scalar::Int32 => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as Float,
+                    Scalar::UInt32 => u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as Float,
+                    Scalar::Float32 => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as Float,
+                    Scalar::Float64 => f64::from_le_bytes(buffer)
+                })
+            }
+        }
+    }
+}
+
+impl PlyFile {
+    pub fn load(path: &str) -> Result<PlyFile> {
+        PlyFile::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<PlyFile> {
+        let (format, elements, body) = parse_header(bytes)?;
+
+        let mut source = match format {
+            Format::Ascii => Source::Ascii(std::str::from_utf8(&bytes[body..])
+                .map_err(|_| invalid("ASCII data is not valid text"))?
+                .split_ascii_whitespace()),
+            _ => Source::Binary { bytes: &bytes[body..], position: 0, little_endian: format == Format::BinaryLittleEndian }
+        };
+
+        let mut file = PlyFile {
+            vertices: vec![],
+            normals: vec![],
+            colors: vec![],
+            faces: vec![]
+        };
+
+        for element in &elements {
+            for _ in 0..element.count {
+                file.read_element(element, &mut source)?;
+            }
+        }
+
+        let vertex_count = file.vertices.len();
+        if let Some(face) = file.faces.iter().find(|face| face.iter().any(|&index| index >= vertex_count)) {
+            return Err(invalid(&format!("face {:?} refers past the {} vertices", face, vertex_count)))
+        }
+
+        Ok(file)
+    }
+
+    fn read_element(&mut self, element: &Element, source: &mut Source) -> Result<()> {
+        let mut position = [0.; 3];
+        let mut normal = [0.; 3];
+        let mut has_normal = false;
+        let mut color = [0.; 3];
+        let mut has_color = false;
+
+        for property in &element.properties {
+            match property {
+                Property::Scalar { name, kind } => {
+                    let value = source.read(*kind)?;
+
+                    if element.name == "vertex" {
+                        match name.as_str() {
+                            "x" => position[0] = value,
+                            "y" => position[1] = value,
+                            "z" => position[2] = value,
+                            "nx" => { normal[0] = value; has_normal = true; },
+                            "ny" => normal[1] = value,
+                            "nz" => normal[2] = value,
+                            "red" => { color[0] = value / kind.color_scale(); has_color = true; },
+                            "green" => color[1] = value / kind.color_scale(),
+                            "blue" => color[2] = value / kind.color_scale(),
+                            _ => ()
+                        }
+                    }
+                },
+                Property::List { name, count, item } => {
+                    let length = source.read(*count)?;
+                    if length < 0. {
+                        return Err(invalid("negative list length"))
+                    }
+
+                    let values = (0..length as usize).map(|_| source.read(*item)).collect::<Result<Vec<Float>>>()?;
+
+                    if element.name == "face" && (name == "vertex_indices" || name == "vertex_index") {
+                        if values.len() < 3 || values.iter().any(|&value| value < 0.) {
+                            return Err(invalid(&format!("invalid face {:?}", values)))
+                        }
+                        self.faces.push(values.iter().map(|&value| value as usize).collect());
+                    }
+                }
+            }
+        }
+
+        if element.name == "vertex" {
+            self.vertices.push(Tuple::new_point(position[0], position[1], position[2]));
+            if has_normal {
+                self.normals.push(Tuple::new_vector(normal[0], normal[1], normal[2]));
+            }
+            if has_color {
+                self.colors.push(Color::new(color[0] as f32, color[1] as f32, color[2] as f32));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Polygons are fanned out from their first corner, vertex normals make smooth triangles
+    pub fn to_group(&self) -> Group {
+        let mut group = Group::new();
+        let smooth = self.normals.len() == self.vertices.len();
+
+        for face in &self.faces {
+            for i in 1..face.len() - 1 {
+                let [a, b, c] = [face[0], face[i], face[i + 1]];
+                let (p1, p2, p3) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+
+                let triangle = if smooth {
+                    Triangle::new_smooth(p1, p2, p3, self.normals[a], self.normals[b], self.normals[c])
+                } else {
+                    Triangle::new(p1, p2, p3)
+                };
+                group.add_child(Box::new(triangle));
+            }
+        }
+
+        group
+    }
+
+    // Indexed mesh sharing the file's vertices, polygons are fanned out
+    pub fn to_mesh(&self) -> Mesh {
+        let triangles = self.faces.iter()
+            .flat_map(|face| (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]]))
+            .collect();
+        let mut mesh = Mesh::new(self.vertices.clone(), triangles);
+
+        if self.normals.len() == self.vertices.len() {
+            mesh.set_normals(self.normals.clone());
+        }
+        mesh
+    }
+
+    // Every vertex becomes a point, faces are ignored
+    pub fn to_point_cloud(&self, radius: Float, splat: SplatType) -> PointCloud {
+        let mut cloud = PointCloud::new(self.vertices.clone(), radius, splat);
+
+        if self.colors.len() == self.vertices.len() {
+            cloud.set_colors(self.colors.clone());
+        }
+        if self.normals.len() == self.vertices.len() {
+            cloud.set_normals(self.normals.clone());
+        }
+        cloud
+    }
+
+    // Keeps the polygons whole, as subdivision cages are usually modelled in quads
+    pub fn to_cage(&self) -> Cage {
+        Cage::new(self.vertices.clone(), self.faces.clone())
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// Format, elements in file order and where the data starts
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize)> {
+    const END: &[u8] = b"end_header";
+
+    let end = bytes.windows(END.len()).position(|window| window == END)
+        .ok_or_else(|| invalid("missing end_header"))?;
+    let body = bytes[end..].iter().position(|&byte| byte == b'\n')
+        .map(|newline| end + newline + 1)
+        .ok_or_else(|| invalid("missing end_header"))?;
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|_| invalid("header is not valid text"))?;
+
+    let mut lines = header.lines().map(|line| line.trim());
+    if lines.next() != Some("ply") {
+        return Err(invalid("not a PLY file"))
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+
+    for line in lines {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        match parts.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => (),
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid(&format!("unknown format {}", name)))
+                });
+            },
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid(&format!("invalid element count {}", count)))?,
+                properties: vec![]
+            }),
+            ["property", "list", count, item, name] => elements.last_mut()
+                .ok_or_else(|| invalid("property before any element"))?
+                .properties.push(Property::List { name: name.to_string(), count: Scalar::parse(count)?, item: Scalar::parse(item)? }),
+            ["property", kind, name] => elements.last_mut()
+                .ok_or_else(|| invalid("property before any element"))?
+                .properties.push(Property::Scalar { name: name.to_string(), kind: Scalar::parse(kind)? }),
+            _ => return Err(invalid(&format!("unexpected header line {}", line)))
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid("missing format line"))?;
+    Ok((format, elements, body))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+
+    const HEADER: &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn binary(little_endian: bool) -> Vec<u8> {
+        let format = if little_endian { "binary_little_endian" } else { "binary_big_endian" };
+        let mut bytes = format!("ply\nformat {} 1.0\ncomment made by hand\n{}", format, HEADER).into_bytes();
+
+        for (x, y) in [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)] {
+            for value in [x as f32, y as f32, 0.] {
+                bytes.extend_from_slice(&if little_endian { value.to_le_bytes() } else { value.to_be_bytes() });
+            }
+            bytes.push(255);
+        }
+
+        bytes.push(4);
+        for index in 0..4i32 {
+            bytes.extend_from_slice(&if little_endian { index.to_le_bytes() } else { index.to_be_bytes() });
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn test_ascii_ply() {
+        let content = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\nelement face 1\nproperty list uchar int vertex_index\nend_header\n0 1 0 0 0 -1\n-1 0 0 0 0 -1\n1 0 0 0 0 -1\n3 0 1 2\n";
+        let file = PlyFile::parse(content.as_bytes()).unwrap();
+
+        assert_eq!(file.vertices[1], Tuple::new_point(-1., 0., 0.));
+        assert_eq!(file.normals.len(), 3);
+        assert_eq!(file.faces, vec![vec![0, 1, 2]]);
+
+        let group = file.to_group();
+        let ray = Ray::new(Tuple::new_point(0., 0.5, -2.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(group.intersect(&ray).len(), 1);
+
+        let mesh = file.to_mesh();
+        assert_eq!(mesh.get_vertex_count(), 3);
+        assert_eq!(mesh.is_smooth(), true);
+        assert_eq!(mesh.intersect(&ray).len(), 1);
+        assert_eq!(file.to_cage().get_faces(), file.faces.as_slice());
+    }
+
+    #[test]
+    fn test_binary_ply_in_both_byte_orders() {
+        for little_endian in [true, false] {
+            let file = PlyFile::parse(&binary(little_endian)).unwrap();
+
+            assert_eq!(file.vertices.len(), 4);
+            assert_eq!(file.vertices[2], Tuple::new_point(1., 1., 0.));
+            assert_eq!(file.normals.len(), 0);
+            assert_eq!(file.faces, vec![vec![0, 1, 2, 3]]);
+            assert_eq!(file.to_group().get_children().len(), 2);
+            assert_eq!(file.colors[0], Color::red());
+        }
+    }
+
+    #[test]
+    fn test_point_cloud_from_ply() {
+        let content = "ply\nformat ascii 1.0\nelement vertex 2\nproperty double x\nproperty double y\nproperty double z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n0 0 0 255 0 0\n2 0 0 0 0 255\n";
+        let file = PlyFile::parse(content.as_bytes()).unwrap();
+        let cloud = file.to_point_cloud(0.5, SplatType::Sphere);
+
+        assert_eq!(cloud.get_point_count(), 2);
+        assert_eq!(cloud.get_colors(), &[Color::red(), Color::blue()]);
+        assert_eq!(cloud.get_normals().len(), 0);
+
+        let ray = Ray::new(Tuple::new_point(2., 5., 0.), Tuple::new_vector(0., -1., 0.));
+        let xs = cloud.intersect(&ray);
+        let comps = xs[0].prepare_computations(&ray, &xs);
+        assert_eq!(comps.color, Color::blue());
+    }
+
+    #[test]
+    fn test_invalid_ply_files() {
+        let mut truncated = binary(true);
+        truncated.truncate(truncated.len() - 2);
+        assert_eq!(PlyFile::parse(&truncated).is_err(), true);
+
+        assert_eq!(PlyFile::parse(b"solid cube\n").is_err(), true);
+        assert_eq!(PlyFile::parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty fixed x\nend_header\n1\n").is_err(), true);
+
+        let out_of_range = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n3 0 1 2\n";
+        assert_eq!(PlyFile::parse(out_of_range.as_bytes()).is_err(), true);
+    }
+}
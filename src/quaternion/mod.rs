@@ -0,0 +1,244 @@
+// ? INFO
+// Quaternions extend complex numbers to four components, w + xi + yj + zk. Unit quaternions
+// describe rotations and, unlike matrices, can be interpolated smoothly (slerp).
+
+use std::ops;
+
+use crate::matrix::MatrixType;
+use crate::tuples::{Tuple, Float};
+use crate::utils::NumberUtils;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Quaternion {
+    pub w: Float,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float
+}
+
+impl Quaternion {
+    pub fn new(w: Float, x: Float, y: Float, z: Float) -> Self {
+        Quaternion {
+            w,
+            x,
+            y,
+            z
+        }
+    }
+
+    pub fn identity() -> Self {
+        Quaternion::new(1., 0., 0., 0.)
+    }
+
+    // Rotation around axis by radians, same direction as Matrix::rotation_x/y/z
+    pub fn from_axis_angle(axis: &Tuple, radians: Float) -> Self {
+        let axis = axis.normalize();
+        let half = radians / 2.;
+        Quaternion::new(half.cos(), axis.x * half.sin(), axis.y * half.sin(), axis.z * half.sin())
+    }
+
+    // Rotation part of a matrix, the upper 3x3 has to be a pure rotation
+    pub fn from_rotation_matrix(m: &MatrixType) -> Self {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        let q = if trace > 0. {
+            let s = (trace + 1.).sqrt() * 2.;
+            Quaternion::new(s / 4., (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1. + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.;
+            Quaternion::new((m[2][1] - m[1][2]) / s, s / 4., (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1. + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.;
+            Quaternion::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, s / 4., (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = (1. + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.;
+            Quaternion::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, s / 4.)
+        };
+
+        q.normalize()
+    }
+
+    pub fn to_rotation_matrix(&self) -> MatrixType {
+        let Quaternion { w, x, y, z } = self.normalize();
+
+        vec![
+            vec![1. - 2. * (y * y + z * z), 2. * (x * y - w * z), 2. * (x * z + w * y), 0.],
+            vec![2. * (x * y + w * z), 1. - 2. * (x * x + z * z), 2. * (y * z - w * x), 0.],
+            vec![2. * (x * z - w * y), 2. * (y * z + w * x), 1. - 2. * (x * x + y * y), 0.],
+            vec![0., 0., 0., 1.],
+        ]
+    }
+
+    pub fn is_equal_to(&self, other: &Quaternion) -> bool {
+        NumberUtils::compare_floats(self.w, other.w) &&
+        NumberUtils::compare_floats(self.x, other.x) &&
+        NumberUtils::compare_floats(self.y, other.y) &&
+        NumberUtils::compare_floats(self.z, other.z)
+    }
+
+    pub fn get_magnitude(&self) -> Float {
+        self.calculate_dot_product(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        *self / self.get_magnitude()
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn calculate_dot_product(&self, other: &Quaternion) -> Float {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    // Rotates a vector or a point around the origin, the quaternion has to be unit length
+    pub fn rotate(&self, tuple: &Tuple) -> Tuple {
+        let rotated = *self * Quaternion::new(0., tuple.x, tuple.y, tuple.z) * self.conjugate();
+        Tuple::new_tuple(rotated.x, rotated.y, rotated.z, tuple.w)
+    }
+
+    // Spherical linear interpolation, constant angular speed along the shortest arc
+    pub fn slerp(&self, other: &Quaternion, t: Float) -> Self {
+        let mut other = *other;
+        let mut cos = self.calculate_dot_product(&other);
+
+        if cos < 0. {
+            other = other * -1.;
+            cos = -cos;
+        }
+
+        // nearly the same rotation, a plain lerp avoids dividing by sin of a tiny angle
+        if cos > 0.9995 {
+            return (*self + (other - *self) * t).normalize()
+        }
+
+        let angle = cos.acos();
+        let sin = angle.sin();
+        (*self * (((1. - t) * angle).sin() / sin) + other * ((t * angle).sin() / sin)).normalize()
+    }
+}
+
+impl ops::Add<Quaternion> for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(self.w + other.w, self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl ops::Sub<Quaternion> for Quaternion {
+    type Output = Quaternion;
+
+    fn sub(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(self.w - other.w, self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+// Hamilton product, applies other first and then self when used as rotations
+impl ops::Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w
+        )
+    }
+}
+
+impl ops::Mul<Float> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, scalar: Float) -> Quaternion {
+        Quaternion::new(self.w * scalar, self.x * scalar, self.y * scalar, self.z * scalar)
+    }
+}
+
+impl ops::Div<Float> for Quaternion {
+    type Output = Quaternion;
+
+    fn div(self, scalar: Float) -> Quaternion {
+        Quaternion::new(self.w / scalar, self.x / scalar, self.y / scalar, self.z / scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::PI;
+    use crate::matrix::Matrix;
+
+    #[test]
+    fn test_hamilton_product() {
+        let i = Quaternion::new(0., 1., 0., 0.);
+        let j = Quaternion::new(0., 0., 1., 0.);
+        let k = Quaternion::new(0., 0., 0., 1.);
+
+        assert_eq!(i * j, k);
+        assert_eq!(j * i, k * -1.);
+        assert_eq!(i * i, Quaternion::new(-1., 0., 0., 0.));
+    }
+
+    #[test]
+    fn test_axis_angle_matches_rotation_matrices() {
+        let axes = [
+            (Tuple::new_vector(1., 0., 0.), Matrix::rotation_x(PI / 3.)),
+            (Tuple::new_vector(0., 1., 0.), Matrix::rotation_y(PI / 3.)),
+            (Tuple::new_vector(0., 0., 1.), Matrix::rotation_z(PI / 3.)),
+        ];
+
+        for (axis, matrix) in axes.iter() {
+            let q = Quaternion::from_axis_angle(axis, PI / 3.);
+            assert_eq!(Matrix::are_equal(&q.to_rotation_matrix(), matrix), true);
+        }
+    }
+
+    #[test]
+    fn test_rotate_point() {
+        let q = Quaternion::from_axis_angle(&Tuple::new_vector(0., 0., 1.), PI / 2.);
+        let rotated = q.rotate(&Tuple::new_point(1., 0., 0.));
+
+        assert_eq!(rotated.is_equal_to(&Tuple::new_point(0., 1., 0.)), true);
+    }
+
+    #[test]
+    fn test_from_rotation_matrix_round_trip() {
+        let matrices = [
+            Matrix::rotation_x(2.5),
+            Matrix::multiply(&Matrix::rotation_y(PI), &Matrix::rotation_z(0.3)),
+            Matrix::multiply(&Matrix::rotation_z(-2.9), &Matrix::rotation_x(1.1)),
+            Matrix::identity_4x4(),
+        ];
+
+        for matrix in matrices.iter() {
+            let q = Quaternion::from_rotation_matrix(matrix);
+            assert_eq!(Matrix::are_equal(&q.to_rotation_matrix(), matrix), true);
+        }
+    }
+
+    #[test]
+    fn test_slerp() {
+        let start = Quaternion::identity();
+        let end = Quaternion::from_axis_angle(&Tuple::new_vector(0., 1., 0.), PI / 2.);
+
+        assert_eq!(start.slerp(&end, 0.).is_equal_to(&start), true);
+        assert_eq!(start.slerp(&end, 1.).is_equal_to(&end), true);
+
+        let halfway = start.slerp(&end, 0.5);
+        let expected = Quaternion::from_axis_angle(&Tuple::new_vector(0., 1., 0.), PI / 4.);
+        assert_eq!(halfway.is_equal_to(&expected), true);
+    }
+
+    #[test]
+    fn test_slerp_takes_shortest_arc() {
+        let start = Quaternion::identity();
+        // the same rotation as identity, but with the opposite sign
+        let end = Quaternion::new(-1., 0., 0., 0.);
+
+        assert_eq!(start.slerp(&end, 0.5).is_equal_to(&start), true);
+    }
+}
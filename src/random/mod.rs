@@ -0,0 +1,76 @@
+use crate::tuples::Float;
+
+// Small xorshift64* generator, seeded explicitly so renders are reproducible
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random {
+            // the state must never be zero, splitmix the seed to spread it
+            state: Random::mix(seed) | 1
+        }
+    }
+
+    // Derives a generator from a seed and a pair of coordinates, usually a pixel,
+    // so every pixel gets the same samples no matter the order it is rendered in
+    pub fn for_pixel(seed: u64, x: usize, y: usize) -> Self {
+        Random::new(seed ^ Random::mix(((x as u64) << 32) | y as u64))
+    }
+
+    fn mix(value: u64) -> u64 {
+        let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Uniform float in [0, 1)
+    pub fn next_float(&mut self) -> Float {
+        (self.next_u64() >> 11) as Float / (1u64 << 53) as Float
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut r1 = Random::new(42);
+        let mut r2 = Random::new(42);
+
+        for _ in 0..10 {
+            assert_eq!(r1.next_u64(), r2.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_different_seeds_differ() {
+        let mut r1 = Random::new(1);
+        let mut r2 = Random::new(2);
+
+        assert_ne!(r1.next_u64(), r2.next_u64());
+        assert_ne!(Random::for_pixel(1, 0, 1).next_u64(), Random::for_pixel(1, 1, 0).next_u64());
+    }
+
+    #[test]
+    fn test_next_float_in_unit_range() {
+        let mut random = Random::new(7);
+
+        for _ in 0..1000 {
+            let value = random.next_float();
+            assert_eq!((0. ..1.).contains(&value), true);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::tuples::{Tuple, Float};
+use crate::matrix::{Matrix, MatrixType};
+
+// What a ray is traced for, so shapes can hide from some of them
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum RayKind {
+    Camera,
+    Shadow,
+    // Also diffuse bounces and scattering in the path tracer
+    Reflection,
+    Refraction
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: Tuple,
+    pub direction: Tuple,
+    // Moment inside the camera shutter interval the ray was sent at, for motion blur
+    pub time: Float,
+    pub kind: RayKind
+}
+
+impl Ray {
+    pub fn new(origin: Tuple, direction: Tuple) -> Self {
+        Ray::new_at_time(origin, direction, 0.)
+    }
+
+    pub fn new_at_time(origin: Tuple, direction: Tuple, time: Float) -> Self {
+        Ray {
+            origin,
+            direction,
+            time,
+            kind: RayKind::Camera
+        }
+    }
+
+    pub fn with_kind(self, kind: RayKind) -> Self {
+        Ray { kind, ..self }
+    }
+
+    pub fn position(&self, t: Float) -> Tuple {
+        self.origin + self.direction * t
+    }
+
+    pub fn transform(&self, m: &MatrixType) -> Self {
+        Ray {
+            origin: Matrix::multiply_tuple(m, &self.origin),
+            direction: Matrix::multiply_tuple(m, &self.direction),
+            ..*self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_new_ray() {
+        let origin = Tuple::new_point(1., 2., 3.);
+        let direction = Tuple::new_vector(4., 5., 6.);
+        let ray = Ray::new(origin, direction);
+
+        assert_eq!(ray.origin, origin);
+        assert_eq!(ray.direction, direction);
+        assert_eq!(ray.time, 0.);
+    }
+
+    #[test]
+    fn test_position() {
+        let ray = Ray::new(Tuple::new_point(2., 3., 4.), Tuple::new_vector(1., 0., 0.));
+
+        assert_eq!(ray.position(0.), Tuple::new_point(2., 3., 4.));
+        assert_eq!(ray.position(1.), Tuple::new_point(3., 3., 4.));
+        assert_eq!(ray.position(-1.), Tuple::new_point(1., 3., 4.));
+        assert_eq!(ray.position(2.5), Tuple::new_point(4.5, 3., 4.));
+    }
+
+    #[test]
+    fn test_translate_ray() {
+        let ray = Ray::new(Tuple::new_point(1., 2., 3.), Tuple::new_vector(0., 1., 0.));
+        let translated = ray.transform(&Matrix::translation(3., 4., 5.));
+
+        assert_eq!(translated.origin, Tuple::new_point(4., 6., 8.));
+        assert_eq!(translated.direction, Tuple::new_vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn test_transform_keeps_time() {
+        let ray = Ray::new_at_time(Tuple::new_point(1., 2., 3.), Tuple::new_vector(0., 1., 0.), 0.25);
+
+        assert_eq!(ray.transform(&Matrix::translation(3., 4., 5.)).time, 0.25);
+    }
+
+    #[test]
+    fn test_ray_kind() {
+        let ray = Ray::new(Tuple::new_point(1., 2., 3.), Tuple::new_vector(0., 1., 0.));
+        assert_eq!(ray.kind, RayKind::Camera);
+
+        let shadow = ray.with_kind(RayKind::Shadow);
+        assert_eq!(shadow.transform(&Matrix::translation(3., 4., 5.)).kind, RayKind::Shadow);
+    }
+
+    #[test]
+    fn test_scale_ray() {
+        let ray = Ray::new(Tuple::new_point(1., 2., 3.), Tuple::new_vector(0., 1., 0.));
+        let scaled = ray.transform(&Matrix::scaling(2., 3., 4.));
+
+        assert_eq!(scaled.origin, Tuple::new_point(2., 6., 12.));
+        assert_eq!(scaled.direction, Tuple::new_vector(0., 3., 0.));
+    }
+}
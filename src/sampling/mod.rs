@@ -0,0 +1,359 @@
+use std::f64::consts::PI;
+
+use crate::film::Filter;
+use crate::random::Random;
+use crate::tuples::{Tuple, Float};
+
+// Where inside a pixel the samples land
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum SamplePattern {
+    // Centers of a grid of cells, aliases the same way for every pixel
+    Regular,
+    // Uniform random positions anywhere in the pixel
+    Jittered,
+    // One random position inside every cell of a grid
+    Stratified,
+    // Halton sequence in bases 2 and 3, shifted randomly per pixel
+    Halton,
+    // First two dimensions of the Sobol sequence, shifted randomly per pixel
+    Sobol
+}
+
+// How the camera turns pixels into rays and the samples back into pixels
+#[derive(PartialEq, Debug, Clone)]
+pub struct Sampler {
+    pub samples_per_pixel: usize,
+    pub pattern: SamplePattern,
+    pub filter: Filter,
+    pub seed: u64
+}
+
+impl Sampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        Sampler {
+            samples_per_pixel,
+            pattern: SamplePattern::Stratified,
+            filter: Filter::Box,
+            seed
+        }
+    }
+
+    // Offsets inside the pixel, both in [0, 1)
+    pub fn generate_offsets(&self, random: &mut Random) -> Vec<(Float, Float)> {
+        generate_pixel_samples(self.pattern, self.samples_per_pixel, random)
+    }
+}
+
+pub fn generate_pixel_samples(pattern: SamplePattern, count: usize, random: &mut Random) -> Vec<(Float, Float)> {
+    match pattern {
+        SamplePattern::Regular => grid_samples(count, |_, _| (0.5, 0.5)),
+        SamplePattern::Jittered => (0..count).map(|_| (random.next_float(), random.next_float())).collect(),
+        // the cells of a row also split its height between them, so a single row
+        // still has samples in the top and the bottom of the pixel
+        SamplePattern::Stratified => grid_samples(count, |column, in_row| {
+            (random.next_float(), (column as Float + random.next_float()) / in_row as Float)
+        }),
+        SamplePattern::Halton => shift_samples((0..count).map(halton_2d).collect(), random),
+        SamplePattern::Sobol => shift_samples((0..count).map(sobol_2d).collect(), random)
+    }
+}
+
+// Grid of rows with up to columns cells each. When count is not a multiple of
+// columns the cells of the last row are wider, so the whole pixel is covered.
+// offset gets the column and the number of cells in the row and places the
+// sample inside its cell
+fn grid_samples<F: FnMut(usize, usize) -> (Float, Float)>(count: usize, mut offset: F) -> Vec<(Float, Float)> {
+    let columns = (count as Float).sqrt().ceil().max(1.) as usize;
+    let rows = count.div_ceil(columns).max(1);
+
+    (0..count).map(|index| {
+        let column = index % columns;
+        let row = index / columns;
+        let in_row = if row == rows - 1 { count - row * columns } else { columns };
+        let (x_offset, y_offset) = offset(column, in_row);
+        ((column as Float + x_offset) / in_row as Float, (row as Float + y_offset) / rows as Float)
+    }).collect()
+}
+
+// Cranley-Patterson rotation, keeps the point set but moves it around the unit square
+fn shift_samples(samples: Vec<(Float, Float)>, random: &mut Random) -> Vec<(Float, Float)> {
+    let (shift_x, shift_y) = (random.next_float(), random.next_float());
+    samples.into_iter().map(|(x, y)| ((x + shift_x).fract(), (y + shift_y).fract())).collect()
+}
+
+// Reflects the digits of index in the given base around the decimal point
+pub fn radical_inverse(mut index: usize, base: usize) -> Float {
+    let inverse_base = 1. / base as Float;
+    let mut factor = inverse_base;
+    let mut result = 0.;
+
+    while index > 0 {
+        result += (index % base) as Float * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+
+    result
+}
+
+pub fn halton_2d(index: usize) -> (Float, Float) {
+    (radical_inverse(index, 2), radical_inverse(index, 3))
+}
+
+pub fn sobol_2d(index: usize) -> (Float, Float) {
+    let mut x: u32 = 0;
+    let mut y: u32 = 0;
+    // direction numbers of the second dimension, primitive polynomial x + 1
+    let mut direction: u32 = 1 << 31;
+    let mut bits = index as u32;
+    let mut bit = 0;
+
+    while bits > 0 {
+        if bits & 1 == 1 {
+            x ^= 1 << (31 - bit);
+            y ^= direction;
+        }
+        direction ^= direction >> 1;
+        bits >>= 1;
+        bit += 1;
+    }
+
+    let scale = 1. / (1u64 << 32) as Float;
+    (x as Float * scale, y as Float * scale)
+}
+
+// Two unit vectors perpendicular to the normal and to each other
+pub fn orthonormal_basis(normal: &Tuple) -> (Tuple, Tuple) {
+    let helper = if normal.x.abs() > 0.9 {
+        Tuple::new_vector(0., 1., 0.)
+    } else {
+        Tuple::new_vector(1., 0., 0.)
+    };
+
+    let tangent = helper.get_vector_cross_product(normal).normalize();
+    let bitangent = normal.get_vector_cross_product(&tangent);
+
+    (tangent, bitangent)
+}
+
+// Expresses a direction given around +z in the frame of the normal
+pub fn to_world(local: &Tuple, normal: &Tuple) -> Tuple {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    tangent * local.x + bitangent * local.y + *normal * local.z
+}
+
+// Direction on the hemisphere around the normal with a pdf of cos(theta) / PI,
+// u1 and u2 are uniform numbers in [0, 1)
+pub fn cosine_sample_hemisphere(normal: &Tuple, u1: Float, u2: Float) -> Tuple {
+    let radius = u1.sqrt();
+    let phi = 2. * PI * u2;
+    let local = Tuple::new_vector(radius * phi.cos(), radius * phi.sin(), (1. - u1).max(0.).sqrt());
+
+    to_world(&local, normal).normalize()
+}
+
+// Multiple importance sampling weight for a sample drawn with pdf_a that pdf_b could also have produced
+pub fn power_heuristic(pdf_a: Float, pdf_b: Float) -> Float {
+    let (a, b) = (pdf_a * pdf_a, pdf_b * pdf_b);
+
+    if a + b > 0. {
+        a / (a + b)
+    } else {
+        0.
+    }
+}
+
+// Piecewise constant distribution over [0, 1) with one bin per value, for importance
+// sampling tabulated functions such as environment maps
+#[derive(PartialEq, Debug, Clone)]
+pub struct Distribution1D {
+    values: Vec<Float>,
+    cdf: Vec<Float>,
+    integral: Float
+}
+
+impl Distribution1D {
+    // Negative values count as zero, all zero values fall back to a uniform distribution
+    pub fn new(values: Vec<Float>) -> Self {
+        assert!(!values.is_empty(), "Distribution needs at least one value");
+
+        let count = values.len() as Float;
+        let values: Vec<Float> = values.into_iter().map(|value| value.max(0.)).collect();
+        let mut cdf = vec![0.];
+        for value in &values {
+            cdf.push(cdf[cdf.len() - 1] + value / count);
+        }
+
+        let integral = cdf[values.len()];
+        if integral > 0. {
+            cdf.iter_mut().for_each(|value| *value /= integral);
+        } else {
+            cdf = (0..=values.len()).map(|i| i as Float / count).collect();
+        }
+
+        Distribution1D {
+            values,
+            cdf,
+            integral
+        }
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.values.len()
+    }
+
+    // Mean of the values
+    pub fn get_integral(&self) -> Float {
+        self.integral
+    }
+
+    pub fn pdf(&self, index: usize) -> Float {
+        if self.integral > 0. {
+            self.values[index] / self.integral
+        } else {
+            1.
+        }
+    }
+
+    // Position in [0, 1) for a uniform number u, with the bin it fell in and its density
+    pub fn sample(&self, u: Float) -> (Float, usize, Float) {
+        let index = self.cdf.partition_point(|&value| value <= u).clamp(1, self.values.len()) - 1;
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0. { (u - self.cdf[index]) / width } else { 0. };
+
+        ((index as Float + offset.clamp(0., 1.)) / self.values.len() as Float, index, self.pdf(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::utils::NumberUtils;
+
+    #[test]
+    fn test_orthonormal_basis() {
+        let normal = Tuple::new_vector(1., 2., 3.).normalize();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+
+        assert_eq!(NumberUtils::compare_floats(tangent.calculate_dot_product(&normal), 0.), true);
+        assert_eq!(NumberUtils::compare_floats(bitangent.calculate_dot_product(&normal), 0.), true);
+        assert_eq!(NumberUtils::compare_floats(tangent.calculate_dot_product(&bitangent), 0.), true);
+        assert_eq!(NumberUtils::compare_floats(bitangent.get_magnitude(), 1.), true);
+    }
+
+    #[test]
+    fn test_cosine_samples_stay_in_hemisphere() {
+        let normal = Tuple::new_vector(0., 1., 0.);
+        let mut random = Random::new(3);
+        let mut average_cos = 0.;
+
+        for _ in 0..2000 {
+            let direction = cosine_sample_hemisphere(&normal, random.next_float(), random.next_float());
+            let cos = direction.calculate_dot_product(&normal);
+            assert_eq!(cos >= 0., true);
+            assert_eq!(NumberUtils::compare_floats(direction.get_magnitude(), 1.), true);
+            average_cos += cos / 2000.;
+        }
+
+        // E[cos] for a cosine weighted hemisphere is 2/3
+        assert_eq!(f64::abs(average_cos - 2. / 3.) < 0.02, true);
+    }
+
+    #[test]
+    fn test_regular_samples() {
+        let samples = generate_pixel_samples(SamplePattern::Regular, 4, &mut Random::new(0));
+
+        assert_eq!(samples, vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]);
+        assert_eq!(generate_pixel_samples(SamplePattern::Regular, 1, &mut Random::new(0)), vec![(0.5, 0.5)]);
+    }
+
+    #[test]
+    fn test_stratified_samples_fill_every_cell() {
+        let samples = generate_pixel_samples(SamplePattern::Stratified, 9, &mut Random::new(4));
+
+        for (index, (x, y)) in samples.iter().enumerate() {
+            assert_eq!((x * 3.).floor() as usize, index % 3);
+            assert_eq!((y * 3.).floor() as usize, index / 3);
+        }
+    }
+
+    #[test]
+    fn test_grid_samples_cover_the_pixel() {
+        assert_eq!(generate_pixel_samples(SamplePattern::Regular, 2, &mut Random::new(0)), vec![(0.25, 0.5), (0.75, 0.5)]);
+        assert_eq!(generate_pixel_samples(SamplePattern::Regular, 3, &mut Random::new(0)), vec![(0.25, 0.25), (0.75, 0.25), (0.5, 0.75)]);
+
+        // every half of the pixel, left, right, top and bottom, gets a sample
+        let mut random = Random::new(7);
+        for count in [2, 3, 5].iter().copied() {
+            for _ in 0..100 {
+                let samples = generate_pixel_samples(SamplePattern::Stratified, count, &mut random);
+                for &(low, high) in [(0., 0.5), (0.5, 1.)].iter() {
+                    assert_eq!(samples.iter().any(|&(x, _)| x >= low && x < high), true);
+                    assert_eq!(samples.iter().any(|&(_, y)| y >= low && y < high), true);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_samples_stay_inside_pixel() {
+        let patterns = [
+            SamplePattern::Regular,
+            SamplePattern::Jittered,
+            SamplePattern::Stratified,
+            SamplePattern::Halton,
+            SamplePattern::Sobol
+        ];
+        let mut random = Random::new(12);
+
+        for pattern in patterns.iter() {
+            let samples = generate_pixel_samples(*pattern, 7, &mut random);
+            assert_eq!(samples.len(), 7);
+
+            for (x, y) in samples {
+                assert_eq!((0. ..1.).contains(&x) && (0. ..1.).contains(&y), true);
+            }
+        }
+    }
+
+    #[test]
+    fn test_halton_sequence() {
+        assert_eq!(halton_2d(0), (0., 0.));
+        assert_eq!(halton_2d(1), (0.5, 1. / 3.));
+        assert_eq!(halton_2d(2), (0.25, 2. / 3.));
+        assert_eq!(NumberUtils::compare_floats(radical_inverse(3, 3), 1. / 9.), true);
+    }
+
+    #[test]
+    fn test_sobol_sequence() {
+        let points: Vec<(Float, Float)> = (0..4).map(sobol_2d).collect();
+
+        assert_eq!(points, vec![(0., 0.), (0.5, 0.5), (0.25, 0.75), (0.75, 0.25)]);
+    }
+
+    #[test]
+    fn test_distribution_follows_values() {
+        let distribution = Distribution1D::new(vec![1., 3., 0., 0.]);
+
+        assert_eq!(distribution.get_integral(), 1.);
+        assert_eq!(distribution.sample(0.), (0., 0, 1.));
+        assert_eq!(distribution.sample(0.5), (1. / 3., 1, 3.));
+        assert_eq!(distribution.sample(0.999).1, 1);
+        assert_eq!(distribution.pdf(2), 0.);
+    }
+
+    #[test]
+    fn test_zero_distribution_is_uniform() {
+        let distribution = Distribution1D::new(vec![0., 0.]);
+
+        assert_eq!(distribution.sample(0.75), (0.75, 1, 1.));
+    }
+
+    #[test]
+    fn test_power_heuristic() {
+        assert_eq!(power_heuristic(1., 1.), 0.5);
+        assert_eq!(power_heuristic(3., 1.), 0.9);
+        assert_eq!(power_heuristic(0., 0.), 0.);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::bounds::BoundingBox;
+use crate::fractal::Fractal;
+use crate::tuples::{Tuple, Float};
+
+// Signed distance function, negative inside the surface. Primitives are centered at the
+// origin and combine into trees. Smooth combinators blend over a distance of k, zero gives
+// the hard version. Twisting and sharp blends are not true distances any more, see
+// DistanceField::step_scale for tracing them safely. Fractals only estimate the distance
+#[derive(PartialEq, Debug, Clone)]
+pub enum Sdf {
+    Sphere { radius: Float },
+    Box { half_size: Tuple },
+    // Same extent as a box of half_size with edges rounded off by radius
+    RoundedBox { half_size: Tuple, radius: Float },
+    Capsule { a: Tuple, b: Tuple, radius: Float },
+    // Ring around the y axis
+    Torus { major_radius: Float, minor_radius: Float },
+    Union(Box<Sdf>, Box<Sdf>, Float),
+    Intersection(Box<Sdf>, Box<Sdf>, Float),
+    // The first with the second cut away
+    Subtraction(Box<Sdf>, Box<Sdf>, Float),
+    Translate(Box<Sdf>, Tuple),
+    // Copies every period along each axis, a zero period leaves that axis alone
+    Repeat(Box<Sdf>, Tuple),
+    // Rotation around the y axis growing by rate radians per unit of height
+    Twist(Box<Sdf>, Float),
+    Fractal(Fractal)
+}
+
+impl Sdf {
+    pub fn new_sphere(radius: Float) -> Self {
+        Sdf::Sphere { radius }
+    }
+
+    pub fn new_box(half_size: Tuple) -> Self {
+        Sdf::Box { half_size }
+    }
+
+    pub fn new_rounded_box(half_size: Tuple, radius: Float) -> Self {
+        Sdf::RoundedBox { half_size, radius }
+    }
+
+    pub fn new_capsule(a: Tuple, b: Tuple, radius: Float) -> Self {
+        Sdf::Capsule { a, b, radius }
+    }
+
+    pub fn new_torus(major_radius: Float, minor_radius: Float) -> Self {
+        Sdf::Torus { major_radius, minor_radius }
+    }
+
+    pub fn new_fractal(fractal: Fractal) -> Self {
+        Sdf::Fractal(fractal)
+    }
+
+    pub fn smooth_union(self, other: Sdf, k: Float) -> Self {
+        Sdf::Union(Box::new(self), Box::new(other), k)
+    }
+
+    pub fn smooth_intersection(self, other: Sdf, k: Float) -> Self {
+        Sdf::Intersection(Box::new(self), Box::new(other), k)
+    }
+
+    pub fn smooth_subtraction(self, other: Sdf, k: Float) -> Self {
+        Sdf::Subtraction(Box::new(self), Box::new(other), k)
+    }
+
+    pub fn translate(self, offset: Tuple) -> Self {
+        Sdf::Translate(Box::new(self), offset)
+    }
+
+    pub fn repeat(self, period: Tuple) -> Self {
+        Sdf::Repeat(Box::new(self), period)
+    }
+
+    pub fn twist(self, rate: Float) -> Self {
+        Sdf::Twist(Box::new(self), rate)
+    }
+
+    pub fn distance(&self, point: &Tuple) -> Float {
+        match self {
+            Sdf::Sphere { radius } => length(point.x, point.y, point.z) - radius,
+            Sdf::Box { half_size } => box_distance(point, half_size),
+            Sdf::RoundedBox { half_size, radius } => {
+                let inner = Tuple::new_vector(half_size.x - radius, half_size.y - radius, half_size.z - radius);
+                box_distance(point, &inner) - radius
+            },
+            Sdf::Capsule { a, b, radius } => {
+                let (to_point, axis) = (*point - *a, *b - *a);
+                let h = (to_point.calculate_dot_product(&axis) / axis.calculate_dot_product(&axis)).clamp(0., 1.);
+                (to_point - axis * h).get_magnitude() - radius
+            },
+            Sdf::Torus { major_radius, minor_radius } => {
+                length(length(point.x, 0., point.z) - major_radius, point.y, 0.) - minor_radius
+            },
+            Sdf::Union(a, b, k) => smooth_min(a.distance(point), b.distance(point), *k),
+            Sdf::Intersection(a, b, k) => -smooth_min(-a.distance(point), -b.distance(point), *k),
+            Sdf::Subtraction(a, b, k) => -smooth_min(-a.distance(point), b.distance(point), *k),
+            Sdf::Translate(sdf, _) | Sdf::Repeat(sdf, _) | Sdf::Twist(sdf, _) => sdf.distance(&self.inner_point(point)),
+            Sdf::Fractal(fractal) => fractal.distance(point)
+        }
+    }
+
+    // Orbit trap of the fractal closest to the point, None when there is no fractal
+    pub fn orbit_trap(&self, point: &Tuple) -> Option<Float> {
+        match self {
+            Sdf::Union(a, b, _) | Sdf::Intersection(a, b, _) | Sdf::Subtraction(a, b, _) => {
+                match (a.orbit_trap(point), b.orbit_trap(point)) {
+                    (Some(trap_a), Some(trap_b)) => {
+                        Some(if a.distance(point).abs() <= b.distance(point).abs() { trap_a } else { trap_b })
+                    },
+                    (trap_a, trap_b) => trap_a.or(trap_b)
+                }
+            },
+            Sdf::Translate(sdf, _) | Sdf::Repeat(sdf, _) | Sdf::Twist(sdf, _) => sdf.orbit_trap(&self.inner_point(point)),
+            Sdf::Fractal(fractal) => Some(fractal.orbit_trap(point)),
+            _ => None
+        }
+    }
+
+    // Point in the space of the wrapped function for domain operations
+    fn inner_point(&self, point: &Tuple) -> Tuple {
+        match self {
+            Sdf::Translate(_, offset) => *point - *offset,
+            Sdf::Repeat(_, period) => {
+                let wrap = |value: Float, period: Float| if period > 0. { value - period * (value / period).round() } else { value };
+                Tuple::new_point(wrap(point.x, period.x), wrap(point.y, period.y), wrap(point.z, period.z))
+            },
+            Sdf::Twist(_, rate) => {
+                let (sin, cos) = (rate * point.y).sin_cos();
+                Tuple::new_point(cos * point.x - sin * point.z, point.y, sin * point.x + cos * point.z)
+            },
+            _ => *point
+        }
+    }
+
+    // Box the surface stays inside, infinite along repeated axes
+    pub fn get_bounds(&self) -> BoundingBox {
+        let symmetric = |x: Float, y: Float, z: Float| BoundingBox::new_with_bounds(Tuple::new_point(-x, -y, -z), Tuple::new_point(x, y, z));
+
+        match self {
+            Sdf::Sphere { radius } => symmetric(*radius, *radius, *radius),
+            Sdf::Box { half_size } | Sdf::RoundedBox { half_size, .. } => symmetric(half_size.x, half_size.y, half_size.z),
+            Sdf::Capsule { a, b, radius } => {
+                let mut bounds = BoundingBox::new();
+                for point in [*a, *b] {
+                    bounds.add_point(&(point - Tuple::new_vector(*radius, *radius, *radius)));
+                    bounds.add_point(&(point + Tuple::new_vector(*radius, *radius, *radius)));
+                }
+                bounds
+            },
+            Sdf::Torus { major_radius, minor_radius } => symmetric(major_radius + minor_radius, *minor_radius, major_radius + minor_radius),
+            // smooth unions bulge out by up to a quarter of k
+            Sdf::Union(a, b, k) => {
+                let mut bounds = a.get_bounds();
+                bounds.merge(&b.get_bounds());
+                let bulge = Tuple::new_vector(k / 4., k / 4., k / 4.);
+                BoundingBox::new_with_bounds(bounds.min - bulge, bounds.max + bulge)
+            },
+            Sdf::Intersection(a, b, _) => {
+                let (a, b) = (a.get_bounds(), b.get_bounds());
+                BoundingBox::new_with_bounds(
+                    Tuple::new_point(a.min.x.max(b.min.x), a.min.y.max(b.min.y), a.min.z.max(b.min.z)),
+                    Tuple::new_point(a.max.x.min(b.max.x), a.max.y.min(b.max.y), a.max.z.min(b.max.z))
+                )
+            },
+            Sdf::Subtraction(a, _, _) => a.get_bounds(),
+            Sdf::Translate(sdf, offset) => {
+                let bounds = sdf.get_bounds();
+                BoundingBox::new_with_bounds(bounds.min + *offset, bounds.max + *offset)
+            },
+            Sdf::Repeat(sdf, period) => {
+                let bounds = sdf.get_bounds();
+                let axis = |min: Float, max: Float, period: Float| if period > 0. { (Float::NEG_INFINITY, Float::INFINITY) } else { (min, max) };
+                let (x, y, z) = (axis(bounds.min.x, bounds.max.x, period.x), axis(bounds.min.y, bounds.max.y, period.y), axis(bounds.min.z, bounds.max.z, period.z));
+                BoundingBox::new_with_bounds(Tuple::new_point(x.0, y.0, z.0), Tuple::new_point(x.1, y.1, z.1))
+            },
+            // any rotation around y stays within the circle through the farthest corner
+            Sdf::Twist(sdf, _) => {
+                let bounds = sdf.get_bounds();
+                let radius = length(bounds.min.x.abs().max(bounds.max.x.abs()), 0., bounds.min.z.abs().max(bounds.max.z.abs()));
+                BoundingBox::new_with_bounds(Tuple::new_point(-radius, bounds.min.y, -radius), Tuple::new_point(radius, bounds.max.y, radius))
+            },
+            Sdf::Fractal(fractal) => fractal.get_bounds()
+        }
+    }
+}
+
+fn length(x: Float, y: Float, z: Float) -> Float {
+    (x * x + y * y + z * z).sqrt()
+}
+
+pub(crate) fn box_distance(point: &Tuple, half_size: &Tuple) -> Float {
+    let (qx, qy, qz) = (point.x.abs() - half_size.x, point.y.abs() - half_size.y, point.z.abs() - half_size.z);
+    length(qx.max(0.), qy.max(0.), qz.max(0.)) + qx.max(qy).max(qz).min(0.)
+}
+
+// Polynomial smooth minimum, equal to min when the values are k or more apart
+pub fn smooth_min(a: Float, b: Float, k: Float) -> Float {
+    if k <= 0. {
+        return a.min(b)
+    }
+
+    let h = (k - (a - b).abs()).max(0.) / k;
+    a.min(b) - h * h * k / 4.
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::utils::NumberUtils;
+
+    #[test]
+    fn test_primitive_distances() {
+        let point = Tuple::new_point(2., 0., 0.);
+
+        assert_eq!(Sdf::new_sphere(1.).distance(&point), 1.);
+        assert_eq!(Sdf::new_box(Tuple::new_vector(1., 1., 1.)).distance(&Tuple::new_point(2., 2., 0.)), 2_f64.sqrt());
+        assert_eq!(Sdf::new_box(Tuple::new_vector(1., 2., 3.)).distance(&Tuple::new_point(0., 0., 0.)), -1.);
+        assert_eq!(Sdf::new_rounded_box(Tuple::new_vector(1., 1., 1.), 0.25).distance(&point), 1.);
+        assert_eq!(NumberUtils::compare_floats(Sdf::new_rounded_box(Tuple::new_vector(1., 1., 1.), 0.25).distance(&Tuple::new_point(1., 1., 1.)), 0.1875_f64.sqrt() - 0.25), true);
+
+        let capsule = Sdf::new_capsule(Tuple::new_point(0., -1., 0.), Tuple::new_point(0., 1., 0.), 0.5);
+        assert_eq!(capsule.distance(&point), 1.5);
+        assert_eq!(capsule.distance(&Tuple::new_point(0., 3., 0.)), 1.5);
+
+        let torus = Sdf::new_torus(2., 0.5);
+        assert_eq!(torus.distance(&Tuple::new_point(0., 0., 0.)), 1.5);
+        assert_eq!(torus.distance(&Tuple::new_point(0., 0., 2.)), -0.5);
+    }
+
+    #[test]
+    fn test_combinators() {
+        let left = Sdf::new_sphere(1.).translate(Tuple::new_vector(-1., 0., 0.));
+        let right = Sdf::new_sphere(1.).translate(Tuple::new_vector(1., 0., 0.));
+        let between = Tuple::new_point(0., 1., 0.);
+
+        let hard = left.clone().smooth_union(right.clone(), 0.);
+        let smooth = left.clone().smooth_union(right.clone(), 0.5);
+        assert_eq!(hard.distance(&between), 2_f64.sqrt() - 1.);
+        assert_eq!(smooth.distance(&between) < hard.distance(&between), true);
+
+        let lens = left.clone().smooth_intersection(right.clone(), 0.);
+        assert_eq!(lens.distance(&Tuple::new_point(0., 0., 0.)), 0.);
+        assert_eq!(lens.distance(&Tuple::new_point(-1.5, 0., 0.)), 1.5);
+
+        let bitten = left.smooth_subtraction(right, 0.);
+        assert_eq!(bitten.distance(&Tuple::new_point(0.5, 0., 0.)), 0.5);
+        assert_eq!(bitten.distance(&Tuple::new_point(-1.5, 0., 0.)), -0.5);
+        assert_eq!(smooth_min(1., 3., 1.), 1.);
+    }
+
+    #[test]
+    fn test_domain_operations() {
+        let repeated = Sdf::new_sphere(0.5).repeat(Tuple::new_vector(3., 0., 0.));
+        assert_eq!(repeated.distance(&Tuple::new_point(6., 0., 0.)), -0.5);
+        assert_eq!(repeated.distance(&Tuple::new_point(1.5, 1., 0.)), (1.5_f64 * 1.5 + 1.).sqrt() - 0.5);
+        assert_eq!(repeated.distance(&Tuple::new_point(0., 3., 0.)), 2.5);
+
+        let slab = Sdf::new_box(Tuple::new_vector(2., 1., 0.5));
+        let twisted = slab.clone().twist(std::f64::consts::PI / 2.);
+        // at y = 1 the slab is turned a quarter, so its long side lies along z
+        let point = Tuple::new_point(0., 1., 1.5);
+        assert_eq!(slab.distance(&point) > 0., true);
+        assert_eq!(twisted.distance(&point) <= 0., true);
+        assert_eq!(twisted.distance(&Tuple::new_point(1.5, 0., 0.)), slab.distance(&Tuple::new_point(1.5, 0., 0.)));
+    }
+
+    #[test]
+    fn test_bounds() {
+        let torus = Sdf::new_torus(2., 0.5).translate(Tuple::new_vector(0., 1., 0.));
+        let bounds = torus.get_bounds();
+        assert_eq!(bounds.min, Tuple::new_point(-2.5, 0.5, -2.5));
+        assert_eq!(bounds.max, Tuple::new_point(2.5, 1.5, 2.5));
+
+        let union = Sdf::new_sphere(1.).smooth_union(Sdf::new_sphere(1.).translate(Tuple::new_vector(2., 0., 0.)), 0.4);
+        assert_eq!(NumberUtils::compare_floats(union.get_bounds().max.x, 3.1), true);
+
+        let repeated = Sdf::new_sphere(1.).repeat(Tuple::new_vector(0., 0., 4.));
+        assert_eq!(repeated.get_bounds().max.z, Float::INFINITY);
+        assert_eq!(repeated.get_bounds().max.x, 1.);
+
+        let twisted = Sdf::new_box(Tuple::new_vector(1., 1., 1.)).twist(1.);
+        assert_eq!(twisted.get_bounds().max.x, 2_f64.sqrt());
+    }
+
+    #[test]
+    fn test_orbit_trap_of_nested_fractals() {
+        let sponge = Sdf::new_fractal(Fractal::new_menger(2)).translate(Tuple::new_vector(3., 0., 0.));
+        let scene = Sdf::new_sphere(1.).smooth_union(sponge, 0.);
+
+        assert_eq!(Sdf::new_sphere(1.).orbit_trap(&Tuple::new_point(0., 0., 0.)), None);
+        // the sponge is the only fractal, so it colors the whole union
+        assert_eq!(scene.orbit_trap(&Tuple::new_point(3., 0., 0.)), Some(0.5));
+        assert_eq!(scene.get_bounds().max.x, 4.);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::bounds::BoundingBox;
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+use super::curve::split;
+use super::{Shape, ShapeProperties, Triangle};
+
+// Deepest subdivision before Newton takes over, at most 4^5 pieces per patch
+const MAX_DEPTH: usize = 5;
+// How far control points may stray from the corners' bilinear surface, relative to the
+// piece's size, before the piece counts as flat
+const FLATNESS: Float = 0.05;
+const NEWTON_STEPS: usize = 20;
+// Slack on a piece's parameter range so roots right on the border between pieces are found
+const SLACK: Float = 1e-6;
+
+// Part of the patch whose control points lie inside bounds
+#[derive(PartialEq, Debug, Clone)]
+struct Piece {
+    bounds: BoundingBox,
+    u: (Float, Float),
+    v: (Float, Float)
+}
+
+// Bicubic Bézier patch from a 4 x 4 grid of control points, rows along u. The patch is cut
+// into pieces until they are nearly flat, and rays that hit a piece's box are solved for the
+// surface point by Newton iteration from the piece's center. Hits carry their u and v
+#[derive(PartialEq, Debug, Clone)]
+pub struct BezierPatch {
+    properties: ShapeProperties,
+    points: [Tuple; 16],
+    pieces: Vec<Piece>,
+    bounds: BoundingBox
+}
+
+impl BezierPatch {
+    pub fn new(points: [Tuple; 16]) -> Self {
+        let mut patch = BezierPatch {
+            properties: ShapeProperties::new(),
+            points,
+            pieces: vec![],
+            bounds: hull(&points)
+        };
+        patch.subdivide(points, (0., 1.), (0., 1.), 0);
+        patch
+    }
+
+    pub fn get_points(&self) -> &[Tuple; 16] {
+        &self.points
+    }
+
+    pub fn point_at(&self, u: Float, v: Float) -> Tuple {
+        let (bu, bv) = (basis(u), basis(v));
+        self.combine(|i, j| bu[i] * bv[j])
+    }
+
+    // Partial derivatives along u and along v
+    pub fn derivatives_at(&self, u: Float, v: Float) -> (Tuple, Tuple) {
+        let (bu, bv, du, dv) = (basis(u), basis(v), basis_derivative(u), basis_derivative(v));
+        let origin = Tuple::new_point(0., 0., 0.);
+        (self.combine(|i, j| du[i] * bv[j]) - origin, self.combine(|i, j| bu[i] * dv[j]) - origin)
+    }
+
+    // Along u cross along v. Corners where a row of control points collapses, like the top
+    // of the teapot's lid, take the normal from just inside
+    pub fn normal_at(&self, u: Float, v: Float) -> Tuple {
+        let (du, dv) = self.derivatives_at(u, v);
+        let normal = du.get_vector_cross_product(&dv);
+        if normal.get_magnitude() > 1e-12 {
+            return normal.normalize()
+        }
+
+        let nudge = |t: Float| t + (0.5 - t) * 1e-4;
+        let (du, dv) = self.derivatives_at(nudge(u), nudge(v));
+        du.get_vector_cross_product(&dv).normalize()
+    }
+
+    fn combine<W: Fn(usize, usize) -> Float>(&self, weight: W) -> Tuple {
+        let (mut x, mut y, mut z) = (0., 0., 0.);
+        for j in 0..4 {
+            for i in 0..4 {
+                let (point, w) = (self.points[j * 4 + i], weight(i, j));
+                x += point.x * w;
+                y += point.y * w;
+                z += point.z * w;
+            }
+        }
+        Tuple::new_point(x, y, z)
+    }
+
+    fn subdivide(&mut self, points: [Tuple; 16], u: (Float, Float), v: (Float, Float), depth: usize) {
+        let bounds = hull(&points);
+        if depth == MAX_DEPTH || is_flat(&points, &bounds) {
+            self.pieces.push(Piece { bounds, u, v });
+            return
+        }
+
+        let (u_middle, v_middle) = ((u.0 + u.1) / 2., (v.0 + v.1) / 2.);
+        let (low_u, high_u) = split_rows(&points);
+        for (half, u) in [(low_u, (u.0, u_middle)), (high_u, (u_middle, u.1))] {
+            let (low_v, high_v) = split_rows(&transpose(&half));
+            self.subdivide(transpose(&low_v), u, (v.0, v_middle), depth + 1);
+            self.subdivide(transpose(&high_v), u, (v_middle, v.1), depth + 1);
+        }
+    }
+
+    // Solves origin + t direction = S(u, v) starting from the piece's center
+    fn solve(&self, ray: &Ray, piece: &Piece) -> Option<(Float, Float, Float)> {
+        let (mut u, mut v) = ((piece.u.0 + piece.u.1) / 2., (piece.v.0 + piece.v.1) / 2.);
+        let mut t = (self.point_at(u, v) - ray.origin).calculate_dot_product(&ray.direction) / ray.direction.calculate_dot_product(&ray.direction);
+        let tolerance = 1e-10 * (self.bounds.max - self.bounds.min).get_magnitude().max(1.);
+
+        for _ in 0..NEWTON_STEPS {
+            let error = self.point_at(u, v) - ray.position(t);
+            if error.get_magnitude() < tolerance {
+                let inside = |value: Float, range: (Float, Float)| value >= range.0 - SLACK && value <= range.1 + SLACK;
+                return if inside(u, piece.u) && inside(v, piece.v) { Some((t, u.clamp(0., 1.), v.clamp(0., 1.))) } else { None }
+            }
+
+            // the columns are the derivatives along u, v and t, solved by Cramer's rule
+            let (du, dv) = self.derivatives_at(u, v);
+            let dt = ray.direction.negate();
+            let determinant = du.calculate_dot_product(&dv.get_vector_cross_product(&dt));
+            if determinant.abs() < 1e-14 {
+                return None
+            }
+
+            let target = error.negate();
+            u = (u + target.calculate_dot_product(&dv.get_vector_cross_product(&dt)) / determinant).clamp(0., 1.);
+            v = (v + du.calculate_dot_product(&target.get_vector_cross_product(&dt)) / determinant).clamp(0., 1.);
+            t += du.calculate_dot_product(&dv.get_vector_cross_product(&target)) / determinant;
+        }
+        None
+    }
+
+    // Closest parameters to point, from the best of a grid of samples refined by Gauss-Newton
+    fn closest_parameters(&self, point: &Tuple) -> (Float, Float) {
+        const SAMPLES: usize = 8;
+        let distance = |u: Float, v: Float| (self.point_at(u, v) - *point).get_magnitude();
+        let mut best = (0., 0.);
+        for j in 0..=SAMPLES {
+            for i in 0..=SAMPLES {
+                let (u, v) = (i as Float / SAMPLES as Float, j as Float / SAMPLES as Float);
+                if distance(u, v) < distance(best.0, best.1) {
+                    best = (u, v);
+                }
+            }
+        }
+
+        let (mut u, mut v) = best;
+        for _ in 0..NEWTON_STEPS {
+            let (du, dv) = self.derivatives_at(u, v);
+            let offset = *point - self.point_at(u, v);
+            let (a, b, c) = (du.calculate_dot_product(&du), du.calculate_dot_product(&dv), dv.calculate_dot_product(&dv));
+            let determinant = a * c - b * b;
+            if determinant.abs() < 1e-14 {
+                break
+            }
+            let (ru, rv) = (du.calculate_dot_product(&offset), dv.calculate_dot_product(&offset));
+            u = (u + (c * ru - b * rv) / determinant).clamp(0., 1.);
+            v = (v + (a * rv - b * ru) / determinant).clamp(0., 1.);
+        }
+        (u, v)
+    }
+}
+
+impl Shape for BezierPatch {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        if !self.bounds.intersects(ray) {
+            return vec![]
+        }
+
+        let mut hits: Vec<(Float, Float, Float)> = self.pieces.iter()
+            .filter(|piece| piece.bounds.intersects(ray))
+            .filter_map(|piece| self.solve(ray, piece))
+            .collect();
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // neighbouring pieces find roots on their shared border twice
+        hits.dedup_by(|a, b| (a.0 - b.0).abs() < 1e-7 && (a.1 - b.1).abs() + (a.2 - b.2).abs() < 1e-4);
+
+        hits.iter().map(|&(t, u, v)| Intersection::new_with_uv(t, self, u, v)).collect()
+    }
+
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        let (u, v) = self.closest_parameters(point);
+        self.normal_at(u, v)
+    }
+
+    fn local_normal_at_hit(&self, _point: &Tuple, hit: &Intersection) -> Tuple {
+        self.normal_at(hit.u, hit.v)
+    }
+
+    // A grid of segments x segments quads with the patch's own normals, collapsed
+    // triangles are left out
+    fn local_tessellate(&self, segments: usize) -> Vec<Triangle> {
+        let segments = segments.max(1);
+        let parameter = |i: usize| i as Float / segments as Float;
+        let mut triangles = vec![];
+
+        for j in 0..segments {
+            for i in 0..segments {
+                let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)].map(|(i, j)| (parameter(i), parameter(j)));
+                let points = corners.map(|(u, v)| self.point_at(u, v));
+                let normals = corners.map(|(u, v)| self.normal_at(u, v));
+
+                for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+                    let area = (points[b] - points[a]).get_vector_cross_product(&(points[c] - points[a])).get_magnitude();
+                    if area > 1e-12 {
+                        triangles.push(Triangle::new_smooth(points[a], points[b], points[c], normals[a], normals[b], normals[c]));
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+}
+
+fn basis(t: Float) -> [Float; 4] {
+    let s = 1. - t;
+    [s * s * s, 3. * t * s * s, 3. * t * t * s, t * t * t]
+}
+
+fn basis_derivative(t: Float) -> [Float; 4] {
+    let s = 1. - t;
+    [-3. * s * s, 3. * s * s - 6. * t * s, 6. * t * s - 3. * t * t, 3. * t * t]
+}
+
+fn hull(points: &[Tuple; 16]) -> BoundingBox {
+    let mut bounds = BoundingBox::new();
+    for point in points {
+        bounds.add_point(point);
+    }
+    bounds
+}
+
+fn transpose(points: &[Tuple; 16]) -> [Tuple; 16] {
+    let mut transposed = *points;
+    for j in 0..4 {
+        for i in 0..4 {
+            transposed[i * 4 + j] = points[j * 4 + i];
+        }
+    }
+    transposed
+}
+
+// Halves every row, which splits the patch at u = 0.5
+fn split_rows(points: &[Tuple; 16]) -> ([Tuple; 16], [Tuple; 16]) {
+    let (mut low, mut high) = (*points, *points);
+    for row in 0..4 {
+        let (left, right) = split(&[points[row * 4], points[row * 4 + 1], points[row * 4 + 2], points[row * 4 + 3]]);
+        low[row * 4..row * 4 + 4].copy_from_slice(&left);
+        high[row * 4..row * 4 + 4].copy_from_slice(&right);
+    }
+    (low, high)
+}
+
+fn is_flat(points: &[Tuple; 16], bounds: &BoundingBox) -> bool {
+    let size = (bounds.max - bounds.min).get_magnitude();
+    let [p00, p10, p01, p11] = [points[0], points[3], points[12], points[15]];
+
+    (0..16).all(|index| {
+        let (u, v) = ((index % 4) as Float / 3., (index / 4) as Float / 3.);
+        let bottom = p00 + (p10 - p00) * u;
+        let top = p01 + (p11 - p01) * u;
+        let bilinear = bottom + (top - bottom) * v;
+        (points[index] - bilinear).get_magnitude() <= FLATNESS * size
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::convert::TryInto;
+    use crate::utils::NumberUtils;
+
+    // Bilinear patch bulged up in the middle: the corner heights are 0 and the inner four 1
+    fn dome() -> BezierPatch {
+        let points = [0., 1., 2., 3.].iter()
+            .flat_map(|&z| [0., 1., 2., 3.].iter().map(move |&x| {
+                let inner = (x == 1. || x == 2.) && (z == 1. || z == 2.);
+                Tuple::new_point(x, if inner { 1. } else { 0. }, z)
+            }).collect::<Vec<Tuple>>())
+            .collect::<Vec<Tuple>>();
+        BezierPatch::new(points.try_into().unwrap())
+    }
+
+    #[test]
+    fn test_flat_patch() {
+        // evenly spaced control points give the plane from 0 to 3 with u = x / 3 and v = z / 3
+        let points: Vec<Tuple> = (0..16).map(|i| Tuple::new_point((i % 4) as Float, 0., (i / 4) as Float)).collect();
+        let patch = BezierPatch::new(points.try_into().unwrap());
+        assert_eq!(patch.pieces.len(), 1);
+
+        let ray = Ray::new(Tuple::new_point(1.5, 2., 0.75), Tuple::new_vector(0., -1., 0.));
+        let xs = patch.local_intersect(&ray);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 2.), true);
+        assert_eq!(NumberUtils::compare_floats(xs[0].u, 0.5) && NumberUtils::compare_floats(xs[0].v, 0.25), true);
+        assert_eq!(patch.local_normal_at_hit(&ray.position(2.), &xs[0]).is_equal_to(&Tuple::new_vector(0., -1., 0.)), true);
+
+        let outside = Ray::new(Tuple::new_point(3.5, 2., 1.), Tuple::new_vector(0., -1., 0.));
+        assert_eq!(patch.local_intersect(&outside).len(), 0);
+    }
+
+    #[test]
+    fn test_curved_patch() {
+        let patch = dome();
+        assert_eq!(patch.pieces.len() > 1, true);
+
+        // the top of the dome is at 9/16 of the control height, with the normal straight up
+        let down = Ray::new(Tuple::new_point(1.5, 5., 1.5), Tuple::new_vector(0., -1., 0.));
+        let xs = patch.local_intersect(&down);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 5. - 0.5625), true);
+        assert_eq!(patch.local_normal_at_hit(&down.position(xs[0].t), &xs[0]).y.abs() > 0.9999, true);
+
+        // skimming along the dome's side enters and leaves it
+        let across = Ray::new(Tuple::new_point(-1., 0.3, 1.5), Tuple::new_vector(1., 0., 0.));
+        let xs = patch.local_intersect(&across);
+        assert_eq!(xs.len(), 2);
+        for hit in &xs {
+            assert_eq!(patch.point_at(hit.u, hit.v).is_equal_to(&across.position(hit.t)), true);
+        }
+
+        let normal = patch.local_normal_at(&patch.point_at(0.2, 0.5));
+        assert_eq!(normal.is_equal_to(&patch.normal_at(0.2, 0.5)), true);
+    }
+
+    #[test]
+    fn test_tessellated_patch() {
+        let patch = dome();
+        let triangles = patch.local_tessellate(4);
+        assert_eq!(triangles.len(), 32);
+
+        // every tessellated vertex lies on the surface
+        for triangle in &triangles {
+            for point in triangle.get_points() {
+                let (u, v) = patch.closest_parameters(point);
+                assert_eq!(patch.point_at(u, v).is_equal_to(point), true);
+            }
+        }
+    }
+}
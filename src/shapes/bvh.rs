@@ -0,0 +1,136 @@
+use crate::bounds::BoundingBox;
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+// Items a leaf of the hierarchy holds at most
+pub(super) const LEAF_SIZE: usize = 4;
+
+// Box around the items it covers. Leaves own a range of the item order, inner nodes have
+// their first child right after them and the second at second_child
+#[derive(PartialEq, Debug, Clone)]
+pub(super) struct BvhNode {
+    pub(super) bounds: BoundingBox,
+    pub(super) start: usize,
+    pub(super) count: usize,
+    pub(super) second_child: usize
+}
+
+// Bounding volume hierarchy over items that are not shapes of their own, like the points of
+// a cloud or the faces of a mesh. Items are the indices of the boxes it was built from
+#[derive(PartialEq, Debug, Clone)]
+pub(super) struct Bvh {
+    // Item indices in hierarchy order, so every leaf covers a contiguous range
+    pub(super) order: Vec<u32>,
+    pub(super) nodes: Vec<BvhNode>
+}
+
+impl Bvh {
+    pub(super) fn new(boxes: &[BoundingBox]) -> Self {
+        let mut bvh = Bvh {
+            order: (0..boxes.len() as u32).collect(),
+            nodes: vec![]
+        };
+
+        if !boxes.is_empty() {
+            bvh.build(boxes, 0, boxes.len());
+        }
+        bvh
+    }
+
+    // Box around every item, empty without items
+    pub(super) fn get_bounds(&self) -> BoundingBox {
+        self.nodes.first().map_or_else(BoundingBox::new, |root| root.bounds)
+    }
+
+    // Splits at the median along the axis the centers spread furthest, returns the node's index
+    fn build(&mut self, boxes: &[BoundingBox], start: usize, count: usize) -> usize {
+        let mut bounds = BoundingBox::new();
+        let mut centers = BoundingBox::new();
+        for &index in &self.order[start..start + count] {
+            bounds.merge(&boxes[index as usize]);
+            centers.add_point(&boxes[index as usize].get_center());
+        }
+
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode { bounds, start, count, second_child: 0 });
+        if count <= LEAF_SIZE {
+            return node
+        }
+
+        let extent = centers.max - centers.min;
+        let axis = |point: Tuple| if extent.x >= extent.y && extent.x >= extent.z {
+            point.x
+        } else if extent.y >= extent.z {
+            point.y
+        } else {
+            point.z
+        };
+
+        let half = count / 2;
+        self.order[start..start + count].select_nth_unstable_by(half, |a, b| {
+            axis(boxes[*a as usize].get_center()).total_cmp(&axis(boxes[*b as usize].get_center()))
+        });
+
+        self.build(boxes, start, half);
+        let second_child = self.build(boxes, start + half, count - half);
+        self.nodes[node].count = 0;
+        self.nodes[node].second_child = second_child;
+        node
+    }
+
+    // Hands every item in a leaf the ray passes through to visit
+    pub(super) fn traverse<F: FnMut(usize)>(&self, ray: &Ray, mut visit: F) {
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.intersects(ray) {
+                continue
+            }
+
+            if node.count > 0 {
+                for &item in &self.order[node.start..node.start + node.count] {
+                    visit(item as usize);
+                }
+            } else {
+                stack.push(node.second_child);
+                stack.push(index + 1);
+            }
+        }
+    }
+
+    // Hands items to distance_to until none left can be closer to the point than the closest
+    // so far. Items distance_to rejects are infinitely far, returns the closest or None
+    pub(super) fn closest<F: FnMut(usize) -> Float>(&self, point: &Tuple, mut distance_to: F) -> Option<usize> {
+        let mut closest = (Float::INFINITY, None);
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if distance_to_box(&node.bounds, point) > closest.0 {
+                continue
+            }
+
+            if node.count > 0 {
+                for &item in &self.order[node.start..node.start + node.count] {
+                    let distance = distance_to(item as usize);
+                    if distance < closest.0 {
+                        closest = (distance, Some(item as usize));
+                    }
+                }
+            } else {
+                stack.push(node.second_child);
+                stack.push(index + 1);
+            }
+        }
+        closest.1
+    }
+}
+
+fn distance_to_box(bounds: &BoundingBox, point: &Tuple) -> Float {
+    let outside = |value: Float, min: Float, max: Float| (min - value).max(value - max).max(0.);
+    let x = outside(point.x, bounds.min.x, bounds.max.x);
+    let y = outside(point.y, bounds.min.y, bounds.max.y);
+    let z = outside(point.z, bounds.min.z, bounds.max.z);
+    (x * x + y * y + z * z).sqrt()
+}
@@ -0,0 +1,464 @@
+use crate::bounds::BoundingBox;
+use crate::intersection::{Intersection, sort_intersections};
+use crate::ray::Ray;
+use crate::sampling::orthonormal_basis;
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+use super::{Shape, ShapeProperties};
+
+// Levels of halving the curve is cut into at most, 1024 pieces
+const MAX_DEPTH: usize = 10;
+
+// Slack at the ends of the pieces so rays through a joint are not lost between them
+const JOINT_TOLERANCE: Float = 1e-9;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum CurveType {
+    // Flat strip turned to face every ray, cheap for hair and grass seen from afar
+    Ribbon,
+    // Round tube, for cables and anything seen up close
+    Tube
+}
+
+// Cubic Bézier curve swept with a width blended linearly from its start to its end. Rays
+// look at the curve head on: it is halved until the pieces are nearly straight and the
+// pieces whose bounds cover the ray are tested as line segments. u runs along the curve
+// and v across it, 0.5 on the axis. World lights curves with the Kajiya-Kay hair model
+#[derive(PartialEq, Debug, Clone)]
+pub struct Curve {
+    properties: ShapeProperties,
+    points: [Tuple; 4],
+    widths: (Float, Float),
+    curve_type: CurveType,
+    depth: usize,
+    bounds: BoundingBox
+}
+
+// Ray space has the ray start at the origin and run along z
+struct RayFrame {
+    origin: Tuple,
+    right: Tuple,
+    up: Tuple,
+    forward: Tuple,
+    speed: Float
+}
+
+impl RayFrame {
+    fn new(ray: &Ray) -> Self {
+        let speed = ray.direction.get_magnitude();
+        let forward = ray.direction * (1. / speed);
+        let (right, up) = orthonormal_basis(&forward);
+
+        RayFrame {
+            origin: ray.origin,
+            right,
+            up,
+            forward,
+            speed
+        }
+    }
+
+    fn to_ray_space(&self, point: &Tuple) -> Tuple {
+        let offset = *point - self.origin;
+        Tuple::new_point(offset.calculate_dot_product(&self.right), offset.calculate_dot_product(&self.up), offset.calculate_dot_product(&self.forward))
+    }
+}
+
+impl Curve {
+    pub fn new(points: [Tuple; 4], start_width: Float, end_width: Float, curve_type: CurveType) -> Self {
+        let radius = start_width.max(end_width) / 2.;
+        let mut bounds = BoundingBox::new();
+        for point in &points {
+            bounds.add_point(&(*point - Tuple::new_vector(radius, radius, radius)));
+            bounds.add_point(&(*point + Tuple::new_vector(radius, radius, radius)));
+        }
+
+        // Enough halvings for the pieces to stray from straight by under a twentieth of the width
+        let bend = (0..2).map(|i| ((points[i] - points[i + 1]) - (points[i + 1] - points[i + 2])).get_magnitude()).fold(0., Float::max);
+        let tolerance = start_width.max(end_width) / 20.;
+        let depth = if bend > 0. && tolerance > 0. {
+            ((2_f64.sqrt() * 6. * bend / (8. * tolerance)).log2() / 2.).ceil().clamp(0., MAX_DEPTH as Float) as usize
+        } else {
+            0
+        };
+
+        Curve {
+            properties: ShapeProperties::new(),
+            points,
+            widths: (start_width, end_width),
+            curve_type,
+            depth,
+            bounds
+        }
+    }
+
+    pub fn get_points(&self) -> &[Tuple; 4] {
+        &self.points
+    }
+
+    pub fn get_widths(&self) -> (Float, Float) {
+        self.widths
+    }
+
+    pub fn get_type(&self) -> CurveType {
+        self.curve_type
+    }
+
+    pub fn point_at(&self, u: Float) -> Tuple {
+        bezier(&self.points, u)
+    }
+
+    // Derivative of the curve, not normalized
+    pub fn tangent_at(&self, u: Float) -> Tuple {
+        let [p0, p1, p2, p3] = self.points;
+        ((p1 - p0) * ((1. - u) * (1. - u)) + (p2 - p1) * (2. * u * (1. - u)) + (p3 - p2) * (u * u)) * 3.
+    }
+
+    pub fn width_at(&self, u: Float) -> Float {
+        self.widths.0 * (1. - u) + self.widths.1 * u
+    }
+
+    // Coarse samples along the curve and a ternary search around the closest one
+    fn closest_parameter(&self, point: &Tuple) -> Float {
+        let distance = |u: Float| (*point - self.point_at(u)).get_magnitude();
+        let samples = 64;
+        let closest = (0..=samples).map(|i| i as Float / samples as Float)
+            .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+            .unwrap_or(0.);
+
+        let (mut low, mut high) = ((closest - 1. / samples as Float).max(0.), (closest + 1. / samples as Float).min(1.));
+        for _ in 0..40 {
+            let (a, b) = (low + (high - low) / 3., high - (high - low) / 3.);
+            if distance(a) < distance(b) {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+        (low + high) / 2.
+    }
+
+    // Points away from the axis, across the tangent
+    fn tube_normal(&self, point: &Tuple, u: Float) -> Tuple {
+        let offset = *point - self.point_at(u);
+        let tangent = self.tangent_at(u).normalize();
+        (offset - tangent * offset.calculate_dot_product(&tangent)).normalize()
+    }
+
+    fn intersect_piece<'a>(&'a self, frame: &RayFrame, points: &[Tuple; 4], (u0, u1): (Float, Float), depth: usize, xs: &mut Vec<Intersection<'a>>) {
+        // the piece stays within its control points, widened by the radius
+        let radius = self.width_at(u0).max(self.width_at(u1)) / 2.;
+        let (min_x, max_x) = points.iter().fold((Float::INFINITY, Float::NEG_INFINITY), |(low, high), p| (low.min(p.x), high.max(p.x)));
+        let (min_y, max_y) = points.iter().fold((Float::INFINITY, Float::NEG_INFINITY), |(low, high), p| (low.min(p.y), high.max(p.y)));
+        if min_x - radius > 0. || max_x + radius < 0. || min_y - radius > 0. || max_y + radius < 0. {
+            return
+        }
+
+        if depth > 0 {
+            let (first, second) = split(points);
+            let middle = (u0 + u1) / 2.;
+            self.intersect_piece(frame, &first, (u0, middle), depth - 1, xs);
+            self.intersect_piece(frame, &second, (middle, u1), depth - 1, xs);
+            return
+        }
+
+        match self.curve_type {
+            CurveType::Ribbon => self.intersect_ribbon(frame, points, (u0, u1), xs),
+            CurveType::Tube => self.intersect_tube(frame, points, (u0, u1), xs)
+        }
+    }
+
+    fn intersect_ribbon<'a>(&'a self, frame: &RayFrame, points: &[Tuple; 4], (u0, u1): (Float, Float), xs: &mut Vec<Intersection<'a>>) {
+        // where along the straightened piece the ray passes closest
+        let (start, end) = (points[0], points[3]);
+        let (dx, dy) = (end.x - start.x, end.y - start.y);
+        let length_squared = dx * dx + dy * dy;
+        if length_squared == 0. {
+            return
+        }
+
+        let s = -(start.x * dx + start.y * dy) / length_squared;
+        if !(-JOINT_TOLERANCE..=1. + JOINT_TOLERANCE).contains(&s) {
+            return
+        }
+
+        // pieces are visited in order, so a hit on the joint to the previous piece is the last one found
+        let s = s.clamp(0., 1.);
+        let u = u0 + (u1 - u0) * s;
+        if xs.last().is_some_and(|hit| (hit.u - u).abs() < JOINT_TOLERANCE) {
+            return
+        }
+
+        let center = bezier(points, s);
+        let (distance_squared, radius) = (center.x * center.x + center.y * center.y, self.width_at(u) / 2.);
+        if distance_squared > radius * radius {
+            return
+        }
+
+        let side = if dx * center.y - dy * center.x > 0. { 1. } else { -1. };
+        let v = 0.5 + side * distance_squared.sqrt() / (2. * radius);
+        let tangent = self.tangent_at(u).normalize();
+        let normal = (frame.forward - tangent * frame.forward.calculate_dot_product(&tangent)).negate().normalize();
+        xs.push(Intersection::new_with_normal(center.z / frame.speed, self, u, v, normal));
+    }
+
+    // The straightened piece is a cylinder around the line from its start to its end, with the
+    // width where the ray passes closest. Neighbouring pieces are cut apart by the plane across
+    // the curve at their joint, which belongs to the later piece
+    fn intersect_tube<'a>(&'a self, frame: &RayFrame, points: &[Tuple; 4], (u0, u1): (Float, Float), xs: &mut Vec<Intersection<'a>>) {
+        let (start, end) = (points[0], points[3]);
+        let axis = end - start;
+        let length_squared = axis.calculate_dot_product(&axis);
+        if length_squared == 0. {
+            return
+        }
+
+        // the ray's start and direction with the part along the axis taken out
+        let across = |vector: Tuple| vector - axis * (vector.calculate_dot_product(&axis) / length_squared);
+        let offset = across(Tuple::new_vector(-start.x, -start.y, -start.z));
+        let forward = across(Tuple::new_vector(0., 0., 1.));
+        let along_axis = |t: Float| (t * axis.z - start.calculate_dot_product(&axis)) / length_squared;
+
+        let a = forward.calculate_dot_product(&forward);
+        let b = offset.calculate_dot_product(&forward);
+        let closest = if a > EPSILON * EPSILON { -b / a } else { start.z };
+        let s = along_axis(closest).clamp(0., 1.);
+        let radius = self.width_at(u0 + (u1 - u0) * s) / 2.;
+
+        let nearest = offset + forward * closest;
+        let distance_squared = nearest.calculate_dot_product(&nearest);
+        if distance_squared > radius * radius {
+            return
+        }
+
+        let side = if axis.x * nearest.y - axis.y * nearest.x < 0. { 1. } else { -1. };
+        let v = 0.5 + side * distance_squared.sqrt() / (2. * radius);
+
+        // looking down the axis the tube is a disk at each end of the curve
+        if a <= EPSILON * EPSILON {
+            if u0 == 0. {
+                xs.push(Intersection::new_with_normal(start.z / frame.speed, self, 0., v, self.tangent_at(0.).normalize().negate()));
+            }
+            if u1 == 1. {
+                xs.push(Intersection::new_with_normal(end.z / frame.speed, self, 1., v, self.tangent_at(1.).normalize()));
+            }
+            return
+        }
+
+        let direction = |tangent: Tuple| if tangent.calculate_dot_product(&tangent) > 0. { tangent } else { axis };
+        let (enter, leave) = (direction(points[1] - start), direction(end - points[2]));
+        let half_chord = (b * b - a * (offset.calculate_dot_product(&offset) - radius * radius)).sqrt() / a;
+        for &t in &[closest - half_chord, closest + half_chord] {
+            let point = Tuple::new_point(0., 0., t);
+            let past_end = (point - end).calculate_dot_product(&leave);
+            if (point - start).calculate_dot_product(&enter) >= 0. && (past_end < 0. || (u1 == 1. && past_end == 0.)) {
+                xs.push(Intersection::new_with_uv(t / frame.speed, self, u0 + (u1 - u0) * along_axis(t).clamp(0., 1.), v));
+            }
+        }
+    }
+}
+
+fn bezier(points: &[Tuple; 4], u: Float) -> Tuple {
+    let weights = [(1. - u).powi(3), 3. * u * (1. - u).powi(2), 3. * u * u * (1. - u), u.powi(3)];
+    let component = |axis: fn(&Tuple) -> Float| points.iter().zip(weights.iter()).map(|(point, weight)| axis(point) * weight).sum::<Float>();
+    Tuple::new_point(component(|p| p.x), component(|p| p.y), component(|p| p.z))
+}
+
+// de Casteljau halving, both halves are cubic curves again
+pub(super) fn split(points: &[Tuple; 4]) -> ([Tuple; 4], [Tuple; 4]) {
+    let middle = |a: Tuple, b: Tuple| Tuple::new_point((a.x + b.x) / 2., (a.y + b.y) / 2., (a.z + b.z) / 2.);
+    let [p0, p1, p2, p3] = *points;
+    let (a, b, c) = (middle(p0, p1), middle(p1, p2), middle(p2, p3));
+    let (d, e) = (middle(a, b), middle(b, c));
+    let center = middle(d, e);
+
+    ([p0, a, d, center], [center, e, c, p3])
+}
+
+impl Shape for Curve {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        if !self.bounds.intersects(ray) {
+            return vec![]
+        }
+
+        let frame = RayFrame::new(ray);
+        let points = [
+            frame.to_ray_space(&self.points[0]),
+            frame.to_ray_space(&self.points[1]),
+            frame.to_ray_space(&self.points[2]),
+            frame.to_ray_space(&self.points[3])
+        ];
+
+        let mut xs = vec![];
+        self.intersect_piece(&frame, &points, (0., 1.), self.depth, &mut xs);
+        sort_intersections(&mut xs);
+        xs
+    }
+
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        self.tube_normal(point, self.closest_parameter(point))
+    }
+
+    // Ribbons face the ray that hit them, tubes point away from the axis
+    fn local_normal_at_hit(&self, point: &Tuple, hit: &Intersection) -> Tuple {
+        match hit.normal {
+            Some(normal) => normal,
+            None => self.tube_normal(point, hit.u)
+        }
+    }
+
+    fn local_tangent_at_hit(&self, _point: &Tuple, hit: &Intersection) -> Option<Tuple> {
+        Some(self.tangent_at(hit.u).normalize())
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::utils::NumberUtils;
+
+    fn straight(width: Float, curve_type: CurveType) -> Curve {
+        let points = [
+            Tuple::new_point(-1., 0., 0.),
+            Tuple::new_point(-1. / 3., 0., 0.),
+            Tuple::new_point(1. / 3., 0., 0.),
+            Tuple::new_point(1., 0., 0.)
+        ];
+        Curve::new(points, width, width, curve_type)
+    }
+
+    fn arch() -> Curve {
+        let points = [
+            Tuple::new_point(-1., 0., 0.),
+            Tuple::new_point(-1., 2., 0.),
+            Tuple::new_point(1., 2., 0.),
+            Tuple::new_point(1., 0., 0.)
+        ];
+        Curve::new(points, 0.1, 0.1, CurveType::Tube)
+    }
+
+    #[test]
+    fn test_evaluating_a_curve() {
+        let curve = arch();
+
+        assert_eq!(curve.point_at(0.), Tuple::new_point(-1., 0., 0.));
+        assert_eq!(curve.point_at(0.5), Tuple::new_point(0., 1.5, 0.));
+        assert_eq!(curve.tangent_at(0.5), Tuple::new_vector(3., 0., 0.));
+        assert_eq!(curve.tangent_at(0.), Tuple::new_vector(0., 6., 0.));
+        assert_eq!(Curve::new(*curve.get_points(), 0.2, 0.1, CurveType::Ribbon).width_at(0.5), 0.15000000000000002);
+        assert_eq!(straight(0.1, CurveType::Ribbon).depth, 0);
+        assert_eq!(curve.depth > 0, true);
+    }
+
+    #[test]
+    fn test_ribbons_face_the_ray() {
+        let ribbon = straight(0.2, CurveType::Ribbon);
+        let ray = Ray::new(Tuple::new_point(0.25, 0.05, -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = ribbon.local_intersect(&ray);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 5.), true);
+        assert_eq!(NumberUtils::compare_floats(xs[0].u, 0.625), true);
+        // a quarter of the width off the axis
+        assert_eq!(NumberUtils::compare_floats((xs[0].v - 0.5).abs(), 0.25), true);
+
+        let point = ray.position(xs[0].t);
+        assert_eq!(ribbon.local_normal_at_hit(&point, &xs[0]).is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+        assert_eq!(ribbon.local_tangent_at_hit(&point, &xs[0]), Some(Tuple::new_vector(1., 0., 0.)));
+
+        // from above the same ribbon turns to face up
+        let down = Ray::new(Tuple::new_point(0.25, 5., 0.05), Tuple::new_vector(0., -2., 0.));
+        let xs = ribbon.local_intersect(&down);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 2.5), true);
+        assert_eq!(xs[0].normal.unwrap().is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+
+        let past = Ray::new(Tuple::new_point(0.25, 0.15, -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(ribbon.local_intersect(&past).len(), 0);
+    }
+
+    #[test]
+    fn test_tubes_are_round() {
+        let tube = straight(0.2, CurveType::Tube);
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = tube.local_intersect(&ray);
+
+        assert_eq!(xs.len(), 2);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 4.9) && NumberUtils::compare_floats(xs[1].t, 5.1), true);
+        assert_eq!(tube.local_normal_at_hit(&ray.position(xs[0].t), &xs[0]).is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+        assert_eq!(tube.local_normal_at(&Tuple::new_point(0.5, 0.1, 0.)).is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+
+        // a tapered tube is only hit where it is still wide enough
+        let tapered = Curve::new(*tube.get_points(), 0.2, 0.02, CurveType::Tube);
+        let thick = Ray::new(Tuple::new_point(-0.9, 0.04, -5.), Tuple::new_vector(0., 0., 1.));
+        let thin = Ray::new(Tuple::new_point(0.9, 0.04, -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(tapered.local_intersect(&thick).len(), 2);
+        assert_eq!(tapered.local_intersect(&thin).len(), 0);
+    }
+
+    #[test]
+    fn test_tubes_seen_at_an_angle_and_end_on() {
+        let tube = straight(0.2, CurveType::Tube);
+
+        // slanting along the axis the ray spends longer inside the tube
+        let slanted = Ray::new(Tuple::new_point(-5., 0., -5.), Tuple::new_vector(1., 0., 1.));
+        let xs = tube.local_intersect(&slanted);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 4.9) && NumberUtils::compare_floats(xs[1].t, 5.1), true);
+
+        // looking down the axis the ends are disks
+        let along = Ray::new(Tuple::new_point(-5., 0.05, 0.), Tuple::new_vector(1., 0., 0.));
+        let xs = tube.local_intersect(&along);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 4.) && NumberUtils::compare_floats(xs[1].t, 6.), true);
+        assert_eq!(tube.local_normal_at_hit(&along.position(xs[0].t), &xs[0]).is_equal_to(&Tuple::new_vector(-1., 0., 0.)), true);
+
+        let beside = Ray::new(Tuple::new_point(-5., 0.15, 0.), Tuple::new_vector(1., 0., 0.));
+        assert_eq!(tube.local_intersect(&beside).len(), 0);
+    }
+
+    #[test]
+    fn test_intersecting_a_bent_curve() {
+        let curve = arch();
+
+        // down through the top of the arch, and past its legs
+        let top = Ray::new(Tuple::new_point(0., 5., 0.), Tuple::new_vector(0., -1., 0.));
+        let xs = curve.local_intersect(&top);
+        assert_eq!(xs.len(), 2);
+        assert_eq!((xs[0].t - 3.45).abs() < 1e-3, true);
+        assert_eq!((xs[0].u - 0.5).abs() < 1e-3, true);
+
+        let inside = Ray::new(Tuple::new_point(0., 0.5, -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(curve.local_intersect(&inside).len(), 0);
+
+        let leg = Ray::new(Tuple::new_point(-0.95, 0.3, -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = curve.local_intersect(&leg);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(curve.point_at(xs[0].u).x < -0.9, true);
+
+        // shading gets the world space tangent
+        let mut turned = arch();
+        turned.set_transform(crate::matrix::Matrix::rotation_z(std::f64::consts::PI / 2.));
+        let ray = Ray::new(Tuple::new_point(-5., 0., 0.), Tuple::new_vector(1., 0., 0.));
+        let xs = turned.intersect(&ray);
+        let comps = xs[0].prepare_computations(&ray, &xs);
+        assert_eq!(comps.tangent.unwrap().is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+
+        let bounds = curve.local_bounds();
+        assert_eq!(bounds.min, Tuple::new_point(-1.05, -0.05, -0.05));
+        assert_eq!(bounds.max, Tuple::new_point(1.05, 2.05, 0.05));
+    }
+}
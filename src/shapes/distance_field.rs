@@ -0,0 +1,227 @@
+use crate::bounds::BoundingBox;
+use crate::color::Color;
+use crate::fractal::Palette;
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::sdf::Sdf;
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+use super::{Shape, ShapeProperties};
+
+// Surface of a signed distance function found by sphere tracing: the ray advances by the
+// distance to the nearest surface until it gets within epsilon. Every crossing inside the
+// bounds is reported, entering and leaving, so refraction sees the shape as a solid
+#[derive(PartialEq, Debug, Clone)]
+pub struct DistanceField {
+    properties: ShapeProperties,
+    sdf: Sdf,
+    bounds: BoundingBox,
+    pub max_steps: usize,
+    // Well below the offset shading moves hit points by, so shadow rays start clear of the surface
+    pub epsilon: Float,
+    // How far rays are traced when the bounds are infinite, like for repeated shapes
+    pub max_distance: Float,
+    // Fraction of the distance each step advances, below 1 for twisted or otherwise
+    // distorted fields that can overestimate the distance
+    pub step_scale: Float,
+    // Colors the surface by the orbit trap of fractals in the field instead of the material
+    pub palette: Option<Palette>
+}
+
+impl DistanceField {
+    pub fn new(sdf: Sdf) -> Self {
+        DistanceField {
+            properties: ShapeProperties::new(),
+            bounds: sdf.get_bounds(),
+            sdf,
+            max_steps: 512,
+            epsilon: EPSILON / 10.,
+            max_distance: 100.,
+            step_scale: 1.,
+            palette: None
+        }
+    }
+
+    pub fn get_sdf(&self) -> &Sdf {
+        &self.sdf
+    }
+}
+
+impl Shape for DistanceField {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    // Marches on the absolute distance so it keeps going through the inside after a hit. A
+    // touch that does not cross the surface is dropped
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let (t_enter, t_exit) = match self.bounds.intersection_range(ray) {
+            Some(range) => range,
+            None => return vec![]
+        };
+
+        let speed = ray.direction.get_magnitude();
+        let limit = self.max_distance / speed;
+        let (mut t, end) = (t_enter.max(-limit), t_exit.min(limit));
+
+        let mut xs = vec![];
+        // Which side the ray was on before the surface it is touching right now
+        let mut outside = self.sdf.distance(&ray.position(t)) >= 0.;
+        let mut touching = false;
+
+        for _ in 0..self.max_steps {
+            if t > end {
+                break;
+            }
+
+            let distance = self.sdf.distance(&ray.position(t));
+
+            if distance.abs() < self.epsilon {
+                if !touching {
+                    touching = true;
+                    xs.push(Intersection::new(t, self));
+                }
+            } else {
+                if touching && (distance > 0.) == outside {
+                    xs.pop();
+                }
+                // estimated distances can jump across the surface without ever getting close
+                if !touching && (distance > 0.) != outside {
+                    xs.push(Intersection::new(t, self));
+                }
+                touching = false;
+                outside = distance > 0.;
+            }
+
+            t += (distance.abs() * self.step_scale).max(self.epsilon) / speed;
+        }
+
+        xs
+    }
+
+    // Central differences of the field, the gradient points away from the surface
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        let h = self.epsilon;
+        let difference = |offset: Tuple| self.sdf.distance(&(*point + offset)) - self.sdf.distance(&(*point - offset));
+
+        Tuple::new_vector(
+            difference(Tuple::new_vector(h, 0., 0.)),
+            difference(Tuple::new_vector(0., h, 0.)),
+            difference(Tuple::new_vector(0., 0., h))
+        ).normalize()
+    }
+
+    fn local_color_at_hit(&self, point: &Tuple, _hit: &Intersection) -> Option<Color> {
+        let palette = self.palette.as_ref()?;
+        self.sdf.orbit_trap(point).map(|trap| palette.color_at(trap))
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn close(a: Float, b: Float) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_sphere_tracing_a_sphere() {
+        let field = DistanceField::new(Sdf::new_sphere(1.));
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = field.local_intersect(&ray);
+
+        assert_eq!(xs.len(), 2);
+        assert_eq!(close(xs[0].t, 4.) && close(xs[1].t, 6.), true);
+        assert_eq!(field.local_normal_at(&Tuple::new_point(0., 0., -1.)).is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+
+        // from the inside the entry lies behind the ray
+        let inside = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+        let xs = field.local_intersect(&inside);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(close(xs[0].t, -1.) && close(xs[1].t, 1.), true);
+    }
+
+    #[test]
+    fn test_torus_hole_and_transform() {
+        let mut field = DistanceField::new(Sdf::new_torus(1., 0.25));
+
+        let through_hole = Ray::new(Tuple::new_point(0., 5., 0.), Tuple::new_vector(0., -1., 0.));
+        assert_eq!(field.intersect(&through_hole).len(), 0);
+
+        let through_ring = Ray::new(Tuple::new_point(1., 5., 0.), Tuple::new_vector(0., -1., 0.));
+        let xs = field.intersect(&through_ring);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(close(xs[0].t, 4.75), true);
+
+        // a doubled torus keeps its distances right through the transform
+        field.set_transform(crate::matrix::Matrix::scaling(2., 2., 2.));
+        let scaled = Ray::new(Tuple::new_point(2., 5., 0.), Tuple::new_vector(0., -1., 0.));
+        assert_eq!(close(field.intersect(&scaled)[0].t, 4.5), true);
+    }
+
+    #[test]
+    fn test_grazing_and_missing() {
+        let field = DistanceField::new(Sdf::new_box(Tuple::new_vector(1., 1., 1.)));
+        let miss = Ray::new(Tuple::new_point(0., 1.5, -5.), Tuple::new_vector(0., 0., 1.));
+        let along_top = Ray::new(Tuple::new_point(0., 1. + EPSILON / 100., -5.), Tuple::new_vector(0., 0., 1.));
+
+        assert_eq!(field.local_intersect(&miss).len(), 0);
+        assert_eq!(field.local_intersect(&along_top).len(), 0);
+    }
+
+    #[test]
+    fn test_repeated_and_twisted_fields() {
+        let mut spheres = DistanceField::new(Sdf::new_sphere(0.5).repeat(Tuple::new_vector(0., 0., 3.)));
+        spheres.max_distance = 10.;
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+        let ahead: Vec<Float> = spheres.local_intersect(&ray).iter().map(|x| x.t).filter(|t| *t > 0.).collect();
+
+        // the sphere around the origin is left at 0.5, the next ones are entered at 2.5 and 5.5
+        assert_eq!(close(ahead[0], 0.5) && close(ahead[1], 2.5) && close(ahead[3], 5.5), true);
+
+        let mut twisted = DistanceField::new(Sdf::new_box(Tuple::new_vector(1., 1., 0.25)).twist(PI / 4.));
+        twisted.step_scale = 0.5;
+        let side = Ray::new(Tuple::new_point(0.5, 0.9, -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = twisted.local_intersect(&side);
+        assert_eq!(xs.len(), 2);
+
+        // near the top the box is turned by almost 45 degrees so it reaches further along z
+        assert_eq!(xs[0].t < 4.75 - 0.1, true);
+        let normal = twisted.local_normal_at(&side.position(xs[0].t));
+        assert_eq!(normal.z < 0., true);
+    }
+
+    #[test]
+    fn test_fractals_colored_by_orbit_trap() {
+        use crate::fractal::Fractal;
+        use crate::quaternion::Quaternion;
+
+        // q² has the unit ball as its Julia set
+        let mut ball = DistanceField::new(Sdf::new_fractal(Fractal::new_julia(Quaternion::new(0., 0., 0., 0.), 16)));
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let t = ball.intersect(&ray)[0].t;
+        assert_eq!((t - 4.).abs() < 1e-3, true);
+
+        let point = ray.position(t);
+        assert_eq!(ball.color_at_hit(&point, &Intersection::new(t, &ball), 0.), Color::white());
+        ball.palette = Some(Palette::new(vec![Color::red(), Color::blue()]));
+        let color = ball.color_at_hit(&point, &Intersection::new(t, &ball), 0.);
+        assert_eq!(color.green == 0. && color.red + color.blue > 0.99, true);
+
+        let bulb = DistanceField::new(Sdf::new_fractal(Fractal::new_mandelbulb(8., 8)));
+        let xs = bulb.intersect(&ray);
+        assert_eq!(!xs.is_empty() && xs[0].t > 3. && xs[0].t < 5., true);
+    }
+}
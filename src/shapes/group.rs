@@ -0,0 +1,410 @@
+use crate::animation::AnimatedTransform;
+use crate::bounds::BoundingBox;
+use crate::clipping::clip_intersections;
+use crate::intersection::{Intersection, sort_intersections};
+use crate::material::Material;
+use crate::matrix::{Matrix, MatrixType};
+use crate::ray::Ray;
+use crate::tuples::Tuple;
+
+use super::{Shape, ShapeProperties, Triangle, Visibility, LightLinks};
+
+// Collection of shapes treated as one. The group's transform is baked into its children
+// when they are added or when it changes, so children always hold their full transform
+// and normals need no walk up to the parent. Rays missing the bounds skip every child
+#[derive(Debug)]
+pub struct Group {
+    properties: ShapeProperties,
+    name: String,
+    children: Vec<Box<dyn Shape>>,
+    bounds: BoundingBox,
+    // Set on the group and handed to children added later as well. Children of a group
+    // these were never set on keep their own
+    material: Option<Material>,
+    visibility: Option<Visibility>,
+    light_links: Option<LightLinks>
+}
+
+impl Group {
+    pub fn new() -> Self {
+        Group::new_named("")
+    }
+
+    pub fn new_named(name: &str) -> Self {
+        Group {
+            properties: ShapeProperties::new(),
+            name: name.to_string(),
+            children: vec![],
+            bounds: BoundingBox::new(),
+            material: None,
+            visibility: None,
+            light_links: None
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_children(&self) -> &[Box<dyn Shape>] {
+        &self.children
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    pub fn add_child(&mut self, mut child: Box<dyn Shape>) {
+        let transform = Matrix::multiply(&self.properties.transform, child.get_transform());
+        child.set_transform(transform);
+        if let Some(material) = &self.material {
+            child.set_material(material.clone());
+        }
+        if let Some(visibility) = self.visibility {
+            child.set_visibility(visibility);
+        }
+        if let Some(light_links) = &self.light_links {
+            child.set_light_links(light_links.clone());
+        }
+        if let Some(motion) = &self.properties.motion {
+            child.set_motion(child_motion(motion, &self.properties.inverse, child.as_ref()));
+        }
+        self.bounds.merge(&child.get_bounds());
+        self.children.push(child);
+    }
+
+    fn update_bounds(&mut self) {
+        self.bounds = BoundingBox::new();
+        for child in &self.children {
+            self.bounds.merge(&child.get_bounds());
+        }
+    }
+}
+
+// The group's keyframes with the child's place inside the group applied after them, inverse
+// undoes the group's transform that is baked into the child
+fn child_motion(motion: &AnimatedTransform, inverse: &MatrixType, child: &dyn Shape) -> AnimatedTransform {
+    let relative = Matrix::multiply(inverse, child.get_transform());
+    let keyframes: Vec<(_, MatrixType)> = motion.get_keyframes().iter()
+        .map(|keyframe| (keyframe.time, Matrix::multiply(&keyframe.to_matrix(), &relative)))
+        .collect();
+
+    AnimatedTransform::from_matrices(&keyframes)
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Group::new()
+    }
+}
+
+impl Shape for Group {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    // Children are already in world space, so the ray is not transformed
+    fn intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        clip_intersections(&self.properties.clips, ray, self.local_intersect(ray))
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        if !self.bounds.intersects(ray) {
+            return vec![]
+        }
+
+        let mut xs: Vec<Intersection> = self.children.iter()
+            .flat_map(|child| child.intersect(ray))
+            .collect();
+
+        sort_intersections(&mut xs);
+        xs
+    }
+
+    // Intersections always point at a child, never at the group itself
+    fn local_normal_at(&self, _point: &Tuple) -> Tuple {
+        panic!("Groups have no normal of their own, ask the child that was hit")
+    }
+
+    fn is_group(&self) -> bool {
+        true
+    }
+
+    fn for_each_child_mut(&mut self, visit: &mut dyn FnMut(&mut Box<dyn Shape>)) {
+        for child in &mut self.children {
+            visit(child);
+        }
+        self.update_bounds();
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+
+    fn get_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+
+    fn tessellate(&self, segments: usize) -> Vec<Triangle> {
+        self.children.iter().flat_map(|child| child.tessellate(segments)).collect()
+    }
+
+    fn set_transform(&mut self, transform: MatrixType) {
+        let change = Matrix::multiply(&transform, &self.properties.inverse);
+
+        for child in &mut self.children {
+            let child_transform = Matrix::multiply(&change, child.get_transform());
+            child.set_transform(child_transform);
+        }
+
+        self.properties.set_transform(transform);
+        // the children's keyframes hold their place relative to the old transform
+        if let Some(motion) = &self.properties.motion {
+            for child in &mut self.children {
+                child.set_motion(child_motion(motion, &self.properties.inverse, child.as_ref()));
+            }
+        }
+        self.update_bounds();
+    }
+
+    // Every child follows the keyframes, keeping its place inside the group
+    fn set_motion(&mut self, motion: AnimatedTransform) {
+        for child in &mut self.children {
+            let child_motion = child_motion(&motion, &self.properties.inverse, child.as_ref());
+            child.set_motion(child_motion);
+        }
+
+        self.properties.motion = Some(motion);
+        self.update_bounds();
+    }
+
+    fn set_material(&mut self, material: Material) {
+        for child in &mut self.children {
+            child.set_material(material.clone());
+        }
+
+        self.material = Some(material.clone());
+        self.properties.material = material;
+    }
+
+    fn set_visibility(&mut self, visibility: Visibility) {
+        for child in &mut self.children {
+            child.set_visibility(visibility);
+        }
+
+        self.visibility = Some(visibility);
+        self.properties.visibility = visibility;
+    }
+
+    fn set_light_links(&mut self, light_links: LightLinks) {
+        for child in &mut self.children {
+            child.set_light_links(light_links.clone());
+        }
+
+        self.light_links = Some(light_links.clone());
+        self.properties.light_links = light_links;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::shapes::Sphere;
+
+    fn sphere_at(x: f64, y: f64, z: f64) -> Box<dyn Shape> {
+        let mut sphere = Sphere::new();
+        sphere.set_transform(Matrix::translation(x, y, z));
+        Box::new(sphere)
+    }
+
+    #[test]
+    fn test_empty_group() {
+        let group = Group::new();
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+
+        assert_eq!(group.is_empty(), true);
+        assert_eq!(group.intersect(&ray).len(), 0);
+    }
+
+    #[test]
+    fn test_intersect_children_in_order() {
+        let mut group = Group::new();
+        group.add_child(Box::new(Sphere::new()));
+        group.add_child(sphere_at(0., 0., -3.));
+        group.add_child(sphere_at(5., 0., 0.));
+
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = group.intersect(&ray);
+
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[0].is_same_object(group.get_children()[1].as_ref()), true);
+        assert_eq!(xs[2].is_same_object(group.get_children()[0].as_ref()), true);
+    }
+
+    #[test]
+    fn test_group_transform_reaches_children() {
+        let mut group = Group::new();
+        group.set_transform(Matrix::scaling(2., 2., 2.));
+        group.add_child(sphere_at(5., 0., 0.));
+
+        let ray = Ray::new(Tuple::new_point(10., 0., -10.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(group.intersect(&ray).len(), 2);
+
+        // a transform set later moves the children along
+        group.set_transform(Matrix::translation(0., 10., 0.));
+        assert_eq!(group.intersect(&ray).len(), 0);
+        let moved = Ray::new(Tuple::new_point(5., 10., -10.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(group.intersect(&moved).len(), 2);
+    }
+
+    #[test]
+    fn test_normal_on_child_of_nested_groups() {
+        let mut inner = Group::new();
+        inner.set_transform(Matrix::scaling(1., 2., 3.));
+        inner.add_child(sphere_at(5., 0., 0.));
+
+        let mut outer = Group::new();
+        outer.set_transform(Matrix::rotation_y(std::f64::consts::PI / 2.));
+        outer.add_child(Box::new(inner));
+
+        let child = outer.get_children()[0].get_bounds();
+        let normal = outer.intersect(&Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., -1.)))[0]
+            .object.normal_at(&Tuple::new_point(1.7321, 1.1547, -5.5774));
+
+        assert_eq!(child.contains_point(&Tuple::new_point(0., 0., -5.)), true);
+        assert_eq!(normal.is_equal_to(&Tuple::new_vector(0.2857, 0.42854, -0.85716)), true);
+    }
+
+    #[test]
+    fn test_bounds_skip_children() {
+        let mut group = Group::new();
+        group.add_child(sphere_at(0., 0., 0.));
+        group.add_child(sphere_at(4., 0., 0.));
+
+        assert_eq!(group.get_bounds().min, Tuple::new_point(-1., -1., -1.));
+        assert_eq!(group.get_bounds().max, Tuple::new_point(5., 1., 1.));
+
+        let above = Ray::new(Tuple::new_point(0., 5., -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(group.local_intersect(&above).len(), 0);
+    }
+
+    #[test]
+    fn test_material_spreads_to_children() {
+        let mut group = Group::new_named("teapot");
+        group.add_child(Box::new(Sphere::new()));
+        let mut material = Material::new();
+        material.ambient = 1.;
+        group.set_material(material.clone());
+
+        assert_eq!(group.get_name(), "teapot");
+        assert_eq!(group.get_children()[0].get_material(), &material);
+    }
+
+    #[test]
+    fn test_visibility_and_light_links_spread_to_children() {
+        let mut group = Group::new();
+        group.add_child(Box::new(Sphere::new()));
+        group.set_visibility(Visibility { camera: false, ..Visibility::new() });
+        group.set_light_links(LightLinks::Include(vec![1]));
+
+        let child = &group.get_children()[0];
+        assert_eq!(child.is_visible_to(crate::ray::RayKind::Camera), false);
+        assert_eq!(child.is_visible_to(crate::ray::RayKind::Shadow), true);
+        assert_eq!(child.get_properties().light_links, LightLinks::Include(vec![1]));
+    }
+
+    #[test]
+    fn test_children_added_later_take_the_group_settings() {
+        let mut group = Group::new();
+        let mut material = Material::new();
+        material.ambient = 1.;
+        group.set_material(material.clone());
+        group.set_visibility(Visibility { shadows: false, ..Visibility::new() });
+        group.set_light_links(LightLinks::Exclude(vec![0]));
+        group.add_child(Box::new(Sphere::new()));
+
+        let child = &group.get_children()[0];
+        assert_eq!(child.get_material(), &material);
+        assert_eq!(child.is_visible_to(crate::ray::RayKind::Shadow), false);
+        assert_eq!(child.get_properties().light_links, LightLinks::Exclude(vec![0]));
+
+        // without settings of its own the group leaves its children's alone
+        let mut own = Material::new();
+        own.ambient = 0.5;
+        let mut sphere = Sphere::new();
+        sphere.set_material(own.clone());
+        let mut plain = Group::new();
+        plain.add_child(Box::new(sphere));
+        assert_eq!(plain.get_children()[0].get_material(), &own);
+    }
+
+    #[test]
+    fn test_moving_group() {
+        let mut group = Group::new();
+        group.add_child(sphere_at(0., 0., 0.));
+        group.set_motion(AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(-3., 0., 0.)),
+            (1., Matrix::translation(3., 0., 0.)),
+        ]));
+
+        let origin = Tuple::new_point(3., 0., -5.);
+        let direction = Tuple::new_vector(0., 0., 1.);
+        assert_eq!(group.intersect(&Ray::new_at_time(origin, direction, 0.)).len(), 0);
+        assert_eq!(group.intersect(&Ray::new_at_time(origin, direction, 1.)).len(), 2);
+    }
+
+    #[test]
+    fn test_child_added_to_moving_group_follows_it() {
+        let mut group = Group::new();
+        group.set_motion(AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(-3., 0., 0.)),
+            (1., Matrix::translation(3., 0., 0.)),
+        ]));
+        group.add_child(sphere_at(0., 2., 0.));
+
+        let origin = Tuple::new_point(3., 2., -5.);
+        let direction = Tuple::new_vector(0., 0., 1.);
+        assert_eq!(group.intersect(&Ray::new_at_time(origin, direction, 0.)).len(), 0);
+        assert_eq!(group.intersect(&Ray::new_at_time(origin, direction, 1.)).len(), 2);
+        assert_eq!(group.get_bounds().max.x, 4.);
+    }
+
+    #[test]
+    fn test_transforming_moving_group_keeps_children_on_its_keyframes() {
+        let motion = AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(-3., 0., 0.)),
+            (1., Matrix::translation(3., 0., 0.)),
+        ]);
+        let mut moved_first = Group::new();
+        moved_first.add_child(sphere_at(0., 2., 0.));
+        moved_first.set_motion(motion.clone());
+        moved_first.set_transform(Matrix::scaling(2., 2., 2.));
+
+        let mut transformed_first = Group::new();
+        transformed_first.add_child(sphere_at(0., 2., 0.));
+        transformed_first.set_transform(Matrix::scaling(2., 2., 2.));
+        transformed_first.set_motion(motion);
+
+        for &time in &[0., 0.5, 1.] {
+            let moved = moved_first.get_children()[0].get_properties().get_transform_at(time);
+            let transformed = transformed_first.get_children()[0].get_properties().get_transform_at(time);
+            assert_eq!(Matrix::are_equal(&moved, &transformed), true);
+        }
+
+        let origin = Tuple::new_point(3., 2., -5.);
+        let direction = Tuple::new_vector(0., 0., 1.);
+        assert_eq!(moved_first.intersect(&Ray::new_at_time(origin, direction, 0.)).len(), 0);
+        assert_eq!(moved_first.intersect(&Ray::new_at_time(origin, direction, 1.)).len(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_group_has_no_normal() {
+        Group::new().local_normal_at(&Tuple::new_point(0., 0., 0.));
+    }
+}
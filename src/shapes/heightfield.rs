@@ -0,0 +1,337 @@
+use crate::bounds::BoundingBox;
+use crate::canvas::Canvas;
+use crate::intersection::{Intersection, sort_intersections};
+use crate::medium::fractal_noise;
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+use super::{Shape, ShapeProperties, Triangle};
+use super::triangle::intersect_triangle;
+
+// Terrain from a grid of heights spread over x and z from -1 to 1, the height is y. Each
+// cell is a pair of triangles split along the diagonal from its +x to its +z corner, with
+// normals blended from the corners for smooth shading. Rays walk the grid cell by cell
+// instead of testing every triangle
+#[derive(PartialEq, Debug, Clone)]
+pub struct Heightfield {
+    properties: ShapeProperties,
+    width: usize,
+    depth: usize,
+    heights: Vec<Float>,
+    normals: Vec<Tuple>,
+    // Lowest and highest corner of every cell, to skip cells the ray passes above or below
+    cell_ranges: Vec<(Float, Float)>,
+    bounds: BoundingBox
+}
+
+impl Heightfield {
+    // Heights row by row, width samples along x for each of the depth rows along z
+    pub fn new(width: usize, depth: usize, heights: Vec<Float>) -> Self {
+        assert!(width >= 2 && depth >= 2, "a heightfield needs at least 2 x 2 samples");
+        assert_eq!(heights.len(), width * depth, "one height per sample");
+
+        let mut field = Heightfield {
+            properties: ShapeProperties::new(),
+            width,
+            depth,
+            heights,
+            normals: vec![],
+            cell_ranges: vec![],
+            bounds: BoundingBox::new()
+        };
+
+        field.normals = (0..depth).flat_map(|z| (0..width).map(move |x| (x, z))).map(|(x, z)| field.vertex_normal(x, z)).collect();
+        field.cell_ranges = (0..depth - 1).flat_map(|z| (0..width - 1).map(move |x| (x, z))).map(|(x, z)| {
+            let corners = [field.get_height(x, z), field.get_height(x + 1, z), field.get_height(x, z + 1), field.get_height(x + 1, z + 1)];
+            (corners.iter().copied().fold(Float::INFINITY, Float::min), corners.iter().copied().fold(Float::NEG_INFINITY, Float::max))
+        }).collect();
+
+        let (low, high) = field.cell_ranges.iter().fold((Float::INFINITY, Float::NEG_INFINITY), |(low, high), &(min, max)| (low.min(min), high.max(max)));
+        field.bounds = BoundingBox::new_with_bounds(Tuple::new_point(-1., low, -1.), Tuple::new_point(1., high, 1.));
+        field
+    }
+
+    // Luminance of every pixel, image columns run along x and rows along z
+    pub fn from_canvas(image: &Canvas) -> Self {
+        let (width, depth) = (image.get_width(), image.get_height());
+        let heights = (0..depth).flat_map(|z| (0..width).map(move |x| image.get_pixel_at(x, z).get_luminance() as Float)).collect();
+        Heightfield::new(width, depth, heights)
+    }
+
+    // Fractal noise between 0 and 1, frequency is in features across the whole field
+    pub fn from_noise(width: usize, depth: usize, frequency: Float, octaves: usize, seed: u64) -> Self {
+        let heights = (0..depth).flat_map(|z| (0..width).map(move |x| {
+            let point = Tuple::new_point(x as Float / (width - 1) as Float, 0., z as Float / (depth - 1) as Float);
+            fractal_noise(&(point * frequency), octaves, seed)
+        })).collect();
+        Heightfield::new(width, depth, heights)
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn get_height(&self, x: usize, z: usize) -> Float {
+        self.heights[z * self.width + x]
+    }
+
+    // Height of the surface above a point in object space, None outside of the field
+    pub fn height_at(&self, x: Float, z: Float) -> Option<Float> {
+        if !(-1. ..=1.).contains(&x) || !(-1. ..=1.).contains(&z) {
+            return None
+        }
+
+        let (face, u, v) = self.locate(x, z);
+        let [p1, p2, p3] = self.face_points(face);
+        Some(p1.y * (1. - u - v) + p2.y * u + p3.y * v)
+    }
+
+    fn sample_point(&self, x: usize, z: usize) -> Tuple {
+        Tuple::new_point(
+            x as Float / (self.width - 1) as Float * 2. - 1.,
+            self.get_height(x, z),
+            z as Float / (self.depth - 1) as Float * 2. - 1.
+        )
+    }
+
+    // Central differences inside the grid, one sided along the border
+    fn vertex_normal(&self, x: usize, z: usize) -> Tuple {
+        let (left, right) = (x.saturating_sub(1), (x + 1).min(self.width - 1));
+        let (back, front) = (z.saturating_sub(1), (z + 1).min(self.depth - 1));
+        let spacing_x = 2. / (self.width - 1) as Float;
+        let spacing_z = 2. / (self.depth - 1) as Float;
+
+        let slope_x = (self.get_height(right, z) - self.get_height(left, z)) / ((right - left) as Float * spacing_x);
+        let slope_z = (self.get_height(x, front) - self.get_height(x, back)) / ((front - back) as Float * spacing_z);
+        Tuple::new_vector(-slope_x, 1., -slope_z).normalize()
+    }
+
+    // Grid corners of a face, faces are numbered two per cell
+    fn face_corners(&self, face: usize) -> [(usize, usize); 3] {
+        let cell = face / 2;
+        let (x, z) = (cell % (self.width - 1), cell / (self.width - 1));
+
+        if face.is_multiple_of(2) {
+            [(x, z), (x + 1, z), (x, z + 1)]
+        } else {
+            [(x + 1, z + 1), (x, z + 1), (x + 1, z)]
+        }
+    }
+
+    fn face_points(&self, face: usize) -> [Tuple; 3] {
+        self.face_corners(face).map(|(x, z)| self.sample_point(x, z))
+    }
+
+    fn normal_on_face(&self, face: usize, u: Float, v: Float) -> Tuple {
+        let [n1, n2, n3] = self.face_corners(face).map(|(x, z)| self.normals[z * self.width + x]);
+        n2 * u + n3 * v + n1 * (1. - u - v)
+    }
+
+    // Face and barycentric coordinates of the surface above a point
+    fn locate(&self, x: Float, z: Float) -> (usize, Float, Float) {
+        let grid_x = (x + 1.) / 2. * (self.width - 1) as Float;
+        let grid_z = (z + 1.) / 2. * (self.depth - 1) as Float;
+        let cell_x = (grid_x.floor() as usize).min(self.width - 2);
+        let cell_z = (grid_z.floor() as usize).min(self.depth - 2);
+        let (fx, fz) = (grid_x - cell_x as Float, grid_z - cell_z as Float);
+        let cell = cell_z * (self.width - 1) + cell_x;
+
+        if fx + fz <= 1. {
+            (cell * 2, fx, fz)
+        } else {
+            (cell * 2 + 1, 1. - fx, 1. - fz)
+        }
+    }
+
+    fn intersect_cell<'a>(&'a self, ray: &Ray, cell: usize, xs: &mut Vec<Intersection<'a>>) {
+        for face in [cell * 2, cell * 2 + 1] {
+            let [p1, p2, p3] = self.face_points(face);
+            if let Some((t, u, v)) = intersect_triangle(ray, &p1, &(p2 - p1), &(p3 - p1)) {
+                xs.push(Intersection::new_on_face(t, self, face, u, v));
+            }
+        }
+    }
+}
+
+impl Shape for Heightfield {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    // 2D DDA over the cells the ray crosses inside the bounds
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let (t_enter, t_exit) = match self.bounds.intersection_range(ray) {
+            Some(range) => range,
+            None => return vec![]
+        };
+
+        let cells = [(self.width - 1) as Float, (self.depth - 1) as Float];
+        let entry = ray.position(t_enter);
+        let grid = [(entry.x + 1.) / 2. * cells[0], (entry.z + 1.) / 2. * cells[1]];
+        let direction = [ray.direction.x / 2. * cells[0], ray.direction.z / 2. * cells[1]];
+
+        let mut cell = [0, 1].map(|axis| (grid[axis].floor().max(0.) as usize).min(cells[axis] as usize - 1));
+        let step = [0, 1].map(|axis| if direction[axis] > 0. { 1 } else { -1 });
+        // t of the next cell boundary along each axis and the t between two boundaries
+        let mut t_next = [0, 1].map(|axis| match direction[axis] {
+            d if d > 0. => t_enter + (cell[axis] as Float + 1. - grid[axis]) / d,
+            d if d < 0. => t_enter + (cell[axis] as Float - grid[axis]) / d,
+            _ => Float::INFINITY
+        });
+        let t_delta = [0, 1].map(|axis| if direction[axis] != 0. { 1. / direction[axis].abs() } else { Float::INFINITY });
+
+        let mut xs = vec![];
+        let mut t = t_enter;
+
+        loop {
+            let index = cell[1] * (self.width - 1) + cell[0];
+            let t_leave = t_next[0].min(t_next[1]).min(t_exit);
+            let (low, high) = self.cell_ranges[index];
+            let (y1, y2) = (ray.origin.y + ray.direction.y * t, ray.origin.y + ray.direction.y * t_leave);
+
+            if y1.max(y2) >= low && y1.min(y2) <= high {
+                self.intersect_cell(ray, index, &mut xs);
+            }
+
+            if t_leave >= t_exit {
+                break;
+            }
+
+            let axis = if t_next[0] < t_next[1] { 0 } else { 1 };
+            let next = cell[axis] as i64 + step[axis];
+            if next < 0 || next >= cells[axis] as i64 {
+                break;
+            }
+            cell[axis] = next as usize;
+            t = t_next[axis];
+            t_next[axis] += t_delta[axis];
+        }
+
+        // a hit on an edge shared by two triangles is reported by both
+        sort_intersections(&mut xs);
+        xs.dedup_by(|a, b| (a.t - b.t).abs() < crate::utils::EPSILON);
+        xs
+    }
+
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        let (face, u, v) = self.locate(point.x.clamp(-1., 1.), point.z.clamp(-1., 1.));
+        self.normal_on_face(face, u, v)
+    }
+
+    fn local_normal_at_hit(&self, _point: &Tuple, hit: &Intersection) -> Tuple {
+        self.normal_on_face(hit.face, hit.u, hit.v)
+    }
+
+    fn local_tessellate(&self, _segments: usize) -> Vec<Triangle> {
+        (0..(self.width - 1) * (self.depth - 1) * 2).map(|face| {
+            let [p1, p2, p3] = self.face_points(face);
+            let [n1, n2, n3] = self.face_corners(face).map(|(x, z)| self.normals[z * self.width + x]);
+            let mut triangle = Triangle::new_smooth(p1, p2, p3, n1, n2, n3);
+            triangle.set_material(self.properties.material.clone());
+            triangle
+        }).collect()
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color::Color;
+    use crate::utils::EPSILON;
+
+    fn ridge() -> Heightfield {
+        // 5 x 3 samples with a ridge running along z in the middle
+        Heightfield::new(5, 3, vec![
+            0., 0.5, 1., 0.5, 0.,
+            0., 0.5, 1., 0.5, 0.,
+            0., 0.5, 1., 0.5, 0.
+        ])
+    }
+
+    #[test]
+    fn test_flat_field() {
+        let field = Heightfield::new(3, 3, vec![0.5; 9]);
+        let ray = Ray::new(Tuple::new_point(0.3, 2., -0.2), Tuple::new_vector(0., -1., 0.));
+        let xs = field.local_intersect(&ray);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!((xs[0].t - 1.5).abs() < EPSILON, true);
+        assert_eq!(field.normal_at_hit(&ray.position(xs[0].t), &xs[0], 0.), Tuple::new_vector(0., 1., 0.));
+        assert_eq!(field.local_bounds().min.y, 0.5);
+    }
+
+    #[test]
+    fn test_ridge_heights_and_normals() {
+        let field = ridge();
+
+        assert_eq!(field.height_at(0., 0.3), Some(1.));
+        assert_eq!(field.height_at(-0.25, 0.), Some(0.75));
+        assert_eq!(field.height_at(1.5, 0.), None);
+
+        // the slope between the samples and a smooth normal on the crest
+        let side = field.local_normal_at(&Tuple::new_point(-0.75, 0.25, 0.5));
+        assert_eq!(side.is_equal_to(&Tuple::new_vector(-1., 1., 0.).normalize()), true);
+        assert_eq!(field.local_normal_at(&Tuple::new_point(0., 1., 0.)), Tuple::new_vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn test_grid_walk_matches_every_triangle() {
+        let field = Heightfield::from_noise(9, 7, 3., 2, 11);
+        let triangles = field.local_tessellate(1);
+
+        for i in 0..40 {
+            let angle = i as Float * 0.7;
+            let origin = Tuple::new_point(2. * angle.cos(), 1.5, 2. * angle.sin());
+            let target = Tuple::new_point((i as Float * 0.37).sin() * 0.9, 0.2, (i as Float * 0.53).cos() * 0.9);
+            let ray = Ray::new(origin, (target - origin).normalize());
+
+            let mut expected: Vec<Float> = triangles.iter().flat_map(|triangle| triangle.local_intersect(&ray)).map(|x| x.t).collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+            let found: Vec<Float> = field.local_intersect(&ray).iter().map(|x| x.t).collect();
+
+            assert_eq!(found.len(), expected.len());
+            for (a, b) in found.iter().zip(&expected) {
+                assert_eq!((a - b).abs() < EPSILON, true);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rays_missing_the_field() {
+        let field = ridge();
+        let above = Ray::new(Tuple::new_point(-2., 1.5, 0.), Tuple::new_vector(1., 0., 0.));
+        let outside = Ray::new(Tuple::new_point(3., 2., 0.), Tuple::new_vector(0., -1., 0.));
+
+        assert_eq!(field.local_intersect(&above).len(), 0);
+        assert_eq!(field.local_intersect(&outside).len(), 0);
+
+        // sideways through the ridge, in one slope and out the other
+        let through = Ray::new(Tuple::new_point(-2., 0.6, 0.1), Tuple::new_vector(1., 0., 0.));
+        assert_eq!(field.local_intersect(&through).len(), 2);
+    }
+
+    #[test]
+    fn test_from_canvas() {
+        let mut image = Canvas::new(2, 2);
+        image.write_pixel_at(1, 1, Color::white());
+        let field = Heightfield::from_canvas(&image);
+
+        assert_eq!((field.get_width(), field.get_depth()), (2, 2));
+        assert_eq!(field.get_height(0, 0), 0.);
+        assert_eq!((field.get_height(1, 1) - 1.).abs() < 1e-6, true);
+    }
+}
@@ -0,0 +1,201 @@
+use std::rc::Rc;
+
+use crate::bounds::BoundingBox;
+use crate::color::Color;
+use crate::intersection::Intersection;
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+use super::{Shape, ShapeProperties, Triangle, world_normal};
+
+// Copy of a shared shape placed with its own transform and material, so thousands of
+// copies cost one mesh in memory. The shared shape's transform still applies, inside the
+// instance's. Hits point at the instance and keep everything else the shared shape found
+#[derive(Debug, Clone)]
+pub struct Instance {
+    properties: ShapeProperties,
+    shape: Rc<dyn Shape>
+}
+
+impl Instance {
+    // Starts out with the shared shape's material. Groups hit their children instead of
+    // themselves, which the instance could not shade, so they are shared as a Mesh instead
+    pub fn new(shape: Rc<dyn Shape>) -> Self {
+        assert!(!shape.is_group(), "groups cannot be instanced, share them as a mesh");
+
+        let mut properties = ShapeProperties::new();
+        properties.material = shape.get_material().clone();
+
+        Instance {
+            properties,
+            shape
+        }
+    }
+
+    // The hit as the shared shape found it
+    fn shared_hit<'a>(&'a self, hit: &Intersection<'a>) -> Intersection<'a> {
+        Intersection { object: self.shape.as_ref(), ..*hit }
+    }
+
+    pub fn get_shape(&self) -> &Rc<dyn Shape> {
+        &self.shape
+    }
+}
+
+impl Shape for Instance {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        self.shape.intersect(ray).iter()
+            .map(|hit| Intersection { object: self, ..*hit })
+            .collect()
+    }
+
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        self.shape.normal_at(point)
+    }
+
+    // The shared shape moves inside the instance too, so both are placed at the hit's time
+    fn normal_at_hit(&self, world_point: &Tuple, hit: &Intersection, time: Float) -> Tuple {
+        world_normal(&self.properties, world_point, time, |point| self.shape.normal_at_hit(point, &self.shared_hit(hit), time))
+    }
+
+    fn tangent_at_hit(&self, world_point: &Tuple, hit: &Intersection, time: Float) -> Option<Tuple> {
+        let point = Matrix::multiply_tuple(&self.properties.get_inverse_at(time), world_point);
+        let tangent = self.shape.tangent_at_hit(&point, &self.shared_hit(hit), time)?;
+        Some(Matrix::multiply_tuple(&self.properties.get_transform_at(time), &tangent).normalize())
+    }
+
+    fn color_at_hit(&self, world_point: &Tuple, hit: &Intersection, time: Float) -> Color {
+        let point = Matrix::multiply_tuple(&self.properties.get_inverse_at(time), world_point);
+        let shared_point = Matrix::multiply_tuple(&self.shape.get_properties().get_inverse_at(time), &point);
+        self.shape.local_color_at_hit(&shared_point, &self.shared_hit(hit)).unwrap_or(self.properties.material.color)
+    }
+
+    fn local_tessellate(&self, segments: usize) -> Vec<Triangle> {
+        let mut triangles = self.shape.tessellate(segments);
+        for triangle in &mut triangles {
+            triangle.set_material(self.properties.material.clone());
+        }
+        triangles
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.shape.get_bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::FRAC_1_SQRT_2;
+    use crate::material::Material;
+    use crate::animation::AnimatedTransform;
+    use crate::shapes::{Group, Mesh, Sphere};
+    use crate::utils::NumberUtils;
+
+    fn quad() -> Rc<dyn Shape> {
+        let positions = vec![
+            Tuple::new_point(-1., -1., 0.),
+            Tuple::new_point(1., -1., 0.),
+            Tuple::new_point(1., 1., 0.),
+            Tuple::new_point(-1., 1., 0.)
+        ];
+        Rc::new(Mesh::new(positions, vec![[0, 1, 2], [0, 2, 3]]))
+    }
+
+    #[test]
+    fn test_instances_share_one_shape() {
+        let shared = quad();
+        let mut near = Instance::new(Rc::clone(&shared));
+        let mut far = Instance::new(Rc::clone(&shared));
+        near.set_transform(Matrix::translation(0., 0., -1.));
+        far.set_transform(Matrix::translation(5., 0., 3.));
+
+        let mut red = Material::new();
+        red.color = Color::red();
+        far.set_material(red.clone());
+
+        assert_eq!(Rc::strong_count(&shared), 3);
+        assert_eq!(near.get_material().color, Color::white());
+
+        let ray = Ray::new(Tuple::new_point(5.7, 0.3, -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(near.intersect(&ray).len(), 0);
+        let xs = far.intersect(&ray);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 8.);
+        assert_eq!(xs[0].face, 0);
+        assert_eq!(xs[0].is_same_object(&far), true);
+        assert_eq!(xs[0].object.get_material(), &red);
+    }
+
+    #[test]
+    fn test_normals_go_through_both_transforms() {
+        let mut sphere = Sphere::new();
+        sphere.set_transform(Matrix::translation(0., 1., 0.));
+        let mut instance = Instance::new(Rc::new(sphere));
+        instance.set_transform(Matrix::scaling(1., 2., 1.));
+
+        // the shared sphere sits at y = 1, stretched to y = 2 and twice as tall
+        let ray = Ray::new(Tuple::new_point(0., 5., 0.), Tuple::new_vector(0., -1., 0.));
+        let xs = instance.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 1.);
+
+        let point = ray.position(xs[0].t);
+        assert_eq!(instance.normal_at_hit(&point, &xs[0], 0.).is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+        let side = Tuple::new_point(FRAC_1_SQRT_2, 2. + 2. * FRAC_1_SQRT_2, 0.);
+        assert_eq!(instance.normal_at(&side).is_equal_to(&Tuple::new_vector(2., 1., 0.).normalize()), true);
+    }
+
+    #[test]
+    fn test_moving_shared_shape() {
+        let mut sphere = Sphere::new();
+        sphere.set_motion(AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(0., 0., 0.)),
+            (1., Matrix::translation(2., 0., 0.)),
+        ]));
+        let instance = Instance::new(Rc::new(sphere));
+
+        // at time 1 the shared sphere has moved, and the normal is taken where it is then
+        let ray = Ray::new_at_time(Tuple::new_point(2., 5., 0.), Tuple::new_vector(0., -1., 0.), 1.);
+        let xs = instance.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        let top = Tuple::new_point(2., 1., 0.);
+        assert_eq!(instance.normal_at_hit(&top, &xs[0], 1.).is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_groups_cannot_be_instanced() {
+        let mut group = Group::new();
+        group.add_child(Box::new(Sphere::new()));
+        Instance::new(Rc::new(group));
+    }
+
+    #[test]
+    fn test_bounds_and_tessellation() {
+        let mut instance = Instance::new(quad());
+        instance.set_transform(Matrix::scaling(2., 2., 2.));
+        let mut material = Material::new();
+        material.ambient = 0.5;
+        instance.set_material(material.clone());
+
+        let bounds = instance.get_bounds();
+        assert_eq!(bounds.min, Tuple::new_point(-2., -2., 0.));
+        assert_eq!(bounds.max, Tuple::new_point(2., 2., 0.));
+
+        let triangles = instance.tessellate(4);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0].get_material(), &material);
+        assert_eq!(NumberUtils::compare_floats(triangles[0].get_bounds().max.x, 2.), true);
+    }
+}
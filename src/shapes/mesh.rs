@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+
+use crate::bounds::BoundingBox;
+use crate::intersection::{Intersection, sort_intersections};
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+use super::{Shape, ShapeProperties, Triangle};
+use super::bvh::Bvh;
+use super::triangle::intersect_triangle;
+
+// How much each face counts towards a generated vertex normal
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum NormalWeighting {
+    // Big faces dominate, fine for evenly tessellated models
+    Area,
+    // The face's angle at the vertex, independent of how the surface was cut into triangles
+    Angle
+}
+
+// Indexed triangle mesh. Positions, normals and texture coordinates live once in shared
+// buffers and triangles only hold indices into them, so large models need far less memory
+// than one Triangle per face. Normals and texture coordinates are either empty or hold
+// one entry per position. Faces are found through a bounding volume hierarchy built over
+// them, hits record the face in the intersection
+#[derive(PartialEq, Debug, Clone)]
+pub struct Mesh {
+    properties: ShapeProperties,
+    positions: Vec<Tuple>,
+    normals: Vec<Tuple>,
+    texture_coordinates: Vec<(Float, Float)>,
+    triangles: Vec<[usize; 3]>,
+    bounds: BoundingBox,
+    bvh: Bvh
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Tuple>, triangles: Vec<[usize; 3]>) -> Self {
+        assert!(triangles.iter().flatten().all(|&index| index < positions.len()), "triangle index out of range");
+
+        let mut mesh = Mesh {
+            properties: ShapeProperties::new(),
+            positions,
+            normals: vec![],
+            texture_coordinates: vec![],
+            triangles,
+            bounds: BoundingBox::new(),
+            bvh: Bvh::new(&[])
+        };
+        mesh.update_bounds();
+        mesh
+    }
+
+    // Three vertices per triangle, weld afterwards to share them. Normals and texture
+    // coordinates are kept only when every triangle has them
+    pub fn from_triangles(triangles: &[Triangle]) -> Self {
+        let positions = triangles.iter().flat_map(|triangle| *triangle.get_points()).collect();
+        let indices = (0..triangles.len()).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect();
+        let mut mesh = Mesh::new(positions, indices);
+
+        if triangles.iter().all(|triangle| triangle.is_smooth()) {
+            mesh.normals = triangles.iter().flat_map(|triangle| *triangle.get_vertex_normals().unwrap()).collect();
+        }
+        if triangles.iter().all(|triangle| triangle.get_texture_coordinates().is_some()) {
+            mesh.texture_coordinates = triangles.iter().flat_map(|triangle| *triangle.get_texture_coordinates().unwrap()).collect();
+        }
+
+        mesh
+    }
+
+    pub fn get_positions(&self) -> &[Tuple] {
+        &self.positions
+    }
+
+    pub fn get_normals(&self) -> &[Tuple] {
+        &self.normals
+    }
+
+    pub fn get_texture_coordinates(&self) -> &[(Float, Float)] {
+        &self.texture_coordinates
+    }
+
+    pub fn get_triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    pub fn get_vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn get_triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        !self.normals.is_empty()
+    }
+
+    pub fn set_normals(&mut self, normals: Vec<Tuple>) {
+        assert_eq!(normals.len(), self.positions.len(), "one normal per position");
+        self.normals = normals;
+    }
+
+    pub fn set_texture_coordinates(&mut self, coordinates: Vec<(Float, Float)>) {
+        assert_eq!(coordinates.len(), self.positions.len(), "one texture coordinate per position");
+        self.texture_coordinates = coordinates;
+    }
+
+    // Rebuilds the hierarchy too, needed whenever faces are added, removed or moved
+    fn update_bounds(&mut self) {
+        self.bounds = BoundingBox::new();
+        for position in &self.positions {
+            self.bounds.add_point(position);
+        }
+
+        let boxes: Vec<BoundingBox> = self.triangles.iter().map(|triangle| {
+            let mut bounds = BoundingBox::new();
+            for &index in triangle {
+                bounds.add_point(&self.positions[index]);
+            }
+            bounds
+        }).collect();
+        self.bvh = Bvh::new(&boxes);
+    }
+
+    // Unnormalized, its length is twice the face's area
+    fn face_normal(&self, face: usize) -> Tuple {
+        let [a, b, c] = self.triangles[face];
+        let e1 = self.positions[b] - self.positions[a];
+        let e2 = self.positions[c] - self.positions[a];
+        e2.get_vector_cross_product(&e1)
+    }
+
+    // Merges vertices closer than epsilon on every axis, usually EPSILON. Vertices with
+    // different normals or texture coordinates stay apart so seams survive. Triangles that
+    // collapse are dropped, the number of removed vertices is returned
+    pub fn weld(&mut self, epsilon: Float) -> usize {
+        let cell = |point: &Tuple| [(point.x / epsilon).floor() as i64, (point.y / epsilon).floor() as i64, (point.z / epsilon).floor() as i64];
+        let close = |a: &Tuple, b: &Tuple| (a.x - b.x).abs() < epsilon && (a.y - b.y).abs() < epsilon && (a.z - b.z).abs() < epsilon;
+
+        let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        let mut kept: Vec<usize> = vec![];
+        let mut remap = Vec::with_capacity(self.positions.len());
+
+        for (index, position) in self.positions.iter().enumerate() {
+            let [x, y, z] = cell(position);
+            let same = |&&other: &&usize| {
+                close(position, &self.positions[kept[other]])
+                    && (self.normals.is_empty() || close(&self.normals[index], &self.normals[kept[other]]))
+                    && (self.texture_coordinates.is_empty() || {
+                        let (u1, v1) = self.texture_coordinates[index];
+                        let (u2, v2) = self.texture_coordinates[kept[other]];
+                        (u1 - u2).abs() < epsilon && (v1 - v2).abs() < epsilon
+                    })
+            };
+
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidate) = grid.get(&[x + dx, y + dy, z + dz]).and_then(|cell| cell.iter().find(same)) {
+                            found = Some(*candidate);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+
+            remap.push(found.unwrap_or_else(|| {
+                grid.entry([x, y, z]).or_default().push(kept.len());
+                kept.push(index);
+                kept.len() - 1
+            }));
+        }
+
+        let removed = self.positions.len() - kept.len();
+        self.positions = kept.iter().map(|&index| self.positions[index]).collect();
+        if !self.normals.is_empty() {
+            self.normals = kept.iter().map(|&index| self.normals[index]).collect();
+        }
+        if !self.texture_coordinates.is_empty() {
+            self.texture_coordinates = kept.iter().map(|&index| self.texture_coordinates[index]).collect();
+        }
+
+        self.triangles = self.triangles.iter()
+            .map(|triangle| triangle.map(|index| remap[index]))
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .collect();
+        self.update_bounds();
+
+        removed
+    }
+
+    // Replaces the normals with ones averaged from the faces around each vertex. Faces bent
+    // more than crease_angle (radians) away from each other are not blended, so vertices on
+    // a crease are split into one copy per side and hard edges stay sharp
+    pub fn generate_normals(&mut self, crease_angle: Float, weighting: NormalWeighting) {
+        let face_normals: Vec<Tuple> = (0..self.triangles.len()).map(|face| self.face_normal(face)).collect();
+        let unit_normals: Vec<Tuple> = face_normals.iter()
+            .map(|normal| if normal.get_magnitude() > 0. { normal.normalize() } else { *normal })
+            .collect();
+
+        // Weight of every face at each of its corners
+        let weights: Vec<[Float; 3]> = self.triangles.iter().enumerate().map(|(face, triangle)| match weighting {
+            NormalWeighting::Area => [face_normals[face].get_magnitude() / 2.; 3],
+            NormalWeighting::Angle => [0, 1, 2].map(|corner| {
+                let at = self.positions[triangle[corner]];
+                let to_next = self.positions[triangle[(corner + 1) % 3]] - at;
+                let to_previous = self.positions[triangle[(corner + 2) % 3]] - at;
+                let lengths = to_next.get_magnitude() * to_previous.get_magnitude();
+
+                if lengths > 0. {
+                    (to_next.calculate_dot_product(&to_previous) / lengths).clamp(-1., 1.).acos()
+                } else {
+                    0.
+                }
+            })
+        }).collect();
+
+        let mut corners_at: Vec<Vec<(usize, usize)>> = vec![vec![]; self.positions.len()];
+        for (face, triangle) in self.triangles.iter().enumerate() {
+            for (corner, &vertex) in triangle.iter().enumerate() {
+                corners_at[vertex].push((face, corner));
+            }
+        }
+
+        let min_cosine = crease_angle.cos() - EPSILON;
+        let mut positions = vec![];
+        let mut normals: Vec<Tuple> = vec![];
+        let mut texture_coordinates = vec![];
+        // Copies made of each old vertex, one per distinct normal
+        let mut copies: Vec<Vec<usize>> = vec![vec![]; self.positions.len()];
+        let mut triangles = self.triangles.clone();
+
+        for (face, triangle) in self.triangles.iter().enumerate() {
+            for (corner, &vertex) in triangle.iter().enumerate() {
+                let mut sum = Tuple::new_vector(0., 0., 0.);
+                for &(other, other_corner) in &corners_at[vertex] {
+                    if unit_normals[face].calculate_dot_product(&unit_normals[other]) >= min_cosine {
+                        sum = sum + unit_normals[other] * weights[other][other_corner];
+                    }
+                }
+                let normal = if sum.get_magnitude() > 0. { sum.normalize() } else { unit_normals[face] };
+
+                let copy = copies[vertex].iter().copied().find(|&copy| normals[copy].is_equal_to(&normal));
+                triangles[face][corner] = copy.unwrap_or_else(|| {
+                    positions.push(self.positions[vertex]);
+                    normals.push(normal);
+                    if !self.texture_coordinates.is_empty() {
+                        texture_coordinates.push(self.texture_coordinates[vertex]);
+                    }
+                    copies[vertex].push(positions.len() - 1);
+                    positions.len() - 1
+                });
+            }
+        }
+
+        self.positions = positions;
+        self.normals = normals;
+        self.texture_coordinates = texture_coordinates;
+        self.triangles = triangles;
+        self.update_bounds();
+    }
+
+    // Distance from the face's plane and where the point lands on the face, None when it
+    // lands outside or the face is degenerate
+    fn project_onto_face(&self, face: usize, point: &Tuple) -> Option<(Float, Float, Float)> {
+        let [a, b, c] = self.triangles[face];
+        let p1 = self.positions[a];
+        let (e1, e2) = (self.positions[b] - p1, self.positions[c] - p1);
+        let to_point = *point - p1;
+
+        let (d11, d12, d22) = (e1.calculate_dot_product(&e1), e1.calculate_dot_product(&e2), e2.calculate_dot_product(&e2));
+        let (d1p, d2p) = (to_point.calculate_dot_product(&e1), to_point.calculate_dot_product(&e2));
+        let denominator = d11 * d22 - d12 * d12;
+        if denominator.abs() < EPSILON * EPSILON {
+            return None
+        }
+
+        let u = (d22 * d1p - d12 * d2p) / denominator;
+        let v = (d11 * d2p - d12 * d1p) / denominator;
+        if u <= -EPSILON || v <= -EPSILON || u + v >= 1. + EPSILON {
+            return None
+        }
+
+        let distance = to_point.calculate_dot_product(&self.face_normal(face).normalize()).abs();
+        Some((distance, u, v))
+    }
+
+    fn normal_on_face(&self, face: usize, u: Float, v: Float) -> Tuple {
+        let [a, b, c] = self.triangles[face];
+
+        if self.normals.is_empty() {
+            self.face_normal(face).normalize()
+        } else {
+            self.normals[b] * u + self.normals[c] * v + self.normals[a] * (1. - u - v)
+        }
+    }
+
+    // Object space triangles, smooth when the mesh has normals
+    pub fn to_triangles(&self) -> Vec<Triangle> {
+        self.triangles.iter().map(|&[a, b, c]| {
+            let (p1, p2, p3) = (self.positions[a], self.positions[b], self.positions[c]);
+
+            let mut triangle = if self.normals.is_empty() {
+                Triangle::new(p1, p2, p3)
+            } else {
+                Triangle::new_smooth(p1, p2, p3, self.normals[a], self.normals[b], self.normals[c])
+            };
+
+            if !self.texture_coordinates.is_empty() {
+                triangle.set_texture_coordinates([self.texture_coordinates[a], self.texture_coordinates[b], self.texture_coordinates[c]]);
+            }
+            triangle.set_material(self.properties.material.clone());
+            triangle
+        }).collect()
+    }
+}
+
+impl Shape for Mesh {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let mut xs = vec![];
+        self.bvh.traverse(ray, |face| {
+            let [a, b, c] = self.triangles[face];
+            let p1 = self.positions[a];
+            if let Some((t, u, v)) = intersect_triangle(ray, &p1, &(self.positions[b] - p1), &(self.positions[c] - p1)) {
+                xs.push(Intersection::new_on_face(t, self, face, u, v));
+            }
+        });
+
+        sort_intersections(&mut xs);
+        xs
+    }
+
+    // Without a hit the face is the one whose plane lies closest to the point
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        let face = self.bvh.closest(point, |face| self.project_onto_face(face, point).map_or(Float::INFINITY, |(distance, _, _)| distance))
+            .unwrap_or(0);
+        let (_, u, v) = self.project_onto_face(face, point).unwrap_or((0., 0., 0.));
+        self.normal_on_face(face, u, v)
+    }
+
+    fn local_normal_at_hit(&self, _point: &Tuple, hit: &Intersection) -> Tuple {
+        self.normal_on_face(hit.face, hit.u, hit.v)
+    }
+
+    fn local_tessellate(&self, _segments: usize) -> Vec<Triangle> {
+        self.to_triangles()
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
+    use crate::utils::NumberUtils;
+
+    // Two triangles sharing the edge from the origin along x, folded at a right angle
+    fn fold() -> Mesh {
+        Mesh::new(
+            vec![Tuple::new_point(0., 0., 0.), Tuple::new_point(1., 0., 0.), Tuple::new_point(0., 1., 0.), Tuple::new_point(0., 0., 1.)],
+            vec![[0, 1, 2], [1, 0, 3]]
+        )
+    }
+
+    #[test]
+    fn test_weld_merges_duplicated_vertices() {
+        let soup = [
+            Triangle::new(Tuple::new_point(0., 0., 0.), Tuple::new_point(1., 0., 0.), Tuple::new_point(1., 1., 0.)),
+            Triangle::new(Tuple::new_point(0., 0., 0.), Tuple::new_point(1., 1., 0.000001), Tuple::new_point(0., 1., 0.)),
+            // collapses to a line once welded
+            Triangle::new(Tuple::new_point(0., 0., 0.), Tuple::new_point(0.000001, 0., 0.), Tuple::new_point(0., 1., 0.))
+        ];
+        let mut mesh = Mesh::from_triangles(&soup);
+
+        assert_eq!(mesh.get_vertex_count(), 9);
+        assert_eq!(mesh.weld(EPSILON), 5);
+        assert_eq!(mesh.get_vertex_count(), 4);
+        assert_eq!(mesh.get_triangles(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn test_weld_keeps_seams_apart() {
+        let mut mesh = Mesh::new(vec![Tuple::new_point(0., 0., 0.), Tuple::new_point(0., 0., 0.)], vec![]);
+        mesh.set_normals(vec![Tuple::new_vector(1., 0., 0.), Tuple::new_vector(0., 1., 0.)]);
+
+        assert_eq!(mesh.weld(EPSILON), 0);
+
+        mesh.set_normals(vec![Tuple::new_vector(1., 0., 0.), Tuple::new_vector(1., 0., 0.)]);
+        assert_eq!(mesh.weld(EPSILON), 1);
+    }
+
+    #[test]
+    fn test_generate_normals_with_crease_angle() {
+        let mut smooth = fold();
+        smooth.generate_normals(PI, NormalWeighting::Area);
+
+        assert_eq!(smooth.get_vertex_count(), 4);
+        assert_eq!(smooth.get_normals()[0].is_equal_to(&Tuple::new_vector(0., -FRAC_1_SQRT_2, -FRAC_1_SQRT_2)), true);
+        assert_eq!(smooth.get_normals()[2], Tuple::new_vector(0., 0., -1.));
+
+        let mut creased = fold();
+        creased.generate_normals(PI / 3., NormalWeighting::Area);
+
+        // both vertices on the fold are split, each side keeps its face normal
+        assert_eq!(creased.get_vertex_count(), 6);
+        for (face, expected) in [Tuple::new_vector(0., 0., -1.), Tuple::new_vector(0., -1., 0.)].iter().enumerate() {
+            for vertex in creased.get_triangles()[face] {
+                assert_eq!(creased.get_normals()[vertex], *expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_area_and_angle_weighting() {
+        // a small face with a right angle at the origin and a big one with a narrower angle there
+        let positions = vec![
+            Tuple::new_point(0., 0., 0.), Tuple::new_point(1., 0., 0.), Tuple::new_point(0., 1., 0.),
+            Tuple::new_point(0., 0., 4.), Tuple::new_point(0., 4., 1.)
+        ];
+        let mut area = Mesh::new(positions.clone(), vec![[0, 1, 2], [0, 3, 4]]);
+        let mut angle = Mesh::new(positions, vec![[0, 1, 2], [0, 3, 4]]);
+
+        area.generate_normals(PI, NormalWeighting::Area);
+        angle.generate_normals(PI, NormalWeighting::Angle);
+
+        let (by_area, by_angle) = (area.get_normals()[0], angle.get_normals()[0]);
+        assert_eq!(by_area.x > by_area.z.abs(), true);
+        assert_eq!(by_angle.z.abs() > by_angle.x, true);
+    }
+
+    #[test]
+    fn test_intersect_records_face_and_interpolates_normals() {
+        let mut mesh = fold();
+        mesh.generate_normals(PI, NormalWeighting::Area);
+
+        let ray = Ray::new(Tuple::new_point(0.25, -2., 0.25), Tuple::new_vector(0., 1., 0.));
+        let xs = mesh.intersect(&ray);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.);
+        assert_eq!(xs[0].face, 1);
+
+        let point = ray.position(xs[0].t);
+        let normal = mesh.normal_at_hit(&point, &xs[0], 0.);
+        assert_eq!(normal.y < 0. && normal.z < 0., true);
+        assert_eq!(mesh.normal_at(&point).is_equal_to(&normal), true);
+
+        let miss = Ray::new(Tuple::new_point(2., -2., 2.), Tuple::new_vector(0., 1., 0.));
+        assert_eq!(mesh.intersect(&miss).len(), 0);
+    }
+
+    #[test]
+    fn test_large_mesh_goes_through_the_hierarchy() {
+        // a 100 x 100 grid of quads in the z = 0 plane, bumped up by the column number
+        let size = 100;
+        let positions = (0..=size).flat_map(|y| (0..=size).map(move |x| Tuple::new_point(x as Float, y as Float, x as Float / 100.))).collect();
+        let triangles = (0..size).flat_map(|y| (0..size).flat_map(move |x| {
+            let corner = y * (size + 1) + x;
+            vec![[corner, corner + 1, corner + size + 2], [corner, corner + size + 2, corner + size + 1]]
+        })).collect();
+        let mesh = Mesh::new(positions, triangles);
+
+        assert_eq!(mesh.bvh.nodes.iter().map(|node| node.count).sum::<usize>(), 20000);
+        assert_eq!(mesh.bvh.get_bounds(), mesh.local_bounds());
+
+        for (x, y) in [(0.75, 0.25), (12.25, 87.75), (99.75, 99.25), (50.5, 3.25)] {
+            let ray = Ray::new(Tuple::new_point(x, y, -5.), Tuple::new_vector(0., 0., 1.));
+            let xs = mesh.local_intersect(&ray);
+            assert_eq!(xs.len(), 1);
+
+            let quad = y.floor() as usize * size + x.floor() as usize;
+            let face = if x.fract() > y.fract() { quad * 2 } else { quad * 2 + 1 };
+            assert_eq!(xs[0].face, face);
+            assert_eq!(NumberUtils::compare_floats(xs[0].t, 5. + x / 100.), true);
+
+            let point = ray.position(xs[0].t);
+            assert_eq!(mesh.local_normal_at(&point).is_equal_to(&mesh.local_normal_at_hit(&point, &xs[0])), true);
+        }
+
+        assert_eq!(mesh.local_intersect(&Ray::new(Tuple::new_point(100.5, 50., -5.), Tuple::new_vector(0., 0., 1.))).len(), 0);
+    }
+
+    #[test]
+    fn test_to_triangles() {
+        let mut mesh = fold();
+        mesh.set_texture_coordinates(vec![(0., 0.), (1., 0.), (0., 1.), (1., 1.)]);
+        let flat = mesh.to_triangles();
+
+        assert_eq!(flat.len(), 2);
+        assert_eq!(flat[1].is_smooth(), false);
+        assert_eq!(flat[1].get_texture_coordinates(), Some(&[(1., 0.), (0., 0.), (1., 1.)]));
+
+        mesh.generate_normals(PI, NormalWeighting::Angle);
+        assert_eq!(mesh.to_triangles().iter().all(|triangle| triangle.is_smooth()), true);
+        assert_eq!(Mesh::from_triangles(&mesh.to_triangles()).is_smooth(), true);
+    }
+}
@@ -0,0 +1,326 @@
+use crate::bounds::BoundingBox;
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+use super::{Shape, ShapeProperties};
+
+// Bisection steps for a root, enough to get down to the precision of a double
+const BISECTION_STEPS: usize = 64;
+
+// How a ball's influence fades from 1 at its center to 0 at its radius, as a polynomial in
+// the squared distance over the squared radius. Both are smooth where they reach 0, so
+// balls blend without creases
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Falloff {
+    // (1 - x)³
+    Cubic,
+    // Wyvill's soft objects, 1 - 22/9 x + 17/9 x² - 4/9 x³, which is 0.5 at half the radius
+    Wyvill
+}
+
+impl Falloff {
+    // Coefficients from the constant term up
+    fn get_coefficients(&self) -> [Float; 4] {
+        match self {
+            Falloff::Cubic => [1., -3., 3., -1.],
+            Falloff::Wyvill => [1., -22. / 9., 17. / 9., -4. / 9.]
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Ball {
+    pub center: Tuple,
+    pub radius: Float,
+    // Negative strengths carve into the other balls
+    pub strength: Float
+}
+
+// Blobby surface where the summed influence of the balls equals the threshold. Within one
+// stretch of the ray the same balls are in reach, so the field along it is a polynomial of
+// degree 6 whose roots are isolated between the roots of its derivatives and then bisected
+#[derive(PartialEq, Debug, Clone)]
+pub struct Metaballs {
+    properties: ShapeProperties,
+    balls: Vec<Ball>,
+    threshold: Float,
+    falloff: Falloff,
+    bounds: BoundingBox
+}
+
+impl Metaballs {
+    pub fn new(threshold: Float) -> Self {
+        assert!(threshold > 0., "the threshold of metaballs must be positive");
+
+        Metaballs {
+            properties: ShapeProperties::new(),
+            balls: vec![],
+            threshold,
+            falloff: Falloff::Wyvill,
+            bounds: BoundingBox::new()
+        }
+    }
+
+    pub fn get_balls(&self) -> &[Ball] {
+        &self.balls
+    }
+
+    pub fn get_threshold(&self) -> Float {
+        self.threshold
+    }
+
+    pub fn get_falloff(&self) -> Falloff {
+        self.falloff
+    }
+
+    pub fn set_falloff(&mut self, falloff: Falloff) {
+        self.falloff = falloff;
+    }
+
+    pub fn add_ball(&mut self, center: Tuple, radius: Float, strength: Float) {
+        // only balls that add to the field can reach past the threshold
+        if strength > 0. {
+            let margin = Tuple::new_vector(radius, radius, radius);
+            self.bounds.add_point(&(center - margin));
+            self.bounds.add_point(&(center + margin));
+        }
+        self.balls.push(Ball { center, radius, strength });
+    }
+
+    // Summed influence of the balls at point, inside the surface where it exceeds the threshold
+    pub fn density_at(&self, point: &Tuple) -> Float {
+        let coefficients = self.falloff.get_coefficients();
+
+        self.balls.iter()
+            .map(|ball| {
+                let x = (*point - ball.center).get_magnitude().powi(2) / (ball.radius * ball.radius);
+                if x >= 1. { 0. } else { ball.strength * evaluate(&coefficients, x) }
+            })
+            .sum()
+    }
+
+    // Field minus the threshold along the ray, for balls in reach over the whole stretch
+    fn polynomial_along(&self, ray: &Ray, balls: &[&Ball]) -> Vec<Float> {
+        let kernel = self.falloff.get_coefficients();
+        let mut sum = vec![-self.threshold];
+
+        for ball in balls {
+            // squared distance to the center over the squared radius is quadratic in t
+            let to_ray = ray.origin - ball.center;
+            let scale = 1. / (ball.radius * ball.radius);
+            let x = [
+                to_ray.calculate_dot_product(&to_ray) * scale,
+                2. * ray.direction.calculate_dot_product(&to_ray) * scale,
+                ray.direction.calculate_dot_product(&ray.direction) * scale
+            ];
+
+            let mut power = vec![1.];
+            for coefficient in kernel.iter() {
+                sum = add(&sum, &power.iter().map(|c| c * coefficient * ball.strength).collect::<Vec<Float>>());
+                power = multiply(&power, &x);
+            }
+        }
+        sum
+    }
+}
+
+impl Shape for Metaballs {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        if self.bounds.is_empty() || !self.bounds.intersects(ray) {
+            return vec![]
+        }
+
+        // where the ray enters and leaves each ball's reach
+        let mut reaches = vec![];
+        for ball in &self.balls {
+            let to_ray = ray.origin - ball.center;
+            let a = ray.direction.calculate_dot_product(&ray.direction);
+            let b = 2. * ray.direction.calculate_dot_product(&to_ray);
+            let c = to_ray.calculate_dot_product(&to_ray) - ball.radius * ball.radius;
+            let discriminant = b * b - 4. * a * c;
+            if discriminant > 0. {
+                let root = discriminant.sqrt();
+                reaches.push((ball, (-b - root) / (2. * a), (-b + root) / (2. * a)));
+            }
+        }
+
+        let mut breaks: Vec<Float> = reaches.iter().flat_map(|&(_, enter, leave)| vec![enter, leave]).collect();
+        breaks.sort_by(|a, b| a.total_cmp(b));
+
+        let mut xs = vec![];
+        for stretch in breaks.windows(2) {
+            let (start, end) = (stretch[0], stretch[1]);
+            let middle = (start + end) / 2.;
+            let balls: Vec<&Ball> = reaches.iter()
+                .filter(|&&(_, enter, leave)| enter < middle && middle < leave)
+                .map(|&(ball, _, _)| ball)
+                .collect();
+            if balls.iter().all(|ball| ball.strength <= 0.) {
+                continue
+            }
+
+            let polynomial = self.polynomial_along(ray, &balls);
+            for t in roots_in(&polynomial, start, end) {
+                xs.push(Intersection::new(t, self));
+            }
+        }
+        xs
+    }
+
+    // The field grows towards the centers, so the normal is against its gradient
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        let derivative = derivative(&self.falloff.get_coefficients());
+        let mut gradient = Tuple::new_vector(0., 0., 0.);
+
+        for ball in &self.balls {
+            let offset = *point - ball.center;
+            let squared_radius = ball.radius * ball.radius;
+            let x = offset.get_magnitude().powi(2) / squared_radius;
+            if x < 1. {
+                gradient = gradient + offset * (ball.strength * evaluate(&derivative, x) * 2. / squared_radius);
+            }
+        }
+        gradient.negate().normalize()
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+}
+
+// Polynomials are coefficients from the constant term up
+fn evaluate(polynomial: &[Float], t: Float) -> Float {
+    polynomial.iter().rev().fold(0., |sum, coefficient| sum * t + coefficient)
+}
+
+fn derivative(polynomial: &[Float]) -> Vec<Float> {
+    polynomial.iter().enumerate().skip(1).map(|(power, coefficient)| power as Float * coefficient).collect()
+}
+
+fn add(a: &[Float], b: &[Float]) -> Vec<Float> {
+    (0..a.len().max(b.len())).map(|i| a.get(i).unwrap_or(&0.) + b.get(i).unwrap_or(&0.)).collect()
+}
+
+fn multiply(a: &[Float], b: &[Float]) -> Vec<Float> {
+    let mut product = vec![0.; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            product[i + j] += x * y;
+        }
+    }
+    product
+}
+
+// Roots where the sign changes inside [start, end], in order. Between two roots of the
+// derivative the polynomial is monotonic, so each of those pieces holds one root at most
+fn roots_in(polynomial: &[Float], start: Float, end: Float) -> Vec<Float> {
+    if polynomial.len() < 2 {
+        return vec![]
+    }
+
+    let mut bounds = vec![start];
+    bounds.extend(roots_in(&derivative(polynomial), start, end));
+    bounds.push(end);
+
+    bounds.windows(2)
+        .filter_map(|piece| {
+            let (mut low, mut high) = (piece[0], piece[1]);
+            let rising = evaluate(polynomial, low) < 0.;
+            if rising == (evaluate(polynomial, high) < 0.) {
+                return None
+            }
+
+            for _ in 0..BISECTION_STEPS {
+                let middle = (low + high) / 2.;
+                if (evaluate(polynomial, middle) < 0.) == rising {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            Some((low + high) / 2.)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn close(a: Float, b: Float) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_single_ball() {
+        // (1 - x)³ = 1/8 at half the squared radius, so a ball of radius 2 ends at √2
+        let mut blob = Metaballs::new(0.125);
+        assert_eq!(blob.get_falloff(), Falloff::Wyvill);
+        blob.set_falloff(Falloff::Cubic);
+        blob.add_ball(Tuple::new_point(0., 0., 0.), 2., 1.);
+
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = blob.local_intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(close(xs[0].t, 5. - 2_f64.sqrt()) && close(xs[1].t, 5. + 2_f64.sqrt()), true);
+        assert_eq!(blob.local_normal_at(&ray.position(xs[0].t)).is_equal_to(&Tuple::new_vector(0., 0., -1.)), true);
+        assert_eq!(close(blob.density_at(&Tuple::new_point(0., 1., 1.)), 0.125), true);
+
+        // Wyvill's falloff is one half at half the radius
+        blob.set_falloff(Falloff::Wyvill);
+        assert_eq!(close(blob.density_at(&Tuple::new_point(1., 0., 0.)), 0.5), true);
+        assert_eq!(blob.local_intersect(&Ray::new(Tuple::new_point(0., 2.5, -5.), Tuple::new_vector(0., 0., 1.))).len(), 0);
+    }
+
+    #[test]
+    fn test_balls_blend() {
+        let blobs = |apart: Float| {
+            let mut blob = Metaballs::new(0.5);
+            blob.add_ball(Tuple::new_point(-apart / 2., 0., 0.), 1., 1.);
+            blob.add_ball(Tuple::new_point(apart / 2., 0., 0.), 1., 1.);
+            blob
+        };
+        let between = Ray::new(Tuple::new_point(0., 5., 0.), Tuple::new_vector(0., -1., 0.));
+
+        // alone each ball ends at half its radius, close together they merge across the gap
+        assert_eq!(blobs(1.2).local_intersect(&between).len(), 2);
+        assert_eq!(blobs(1.8).local_intersect(&between).len(), 0);
+
+        // along the line through both centers the merged blob is entered and left once
+        let through = Ray::new(Tuple::new_point(-5., 0., 0.), Tuple::new_vector(1., 0., 0.));
+        let merged = blobs(1.2);
+        let xs = merged.local_intersect(&through);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t < 5. - 1.1, true);
+        let normal = merged.local_normal_at(&through.position(xs[0].t));
+        assert_eq!(normal.is_equal_to(&Tuple::new_vector(-1., 0., 0.)), true);
+    }
+
+    #[test]
+    fn test_negative_ball_carves() {
+        let mut blob = Metaballs::new(0.5);
+        blob.add_ball(Tuple::new_point(0., 0., 0.), 2., 1.);
+        blob.add_ball(Tuple::new_point(0., 0., 0.), 0.5, 2.);
+        blob.add_ball(Tuple::new_point(0., 0., 0.), 0.5, -4.);
+
+        assert_eq!(blob.local_bounds().max, Tuple::new_point(2., 2., 2.));
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = blob.local_intersect(&ray);
+        assert_eq!(xs.len(), 4);
+        // the hollow inside faces its center
+        let inner = ray.position(xs[1].t);
+        assert_eq!(blob.local_normal_at(&inner).z > 0.99, true);
+
+        assert_eq!(Metaballs::new(1.).local_intersect(&ray).len(), 0);
+    }
+}
@@ -0,0 +1,378 @@
+use std::borrow::Cow;
+use std::fmt::Debug;
+
+use crate::animation::AnimatedTransform;
+use crate::bounds::BoundingBox;
+use crate::clipping::{Clip, clip_intersections};
+use crate::color::Color;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::{Matrix, MatrixType};
+use crate::ray::{Ray, RayKind};
+use crate::tuples::{Tuple, Float};
+
+pub mod sphere;
+pub mod plane;
+pub mod triangle;
+pub mod group;
+pub mod mesh;
+pub mod heightfield;
+pub mod distance_field;
+pub mod instance;
+pub mod curve;
+pub mod point_cloud;
+pub mod metaballs;
+pub mod bezier_patch;
+mod bvh;
+
+pub use sphere::Sphere;
+pub use plane::Plane;
+pub use triangle::Triangle;
+pub use group::Group;
+pub use mesh::{Mesh, NormalWeighting};
+pub use heightfield::Heightfield;
+pub use distance_field::DistanceField;
+pub use instance::Instance;
+pub use curve::{Curve, CurveType};
+pub use point_cloud::{PointCloud, SplatType};
+pub use metaballs::{Metaballs, Ball, Falloff};
+pub use bezier_patch::BezierPatch;
+
+// Which kinds of rays see a shape, hidden helpers can still cast shadows
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadows: bool,
+    pub reflections: bool,
+    pub refractions: bool
+}
+
+impl Visibility {
+    pub fn new() -> Self {
+        Visibility {
+            camera: true,
+            shadows: true,
+            reflections: true,
+            refractions: true
+        }
+    }
+
+    pub fn is_visible_to(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadows,
+            RayKind::Reflection => self.reflections,
+            RayKind::Refraction => self.refractions
+        }
+    }
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::new()
+    }
+}
+
+// Lights that illuminate a shape, by their index in the world's lights
+#[derive(PartialEq, Debug, Clone)]
+pub enum LightLinks {
+    All,
+    Include(Vec<usize>),
+    Exclude(Vec<usize>)
+}
+
+impl LightLinks {
+    pub fn is_lit_by(&self, light: usize) -> bool {
+        match self {
+            LightLinks::All => true,
+            LightLinks::Include(lights) => lights.contains(&light),
+            LightLinks::Exclude(lights) => !lights.contains(&light)
+        }
+    }
+}
+
+// State every shape carries, the inverse and its transpose are cached
+// since they are needed for every ray and every normal
+#[derive(PartialEq, Debug, Clone)]
+pub struct ShapeProperties {
+    pub transform: MatrixType,
+    pub inverse: MatrixType,
+    pub inverse_transpose: MatrixType,
+    pub material: Material,
+    // Keyframed transform used instead of transform when present, for motion blur
+    pub motion: Option<AnimatedTransform>,
+    // World space regions the shape is cut down to
+    pub clips: Vec<Clip>,
+    pub visibility: Visibility,
+    pub light_links: LightLinks
+}
+
+impl ShapeProperties {
+    pub fn new() -> Self {
+        ShapeProperties {
+            transform: Matrix::identity_4x4(),
+            inverse: Matrix::identity_4x4(),
+            inverse_transpose: Matrix::identity_4x4(),
+            material: Material::new(),
+            motion: None,
+            clips: vec![],
+            visibility: Visibility::new(),
+            light_links: LightLinks::All
+        }
+    }
+
+    pub fn set_transform(&mut self, transform: MatrixType) {
+        self.inverse = Matrix::inverse(&transform);
+        self.inverse_transpose = Matrix::transpose(&self.inverse);
+        self.transform = transform;
+    }
+
+    pub fn get_transform_at(&self, time: Float) -> Cow<'_, MatrixType> {
+        match &self.motion {
+            Some(motion) => Cow::Owned(motion.matrix_at(time)),
+            None => Cow::Borrowed(&self.transform)
+        }
+    }
+
+    pub fn get_inverse_at(&self, time: Float) -> Cow<'_, MatrixType> {
+        match &self.motion {
+            Some(motion) => Cow::Owned(Matrix::inverse(&motion.matrix_at(time))),
+            None => Cow::Borrowed(&self.inverse)
+        }
+    }
+}
+
+impl Default for ShapeProperties {
+    fn default() -> Self {
+        ShapeProperties::new()
+    }
+}
+
+pub trait Shape: Debug {
+    fn get_properties(&self) -> &ShapeProperties;
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties;
+
+    // Both local functions work in object space, the ray and point are already transformed
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>>;
+
+    fn local_normal_at(&self, point: &Tuple) -> Tuple;
+
+    // Shapes that interpolate normals across their surface use the hit's u and v
+    fn local_normal_at_hit(&self, point: &Tuple, _hit: &Intersection) -> Tuple {
+        self.local_normal_at(point)
+    }
+
+    // Direction along fiber shapes like curves, None for surfaces
+    fn local_tangent_at_hit(&self, _point: &Tuple, _hit: &Intersection) -> Option<Tuple> {
+        None
+    }
+
+    // Shapes that color their own surface, like fractals by their orbit trap or point clouds
+    // per point, override the material's color here
+    fn local_color_at_hit(&self, _point: &Tuple, _hit: &Intersection) -> Option<Color> {
+        None
+    }
+
+    // Object space bounds, infinite for shapes like planes
+    fn local_bounds(&self) -> BoundingBox;
+
+    // Object space triangles approximating the surface, segments sets how finely curved
+    // shapes are cut. Shapes that cannot be tessellated, like infinite planes, give none
+    fn local_tessellate(&self, _segments: usize) -> Vec<Triangle> {
+        vec![]
+    }
+
+    // World space tessellation with the transform baked in
+    fn tessellate(&self, segments: usize) -> Vec<Triangle> {
+        self.local_tessellate(segments).iter()
+            .map(|triangle| triangle.transform(&self.get_properties().transform))
+            .collect()
+    }
+
+    fn get_transform(&self) -> &MatrixType {
+        &self.get_properties().transform
+    }
+
+    fn get_inverse_transform(&self) -> &MatrixType {
+        &self.get_properties().inverse
+    }
+
+    fn set_transform(&mut self, transform: MatrixType) {
+        self.get_properties_mut().set_transform(transform);
+    }
+
+    fn set_motion(&mut self, motion: AnimatedTransform) {
+        self.get_properties_mut().motion = Some(motion);
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.get_properties().material
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.get_properties_mut().material = material;
+    }
+
+    fn is_visible_to(&self, kind: RayKind) -> bool {
+        self.get_properties().visibility.is_visible_to(kind)
+    }
+
+    fn set_visibility(&mut self, visibility: Visibility) {
+        self.get_properties_mut().visibility = visibility;
+    }
+
+    fn set_light_links(&mut self, light_links: LightLinks) {
+        self.get_properties_mut().light_links = light_links;
+    }
+
+    fn add_clip(&mut self, clip: Clip) {
+        self.get_properties_mut().clips.push(clip);
+    }
+
+    // Groups hand their intersections to the children that were hit
+    fn is_group(&self) -> bool {
+        false
+    }
+
+    // Shapes holding others hand each of them to visit, which may swap it for another shape.
+    // Their bounds are brought up to date afterwards
+    fn for_each_child_mut(&mut self, _visit: &mut dyn FnMut(&mut Box<dyn Shape>)) {}
+
+    // Intersects with the shape where it is at the ray's time
+    fn intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let local_ray = ray.transform(&self.get_properties().get_inverse_at(ray.time));
+        clip_intersections(&self.get_properties().clips, ray, self.local_intersect(&local_ray))
+    }
+
+    fn normal_at(&self, world_point: &Tuple) -> Tuple {
+        self.normal_at_time(world_point, 0.)
+    }
+
+    fn normal_at_time(&self, world_point: &Tuple, time: Float) -> Tuple {
+        world_normal(self.get_properties(), world_point, time, |local_point| self.local_normal_at(local_point))
+    }
+
+    fn normal_at_hit(&self, world_point: &Tuple, hit: &Intersection, time: Float) -> Tuple {
+        world_normal(self.get_properties(), world_point, time, |local_point| self.local_normal_at_hit(local_point, hit))
+    }
+
+    // Tangents lie along the surface, so unlike normals they go through the transform itself
+    fn tangent_at_hit(&self, world_point: &Tuple, hit: &Intersection, time: Float) -> Option<Tuple> {
+        let properties = self.get_properties();
+        let local_point = Matrix::multiply_tuple(&properties.get_inverse_at(time), world_point);
+        let local_tangent = self.local_tangent_at_hit(&local_point, hit)?;
+        Some(Matrix::multiply_tuple(&properties.get_transform_at(time), &local_tangent).normalize())
+    }
+
+    // Surface color at a world point where the shape is at the given time
+    fn color_at_hit(&self, world_point: &Tuple, hit: &Intersection, time: Float) -> Color {
+        let local_point = Matrix::multiply_tuple(&self.get_properties().get_inverse_at(time), world_point);
+        self.local_color_at_hit(&local_point, hit).unwrap_or(self.get_material().color)
+    }
+
+    // Bounds in world space, covering every keyframe of a moving shape
+    fn get_bounds(&self) -> BoundingBox {
+        let properties = self.get_properties();
+        let local = self.local_bounds();
+
+        match &properties.motion {
+            Some(motion) => {
+                let mut bounds = BoundingBox::new();
+                for keyframe in motion.get_keyframes() {
+                    bounds.merge(&local.transform(&keyframe.to_matrix()));
+                }
+                bounds
+            },
+            None => local.transform(&properties.transform)
+        }
+    }
+}
+
+// Takes the point into object space, asks the shape for its normal there and brings it back
+fn world_normal<F: Fn(&Tuple) -> Tuple>(properties: &ShapeProperties, world_point: &Tuple, time: Float, local_normal_at: F) -> Tuple {
+    let (inverse, inverse_transpose) = match properties.motion {
+        Some(_) => {
+            let inverse = properties.get_inverse_at(time).into_owned();
+            let inverse_transpose = Matrix::transpose(&inverse);
+            (Cow::Owned(inverse), Cow::Owned(inverse_transpose))
+        },
+        None => (Cow::Borrowed(&properties.inverse), Cow::Borrowed(&properties.inverse_transpose))
+    };
+
+    let local_point = Matrix::multiply_tuple(&inverse, world_point);
+    let local_normal = local_normal_at(&local_point);
+    let mut world_normal = Matrix::multiply_tuple(&inverse_transpose, &local_normal);
+    world_normal.w = 0.;
+    world_normal.normalize()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_default_properties() {
+        let properties = ShapeProperties::new();
+
+        assert_eq!(Matrix::are_equal(&properties.transform, &Matrix::identity_4x4()), true);
+        assert_eq!(properties.material, Material::new());
+    }
+
+    #[test]
+    fn test_set_transform_caches_inverse() {
+        let mut properties = ShapeProperties::new();
+        properties.set_transform(Matrix::translation(2., 3., 4.));
+
+        assert_eq!(Matrix::are_equal(&properties.inverse, &Matrix::translation(-2., -3., -4.)), true);
+    }
+
+    #[test]
+    fn test_normal_on_transformed_shape() {
+        let mut sphere = Sphere::new();
+        sphere.set_transform(Matrix::multiply(&Matrix::scaling(1., 0.5, 1.), &Matrix::rotation_z(PI / 5.)));
+
+        let normal = sphere.normal_at(&Tuple::new_point(0., 2_f64.sqrt() / 2., -2_f64.sqrt() / 2.));
+        assert_eq!(normal.is_equal_to(&Tuple::new_vector(0., 0.97014, -0.24254)), true);
+    }
+
+    #[test]
+    fn test_moving_shape_is_hit_at_ray_time() {
+        let mut sphere = Sphere::new();
+        sphere.set_motion(AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(-3., 0., 0.)),
+            (1., Matrix::translation(3., 0., 0.)),
+        ]));
+
+        let origin = Tuple::new_point(0., 0., -5.);
+        let direction = Tuple::new_vector(0., 0., 1.);
+
+        assert_eq!(sphere.intersect(&Ray::new_at_time(origin, direction, 0.)).len(), 0);
+        assert_eq!(sphere.intersect(&Ray::new_at_time(origin, direction, 0.5)).len(), 2);
+        assert_eq!(sphere.intersect(&Ray::new_at_time(origin, direction, 1.)).len(), 0);
+    }
+
+    #[test]
+    fn test_normal_of_moving_shape() {
+        let mut sphere = Sphere::new();
+        sphere.set_motion(AnimatedTransform::from_matrices(&[
+            (0., Matrix::translation(0., 0., 0.)),
+            (1., Matrix::translation(2., 0., 0.)),
+        ]));
+
+        let normal = sphere.normal_at_time(&Tuple::new_point(1., 1., 0.), 0.5);
+        assert_eq!(normal.is_equal_to(&Tuple::new_vector(0., 1., 0.)), true);
+    }
+
+    #[test]
+    fn test_normal_on_translated_shape() {
+        let mut sphere = Sphere::new();
+        sphere.set_transform(Matrix::translation(0., 1., 0.));
+
+        let value = 2_f64.sqrt() / 2.;
+        let normal = sphere.normal_at(&Tuple::new_point(0., 1. + value, -value));
+        assert_eq!(normal.is_equal_to(&Tuple::new_vector(0., value, -value)), true);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::bounds::BoundingBox;
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+use super::{Shape, ShapeProperties};
+
+// Infinite plane in xz, facing up the y axis
+#[derive(PartialEq, Debug, Clone)]
+pub struct Plane {
+    properties: ShapeProperties
+}
+
+impl Plane {
+    pub fn new() -> Self {
+        Plane {
+            properties: ShapeProperties::new()
+        }
+    }
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Plane::new()
+    }
+}
+
+impl Shape for Plane {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        if ray.direction.y.abs() < EPSILON {
+            return vec![]
+        }
+
+        vec![Intersection::new(-ray.origin.y / ray.direction.y, self)]
+    }
+
+    fn local_normal_at(&self, _point: &Tuple) -> Tuple {
+        Tuple::new_vector(0., 1., 0.)
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        BoundingBox::new_with_bounds(
+            Tuple::new_point(Float::NEG_INFINITY, 0., Float::NEG_INFINITY),
+            Tuple::new_point(Float::INFINITY, 0., Float::INFINITY)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_plane_normal_is_constant() {
+        let plane = Plane::new();
+
+        assert_eq!(plane.local_normal_at(&Tuple::new_point(0., 0., 0.)), Tuple::new_vector(0., 1., 0.));
+        assert_eq!(plane.local_normal_at(&Tuple::new_point(10., 0., -10.)), Tuple::new_vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn test_intersect_parallel_and_coplanar() {
+        let plane = Plane::new();
+
+        let parallel = Ray::new(Tuple::new_point(0., 10., 0.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(plane.local_intersect(&parallel).len(), 0);
+
+        let coplanar = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(plane.local_intersect(&coplanar).len(), 0);
+    }
+
+    #[test]
+    fn test_intersect_from_above_and_below() {
+        let plane = Plane::new();
+
+        let above = Ray::new(Tuple::new_point(0., 1., 0.), Tuple::new_vector(0., -1., 0.));
+        let xs = plane.local_intersect(&above);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1.);
+
+        let below = Ray::new(Tuple::new_point(0., -1., 0.), Tuple::new_vector(0., 1., 0.));
+        assert_eq!(plane.local_intersect(&below)[0].t, 1.);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::bounds::BoundingBox;
+use crate::color::Color;
+use crate::intersection::{Intersection, sort_intersections};
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+use super::{Shape, ShapeProperties};
+use super::bvh::Bvh;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum SplatType {
+    Sphere,
+    // Flat disk across the point's normal, or turned to face the ray when there are no normals
+    Disk
+}
+
+// Scanned points drawn as small spheres or disks of one radius, with optional colors and
+// normals per point. Points are plain positions instead of shapes of their own, found
+// through a bounding volume hierarchy built over them. Hits record the point as their face
+#[derive(PartialEq, Debug, Clone)]
+pub struct PointCloud {
+    properties: ShapeProperties,
+    positions: Vec<Tuple>,
+    colors: Vec<Color>,
+    normals: Vec<Tuple>,
+    radius: Float,
+    splat: SplatType,
+    bvh: Bvh
+}
+
+impl PointCloud {
+    pub fn new(positions: Vec<Tuple>, radius: Float, splat: SplatType) -> Self {
+        let margin = Tuple::new_vector(radius, radius, radius);
+        let boxes: Vec<BoundingBox> = positions.iter()
+            .map(|&position| BoundingBox::new_with_bounds(position - margin, position + margin))
+            .collect();
+
+        PointCloud {
+            properties: ShapeProperties::new(),
+            positions,
+            colors: vec![],
+            normals: vec![],
+            radius,
+            splat,
+            bvh: Bvh::new(&boxes)
+        }
+    }
+
+    pub fn get_positions(&self) -> &[Tuple] {
+        &self.positions
+    }
+
+    // Empty when the points take the material's color
+    pub fn get_colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn get_normals(&self) -> &[Tuple] {
+        &self.normals
+    }
+
+    pub fn get_radius(&self) -> Float {
+        self.radius
+    }
+
+    pub fn get_splat(&self) -> SplatType {
+        self.splat
+    }
+
+    pub fn get_point_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn set_colors(&mut self, colors: Vec<Color>) {
+        assert_eq!(colors.len(), self.positions.len(), "one color per point");
+        self.colors = colors;
+    }
+
+    pub fn set_normals(&mut self, normals: Vec<Tuple>) {
+        assert_eq!(normals.len(), self.positions.len(), "one normal per point");
+        self.normals = normals.iter().map(|normal| normal.normalize()).collect();
+    }
+
+    fn intersect_point<'a>(&'a self, ray: &Ray, index: usize, xs: &mut Vec<Intersection<'a>>) {
+        let center = self.positions[index];
+        let to_center = center - ray.origin;
+
+        match (self.splat, self.normals.get(index)) {
+            (SplatType::Sphere, _) => {
+                let a = ray.direction.calculate_dot_product(&ray.direction);
+                let b = -2. * ray.direction.calculate_dot_product(&to_center);
+                let c = to_center.calculate_dot_product(&to_center) - self.radius * self.radius;
+                let discriminant = b * b - 4. * a * c;
+                if discriminant < 0. {
+                    return
+                }
+
+                let root = discriminant.sqrt();
+                xs.push(Intersection::new_on_face((-b - root) / (2. * a), self, index, 0., 0.));
+                xs.push(Intersection::new_on_face((-b + root) / (2. * a), self, index, 0., 0.));
+            },
+            (SplatType::Disk, normal) => {
+                let facing = normal.copied().unwrap_or_else(|| ray.direction.negate().normalize());
+                let speed = ray.direction.calculate_dot_product(&facing);
+                if speed.abs() < EPSILON {
+                    return
+                }
+
+                let t = to_center.calculate_dot_product(&facing) / speed;
+                if (ray.position(t) - center).get_magnitude() > self.radius {
+                    return
+                }
+
+                let hit = Intersection::new_on_face(t, self, index, 0., 0.);
+                xs.push(if normal.is_some() { hit } else { Intersection { normal: Some(facing), ..hit } });
+            }
+        }
+    }
+
+    fn closest_point(&self, point: &Tuple) -> usize {
+        self.bvh.closest(point, |index| (self.positions[index] - *point).get_magnitude()).unwrap_or(0)
+    }
+
+    fn normal_of(&self, point: &Tuple, index: usize) -> Tuple {
+        match (self.splat, self.normals.get(index)) {
+            (SplatType::Disk, Some(normal)) => *normal,
+            _ => (*point - self.positions[index]).normalize()
+        }
+    }
+}
+
+impl Shape for PointCloud {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let mut xs = vec![];
+        self.bvh.traverse(ray, |point| self.intersect_point(ray, point, &mut xs));
+
+        sort_intersections(&mut xs);
+        xs
+    }
+
+    // Without a hit the closest point is looked up
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        self.normal_of(point, self.closest_point(point))
+    }
+
+    fn local_normal_at_hit(&self, point: &Tuple, hit: &Intersection) -> Tuple {
+        match hit.normal {
+            Some(normal) => normal,
+            None => self.normal_of(point, hit.face)
+        }
+    }
+
+    fn local_color_at_hit(&self, _point: &Tuple, hit: &Intersection) -> Option<Color> {
+        self.colors.get(hit.face).copied()
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        self.bvh.get_bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::shapes::bvh::LEAF_SIZE;
+    use crate::utils::NumberUtils;
+
+    // A line of points one apart along x, from 0 to count - 1
+    fn line(count: usize, splat: SplatType) -> PointCloud {
+        let positions = (0..count).map(|x| Tuple::new_point(x as Float, 0., 0.)).collect();
+        PointCloud::new(positions, 0.25, splat)
+    }
+
+    #[test]
+    fn test_hierarchy_covers_every_point() {
+        let cloud = line(100, SplatType::Sphere);
+
+        assert_eq!(cloud.local_bounds().min, Tuple::new_point(-0.25, -0.25, -0.25));
+        assert_eq!(cloud.local_bounds().max, Tuple::new_point(99.25, 0.25, 0.25));
+        assert_eq!(cloud.bvh.nodes.iter().filter(|node| node.count > 0).all(|node| node.count <= LEAF_SIZE), true);
+        assert_eq!(cloud.bvh.nodes.iter().map(|node| node.count).sum::<usize>(), 100);
+
+        // every point is found again by a ray straight down onto it
+        for x in 0..100 {
+            let ray = Ray::new(Tuple::new_point(x as Float, 5., 0.), Tuple::new_vector(0., -1., 0.));
+            let xs = cloud.local_intersect(&ray);
+            assert_eq!(xs.len(), 2);
+            assert_eq!(xs[0].face, x);
+            assert_eq!(xs[0].t, 4.75);
+        }
+
+        assert_eq!(PointCloud::new(vec![], 1., SplatType::Disk).local_intersect(&Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.))).len(), 0);
+    }
+
+    #[test]
+    fn test_spheres_along_a_ray_with_colors() {
+        let mut cloud = line(10, SplatType::Sphere);
+        cloud.set_colors((0..10).map(|x| Color::new(x as f32 / 10., 0., 0.)).collect());
+
+        let ray = Ray::new(Tuple::new_point(-5., 0., 0.), Tuple::new_vector(1., 0., 0.));
+        let xs = cloud.local_intersect(&ray);
+        assert_eq!(xs.len(), 20);
+        assert_eq!(xs[2].t, 5.75);
+        assert_eq!(xs[2].face, 1);
+
+        let point = ray.position(xs[2].t);
+        assert_eq!(cloud.local_normal_at_hit(&point, &xs[2]).is_equal_to(&Tuple::new_vector(-1., 0., 0.)), true);
+        assert_eq!(cloud.local_color_at_hit(&point, &xs[2]), Some(Color::new(0.1, 0., 0.)));
+        assert_eq!(cloud.local_normal_at(&Tuple::new_point(3., 0.25, 0.)), Tuple::new_vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn test_disks() {
+        let facing = line(3, SplatType::Disk);
+        let slanted = Ray::new(Tuple::new_point(1., -4., -3.), Tuple::new_vector(0., 4., 3.));
+        let xs = facing.local_intersect(&slanted);
+
+        // without normals each disk turns to face the ray, so the hit is at the center
+        assert_eq!(xs.len(), 1);
+        assert_eq!(NumberUtils::compare_floats(xs[0].t, 1.), true);
+        assert_eq!(xs[0].normal.unwrap().is_equal_to(&Tuple::new_vector(0., -0.8, -0.6)), true);
+
+        let mut oriented = line(3, SplatType::Disk);
+        oriented.set_normals(vec![Tuple::new_vector(0., 2., 0.); 3]);
+        let down = Ray::new(Tuple::new_point(1.2, 5., 0.1), Tuple::new_vector(0., -1., 0.));
+        let xs = oriented.local_intersect(&down);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 5.);
+        assert_eq!(oriented.local_normal_at_hit(&down.position(5.), &xs[0]), Tuple::new_vector(0., 1., 0.));
+
+        // edge on, an oriented disk is missed
+        let along = Ray::new(Tuple::new_point(1., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(oriented.local_intersect(&along).len(), 0);
+        assert_eq!(oriented.local_intersect(&Ray::new(Tuple::new_point(1.3, 5., 0.), Tuple::new_vector(0., -1., 0.))).len(), 0);
+    }
+}
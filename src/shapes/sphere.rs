@@ -0,0 +1,163 @@
+use std::f64::consts::PI;
+
+use crate::bounds::BoundingBox;
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+
+use super::{Shape, ShapeProperties, Triangle};
+
+// Unit sphere centered at the origin, use the transform to move and resize it
+#[derive(PartialEq, Debug, Clone)]
+pub struct Sphere {
+    properties: ShapeProperties
+}
+
+impl Sphere {
+    pub fn new() -> Self {
+        Sphere {
+            properties: ShapeProperties::new()
+        }
+    }
+}
+
+impl Default for Sphere {
+    fn default() -> Self {
+        Sphere::new()
+    }
+}
+
+impl Shape for Sphere {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let sphere_to_ray = ray.origin - Tuple::new_point(0., 0., 0.);
+
+        let a = ray.direction.calculate_dot_product(&ray.direction);
+        let b = 2. * ray.direction.calculate_dot_product(&sphere_to_ray);
+        let c = sphere_to_ray.calculate_dot_product(&sphere_to_ray) - 1.;
+
+        let discriminant = b * b - 4. * a * c;
+
+        if discriminant < 0. {
+            return vec![]
+        }
+
+        let t1 = (-b - discriminant.sqrt()) / (2. * a);
+        let t2 = (-b + discriminant.sqrt()) / (2. * a);
+
+        vec![Intersection::new(t1, self), Intersection::new(t2, self)]
+    }
+
+    fn local_normal_at(&self, point: &Tuple) -> Tuple {
+        *point - Tuple::new_point(0., 0., 0.)
+    }
+
+    // Latitude rings and longitude slices with the sphere normals at every corner
+    fn local_tessellate(&self, segments: usize) -> Vec<Triangle> {
+        let slices = segments.max(3);
+        let rings = (segments / 2).max(2);
+        let point = |ring: usize, slice: usize| {
+            let theta = PI * ring as Float / rings as Float;
+            let phi = 2. * PI * slice as Float / slices as Float;
+            Tuple::new_point(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+        };
+        let normal = |p: Tuple| p - Tuple::new_point(0., 0., 0.);
+        let mut triangles = vec![];
+
+        for ring in 0..rings {
+            for slice in 0..slices {
+                let a = point(ring, slice);
+                let b = point(ring + 1, slice);
+                let c = point(ring + 1, slice + 1);
+                let d = point(ring, slice + 1);
+
+                // the top and bottom rings meet in a single point
+                if ring > 0 {
+                    triangles.push(Triangle::new_smooth(a, c, d, normal(a), normal(c), normal(d)));
+                }
+                if ring < rings - 1 {
+                    triangles.push(Triangle::new_smooth(a, b, c, normal(a), normal(b), normal(c)));
+                }
+            }
+        }
+
+        triangles
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        BoundingBox::new_with_bounds(Tuple::new_point(-1., -1., -1.), Tuple::new_point(1., 1., 1.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::matrix::Matrix;
+
+    #[test]
+    fn test_ray_intersects_sphere() {
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let sphere = Sphere::new();
+        let xs = sphere.intersect(&ray);
+
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.);
+        assert_eq!(xs[1].t, 6.);
+    }
+
+    #[test]
+    fn test_ray_tangent_and_miss() {
+        let sphere = Sphere::new();
+
+        let tangent = Ray::new(Tuple::new_point(0., 1., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = sphere.intersect(&tangent);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 5.);
+
+        let miss = Ray::new(Tuple::new_point(0., 2., -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(sphere.intersect(&miss).len(), 0);
+    }
+
+    #[test]
+    fn test_ray_originates_inside_sphere() {
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., 1.));
+        let sphere = Sphere::new();
+        let xs = sphere.intersect(&ray);
+
+        assert_eq!(xs[0].t, -1.);
+        assert_eq!(xs[1].t, 1.);
+    }
+
+    #[test]
+    fn test_intersect_scaled_and_translated_sphere() {
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let mut sphere = Sphere::new();
+
+        sphere.set_transform(Matrix::scaling(2., 2., 2.));
+        let xs = sphere.intersect(&ray);
+        assert_eq!(xs[0].t, 3.);
+        assert_eq!(xs[1].t, 7.);
+
+        sphere.set_transform(Matrix::translation(5., 0., 0.));
+        assert_eq!(sphere.intersect(&ray).len(), 0);
+    }
+
+    #[test]
+    fn test_sphere_normal() {
+        let sphere = Sphere::new();
+        let value = 3_f64.sqrt() / 3.;
+
+        assert_eq!(sphere.normal_at(&Tuple::new_point(1., 0., 0.)), Tuple::new_vector(1., 0., 0.));
+        let normal = sphere.normal_at(&Tuple::new_point(value, value, value));
+        assert_eq!(normal.is_equal_to(&Tuple::new_vector(value, value, value)), true);
+        assert_eq!(normal.is_equal_to(&normal.normalize()), true);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::bounds::BoundingBox;
+use crate::intersection::Intersection;
+use crate::matrix::{Matrix, MatrixType};
+use crate::ray::Ray;
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+use super::{Shape, ShapeProperties};
+
+// Flat triangle, or a smooth one when it has vertex normals to blend across its surface.
+// Hits carry the barycentric u and v of the second and third point
+#[derive(PartialEq, Debug, Clone)]
+pub struct Triangle {
+    properties: ShapeProperties,
+    points: [Tuple; 3],
+    e1: Tuple,
+    e2: Tuple,
+    normal: Tuple,
+    normals: Option<[Tuple; 3]>,
+    texture_coordinates: Option<[(Float, Float); 3]>
+}
+
+impl Triangle {
+    pub fn new(p1: Tuple, p2: Tuple, p3: Tuple) -> Self {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+
+        Triangle {
+            properties: ShapeProperties::new(),
+            points: [p1, p2, p3],
+            e1,
+            e2,
+            normal: e2.get_vector_cross_product(&e1).normalize(),
+            normals: None,
+            texture_coordinates: None
+        }
+    }
+
+    pub fn new_smooth(p1: Tuple, p2: Tuple, p3: Tuple, n1: Tuple, n2: Tuple, n3: Tuple) -> Self {
+        Triangle {
+            normals: Some([n1, n2, n3]),
+            ..Triangle::new(p1, p2, p3)
+        }
+    }
+
+    pub fn get_points(&self) -> &[Tuple; 3] {
+        &self.points
+    }
+
+    pub fn get_normal(&self) -> Tuple {
+        self.normal
+    }
+
+    pub fn get_vertex_normals(&self) -> Option<&[Tuple; 3]> {
+        self.normals.as_ref()
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.normals.is_some()
+    }
+
+    pub fn get_texture_coordinates(&self) -> Option<&[(Float, Float); 3]> {
+        self.texture_coordinates.as_ref()
+    }
+
+    pub fn set_texture_coordinates(&mut self, coordinates: [(Float, Float); 3]) {
+        self.texture_coordinates = Some(coordinates);
+    }
+
+    // Copy with the matrix applied to the points and normals, the material is kept
+    pub fn transform(&self, m: &MatrixType) -> Triangle {
+        let [p1, p2, p3] = self.points.map(|point| Matrix::multiply_tuple(m, &point));
+
+        let mut triangle = match &self.normals {
+            Some(normals) => {
+                let inverse_transpose = Matrix::transpose(&Matrix::inverse(m));
+                let [n1, n2, n3] = normals.map(|normal| {
+                    let mut normal = Matrix::multiply_tuple(&inverse_transpose, &normal);
+                    normal.w = 0.;
+                    normal.normalize()
+                });
+                Triangle::new_smooth(p1, p2, p3, n1, n2, n3)
+            },
+            None => Triangle::new(p1, p2, p3)
+        };
+
+        triangle.texture_coordinates = self.texture_coordinates;
+        triangle.properties.material = self.properties.material.clone();
+        triangle
+    }
+
+    // Texture coordinates blended for a hit at u and v
+    pub fn get_texture_coordinates_at(&self, u: Float, v: Float) -> Option<(Float, Float)> {
+        self.texture_coordinates.map(|[t1, t2, t3]| {
+            let w = 1. - u - v;
+            (t1.0 * w + t2.0 * u + t3.0 * v, t1.1 * w + t2.1 * u + t3.1 * v)
+        })
+    }
+}
+
+impl Shape for Triangle {
+    fn get_properties(&self) -> &ShapeProperties {
+        &self.properties
+    }
+
+    fn get_properties_mut(&mut self) -> &mut ShapeProperties {
+        &mut self.properties
+    }
+
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        match intersect_triangle(ray, &self.points[0], &self.e1, &self.e2) {
+            Some((t, u, v)) => vec![Intersection::new_with_uv(t, self, u, v)],
+            None => vec![]
+        }
+    }
+
+    fn local_normal_at(&self, _point: &Tuple) -> Tuple {
+        self.normal
+    }
+
+    fn local_normal_at_hit(&self, _point: &Tuple, hit: &Intersection) -> Tuple {
+        match &self.normals {
+            Some([n1, n2, n3]) => *n2 * hit.u + *n3 * hit.v + *n1 * (1. - hit.u - hit.v),
+            None => self.normal
+        }
+    }
+
+    fn local_tessellate(&self, _segments: usize) -> Vec<Triangle> {
+        let mut triangle = self.clone();
+        triangle.properties = ShapeProperties::new();
+        triangle.properties.material = self.properties.material.clone();
+        vec![triangle]
+    }
+
+    fn local_bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::new();
+        for point in &self.points {
+            bounds.add_point(point);
+        }
+        bounds
+    }
+}
+
+// Möller-Trumbore, gives t and the barycentric u and v of the hit
+pub(crate) fn intersect_triangle(ray: &Ray, p1: &Tuple, e1: &Tuple, e2: &Tuple) -> Option<(Float, Float, Float)> {
+    let dir_cross_e2 = ray.direction.get_vector_cross_product(e2);
+    let determinant = e1.calculate_dot_product(&dir_cross_e2);
+
+    if determinant.abs() < EPSILON {
+        return None
+    }
+
+    let f = 1. / determinant;
+    let p1_to_origin = ray.origin - *p1;
+    let u = f * p1_to_origin.calculate_dot_product(&dir_cross_e2);
+
+    if !(0. ..=1.).contains(&u) {
+        return None
+    }
+
+    let origin_cross_e1 = p1_to_origin.get_vector_cross_product(e1);
+    let v = f * ray.direction.calculate_dot_product(&origin_cross_e1);
+
+    if v < 0. || u + v > 1. {
+        return None
+    }
+
+    Some((f * e2.calculate_dot_product(&origin_cross_e1), u, v))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn triangle() -> Triangle {
+        Triangle::new(Tuple::new_point(0., 1., 0.), Tuple::new_point(-1., 0., 0.), Tuple::new_point(1., 0., 0.))
+    }
+
+    fn smooth_triangle() -> Triangle {
+        Triangle::new_smooth(
+            Tuple::new_point(0., 1., 0.), Tuple::new_point(-1., 0., 0.), Tuple::new_point(1., 0., 0.),
+            Tuple::new_vector(0., 1., 0.), Tuple::new_vector(-1., 0., 0.), Tuple::new_vector(1., 0., 0.)
+        )
+    }
+
+    #[test]
+    fn test_new_triangle() {
+        let triangle = triangle();
+
+        assert_eq!(triangle.e1, Tuple::new_vector(-1., -1., 0.));
+        assert_eq!(triangle.e2, Tuple::new_vector(1., -1., 0.));
+        assert_eq!(triangle.get_normal(), Tuple::new_vector(0., 0., -1.));
+        assert_eq!(triangle.local_normal_at(&Tuple::new_point(0., 0.5, 0.)), Tuple::new_vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn test_rays_missing_the_triangle() {
+        let triangle = triangle();
+        let parallel = Ray::new(Tuple::new_point(0., -1., -2.), Tuple::new_vector(0., 1., 0.));
+        let past_p1_p3 = Ray::new(Tuple::new_point(1., 1., -2.), Tuple::new_vector(0., 0., 1.));
+        let past_p1_p2 = Ray::new(Tuple::new_point(-1., 1., -2.), Tuple::new_vector(0., 0., 1.));
+        let past_p2_p3 = Ray::new(Tuple::new_point(0., -1., -2.), Tuple::new_vector(0., 0., 1.));
+
+        for ray in [parallel, past_p1_p3, past_p1_p2, past_p2_p3] {
+            assert_eq!(triangle.local_intersect(&ray).len(), 0);
+        }
+    }
+
+    #[test]
+    fn test_ray_strikes_triangle() {
+        let triangle = triangle();
+        let ray = Ray::new(Tuple::new_point(0., 0.5, -2.), Tuple::new_vector(0., 0., 1.));
+        let xs = triangle.local_intersect(&ray);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.);
+    }
+
+    #[test]
+    fn test_smooth_triangle_interpolates_normal() {
+        let triangle = smooth_triangle();
+        let ray = Ray::new(Tuple::new_point(-0.2, 0.3, -2.), Tuple::new_vector(0., 0., 1.));
+        let xs = triangle.local_intersect(&ray);
+
+        assert_eq!((xs[0].u - 0.45).abs() < 1e-9 && (xs[0].v - 0.25).abs() < 1e-9, true);
+
+        let hit = Intersection::new_with_uv(1., &triangle, 0.45, 0.25);
+        let normal = triangle.normal_at_hit(&Tuple::new_point(0., 0., 0.), &hit, 0.);
+        assert_eq!(normal.is_equal_to(&Tuple::new_vector(-0.5547, 0.83205, 0.)), true);
+    }
+
+    #[test]
+    fn test_texture_coordinates_and_bounds() {
+        let mut triangle = triangle();
+        assert_eq!(triangle.get_texture_coordinates_at(0.5, 0.5), None);
+
+        triangle.set_texture_coordinates([(0., 0.), (1., 0.), (0., 1.)]);
+        assert_eq!(triangle.get_texture_coordinates_at(0.25, 0.5), Some((0.25, 0.5)));
+
+        let bounds = triangle.local_bounds();
+        assert_eq!(bounds.min, Tuple::new_point(-1., 0., 0.));
+        assert_eq!(bounds.max, Tuple::new_point(1., 1., 0.));
+    }
+}
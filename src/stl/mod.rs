@@ -0,0 +1,191 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::shapes::{Group, Mesh, Triangle};
+use crate::tuples::{Tuple, Float};
+use crate::utils::EPSILON;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct StlTriangle {
+    // As stored in the file, exporters often leave it zero
+    pub normal: Tuple,
+    pub vertices: [Tuple; 3]
+}
+
+// STL triangle soup from 3D printing tools, ASCII or binary
+#[derive(PartialEq, Debug, Clone)]
+pub struct StlFile {
+    pub name: String,
+    pub triangles: Vec<StlTriangle>
+}
+
+impl StlFile {
+    pub fn load(path: &str) -> Result<StlFile> {
+        StlFile::parse(&fs::read(path)?)
+    }
+
+    // Binary files may also start with "solid", so the size decides first
+    pub fn parse(bytes: &[u8]) -> Result<StlFile> {
+        if bytes.len() >= 84 {
+            let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+
+            if bytes.len() == 84 + count * 50 {
+                return StlFile::parse_binary(bytes, count)
+            }
+        }
+
+        if bytes.starts_with(b"solid") {
+            let text = std::str::from_utf8(bytes).map_err(|_| invalid("ASCII STL is not valid text"))?;
+            return StlFile::parse_ascii(text)
+        }
+
+        Err(invalid("neither an ASCII nor a binary STL file"))
+    }
+
+    fn parse_binary(bytes: &[u8], count: usize) -> Result<StlFile> {
+        let header = String::from_utf8_lossy(&bytes[..80]);
+        let name = header.trim_end_matches('\0').trim().to_string();
+
+        let triangles = (0..count).map(|i| {
+            let record = &bytes[84 + i * 50..84 + (i + 1) * 50];
+            let value = |index: usize| f32::from_le_bytes([record[index * 4], record[index * 4 + 1], record[index * 4 + 2], record[index * 4 + 3]]) as Float;
+            let point = |first: usize| Tuple::new_point(value(first), value(first + 1), value(first + 2));
+
+            StlTriangle {
+                normal: Tuple::new_vector(value(0), value(1), value(2)),
+                vertices: [point(3), point(6), point(9)]
+            }
+        }).collect();
+
+        Ok(StlFile { name, triangles })
+    }
+
+    fn parse_ascii(text: &str) -> Result<StlFile> {
+        let mut name = String::new();
+        let mut triangles = vec![];
+        let mut normal = Tuple::new_vector(0., 0., 0.);
+        let mut vertices: Vec<Tuple> = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let numbers = |values: &[&str]| -> Result<Tuple> {
+                let parsed = values.iter()
+                    .map(|value| value.parse::<Float>().map_err(|_| invalid(&format!("line {}: invalid number {}", number, value))))
+                    .collect::<Result<Vec<Float>>>()?;
+
+                match parsed.as_slice() {
+                    [x, y, z] => Ok(Tuple::new_vector(*x, *y, *z)),
+                    _ => Err(invalid(&format!("line {}: expected three numbers", number)))
+                }
+            };
+
+            match parts.as_slice() {
+                [] | ["outer", "loop"] | ["endloop"] => (),
+                ["solid", rest @ ..] => name = rest.join(" "),
+                ["endsolid", ..] => break,
+                ["facet", "normal", values @ ..] => {
+                    normal = numbers(values)?;
+                    vertices.clear();
+                },
+                ["vertex", values @ ..] => {
+                    let vector = numbers(values)?;
+                    vertices.push(Tuple::new_point(vector.x, vector.y, vector.z));
+                },
+                ["endfacet"] => {
+                    if vertices.len() != 3 {
+                        return Err(invalid(&format!("line {}: facet has {} vertices instead of 3", number, vertices.len())))
+                    }
+                    triangles.push(StlTriangle { normal, vertices: [vertices[0], vertices[1], vertices[2]] });
+                },
+                _ => return Err(invalid(&format!("line {}: unexpected {}", number, line.trim())))
+            }
+        }
+
+        Ok(StlFile { name, triangles })
+    }
+
+    // Facets with all three corners in a line are left out
+    pub fn to_group(&self) -> Group {
+        let mut group = Group::new_named(&self.name);
+
+        for triangle in &self.triangles {
+            let [p1, p2, p3] = triangle.vertices;
+
+            if (p2 - p1).get_vector_cross_product(&(p3 - p1)).get_magnitude() > 0. {
+                group.add_child(Box::new(Triangle::new(p1, p2, p3)));
+            }
+        }
+
+        group
+    }
+
+    // STL repeats every corner per facet, welding the mesh makes them shared
+    pub fn to_mesh(&self) -> Mesh {
+        let positions = self.triangles.iter().flat_map(|triangle| triangle.vertices).collect();
+        let triangles = (0..self.triangles.len()).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect();
+        let mut mesh = Mesh::new(positions, triangles);
+        mesh.weld(EPSILON);
+        mesh
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const ASCII: &str = "solid wedge\n  facet normal 0 0 -1\n    outer loop\n      vertex 0 1 0\n      vertex -1 0 0\n      vertex 1 0 0\n    endloop\n  endfacet\n  facet normal 0 0 0\n    outer loop\n      vertex 0 0 0\n      vertex 1 0 0\n      vertex 2 0 0\n    endloop\n  endfacet\nendsolid wedge\n";
+
+    #[test]
+    fn test_ascii_stl() {
+        let file = StlFile::parse(ASCII.as_bytes()).unwrap();
+
+        assert_eq!(file.name, "wedge");
+        assert_eq!(file.triangles.len(), 2);
+        assert_eq!(file.triangles[0].normal, Tuple::new_vector(0., 0., -1.));
+        assert_eq!(file.triangles[0].vertices[2], Tuple::new_point(1., 0., 0.));
+        // the second facet is degenerate
+        assert_eq!(file.to_group().get_children().len(), 1);
+
+        // welding shares the corner both facets have, the line facet survives as it has
+        // no repeated corner
+        let mut mesh = file.to_mesh();
+        assert_eq!(mesh.get_vertex_count(), 5);
+        assert_eq!(mesh.get_triangle_count(), 2);
+        assert_eq!(mesh.weld(EPSILON), 0);
+    }
+
+    #[test]
+    fn test_binary_stl() {
+        // the header starting with solid must not fool the reader
+        let mut bytes = b"solid but actually binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        for value in [0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+            bytes.extend_from_slice(&(value as f32).to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 0]);
+
+        let file = StlFile::parse(&bytes).unwrap();
+
+        assert_eq!(file.name, "solid but actually binary");
+        assert_eq!(file.triangles[0].normal, Tuple::new_vector(0., 0., 1.));
+        assert_eq!(file.triangles[0].vertices[1], Tuple::new_point(1., 0., 0.));
+    }
+
+    #[test]
+    fn test_invalid_stl_files() {
+        let error = StlFile::parse(b"solid bad\nfacet normal 0 0 1\nouter loop\nvertex 0 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 4: expected three numbers");
+
+        let short = StlFile::parse(b"solid bad\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nendloop\nendfacet\n").unwrap_err();
+        assert_eq!(short.to_string(), "line 6: facet has 1 vertices instead of 3");
+
+        assert_eq!(StlFile::parse(b"ply\n").is_err(), true);
+    }
+}
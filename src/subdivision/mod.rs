@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::shapes::Mesh;
+use crate::tuples::{Tuple, Float};
+
+// Edges are keyed by their vertices, smallest index first
+type Edge = (usize, usize);
+
+fn edge(a: usize, b: usize) -> Edge {
+    (a.min(b), a.max(b))
+}
+
+// Polygon control cage refined by Loop or Catmull-Clark subdivision. Creased edges carry a
+// sharpness counted in levels: they are refined as sharp curves for that many levels and
+// smoothly after, a fractional last level blends the two and infinite sharpness keeps them
+// sharp for good. Open boundaries and edges shared by more than two faces are always sharp
+#[derive(PartialEq, Debug, Clone)]
+pub struct Cage {
+    positions: Vec<Tuple>,
+    faces: Vec<Vec<usize>>,
+    creases: HashMap<Edge, Float>
+}
+
+// Connectivity of one level, edges are numbered in the order the faces first use them
+struct Topology {
+    edges: Vec<Edge>,
+    edge_indices: HashMap<Edge, usize>,
+    edge_faces: Vec<Vec<usize>>,
+    neighbours: Vec<Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>
+}
+
+impl Topology {
+    fn new(vertex_count: usize, faces: &[Vec<usize>]) -> Self {
+        let mut topology = Topology {
+            edges: vec![],
+            edge_indices: HashMap::new(),
+            edge_faces: vec![],
+            neighbours: vec![vec![]; vertex_count],
+            vertex_faces: vec![vec![]; vertex_count]
+        };
+
+        for (face, vertices) in faces.iter().enumerate() {
+            for (i, &a) in vertices.iter().enumerate() {
+                let b = vertices[(i + 1) % vertices.len()];
+                let key = edge(a, b);
+                topology.vertex_faces[a].push(face);
+
+                let index = match topology.edge_indices.get(&key) {
+                    Some(&index) => index,
+                    None => {
+                        topology.edges.push(key);
+                        topology.edge_faces.push(vec![]);
+                        topology.neighbours[a].push(b);
+                        topology.neighbours[b].push(a);
+                        topology.edge_indices.insert(key, topology.edges.len() - 1);
+                        topology.edges.len() - 1
+                    }
+                };
+                topology.edge_faces[index].push(face);
+            }
+        }
+
+        topology
+    }
+
+    fn get_edge_index(&self, a: usize, b: usize) -> usize {
+        self.edge_indices[&edge(a, b)]
+    }
+}
+
+// Point at the weighted sum of the given points, the weights should add up to one
+fn weighted<I: IntoIterator<Item = (Tuple, Float)>>(terms: I) -> Tuple {
+    terms.into_iter().fold(Tuple::new_point(0., 0., 0.), |sum, (point, weight)| {
+        Tuple::new_point(sum.x + point.x * weight, sum.y + point.y * weight, sum.z + point.z * weight)
+    })
+}
+
+// Semi-sharp rule, sharpness of one or more is fully sharp
+fn blend(smooth: Tuple, sharp: Tuple, sharpness: Float) -> Tuple {
+    let weight = sharpness.min(1.);
+    weighted([(smooth, 1. - weight), (sharp, weight)])
+}
+
+fn average(points: &[Tuple]) -> Tuple {
+    let weight = 1. / points.len() as Float;
+    weighted(points.iter().map(|&point| (point, weight)))
+}
+
+impl Cage {
+    pub fn new(positions: Vec<Tuple>, faces: Vec<Vec<usize>>) -> Self {
+        assert!(faces.iter().all(|face| face.len() >= 3), "faces need at least three vertices");
+        assert!(faces.iter().flatten().all(|&index| index < positions.len()), "face index out of range");
+
+        Cage {
+            positions,
+            faces,
+            creases: HashMap::new()
+        }
+    }
+
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        Cage::new(mesh.get_positions().to_vec(), mesh.get_triangles().iter().map(|triangle| triangle.to_vec()).collect())
+    }
+
+    pub fn get_positions(&self) -> &[Tuple] {
+        &self.positions
+    }
+
+    pub fn get_faces(&self) -> &[Vec<usize>] {
+        &self.faces
+    }
+
+    pub fn is_triangular(&self) -> bool {
+        self.faces.iter().all(|face| face.len() == 3)
+    }
+
+    pub fn get_crease(&self, a: usize, b: usize) -> Float {
+        self.creases.get(&edge(a, b)).copied().unwrap_or(0.)
+    }
+
+    // Sharpness of the edge between two vertices, zero removes the crease
+    pub fn set_crease(&mut self, a: usize, b: usize, sharpness: Float) {
+        if sharpness > 0. {
+            self.creases.insert(edge(a, b), sharpness);
+        } else {
+            self.creases.remove(&edge(a, b));
+        }
+    }
+
+    // Polygons are fanned out from their first corner
+    pub fn to_mesh(&self) -> Mesh {
+        let triangles = self.faces.iter()
+            .flat_map(|face| (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]]))
+            .collect();
+        Mesh::new(self.positions.clone(), triangles)
+    }
+
+    // Each level splits every triangle into four. Triangles with a repeated corner have no
+    // area and are left out
+    pub fn loop_subdivide(&self, levels: usize) -> Cage {
+        assert!(self.is_triangular(), "Loop subdivision needs a triangle cage");
+        let mut cage = self.clone();
+        cage.faces.retain(|face| face[0] != face[1] && face[1] != face[2] && face[2] != face[0]);
+        (0..levels).fold(cage, |cage, _| cage.loop_level())
+    }
+
+    // Each level splits every polygon into one quad per corner, so after the first level
+    // the cage is all quads whatever it started with
+    pub fn catmull_clark(&self, levels: usize) -> Cage {
+        (0..levels).fold(self.clone(), |cage, _| cage.catmull_clark_level())
+    }
+
+    fn get_sharpness(&self, topology: &Topology, index: usize) -> Float {
+        let (a, b) = topology.edges[index];
+        if topology.edge_faces[index].len() != 2 { Float::INFINITY } else { self.get_crease(a, b) }
+    }
+
+    // Vertices on two sharp edges follow the crease curve, more than two make a corner
+    // that stays put and the rest use the scheme's smooth rule. Creases softer than a level
+    // blend their rule with the smooth one by the average sharpness. Unused vertices are kept
+    fn vertex_point<F: Fn(usize) -> Tuple>(&self, topology: &Topology, vertex: usize, smooth: F) -> Tuple {
+        let sharp: Vec<(usize, Float)> = topology.neighbours[vertex].iter()
+            .map(|&other| (other, self.get_sharpness(topology, topology.get_edge_index(vertex, other))))
+            .filter(|&(_, sharpness)| sharpness > 0.)
+            .collect();
+        let sharpness = sharp.iter().map(|&(_, sharpness)| sharpness).sum::<Float>() / sharp.len() as Float;
+
+        let rule = match sharp.as_slice() {
+            [(a, _), (b, _)] => weighted([(self.positions[vertex], 0.75), (self.positions[*a], 0.125), (self.positions[*b], 0.125)]),
+            [_, _, _, ..] => self.positions[vertex],
+            _ if topology.neighbours[vertex].is_empty() => return self.positions[vertex],
+            _ => return smooth(vertex)
+        };
+
+        if sharpness >= 1. { rule } else { blend(smooth(vertex), rule, sharpness) }
+    }
+
+    // Creases carry over to both halves of their edge with one level less, creases on
+    // vertex pairs without an edge are dropped
+    fn refine_creases(&self, topology: &Topology, first_edge_point: usize) -> HashMap<Edge, Float> {
+        let mut creases = HashMap::new();
+
+        for (&(a, b), &sharpness) in &self.creases {
+            if let (true, Some(index)) = (sharpness > 1., topology.edge_indices.get(&(a, b))) {
+                let middle = first_edge_point + index;
+                creases.insert(edge(a, middle), sharpness - 1.);
+                creases.insert(edge(middle, b), sharpness - 1.);
+            }
+        }
+
+        creases
+    }
+
+    fn loop_level(&self) -> Cage {
+        let topology = Topology::new(self.positions.len(), &self.faces);
+        let edge_start = self.positions.len();
+
+        let mut positions: Vec<Tuple> = (0..self.positions.len()).map(|vertex| self.vertex_point(&topology, vertex, |vertex| {
+            let neighbours = &topology.neighbours[vertex];
+            let n = neighbours.len() as Float;
+            let beta = (0.625 - (0.375 + 0.25 * (2. * PI / n).cos()).powi(2)) / n;
+
+            weighted(std::iter::once((self.positions[vertex], 1. - n * beta))
+                .chain(neighbours.iter().map(|&other| (self.positions[other], beta))))
+        })).collect();
+
+        for (index, &(a, b)) in topology.edges.iter().enumerate() {
+            let sharpness = self.get_sharpness(&topology, index);
+            let sharp = average(&[self.positions[a], self.positions[b]]);
+            positions.push(if sharpness >= 1. {
+                sharp
+            } else {
+                let opposite = topology.edge_faces[index].iter()
+                    .flat_map(|&face| self.faces[face].iter().copied().find(|&vertex| vertex != a && vertex != b));
+
+                let smooth = weighted([(self.positions[a], 0.375), (self.positions[b], 0.375)].iter().copied()
+                    .chain(opposite.map(|vertex| (self.positions[vertex], 0.125))));
+                blend(smooth, sharp, sharpness)
+            });
+        }
+
+        let faces = self.faces.iter().flat_map(|face| {
+            let [a, b, c] = [face[0], face[1], face[2]];
+            let [ab, bc, ca] = [(a, b), (b, c), (c, a)].map(|(from, to)| edge_start + topology.get_edge_index(from, to));
+            [vec![a, ab, ca], vec![ab, b, bc], vec![ca, bc, c], vec![ab, bc, ca]]
+        }).collect();
+
+        Cage {
+            positions,
+            faces,
+            creases: self.refine_creases(&topology, edge_start)
+        }
+    }
+
+    fn catmull_clark_level(&self) -> Cage {
+        let topology = Topology::new(self.positions.len(), &self.faces);
+        let edge_start = self.positions.len();
+        let face_start = edge_start + topology.edges.len();
+
+        let face_points: Vec<Tuple> = self.faces.iter()
+            .map(|face| average(&face.iter().map(|&vertex| self.positions[vertex]).collect::<Vec<Tuple>>()))
+            .collect();
+
+        let mut positions: Vec<Tuple> = (0..self.positions.len()).map(|vertex| self.vertex_point(&topology, vertex, |vertex| {
+            let neighbours = &topology.neighbours[vertex];
+            let n = neighbours.len() as Float;
+            let faces = average(&topology.vertex_faces[vertex].iter().map(|&face| face_points[face]).collect::<Vec<Tuple>>());
+            let midpoints = average(&neighbours.iter().map(|&other| average(&[self.positions[vertex], self.positions[other]])).collect::<Vec<Tuple>>());
+
+            weighted([(faces, 1. / n), (midpoints, 2. / n), (self.positions[vertex], (n - 3.) / n)])
+        })).collect();
+
+        for (index, &(a, b)) in topology.edges.iter().enumerate() {
+            let sharpness = self.get_sharpness(&topology, index);
+            let sharp = average(&[self.positions[a], self.positions[b]]);
+            positions.push(if sharpness >= 1. {
+                sharp
+            } else {
+                let [f1, f2] = [topology.edge_faces[index][0], topology.edge_faces[index][1]];
+                blend(average(&[self.positions[a], self.positions[b], face_points[f1], face_points[f2]]), sharp, sharpness)
+            });
+        }
+        positions.extend(face_points);
+
+        let topology = &topology;
+        let faces = self.faces.iter().enumerate().flat_map(|(face, vertices)| {
+            let count = vertices.len();
+            (0..count).map(move |i| {
+                let (previous, current, next) = (vertices[(i + count - 1) % count], vertices[i], vertices[(i + 1) % count]);
+                vec![current, edge_start + topology.get_edge_index(current, next), face_start + face, edge_start + topology.get_edge_index(previous, current)]
+            }).collect::<Vec<Vec<usize>>>()
+        }).collect();
+
+        Cage {
+            positions,
+            faces,
+            creases: self.refine_creases(topology, edge_start)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+
+    fn tetrahedron() -> Cage {
+        Cage::new(
+            vec![Tuple::new_point(1., 1., 1.), Tuple::new_point(1., -1., -1.), Tuple::new_point(-1., 1., -1.), Tuple::new_point(-1., -1., 1.)],
+            vec![vec![0, 1, 2], vec![0, 3, 1], vec![0, 2, 3], vec![1, 3, 2]]
+        )
+    }
+
+    fn cube() -> Cage {
+        let positions = (0..8).map(|i| Tuple::new_point(
+            if i & 1 == 0 { -1. } else { 1. },
+            if i & 2 == 0 { -1. } else { 1. },
+            if i & 4 == 0 { -1. } else { 1. }
+        )).collect();
+        Cage::new(positions, vec![
+            vec![0, 2, 3, 1], vec![4, 5, 7, 6], vec![0, 1, 5, 4],
+            vec![2, 6, 7, 3], vec![0, 4, 6, 2], vec![1, 3, 7, 5]
+        ])
+    }
+
+    #[test]
+    fn test_loop_subdivision_refines_and_shrinks() {
+        let cage = tetrahedron().loop_subdivide(2);
+
+        assert_eq!(cage.get_faces().len(), 64);
+        assert_eq!(cage.get_positions().len(), 34);
+        assert_eq!(cage.is_triangular(), true);
+
+        // the smooth limit surface pulls the corners in
+        let corner = cage.get_positions()[0];
+        assert_eq!(corner.x < 1. && corner.x > 0., true);
+        assert_eq!(corner.w, 1.);
+    }
+
+    #[test]
+    fn test_sharp_creases_keep_corners_and_edges() {
+        let mut cage = tetrahedron();
+        for (a, b) in [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)] {
+            cage.set_crease(a, b, Float::INFINITY);
+        }
+        assert_eq!(cage.loop_subdivide(1).get_crease(0, 4), Float::INFINITY);
+
+        let refined = cage.loop_subdivide(2);
+        assert_eq!(refined.get_positions()[0], Tuple::new_point(1., 1., 1.));
+
+        // the point made for the first edge stays halfway along it
+        let midpoint = refined.get_positions()[4];
+        assert_eq!(midpoint.is_equal_to(&Tuple::new_point(1., 0., 0.)), true);
+    }
+
+    #[test]
+    fn test_semi_sharp_crease_wears_off() {
+        let mut cage = tetrahedron();
+        cage.set_crease(0, 1, 1.);
+        let once = cage.loop_subdivide(1);
+
+        assert_eq!(once.get_positions()[4].is_equal_to(&Tuple::new_point(1., 0., 0.)), true);
+        assert_eq!(once.get_crease(0, 4), 0.);
+
+        let smooth = tetrahedron().loop_subdivide(1);
+        assert_eq!(smooth.get_positions()[4].is_equal_to(&Tuple::new_point(1., 0., 0.)), false);
+
+        cage.set_crease(0, 1, 0.);
+        assert_eq!(cage, tetrahedron());
+    }
+
+    #[test]
+    fn test_fractional_crease_blends_smooth_and_sharp() {
+        let mut cage = tetrahedron();
+        cage.set_crease(0, 1, 0.5);
+        let half = cage.loop_subdivide(1);
+        let smooth = tetrahedron().loop_subdivide(1);
+        cage.set_crease(0, 1, 1.);
+        let sharp = cage.loop_subdivide(1);
+
+        // the edge point lands halfway between the smooth and the sharp one
+        let [a, b] = [smooth.get_positions()[4], sharp.get_positions()[4]];
+        let halfway = Tuple::new_point((a.x + b.x) / 2., (a.y + b.y) / 2., (a.z + b.z) / 2.);
+        assert_eq!(half.get_positions()[4].is_equal_to(&halfway), true);
+        assert_eq!(half.get_crease(0, 4), 0.);
+    }
+
+    #[test]
+    fn test_loop_skips_degenerate_triangles() {
+        let positions = vec![Tuple::new_point(0., 0., 0.), Tuple::new_point(1., 0., 0.), Tuple::new_point(0., 1., 0.), Tuple::new_point(2., 2., 0.)];
+        let cage = Cage::new(positions, vec![vec![0, 1, 2], vec![3, 3, 1]]);
+
+        assert_eq!(cage.loop_subdivide(1).get_faces().len(), 4);
+    }
+
+    #[test]
+    fn test_catmull_clark_cube() {
+        let cage = cube().catmull_clark(1);
+
+        assert_eq!(cage.get_faces().len(), 24);
+        assert_eq!(cage.get_positions().len(), 26);
+        assert_eq!(cage.get_faces().iter().all(|face| face.len() == 4), true);
+        // the corner rule gives (F + 2R + (n - 3)P) / n
+        let value = 5. / 9.;
+        assert_eq!(cage.get_positions()[7].is_equal_to(&Tuple::new_point(value, value, value)), true);
+
+        let mesh = cube().catmull_clark(2).to_mesh();
+        assert_eq!(mesh.get_triangle_count(), 192);
+
+        let ray = Ray::new(Tuple::new_point(0.1, 0.2, -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = mesh.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t > 4. && xs[0].t < 4.5, true);
+    }
+
+    #[test]
+    fn test_catmull_clark_creased_cube_stays_boxy() {
+        let mut cage = cube();
+        for face in cube().get_faces() {
+            for i in 0..4 {
+                cage.set_crease(face[i], face[(i + 1) % 4], Float::INFINITY);
+            }
+        }
+        let refined = cage.catmull_clark(2);
+
+        assert_eq!(refined.get_positions()[7], Tuple::new_point(1., 1., 1.));
+        // every point stays on the surface of the original cube
+        for point in refined.get_positions() {
+            let outermost = point.x.abs().max(point.y.abs()).max(point.z.abs());
+            assert_eq!((outermost - 1.).abs() < 1e-9, true);
+        }
+    }
+
+    #[test]
+    fn test_from_mesh_and_open_boundaries() {
+        let mesh = Mesh::new(
+            vec![Tuple::new_point(0., 0., 0.), Tuple::new_point(1., 0., 0.), Tuple::new_point(0., 1., 0.)],
+            vec![[0, 1, 2]]
+        );
+        let refined = Cage::from_mesh(&mesh).loop_subdivide(1);
+
+        // boundary edges split at their middle and the outline stays in the plane
+        assert_eq!(refined.get_positions()[3], Tuple::new_point(0.5, 0., 0.));
+        assert_eq!(refined.get_positions().iter().all(|point| point.z == 0.), true);
+        assert_eq!(refined.to_mesh().get_triangle_count(), 4);
+    }
+}
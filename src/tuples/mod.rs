@@ -156,6 +156,7 @@ impl ops::Mul<Float> for Tuple {
     }
 }
 
+#[cfg(test)]
 mod tests {
 
     use super::*;
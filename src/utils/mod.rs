@@ -1,15 +1,21 @@
+use crate::tuples::Float;
+
+// Tolerance used for every float comparison and for nudging points off surfaces
+pub const EPSILON: Float = 0.00001;
+
 pub struct NumberUtils {}
 
 impl NumberUtils {
     pub fn compare_floats(a: f64, b: f64) -> bool {
-        f64::abs(a-b) < 0.00001
+        f64::abs(a-b) < EPSILON
     }
 
     pub fn compare_floats_32(a: f32, b: f32) -> bool {
-        f32::abs(a-b) < 0.00001
+        f32::abs(a-b) < EPSILON as f32
     }
 }
 
+#[cfg(test)]
 mod tests {
 
     use super::*;
@@ -28,4 +34,4 @@ mod tests {
     fn test_compare_floats_with_way_too_small_numbers() {
         assert_eq!(NumberUtils::compare_floats(0.000000000001, 0.00001), true);  
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,476 @@
+use std::borrow::Cow;
+
+use crate::bounds::BoundingBox;
+use crate::camera::Camera;
+use crate::clipping::{Clip, clip_intersections};
+use crate::color::Color;
+use crate::displacement::displace_shape;
+use crate::environment::Environment;
+use crate::intersection::{Intersection, Computations, hit, sort_intersections};
+use crate::light::{PointLight, lighting, hair_lighting};
+use crate::material::Material;
+use crate::medium::Medium;
+use crate::ray::{Ray, RayKind};
+use crate::shapes::Shape;
+use crate::tuples::{Tuple, Float};
+
+// How many times reflection and refraction rays may bounce in color_at
+pub const MAX_RECURSION_DEPTH: usize = 5;
+
+#[derive(Debug, Default)]
+pub struct World {
+    pub objects: Vec<Box<dyn Shape>>,
+    pub lights: Vec<PointLight>,
+    // Medium filling all space outside of shapes with a medium of their own, path tracer only
+    pub fog: Option<Medium>,
+    // What rays that miss everything see, black when there is none
+    pub environment: Option<Environment>,
+    // Section planes and boxes cutting every object
+    pub clips: Vec<Clip>
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            objects: vec![],
+            lights: vec![],
+            fog: None,
+            environment: None,
+            clips: vec![]
+        }
+    }
+
+    pub fn add_object(&mut self, object: Box<dyn Shape>) {
+        self.objects.push(object);
+    }
+
+    pub fn add_light(&mut self, light: PointLight) {
+        self.lights.push(light);
+    }
+
+    // Swaps every object with a displaced material for its refined and displaced mesh, as
+    // seen from the camera, down into groups. Needs to run once before rendering
+    pub fn displace(&mut self, camera: &Camera, edge_length: Float) {
+        for object in &mut self.objects {
+            displace_object(object, camera, edge_length);
+        }
+    }
+
+    // Box around every object with finite bounds, infinite ones like planes are left out
+    pub fn get_bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::new();
+        for object in &self.objects {
+            let object_bounds = object.get_bounds();
+            let finite = [object_bounds.min, object_bounds.max].iter().all(|corner| {
+                corner.x.is_finite() && corner.y.is_finite() && corner.z.is_finite()
+            });
+            if finite {
+                bounds.merge(&object_bounds);
+            }
+        }
+        bounds
+    }
+
+    // All intersections with every object visible to the kind of ray, sorted by t
+    pub fn intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let mut xs: Vec<Intersection> = self.objects.iter()
+            .flat_map(|object| object.intersect(ray))
+            .filter(|intersection| intersection.object.is_visible_to(ray.kind))
+            .collect();
+
+        sort_intersections(&mut xs);
+        clip_intersections(&self.clips, ray, xs)
+    }
+
+    pub fn is_shadowed(&self, light_position: &Tuple, point: &Tuple) -> bool {
+        self.is_shadowed_at_time(light_position, point, 0.)
+    }
+
+    // Shadow test against the objects where they are at the given time
+    pub fn is_shadowed_at_time(&self, light_position: &Tuple, point: &Tuple, time: Float) -> bool {
+        let v = *light_position - *point;
+        let distance = v.get_magnitude();
+        let ray = Ray::new_at_time(*point, v.normalize(), time).with_kind(RayKind::Shadow);
+        let xs = self.intersect(&ray);
+
+        match hit(&xs) {
+            Some(intersection) => intersection.t < distance,
+            None => false
+        }
+    }
+
+    // Whitted-style shading, Phong for every light plus reflection and refraction
+    pub fn shade_hit(&self, comps: &Computations, remaining: usize) -> Color {
+        let material = comps.object.get_material();
+        // shapes coloring their own surface are lit like their material in that color
+        let surface_material = if comps.color == material.color {
+            Cow::Borrowed(material)
+        } else {
+            Cow::Owned(Material { color: comps.color, ..material.clone() })
+        };
+
+        let light_links = &comps.object.get_properties().light_links;
+        let linked = self.lights.iter().enumerate().filter(|(index, _)| light_links.is_lit_by(*index));
+
+        let surface = linked.fold(material.emissive, |color, (_, light)| {
+            let in_shadow = self.is_shadowed_at_time(&light.position, &comps.over_point, comps.time);
+            color + match &comps.tangent {
+                Some(tangent) => hair_lighting(&surface_material, light, &comps.over_point, &comps.eyev, tangent, in_shadow),
+                None => lighting(&surface_material, light, &comps.over_point, &comps.eyev, &comps.normalv, in_shadow)
+            }
+        });
+
+        let reflected = self.reflected_color(comps, remaining);
+        let refracted = self.refracted_color(comps, remaining);
+
+        if material.reflective > 0. && material.transparency > 0. {
+            let reflectance = comps.schlick() as f32;
+            return surface + reflected * reflectance + refracted * (1. - reflectance)
+        }
+
+        surface + reflected + refracted
+    }
+
+    pub fn color_at(&self, ray: &Ray, remaining: usize) -> Color {
+        let xs = self.intersect(ray);
+
+        match hit(&xs) {
+            Some(intersection) => {
+                let comps = intersection.prepare_computations(ray, &xs);
+                self.shade_hit(&comps, remaining)
+            },
+            None => self.background(&ray.direction)
+        }
+    }
+
+    pub fn background(&self, direction: &Tuple) -> Color {
+        self.environment.as_ref().map_or(Color::black(), |environment| environment.color_in(direction))
+    }
+
+    pub fn reflected_color(&self, comps: &Computations, remaining: usize) -> Color {
+        let reflective = comps.object.get_material().reflective;
+
+        if remaining == 0 || reflective == 0. {
+            return Color::black()
+        }
+
+        let reflect_ray = Ray::new_at_time(comps.over_point, comps.reflectv, comps.time).with_kind(RayKind::Reflection);
+        self.color_at(&reflect_ray, remaining - 1) * reflective as f32
+    }
+
+    pub fn refracted_color(&self, comps: &Computations, remaining: usize) -> Color {
+        let transparency = comps.object.get_material().transparency;
+
+        if remaining == 0 || transparency == 0. {
+            return Color::black()
+        }
+
+        match comps.refract_direction() {
+            Some(direction) => {
+                let refract_ray = Ray::new_at_time(comps.under_point, direction, comps.time).with_kind(RayKind::Refraction);
+                self.color_at(&refract_ray, remaining - 1) * transparency as f32
+            },
+            None => Color::black()
+        }
+    }
+}
+
+// Groups are displaced child by child, so each child keeps its own material
+fn displace_object(object: &mut Box<dyn Shape>, camera: &Camera, edge_length: Float) {
+    if object.is_group() {
+        object.for_each_child_mut(&mut |child| displace_object(child, camera, edge_length));
+    } else if let Some(mesh) = displace_shape(object.as_ref(), camera, edge_length) {
+        *object = Box::new(mesh);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+
+    use super::*;
+    use crate::material::Material;
+    use crate::matrix::Matrix;
+    use crate::shapes::{Sphere, Plane, Visibility, LightLinks};
+
+    // The two concentric spheres and single light most world tests start from
+    pub fn default_world() -> World {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Tuple::new_point(-10., 10., -10.), Color::white()));
+
+        let mut outer = Sphere::new();
+        let mut material = Material::new();
+        material.color = Color::new(0.8, 1.0, 0.6);
+        material.diffuse = 0.7;
+        material.specular = 0.2;
+        outer.set_material(material);
+
+        let mut inner = Sphere::new();
+        inner.set_transform(Matrix::scaling(0.5, 0.5, 0.5));
+
+        world.add_object(Box::new(outer));
+        world.add_object(Box::new(inner));
+        world
+    }
+
+    #[test]
+    fn test_displace_replaces_displaced_objects() {
+        let mut world = default_world();
+        let mut material = Material::new();
+        material.displacement = Some(crate::displacement::Displacement::new_noise(2., 2, 3, 0.2));
+        world.objects[1].set_material(material);
+
+        let mut camera = crate::camera::Camera::new(50, 50, std::f64::consts::PI / 3.);
+        camera.set_transform(Matrix::view_transform(&Tuple::new_point(0., 0., -5.), &Tuple::new_point(0., 0., 0.), &Tuple::new_vector(0., 1., 0.)));
+        world.displace(&camera, 4.);
+
+        assert_eq!(world.objects[0].get_material().displacement, None);
+        assert_eq!(world.objects[1].get_material().displacement, None);
+        assert_eq!(world.objects[1].tessellate(1).len() > 100, true);
+
+        // the inner sphere has only grown, so it stays inside the outer one
+        let ray = Ray::new(Tuple::new_point(0.03, 0.05, -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = world.intersect(&ray);
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[1].t < 4.5 && xs[1].t > 4., true);
+    }
+
+    #[test]
+    fn test_displace_reaches_into_groups() {
+        let mut sphere = Sphere::new();
+        let mut material = Material::new();
+        material.displacement = Some(crate::displacement::Displacement::new_noise(2., 2, 3, 0.2));
+        sphere.set_material(material);
+        let mut inner = crate::shapes::Group::new();
+        inner.add_child(Box::new(sphere));
+        let mut outer = crate::shapes::Group::new();
+        outer.add_child(Box::new(inner));
+
+        let mut world = World::new();
+        world.add_object(Box::new(outer));
+        let mut camera = crate::camera::Camera::new(50, 50, std::f64::consts::PI / 3.);
+        camera.set_transform(Matrix::view_transform(&Tuple::new_point(0., 0., -5.), &Tuple::new_point(0., 0., 0.), &Tuple::new_vector(0., 1., 0.)));
+        world.displace(&camera, 4.);
+
+        let triangles = world.objects[0].tessellate(1);
+        assert_eq!(triangles.len() > 100, true);
+        assert_eq!(triangles.iter().all(|triangle| triangle.get_material().displacement.is_none()), true);
+
+        // the sphere has grown, and the groups' bounds with it
+        let bounds = world.objects[0].get_bounds();
+        assert_eq!(bounds.max.z > 1., true);
+        let ray = Ray::new(Tuple::new_point(0.03, 0.05, -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(world.intersect(&ray)[0].t < 4., true);
+    }
+
+    #[test]
+    fn test_displaced_objects_keep_visibility_and_light_links() {
+        let mut world = default_world();
+        world.add_light(PointLight::new(Tuple::new_point(10., 10., -10.), Color::white()));
+        let mut material = Material::new();
+        material.displacement = Some(crate::displacement::Displacement::new_noise(2., 2, 3, 0.2));
+        world.objects[0].set_material(material);
+        world.objects[0].set_visibility(Visibility { camera: false, ..Visibility::new() });
+        world.objects[0].set_light_links(LightLinks::Include(vec![1]));
+        world.objects[0].add_clip(Clip::new_plane(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 1., 0.)));
+
+        let mut camera = crate::camera::Camera::new(50, 50, std::f64::consts::PI / 3.);
+        camera.set_transform(Matrix::view_transform(&Tuple::new_point(0., 0., -5.), &Tuple::new_point(0., 0., 0.), &Tuple::new_vector(0., 1., 0.)));
+        world.displace(&camera, 4.);
+
+        // the camera still sees through the displaced outer sphere, other rays still hit it
+        let ray = Ray::new(Tuple::new_point(0., -0.1, -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(world.intersect(&ray).len(), 2);
+        assert_eq!(world.intersect(&ray.with_kind(RayKind::Reflection)).len(), 4);
+        // and its upper half is still cut away
+        let above = Ray::new(Tuple::new_point(0., 0.1, -5.), Tuple::new_vector(0., 0., 1.)).with_kind(RayKind::Reflection);
+        assert_eq!(world.intersect(&above).len(), 2);
+
+        let properties = world.objects[0].get_properties();
+        assert_eq!(properties.light_links, LightLinks::Include(vec![1]));
+        assert_eq!(properties.light_links.is_lit_by(0), false);
+        assert_eq!(properties.clips.len(), 1);
+    }
+
+    #[test]
+    fn test_intersect_world() {
+        let world = default_world();
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = world.intersect(&ray);
+
+        let ts: Vec<f64> = xs.iter().map(|intersection| intersection.t).collect();
+        assert_eq!(ts, vec![4., 4.5, 5.5, 6.]);
+    }
+
+    #[test]
+    fn test_section_view() {
+        let mut world = default_world();
+        let mut section = Clip::new_plane(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 0., -1.));
+        section.cap = Some(Color::red());
+        world.clips.push(section);
+
+        // the front halves are cut away and the ray first meets the inner sphere's cut face
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let xs = world.intersect(&ray);
+        assert_eq!(xs.iter().map(|intersection| intersection.t).collect::<Vec<f64>>(), vec![5., 5.5, 6.]);
+        assert_eq!(xs[0].is_same_object(world.objects[1].as_ref()), true);
+        assert_eq!(xs[0].prepare_computations(&ray, &xs).color, Color::red());
+
+        // cut away parts cast no shadows
+        world.clips[0].cap = None;
+        assert_eq!(world.is_shadowed(&Tuple::new_point(0., 0., -10.), &Tuple::new_point(0., 0., -0.2)), false);
+    }
+
+    #[test]
+    fn test_shade_intersection() {
+        let world = default_world();
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let intersection = Intersection::new(4., world.objects[0].as_ref());
+        let comps = intersection.prepare_computations(&ray, &[intersection]);
+
+        let color = world.shade_hit(&comps, MAX_RECURSION_DEPTH);
+        assert_eq!(color.is_equal_to(Color::new(0.38066, 0.47583, 0.2855)), true);
+    }
+
+    #[test]
+    fn test_color_at_miss_and_hit() {
+        let world = default_world();
+
+        let miss = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 1., 0.));
+        assert_eq!(world.color_at(&miss, MAX_RECURSION_DEPTH), Color::black());
+
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let color = world.color_at(&ray, MAX_RECURSION_DEPTH);
+        assert_eq!(color.is_equal_to(Color::new(0.38066, 0.47583, 0.2855)), true);
+    }
+
+    #[test]
+    fn test_miss_shows_environment() {
+        let mut world = default_world();
+        world.environment = Some(Environment::Constant(Color::new(0.2, 0.4, 0.8)));
+
+        let miss = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 1., 0.));
+        assert_eq!(world.color_at(&miss, MAX_RECURSION_DEPTH), Color::new(0.2, 0.4, 0.8));
+    }
+
+    #[test]
+    fn test_shadows() {
+        let world = default_world();
+        let light = world.lights[0].position;
+
+        assert_eq!(world.is_shadowed(&light, &Tuple::new_point(0., 10., 0.)), false);
+        assert_eq!(world.is_shadowed(&light, &Tuple::new_point(10., -10., 10.)), true);
+        assert_eq!(world.is_shadowed(&light, &Tuple::new_point(-20., 20., -20.)), false);
+        assert_eq!(world.is_shadowed(&light, &Tuple::new_point(-2., 2., -2.)), false);
+    }
+
+    #[test]
+    fn test_visibility_flags() {
+        let mut world = default_world();
+        let light = world.lights[0].position;
+        let hidden = Visibility { camera: false, ..Visibility::new() };
+        world.objects[0].set_visibility(hidden);
+
+        // the camera looks through the outer sphere, which still casts its shadow
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(world.intersect(&ray).iter().map(|intersection| intersection.t).collect::<Vec<f64>>(), vec![4.5, 5.5]);
+        assert_eq!(world.intersect(&ray.with_kind(RayKind::Reflection)).len(), 4);
+        assert_eq!(world.is_shadowed(&light, &Tuple::new_point(10., -10., 10.)), true);
+
+        let shadowless = Visibility { shadows: false, ..Visibility::new() };
+        world.objects[0].set_visibility(shadowless);
+        world.objects[1].set_visibility(shadowless);
+        assert_eq!(world.is_shadowed(&light, &Tuple::new_point(10., -10., 10.)), false);
+    }
+
+    #[test]
+    fn test_light_linking() {
+        let mut world = default_world();
+        world.add_light(PointLight::new(Tuple::new_point(10., 10., -10.), Color::white()));
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        let shade = |world: &World| {
+            let intersection = Intersection::new(4., world.objects[0].as_ref());
+            world.shade_hit(&intersection.prepare_computations(&ray, &[intersection]), MAX_RECURSION_DEPTH)
+        };
+
+        // the lights mirror each other across the hit, so each adds the same
+        let both = shade(&world);
+        world.objects[0].set_light_links(LightLinks::Include(vec![0]));
+        let first = shade(&world);
+        assert_eq!(first.is_equal_to(Color::new(0.38066, 0.47583, 0.2855)), true);
+        assert_eq!(both.is_equal_to(first * 2.), true);
+        world.objects[0].set_light_links(LightLinks::Exclude(vec![1]));
+        assert_eq!(shade(&world), first);
+
+        world.objects[0].set_light_links(LightLinks::Exclude(vec![0, 1]));
+        assert_eq!(shade(&world), Color::black());
+    }
+
+    #[test]
+    fn test_emissive_surface_glows_without_lights() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.set_material(Material::new_emissive(Color::new(0.5, 0.25, 1.)));
+        world.add_object(Box::new(sphere));
+
+        let ray = Ray::new(Tuple::new_point(0., 0., -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(world.color_at(&ray, MAX_RECURSION_DEPTH), Color::new(0.5, 0.25, 1.));
+    }
+
+    #[test]
+    fn test_reflected_color() {
+        let mut world = default_world();
+        let mut plane = Plane::new();
+        let mut material = Material::new();
+        material.reflective = 0.5;
+        plane.set_material(material);
+        plane.set_transform(Matrix::translation(0., -1., 0.));
+        world.add_object(Box::new(plane));
+
+        let value = 2_f64.sqrt() / 2.;
+        let ray = Ray::new(Tuple::new_point(0., 0., -3.), Tuple::new_vector(0., -value, value));
+        let intersection = Intersection::new(2_f64.sqrt(), world.objects[2].as_ref());
+        let comps = intersection.prepare_computations(&ray, &[intersection]);
+
+        let color = world.reflected_color(&comps, MAX_RECURSION_DEPTH);
+        assert_eq!(f32::abs(color.red - 0.19032) < 0.001, true);
+        assert_eq!(world.reflected_color(&comps, 0), Color::black());
+    }
+
+    #[test]
+    fn test_mutually_reflective_surfaces_terminate() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Tuple::new_point(0., 0., 0.), Color::white()));
+
+        let mut material = Material::new();
+        material.reflective = 1.;
+
+        let mut lower = Plane::new();
+        lower.set_material(material.clone());
+        lower.set_transform(Matrix::translation(0., -1., 0.));
+        let mut upper = Plane::new();
+        upper.set_material(material);
+        upper.set_transform(Matrix::translation(0., 1., 0.));
+        world.add_object(Box::new(lower));
+        world.add_object(Box::new(upper));
+
+        let ray = Ray::new(Tuple::new_point(0., 0., 0.), Tuple::new_vector(0., 1., 0.));
+        world.color_at(&ray, MAX_RECURSION_DEPTH);
+    }
+
+    #[test]
+    fn test_refracted_color_under_total_internal_reflection() {
+        let mut world = default_world();
+        let mut material = world.objects[0].get_material().clone();
+        material.transparency = 1.;
+        material.refractive_index = 1.5;
+        world.objects[0].set_material(material);
+
+        let value = 2_f64.sqrt() / 2.;
+        let ray = Ray::new(Tuple::new_point(0., 0., value), Tuple::new_vector(0., 1., 0.));
+        let object = world.objects[0].as_ref();
+        let xs = vec![Intersection::new(-value, object), Intersection::new(value, object)];
+        let comps = xs[1].prepare_computations(&ray, &xs);
+
+        assert_eq!(world.refracted_color(&comps, MAX_RECURSION_DEPTH), Color::black());
+    }
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::color::Color;
+use crate::shapes::{PointCloud, SplatType};
+use crate::tuples::{Tuple, Float};
+
+// Plain text point clouds as exported from scanners, one point per line as x y z or
+// x y z r g b, separated by spaces, tabs or commas. Colors are read as 0 to 255 when any
+// component in the file is above 1, otherwise as 0 to 1. Lines starting with # or // are
+// comments
+#[derive(PartialEq, Debug, Clone)]
+pub struct XyzFile {
+    pub points: Vec<Tuple>,
+    // Empty when the lines have no colors
+    pub colors: Vec<Color>
+}
+
+impl XyzFile {
+    pub fn load(path: &str) -> Result<XyzFile> {
+        let text = fs::read_to_string(path)?;
+        XyzFile::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<XyzFile> {
+        let mut points = vec![];
+        let mut colors: Vec<[Float; 3]> = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue
+            }
+
+            let values = line.split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<Float>().map_err(|_| invalid(&format!("line {}: invalid number {}", number, value))))
+                .collect::<Result<Vec<Float>>>()?;
+
+            match values.as_slice() {
+                [x, y, z] if colors.is_empty() => points.push(Tuple::new_point(*x, *y, *z)),
+                [x, y, z, r, g, b] if colors.len() == points.len() => {
+                    points.push(Tuple::new_point(*x, *y, *z));
+                    colors.push([*r, *g, *b]);
+                },
+                [_, _, _] | [_, _, _, _, _, _] => return Err(invalid(&format!("line {}: points with and without colors are mixed", number))),
+                _ => return Err(invalid(&format!("line {}: expected 3 or 6 values, found {}", number, values.len())))
+            }
+        }
+
+        let scale = if colors.iter().flatten().any(|&value| value > 1.) { 255. } else { 1. };
+        let colors = colors.iter()
+            .map(|[r, g, b]| Color::new((r / scale) as f32, (g / scale) as f32, (b / scale) as f32))
+            .collect();
+
+        Ok(XyzFile { points, colors })
+    }
+
+    pub fn to_point_cloud(&self, radius: Float, splat: SplatType) -> PointCloud {
+        let mut cloud = PointCloud::new(self.points.clone(), radius, splat);
+
+        if !self.colors.is_empty() {
+            cloud.set_colors(self.colors.clone());
+        }
+        cloud
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+
+    #[test]
+    fn test_xyz_points() {
+        let file = XyzFile::parse("# scan\n0 0 0\n\n1.5\t2 -3\n").unwrap();
+
+        assert_eq!(file.points, vec![Tuple::new_point(0., 0., 0.), Tuple::new_point(1.5, 2., -3.)]);
+        assert_eq!(file.colors.len(), 0);
+
+        let cloud = file.to_point_cloud(0.1, SplatType::Disk);
+        let ray = Ray::new(Tuple::new_point(1.5, 2., -5.), Tuple::new_vector(0., 0., 1.));
+        assert_eq!(cloud.intersect(&ray)[0].t, 2.);
+        assert_eq!(cloud.get_colors().len(), 0);
+    }
+
+    #[test]
+    fn test_xyzrgb_points() {
+        let bytes = XyzFile::parse("0,0,0,255,0,0\n1,0,0,0,51,255\n").unwrap();
+        assert_eq!(bytes.colors, vec![Color::red(), Color::new(0., 0.2, 1.)]);
+
+        let fractions = XyzFile::parse("0 0 0 1 0.5 0\n").unwrap();
+        assert_eq!(fractions.colors, vec![Color::new(1., 0.5, 0.)]);
+        assert_eq!(fractions.to_point_cloud(1., SplatType::Sphere).get_colors(), &[Color::new(1., 0.5, 0.)]);
+    }
+
+    #[test]
+    fn test_invalid_xyz_files() {
+        assert_eq!(XyzFile::parse("0 0\n").is_err(), true);
+        assert_eq!(XyzFile::parse("0 0 zero\n").is_err(), true);
+        assert_eq!(XyzFile::parse("0 0 0\n1 1 1 255 0 0\n").is_err(), true);
+        assert_eq!(XyzFile::parse("1 1 1 255 0 0\n0 0 0\n").is_err(), true);
+    }
+}